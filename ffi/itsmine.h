@@ -0,0 +1,44 @@
+/* C interface to the itsmine stressors. Link against libitsmine_ffi.so or
+ * libitsmine_ffi.a built from the itsmine-ffi crate. */
+#ifndef ITSMINE_H
+#define ITSMINE_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct itsmine_handle itsmine_handle;
+
+/* Allocates and touches `bytes` of memory, holding it for `duration_ms`
+ * (0 releases it as soon as it has been touched). Never returns NULL. */
+itsmine_handle *itsmine_start_memory(uint64_t bytes, uint64_t duration_ms);
+
+/* Runs `threads` busy threads for `duration_ms` (0 runs one iteration). */
+itsmine_handle *itsmine_start_threads(uint32_t threads, uint64_t duration_ms);
+
+/* Starts the registered stressor `name` (as listed by `itsmine list`) with
+ * whitespace-separated command-line `args`, which may be NULL, for
+ * `duration_ms` (0 runs it until it finishes or is stopped). Returns NULL if the name or
+ * arguments are invalid; see itsmine_last_error(). */
+itsmine_handle *itsmine_start(const char *name, const char *args, uint64_t duration_ms);
+
+/* Returns 1 once the stressor has finished, 0 otherwise, and -1 for a NULL
+ * handle. */
+int itsmine_is_finished(const itsmine_handle *handle);
+
+/* Cancels the stressor, waits for it to tear down, frees the handle and
+ * returns 0 on success or -1 on failure or a NULL handle; see
+ * itsmine_last_error(). */
+int itsmine_stop(itsmine_handle *handle);
+
+/* Message of the last failure on this thread, or NULL. Valid until the next
+ * call into itsmine from the same thread. */
+const char *itsmine_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,197 @@
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char, c_int};
+use std::time::Duration;
+
+use itsmine_rs::{Resource, Stress, registry};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Opaque handle to a running stressor (`itsmine_handle` in `itsmine.h`).
+pub struct Handle(Stress);
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).ok();
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+
+fn start(resource: Resource, duration_ms: u64) -> *mut Handle {
+    let duration = (duration_ms > 0).then(|| Duration::from_millis(duration_ms));
+    Box::into_raw(Box::new(Handle(Stress::start(resource, duration))))
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn itsmine_start_memory(bytes: u64, duration_ms: u64) -> *mut Handle {
+    start(
+        Resource::Memory {
+            arg: format!("{bytes}B"),
+            bench: None,
+        },
+        duration_ms,
+    )
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn itsmine_start_threads(threads: u32, duration_ms: u64) -> *mut Handle {
+    start(Resource::Thread { num: threads }, duration_ms)
+}
+
+/// The registered stressor `name` with its whitespace-separated `args`,
+/// checked before it starts.
+fn registered(name: &CStr, args: &CStr) -> Result<Resource, anyhow::Error> {
+    let name = name
+        .to_str()
+        .map_err(|e| anyhow::anyhow!("stressor name is not UTF-8: {e}"))?;
+    let args: Vec<String> = args
+        .to_str()
+        .map_err(|e| anyhow::anyhow!("{name}: arguments are not UTF-8: {e}"))?
+        .split_whitespace()
+        .map(str::to_string)
+        .collect();
+    let name = registry::registry()
+        .into_iter()
+        .map(|registration| registration.name)
+        .find(|registered| *registered == name)
+        .ok_or_else(|| anyhow::anyhow!("unknown stressor '{name}'"))?;
+    registry::stressor(name, &args)?;
+    Ok(Resource::Registered { name, args })
+}
+
+/// # Safety
+///
+/// `name` and `args` must each be NULL or a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn itsmine_start(
+    name: *const c_char,
+    args: *const c_char,
+    duration_ms: u64,
+) -> *mut Handle {
+    if name.is_null() {
+        set_last_error("stressor name is NULL".to_string());
+        return std::ptr::null_mut();
+    }
+    // SAFETY: both are NUL-terminated strings, as guaranteed by the caller.
+    let (name, args) = unsafe {
+        (
+            CStr::from_ptr(name),
+            match args.is_null() {
+                true => c"",
+                false => CStr::from_ptr(args),
+            },
+        )
+    };
+    match registered(name, args) {
+        Ok(resource) => start(resource, duration_ms),
+        Err(e) => {
+            set_last_error(format!("{e:#}"));
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// # Safety
+///
+/// `handle` must be NULL or come from an `itsmine_start*` function and not
+/// have been passed to `itsmine_stop` yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn itsmine_is_finished(handle: *const Handle) -> c_int {
+    // SAFETY: NULL or live, as guaranteed by the caller.
+    let Some(handle) = (unsafe { handle.as_ref() }) else {
+        set_last_error("handle is NULL".to_string());
+        return -1;
+    };
+    handle.0.is_finished() as c_int
+}
+
+/// # Safety
+///
+/// `handle` must be NULL or come from an `itsmine_start*` function; it is
+/// freed and must not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn itsmine_stop(handle: *mut Handle) -> c_int {
+    if handle.is_null() {
+        set_last_error("handle is NULL".to_string());
+        return -1;
+    }
+    // SAFETY: ownership is handed back by the caller.
+    let handle = unsafe { Box::from_raw(handle) };
+    handle.0.stop();
+    match handle.0.wait() {
+        Ok(()) => 0,
+        Err(e) => {
+            set_last_error(format!("{e:#}"));
+            -1
+        }
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn itsmine_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |message| message.as_ptr())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last_error() -> String {
+        let message = itsmine_last_error();
+        assert!(!message.is_null());
+        // SAFETY: non-NULL messages are live until the next call.
+        unsafe { CStr::from_ptr(message) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn rejects_null_arguments() {
+        // SAFETY: NULL is allowed for every argument.
+        unsafe {
+            assert!(itsmine_start(std::ptr::null(), std::ptr::null(), 0).is_null());
+            assert_eq!(last_error(), "stressor name is NULL");
+            assert_eq!(itsmine_is_finished(std::ptr::null()), -1);
+            assert_eq!(itsmine_stop(std::ptr::null_mut()), -1);
+        }
+        assert_eq!(last_error(), "handle is NULL");
+    }
+
+    #[test]
+    fn rejects_invalid_names_and_arguments() {
+        let invalid = c"\xff\xfe";
+        // SAFETY: every argument is a NUL-terminated string.
+        unsafe {
+            assert!(itsmine_start(invalid.as_ptr(), c"".as_ptr(), 0).is_null());
+            assert!(last_error().contains("not UTF-8"));
+            assert!(itsmine_start(c"sem".as_ptr(), invalid.as_ptr(), 0).is_null());
+            assert!(last_error().starts_with("sem: arguments are not UTF-8"));
+            assert!(itsmine_start(c"nonesuch".as_ptr(), c"".as_ptr(), 0).is_null());
+            assert_eq!(last_error(), "unknown stressor 'nonesuch'");
+            assert!(itsmine_start(c"sem".as_ptr(), c"--bogus".as_ptr(), 0).is_null());
+            assert!(last_error().starts_with("sem: "));
+        }
+    }
+
+    #[test]
+    fn runs_and_frees_stressors() {
+        // SAFETY: the handles come from itsmine_start* and are stopped once.
+        unsafe {
+            let handle = itsmine_start(c"sem".as_ptr(), c"--threads 1 --duration 50ms".as_ptr(), 0);
+            assert!(!handle.is_null());
+            while itsmine_is_finished(handle) == 0 {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            assert_eq!(itsmine_stop(handle), 0);
+
+            // Stopped early, before its duration is up.
+            let handle = itsmine_start_threads(1, 60_000);
+            assert_eq!(itsmine_is_finished(handle), 0);
+            assert_eq!(itsmine_stop(handle), 0);
+            assert_eq!(itsmine_stop(itsmine_start_memory(4096, 0)), 0);
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use itsmine_rs::{Resource, Stress, report, telemetry};
+use pyo3::IntoPyObjectExt;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use serde_json::{Map, Value};
+
+/// Measurements of the current run, gathered from the report as stressors
+/// record them.
+static MEASURED: Mutex<Vec<(String, Value)>> = Mutex::new(vec![]);
+
+/// Starts a new run: drops what was measured and recorded before, so
+/// `metrics()` only reports stressors started since. Stressors still
+/// running keep adding what they measure when they finish.
+fn reset() {
+    report::take_measurements();
+    report::take_degradations();
+    telemetry::clear_history();
+    MEASURED.lock().unwrap_or_else(|e| e.into_inner()).clear();
+}
+
+/// The current run's measurements so far.
+fn measured() -> Map<String, Value> {
+    let mut measured = MEASURED.lock().unwrap_or_else(|e| e.into_inner());
+    measured.extend(report::take_measurements());
+    measured.iter().cloned().collect()
+}
+
+fn to_python<'py>(py: Python<'py>, value: &Value) -> PyResult<Bound<'py, PyAny>> {
+    match value {
+        Value::Null => Ok(py.None().into_bound(py)),
+        Value::Bool(b) => b.into_bound_py_any(py),
+        Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+            (Some(u), _) => u.into_bound_py_any(py),
+            (None, Some(i)) => i.into_bound_py_any(py),
+            (None, None) => n.as_f64().unwrap_or(f64::NAN).into_bound_py_any(py),
+        },
+        Value::String(s) => s.into_bound_py_any(py),
+        Value::Array(items) => {
+            let list = PyList::empty(py);
+            for item in items {
+                list.append(to_python(py, item)?)?;
+            }
+            list.into_bound_py_any(py)
+        }
+        Value::Object(fields) => {
+            let dict = PyDict::new(py);
+            for (key, field) in fields {
+                dict.set_item(key, to_python(py, field)?)?;
+            }
+            dict.into_bound_py_any(py)
+        }
+    }
+}
+
+/// A stressor running in the background.
+#[pyclass]
+struct Stressor {
+    stress: Option<Stress>,
+}
+
+#[pymethods]
+impl Stressor {
+    /// Whether the stressor has finished.
+    fn done(&self) -> bool {
+        self.stress.as_ref().is_none_or(Stress::is_finished)
+    }
+
+    /// Asks the stressor to tear down early.
+    fn stop(&self) {
+        if let Some(stress) = &self.stress {
+            stress.stop();
+        }
+    }
+
+    /// Blocks until the stressor finishes, raising RuntimeError if it failed.
+    fn wait(&mut self, py: Python<'_>) -> PyResult<()> {
+        let Some(stress) = self.stress.take() else {
+            return Ok(());
+        };
+        py.detach(|| stress.wait())
+            .map_err(|e| PyRuntimeError::new_err(format!("{e:#}")))
+    }
+}
+
+fn start(resource: Resource, duration: Option<f64>) -> PyResult<Stressor> {
+    let duration = duration
+        .map(Duration::try_from_secs_f64)
+        .transpose()
+        .map_err(|e| PyValueError::new_err(format!("invalid duration: {e}")))?;
+    reset();
+    Ok(Stressor {
+        stress: Some(Stress::start(resource, duration)),
+    })
+}
+
+/// Allocates and touches `size` (e.g. "512M"), holding it for `duration`
+/// seconds if given.
+#[pyfunction]
+#[pyo3(signature = (size, duration = None))]
+fn memory(size: String, duration: Option<f64>) -> PyResult<Stressor> {
+    start(
+        Resource::Memory {
+            arg: size,
+            bench: None,
+        },
+        duration,
+    )
+}
+
+/// Runs `num` busy threads, for `duration` seconds if given.
+#[pyfunction]
+#[pyo3(signature = (num, duration = None))]
+fn threads(num: u32, duration: Option<f64>) -> PyResult<Stressor> {
+    start(Resource::Thread { num }, duration)
+}
+
+/// What the stressors of the current run measured, as the report records
+/// it, e.g. `{"memory": {"bytes": ...}}`.
+#[pyfunction]
+fn metrics(py: Python<'_>) -> PyResult<Bound<'_, PyAny>> {
+    to_python(py, &Value::Object(measured()))
+}
+
+/// Gauge points of the current run, as `{name: [(seconds, value), ...]}`.
+#[pyfunction]
+fn series() -> BTreeMap<String, Vec<(f64, u64)>> {
+    telemetry::history()
+        .into_iter()
+        .map(|(name, series)| {
+            let points = series
+                .into_iter()
+                .map(|(at, value)| (at.as_secs_f64(), value))
+                .collect();
+            (name, points)
+        })
+        .collect()
+}
+
+#[pymodule]
+fn itsmine(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    telemetry::record_history();
+    m.add_class::<Stressor>()?;
+    m.add_function(wrap_pyfunction!(memory, m)?)?;
+    m.add_function(wrap_pyfunction!(threads, m)?)?;
+    m.add_function(wrap_pyfunction!(metrics, m)?)?;
+    m.add_function(wrap_pyfunction!(series, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_run_reports_only_its_own_measurements() {
+        reset();
+        report::measure("first", Value::from(1));
+        assert_eq!(measured().get("first"), Some(&Value::from(1)));
+        // Fetching again still returns what the run measured.
+        report::measure("second", Value::from(2));
+        assert_eq!(measured().len(), 2);
+
+        reset();
+        assert!(measured().is_empty());
+        report::measure("third", Value::from(3));
+        assert_eq!(measured().keys().collect::<Vec<_>>(), ["third"]);
+    }
+}
@@ -0,0 +1,147 @@
+//! `itsmine address-space`: reserves virtual address space `PROT_NONE`
+//! without committing any of it, driving VSZ towards its limits while RSS
+//! stays flat.
+
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+use crate::monitor::parse_meminfo_kb;
+use crate::{CancellationToken, StressError, Stressor, parse, report};
+
+const PAGE: u64 = 4096;
+
+#[derive(Clone, Debug, PartialEq, clap::Args)]
+pub struct Args {
+    /// How long to hold the reservation, e.g. 10s
+    #[arg(long, value_parser = parse::duration, default_value = "10s")]
+    pub duration: Duration,
+    /// Address space to reserve, e.g. 1T; by default, as much as the kernel
+    /// hands out
+    #[arg(long, value_name = "BYTES", value_parser = parse::bytes)]
+    pub bytes: Option<u64>,
+    /// Size of each mapping; halved whenever the kernel refuses one
+    #[arg(long, value_name = "BYTES", value_parser = parse::bytes, default_value = "64G")]
+    pub chunk: u64,
+}
+
+/// `PROT_NONE` mappings, unmapped on drop.
+pub(crate) struct Reservations(Vec<(*mut libc::c_void, usize)>);
+
+impl Reservations {
+    /// Maps up to `limit` bytes in `chunk`-sized pieces, halving the chunk
+    /// each time a mapping fails until it would drop below a page.
+    pub(crate) fn reserve(limit: u64, mut chunk: u64, cancel: &CancellationToken) -> Self {
+        let mut reservations = Reservations(vec![]);
+        chunk = chunk.next_multiple_of(PAGE);
+        while chunk >= PAGE && !cancel.is_cancelled() {
+            let len = chunk.min(limit - reservations.bytes());
+            if len < PAGE {
+                break;
+            }
+            // SAFETY: a fresh anonymous mapping aliases nothing.
+            let ptr = unsafe {
+                libc::mmap(
+                    std::ptr::null_mut(),
+                    len as usize,
+                    libc::PROT_NONE,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+                    -1,
+                    0,
+                )
+            };
+            match ptr == libc::MAP_FAILED {
+                true => chunk /= 2,
+                false => reservations.0.push((ptr, len as usize)),
+            }
+        }
+        reservations
+    }
+
+    pub(crate) fn bytes(&self) -> u64 {
+        self.0.iter().map(|&(_, len)| len as u64).sum()
+    }
+}
+
+impl Drop for Reservations {
+    fn drop(&mut self) {
+        for &(ptr, len) in &self.0 {
+            // SAFETY: each mapping came from mmap in `reserve` and is
+            // unmapped once.
+            unsafe { libc::munmap(ptr, len) };
+        }
+    }
+}
+
+/// This process's virtual and resident size in bytes.
+pub(crate) fn sizes() -> (Option<u64>, Option<u64>) {
+    let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+    let bytes = |key| parse_meminfo_kb(&status, key).map(|kb| kb * 1024);
+    (bytes("VmSize"), bytes("VmRSS"))
+}
+
+impl Stressor for Args {
+    fn name(&self) -> &'static str {
+        "address-space"
+    }
+
+    fn params(&self) -> Value {
+        json!({
+            "duration_secs": self.duration.as_secs_f64(),
+            "bytes": self.bytes,
+            "chunk_bytes": self.chunk,
+        })
+    }
+
+    fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        if self.chunk < PAGE {
+            return Err(StressError::InvalidSize("--chunk must be at least 4K".to_string()).into());
+        }
+        let started = Instant::now();
+        let reservations =
+            Reservations::reserve(self.bytes.unwrap_or(u64::MAX), self.chunk, cancel);
+        let reserved = reservations.bytes();
+        if let Some(bytes) = self.bytes
+            && reserved < bytes
+        {
+            log::warn!("The kernel only handed out {reserved} of {bytes} bytes.");
+        }
+        let (vm_size, vm_rss) = sizes();
+        let summary = json!({
+            "reserved_bytes": reserved,
+            "mappings": reservations.0.len(),
+            "reserve_secs": started.elapsed().as_secs_f64(),
+            "vm_size_bytes": vm_size,
+            "vm_rss_bytes": vm_rss,
+        });
+        log::info!(
+            "Holding {reserved} bytes of address space in {} mappings for {:?}.",
+            reservations.0.len(),
+            self.duration
+        );
+        cancel.sleep_until(Instant::now() + self.duration);
+        drop(reservations);
+        log::info!("Address space: {summary}.");
+        report::measure(self.name(), summary);
+        match cancel.is_cancelled() {
+            true => Err(StressError::Interrupted.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserves_without_committing() {
+        let (_, rss_before) = sizes();
+        let reservations = Reservations::reserve(1 << 30, 256 << 20, &CancellationToken::new());
+        assert_eq!(reservations.0.len(), 4);
+        assert_eq!(reservations.bytes(), 1 << 30);
+        let (vm_size, rss_after) = sizes();
+        assert!(vm_size.unwrap() >= 1 << 30);
+        assert!(rss_after.unwrap() < rss_before.unwrap() + (64 << 20));
+    }
+}
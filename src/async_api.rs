@@ -0,0 +1,104 @@
+use std::time::SystemTime;
+
+use crate::report::Report;
+use crate::{CancellationToken, Resource};
+
+/// Cancels the stressor if the future running it is dropped early, e.g. when
+/// it loses a `tokio::select!` race.
+struct CancelOnDrop(Option<CancellationToken>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(token) = self.0.take() {
+            token.cancel();
+        }
+    }
+}
+
+impl Resource {
+    /// Runs the stressor on tokio's blocking pool and reports the outcome.
+    /// Cancelling `cancel` or dropping the future tears the stressor down.
+    pub async fn run(&self, cancel: CancellationToken) -> Report {
+        let token = cancel.child_token();
+        let mut guard = CancelOnDrop(Some(token.clone()));
+        let resource = self.clone();
+        let started = SystemTime::now();
+        let outcome = tokio::task::spawn_blocking(move || crate::run_stressor(resource, &token))
+            .await
+            .unwrap_or_else(|e| Err(anyhow::anyhow!("Stressor task failed: {e}")));
+        guard.0 = None;
+        let mut report = Report::new(self.name(), self.params(), started, &outcome);
+        report.collect();
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::report::Status;
+
+    #[tokio::test]
+    async fn run_reports_outcome() {
+        let report = Resource::Thread { num: 1 }
+            .run(CancellationToken::new())
+            .await;
+        assert_eq!(report.status, Status::Ok);
+        assert_eq!(report.stressor, "thread");
+        assert!(report.verification.is_some_and(|v| v.checked == 1));
+
+        let report = Resource::Memory {
+            arg: "0K".to_string(),
+            bench: None,
+        }
+        .run(CancellationToken::new())
+        .await;
+        assert_eq!(report.status, Status::Failed);
+    }
+
+    #[tokio::test]
+    async fn dropping_the_future_cancels_the_stressor() {
+        use std::io::Read;
+
+        // The stressor streams to this listener until it is torn down,
+        // which the listener sees as the connection closing.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = listener.local_addr().unwrap();
+        let sink = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(10)))
+                .unwrap();
+            let mut buffer = [0; 64 << 10];
+            loop {
+                match stream.read(&mut buffer) {
+                    Ok(0) => return Ok(Instant::now()),
+                    Ok(_) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        });
+        let dir = std::env::temp_dir().join(format!("itsmine-async-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let scenario = dir.join("hold.txt");
+        std::fs::write(
+            &scenario,
+            format!("phase hold 60s\n  net --target {target} --connections 1 --duration 1h\n"),
+        )
+        .unwrap();
+        let resource = Resource::Run { scenario };
+        let run = resource.run(CancellationToken::new());
+
+        let timed_out = tokio::time::timeout(Duration::from_millis(300), run).await;
+        assert!(timed_out.is_err());
+        let dropped = Instant::now();
+        let closed = sink
+            .join()
+            .unwrap()
+            .expect("stressor kept streaming after the drop");
+        assert!(closed.duration_since(dropped) < Duration::from_secs(5));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,238 @@
+//! `itsmine balloon`: holds memory whose size an external controller sets
+//! over HTTP, so itsmine can stand in for a software memory balloon.
+//!
+//! `GET /stressors/mem/size` returns `{"bytes": N}` for the memory held;
+//! `PUT` the same path with `{"bytes": N}` (a number, or a size like "2G")
+//! to grow or shrink it. The response comes once the memory is touched or
+//! freed.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+use crate::buffer::Buffer;
+use crate::{CancellationToken, StressError, Stressor, events, parse, report};
+
+/// Memory is held, and released, in pieces of this size.
+const CHUNK: u64 = 64 << 20;
+const PATH: &str = "/stressors/mem/size";
+const POLL: Duration = Duration::from_millis(50);
+/// How long a client may take to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug, PartialEq, clap::Args)]
+pub struct Args {
+    /// Address to serve the control API on
+    #[arg(long, value_name = "HOST:PORT", default_value = "127.0.0.1:9400")]
+    pub listen: String,
+    /// Memory to hold before the first request, e.g. 1G
+    #[arg(long, value_name = "BYTES", value_parser = parse::bytes, default_value = "0")]
+    pub initial: u64,
+    /// How long to serve, e.g. 1h; until interrupted if omitted
+    #[arg(long, value_parser = parse::duration)]
+    pub duration: Option<Duration>,
+}
+
+/// The memory held, touched in full.
+#[derive(Default)]
+struct Balloon {
+    chunks: Vec<Buffer>,
+    resizes: u64,
+    peak: u64,
+}
+
+impl Balloon {
+    fn bytes(&self) -> u64 {
+        self.chunks.iter().map(|chunk| chunk.size() as u64).sum()
+    }
+
+    /// Frees or allocates chunks until exactly `bytes` are held. A failed
+    /// allocation keeps whatever was reached.
+    fn resize(&mut self, bytes: u64) -> Result<(), StressError> {
+        while self.bytes() > bytes {
+            let excess = self.bytes() - bytes;
+            let last = self.chunks.pop().expect("holding more than zero bytes");
+            if (last.size() as u64) > excess {
+                self.push(last.size() as u64 - excess)?;
+            }
+        }
+        while self.bytes() < bytes {
+            self.push((bytes - self.bytes()).min(CHUNK))?;
+        }
+        self.resizes += 1;
+        self.peak = self.peak.max(bytes);
+        events::emit("balloon_resized", json!({ "bytes": bytes }));
+        Ok(())
+    }
+
+    fn push(&mut self, bytes: u64) -> Result<(), StressError> {
+        let mut chunk = Buffer::allocate(bytes as usize)?;
+        chunk.touch_to(bytes as usize);
+        self.chunks.push(chunk);
+        Ok(())
+    }
+}
+
+/// The status line and JSON body answering `method` on `path`.
+fn route(method: &str, path: &str, body: &str, balloon: &mut Balloon) -> (&'static str, Value) {
+    if path != PATH {
+        return ("404 Not Found", json!({ "error": "not found" }));
+    }
+    match method {
+        "GET" => ("200 OK", json!({ "bytes": balloon.bytes() })),
+        "PUT" => {
+            let bytes = match serde_json::from_str::<Value>(body)
+                .ok()
+                .and_then(|request| request.get("bytes").cloned())
+            {
+                Some(Value::Number(n)) => n.as_u64().ok_or_else(|| format!("invalid size {n}")),
+                Some(Value::String(s)) => parse::bytes(&s),
+                _ => Err("expected {\"bytes\": N}".to_string()),
+            };
+            let bytes = match bytes {
+                Ok(bytes) => bytes,
+                Err(e) => return ("400 Bad Request", json!({ "error": e })),
+            };
+            log::info!("Resizing the balloon to {bytes} bytes.");
+            match balloon.resize(bytes) {
+                Ok(()) => ("200 OK", json!({ "bytes": balloon.bytes() })),
+                Err(e) => (
+                    "507 Insufficient Storage",
+                    json!({ "error": e.to_string(), "bytes": balloon.bytes() }),
+                ),
+            }
+        }
+        _ => (
+            "405 Method Not Allowed",
+            json!({ "error": "use GET or PUT" }),
+        ),
+    }
+}
+
+fn respond(stream: TcpStream, balloon: &mut Balloon) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            length = value.trim().parse().unwrap_or(0);
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    let mut words = request_line.split_whitespace();
+    let (method, path) = (words.next().unwrap_or(""), words.next().unwrap_or("/"));
+    let (status, body) = route(method, path, &String::from_utf8_lossy(&body), balloon);
+    let body = format!("{body}\n");
+    write!(
+        &stream,
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+impl Stressor for Args {
+    fn name(&self) -> &'static str {
+        "balloon"
+    }
+
+    fn params(&self) -> Value {
+        json!({
+            "listen": self.listen,
+            "initial_bytes": self.initial,
+            "duration_secs": self.duration.map(|d| d.as_secs_f64()),
+        })
+    }
+
+    fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        let listener = TcpListener::bind(&self.listen)
+            .map_err(|e| anyhow::anyhow!("Failed to bind balloon API {}: {e}", self.listen))?;
+        listener.set_nonblocking(true)?;
+        let mut balloon = Balloon::default();
+        balloon.resize(self.initial)?;
+        log::info!(
+            "Holding {} bytes; PUT {{\"bytes\": N}} to http://{}{PATH} to resize.",
+            self.initial,
+            listener.local_addr()?
+        );
+
+        let deadline = self.duration.map(|duration| Instant::now() + duration);
+        while !cancel.is_cancelled() && deadline.is_none_or(|deadline| Instant::now() < deadline) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(e) = respond(stream, &mut balloon) {
+                        log::debug!("Balloon request failed: {e}");
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    cancel.sleep_until(Instant::now() + POLL);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let summary = json!({
+            "resizes": balloon.resizes,
+            "peak_bytes": balloon.peak,
+            "final_bytes": balloon.bytes(),
+        });
+        log::info!("Balloon: {summary}.");
+        report::measure(self.name(), summary);
+        // Without a duration, an interruption is how the balloon ends.
+        match cancel.is_cancelled() && self.duration.is_some() {
+            true => Err(StressError::Interrupted.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grows_and_shrinks_in_chunks() {
+        let mut balloon = Balloon::default();
+        balloon.resize(CHUNK + 4096).unwrap();
+        assert_eq!(balloon.chunks.len(), 2);
+        assert_eq!(balloon.bytes(), CHUNK + 4096);
+        balloon.resize(CHUNK / 2).unwrap();
+        assert_eq!(balloon.chunks.len(), 1);
+        assert_eq!(balloon.bytes(), CHUNK / 2);
+        balloon.resize(0).unwrap();
+        assert!(balloon.chunks.is_empty());
+        assert_eq!((balloon.resizes, balloon.peak), (3, CHUNK + 4096));
+    }
+
+    #[test]
+    fn routes_size_requests() {
+        let mut balloon = Balloon::default();
+        let mut put = |body| route("PUT", PATH, body, &mut balloon);
+        assert_eq!(
+            put(r#"{"bytes": 8192}"#),
+            ("200 OK", json!({ "bytes": 8192 }))
+        );
+        assert_eq!(put(r#"{"bytes": "1M"}"#).1, json!({ "bytes": 1 << 20 }));
+        assert_eq!(put(r#"{"size": 1}"#).0, "400 Bad Request");
+        assert_eq!(
+            route("GET", PATH, "", &mut balloon),
+            ("200 OK", json!({ "bytes": 1 << 20 }))
+        );
+        assert_eq!(route("GET", "/other", "", &mut balloon).0, "404 Not Found");
+        assert_eq!(
+            route("POST", PATH, "", &mut balloon).0,
+            "405 Method Not Allowed"
+        );
+    }
+}
@@ -0,0 +1,146 @@
+//! `itsmine bank-conflict`: reads addresses a power-of-two stride apart
+//! within huge pages, which on most memory controllers land in the same DRAM
+//! bank but different rows, so nearly every access misses the row buffer.
+
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+use crate::tlb::{self, HUGE_PAGE, Reservation};
+use crate::{CancellationToken, StressError, Stressor, parse, report};
+
+const LINE: usize = 64;
+
+#[derive(Clone, Debug, PartialEq, clap::Args)]
+pub struct Args {
+    /// How long to run, e.g. 10s
+    #[arg(long, value_parser = parse::duration, default_value = "10s")]
+    pub duration: Duration,
+    /// Memory to spread the accesses over, e.g. 256M
+    #[arg(long, value_name = "BYTES", value_parser = parse::bytes, default_value = "256M")]
+    pub bytes: u64,
+    /// Distance between accesses, a power of two from 64 to 2M; which
+    /// strides share a bank depends on the memory controller's address
+    /// mapping
+    #[arg(long, value_name = "BYTES", value_parser = stride, default_value = "256K")]
+    pub stride: u64,
+}
+
+fn stride(s: &str) -> Result<u64, String> {
+    let stride = parse::bytes(s)?;
+    match stride.is_power_of_two() && (LINE as u64..=HUGE_PAGE as u64).contains(&stride) {
+        true => Ok(stride),
+        false => Err(format!("stride '{s}' must be a power of two from 64 to 2M")),
+    }
+}
+
+/// Every `stride`-th byte of each huge page starting at `regions`. Huge
+/// pages are physically contiguous, so the offsets within them, and with
+/// them the bank bits, are the same for every page.
+fn addresses(regions: &[usize], stride: usize) -> Vec<usize> {
+    regions
+        .iter()
+        .flat_map(|region| {
+            (0..HUGE_PAGE)
+                .step_by(stride)
+                .map(move |offset| region + offset)
+        })
+        .collect()
+}
+
+/// Reads each address once.
+fn sweep(addresses: &[usize]) {
+    for &address in addresses {
+        // SAFETY: every address lies in a readable region of the
+        // reservation.
+        unsafe { std::ptr::read_volatile(address as *const u8) };
+    }
+}
+
+impl Stressor for Args {
+    fn name(&self) -> &'static str {
+        "bank-conflict"
+    }
+
+    fn params(&self) -> Value {
+        json!({
+            "duration_secs": self.duration.as_secs_f64(),
+            "bytes": self.bytes,
+            "stride_bytes": self.stride,
+        })
+    }
+
+    fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        let reservation = Reservation::new(self.bytes)
+            .map_err(|e| anyhow::anyhow!("Failed to reserve {} bytes: {e}", self.bytes))?;
+        if reservation.regions == 0 {
+            return Err(StressError::InvalidSize("--bytes must be at least 2M".to_string()).into());
+        }
+        let regions = (0..reservation.regions)
+            .map(|index| reservation.open(index, true))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let addresses = addresses(&regions, self.stride as usize);
+        for &address in &addresses {
+            // SAFETY: as in `sweep`; writing faults the huge page in.
+            unsafe { (address as *mut u8).write(1) };
+        }
+        let huge_bytes = tlb::anon_huge_bytes();
+        if huge_bytes.is_some_and(|bytes| bytes < (regions.len() * HUGE_PAGE) as u64) {
+            log::warn!(
+                "Not every region got a transparent huge page; accesses there hit random banks."
+            );
+        }
+        log::info!(
+            "Reading {} addresses {} bytes apart for {:?}.",
+            addresses.len(),
+            self.stride,
+            self.duration
+        );
+
+        let started = Instant::now();
+        let deadline = started + self.duration;
+        let mut accesses = 0u64;
+        while Instant::now() < deadline && !cancel.is_cancelled() {
+            sweep(&addresses);
+            accesses += addresses.len() as u64;
+        }
+        let elapsed = started.elapsed();
+        let summary = json!({
+            "addresses": addresses.len(),
+            "accesses": accesses,
+            "ns_per_access": elapsed.as_nanos() as f64 / accesses.max(1) as f64,
+            "accesses_per_sec": accesses as f64 / elapsed.as_secs_f64(),
+            "anon_huge_bytes": huge_bytes,
+        });
+        log::info!("Bank conflicts: {summary}.");
+        report::measure(self.name(), summary);
+        match cancel.is_cancelled() {
+            true => Err(StressError::Interrupted.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strides_are_powers_of_two_within_a_huge_page() {
+        assert_eq!(stride("256K"), Ok(256 << 10));
+        assert_eq!(stride("64"), Ok(64));
+        assert!(stride("32").is_err());
+        assert!(stride("96K").is_err());
+        assert!(stride("4M").is_err());
+    }
+
+    #[test]
+    fn repeats_the_offsets_in_every_huge_page() {
+        let regions = [0, 4 * HUGE_PAGE];
+        let addresses = addresses(&regions, 512 << 10);
+        assert_eq!(addresses.len(), 8);
+        assert_eq!(addresses[1], 512 << 10);
+        assert_eq!(addresses[4], 4 * HUGE_PAGE);
+        assert_eq!(addresses[5] - addresses[4], addresses[1] - addresses[0]);
+    }
+}
@@ -0,0 +1,175 @@
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::time::Duration;
+
+use crate::CancellationToken;
+
+/// How often a waiting thread re-checks for cancellation.
+const POLL: Duration = Duration::from_millis(50);
+
+static POLICY: OnceLock<SyncPolicy> = OnceLock::new();
+
+/// When thread stressor workers line up before continuing.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SyncPolicy {
+    /// Start the workload on every thread at the same instant.
+    pub start: bool,
+    /// Line up again after every this many iterations.
+    pub every: Option<u64>,
+}
+
+impl SyncPolicy {
+    pub fn is_enabled(&self) -> bool {
+        self.start || self.every.is_some()
+    }
+
+    /// Whether a worker should wait after finishing `iteration` (1-based).
+    pub fn resync_after(&self, iteration: u64) -> bool {
+        self.every
+            .is_some_and(|n| n > 0 && iteration.is_multiple_of(n))
+    }
+}
+
+pub fn configure(policy: SyncPolicy) {
+    let _ = POLICY.set(policy);
+}
+
+pub fn policy() -> SyncPolicy {
+    POLICY.get().copied().unwrap_or_default()
+}
+
+/// A reusable barrier that gives up on cancellation and lets finished
+/// parties leave, so a worker ending early never strands the others.
+pub struct Barrier {
+    state: Mutex<State>,
+    released: Condvar,
+}
+
+struct State {
+    parties: usize,
+    waiting: usize,
+    generation: u64,
+}
+
+impl State {
+    fn release(&mut self, released: &Condvar) {
+        self.waiting = 0;
+        self.generation += 1;
+        released.notify_all();
+    }
+}
+
+impl Barrier {
+    pub fn new(parties: usize) -> Self {
+        Barrier {
+            state: Mutex::new(State {
+                parties,
+                waiting: 0,
+                generation: 0,
+            }),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Blocks until every remaining party has arrived. Returns `false` if
+    /// `cancel` fired first.
+    pub fn wait(&self, cancel: &CancellationToken) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let generation = state.generation;
+        state.waiting += 1;
+        if state.waiting >= state.parties {
+            state.release(&self.released);
+            return true;
+        }
+        loop {
+            state = self
+                .released
+                .wait_timeout(state, POLL)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+            if state.generation != generation {
+                return true;
+            }
+            if cancel.is_cancelled() {
+                state.waiting -= 1;
+                return false;
+            }
+        }
+    }
+
+    /// A handle for one party that leaves the barrier when dropped, even if
+    /// its thread panics.
+    pub fn party(&self) -> Party<'_> {
+        Party(self)
+    }
+
+    /// Removes a party that won't wait again, releasing the others if they
+    /// were only waiting for it.
+    pub fn leave(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.parties = state.parties.saturating_sub(1);
+        if state.waiting > 0 && state.waiting >= state.parties {
+            state.release(&self.released);
+        }
+    }
+}
+
+pub struct Party<'a>(&'a Barrier);
+
+impl Party<'_> {
+    pub fn wait(&self, cancel: &CancellationToken) -> bool {
+        self.0.wait(cancel)
+    }
+}
+
+impl Drop for Party<'_> {
+    fn drop(&mut self) {
+        self.0.leave();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    #[test]
+    fn releases_when_all_arrive_or_leave() {
+        let barrier = Arc::new(Barrier::new(3));
+        let cancel = CancellationToken::new();
+        let started = Instant::now();
+        let waiters: Vec<_> = (0..2)
+            .map(|_| {
+                let (barrier, cancel) = (barrier.clone(), cancel.clone());
+                std::thread::spawn(move || barrier.wait(&cancel))
+            })
+            .collect();
+        std::thread::sleep(Duration::from_millis(100));
+        // The third party finishing early must not strand the other two.
+        barrier.leave();
+        for waiter in waiters {
+            assert!(waiter.join().unwrap());
+        }
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn cancellation_abandons_the_wait() {
+        let barrier = Barrier::new(2);
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        assert!(!barrier.wait(&cancel));
+    }
+
+    #[test]
+    fn resyncs_every_n_iterations() {
+        let policy = SyncPolicy {
+            start: false,
+            every: Some(3),
+        };
+        assert!(policy.is_enabled());
+        assert!(!policy.resync_after(2));
+        assert!(policy.resync_after(3));
+        assert!(!SyncPolicy::default().is_enabled());
+    }
+}
@@ -0,0 +1,280 @@
+use std::sync::OnceLock;
+#[cfg(feature = "raw-alloc")]
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::StressError;
+
+#[cfg(feature = "raw-alloc")]
+static SAFE: AtomicBool = AtomicBool::new(false);
+static PROTECTION: OnceLock<Protection> = OnceLock::new();
+
+/// Memory kept out of reach of the rest of the system.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Protection {
+    /// `memfd_secret`: unmapped from the kernel's direct map, locked and
+    /// never dumped
+    Secret,
+    /// Anonymous memory locked with `mlock` and excluded from core dumps
+    Locked,
+}
+
+impl Protection {
+    pub fn name(self) -> &'static str {
+        match self {
+            Protection::Secret => "secret",
+            Protection::Locked => "locked",
+        }
+    }
+}
+
+/// Selects the `Vec`-backed allocator even when raw allocation is compiled
+/// in. Without the `raw-alloc` feature every buffer is `Vec`-backed.
+#[cfg_attr(not(feature = "raw-alloc"), allow(unused_variables))]
+pub fn set_safe(safe: bool) {
+    #[cfg(feature = "raw-alloc")]
+    SAFE.store(safe, Ordering::Relaxed);
+}
+
+/// Backs every later buffer with protected memory, taking precedence over
+/// the allocator choice. Only the first call has an effect.
+pub fn set_protection(protection: Protection) {
+    if PROTECTION.set(protection).is_ok() {
+        log::info!(
+            "Backing memory stressors with {} memory.",
+            protection.name()
+        );
+    }
+}
+
+/// Whether `RLIMIT_MEMLOCK` lets protected memory of any size be locked
+/// without `CAP_IPC_LOCK`.
+pub fn memlock_unlimited() -> bool {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: `limit` is a valid out-pointer for the duration of the call.
+    let read = unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) } == 0;
+    read && limit.rlim_cur == libc::RLIM_INFINITY
+}
+
+/// Memory held by the memory stressor. Pages are only committed as they are
+/// touched, so allocation and touching can be reported separately.
+pub enum Buffer {
+    Safe {
+        bytes: Vec<u8>,
+        len: usize,
+    },
+    #[cfg(feature = "raw-alloc")]
+    Raw {
+        ptr: std::ptr::NonNull<u8>,
+        layout: std::alloc::Layout,
+        touched: usize,
+    },
+    Protected {
+        ptr: std::ptr::NonNull<u8>,
+        len: usize,
+        touched: usize,
+        /// The `memfd_secret` descriptor, closed once the mapping is gone.
+        secret: Option<std::os::fd::OwnedFd>,
+    },
+}
+
+impl Buffer {
+    pub fn allocate(len: usize) -> Result<Self, StressError> {
+        if let Some(&protection) = PROTECTION.get() {
+            return Buffer::protected(len, protection);
+        }
+        #[cfg(feature = "raw-alloc")]
+        if !SAFE.load(Ordering::Relaxed) {
+            let layout = std::alloc::Layout::from_size_align(len, 8)
+                .map_err(|e| StressError::InvalidSize(format!("Invalid memory size {len}: {e}")))?;
+            // SAFETY: the stressor rejects zero sizes, so the layout is non-empty.
+            let ptr = unsafe { std::alloc::alloc(layout) };
+            let ptr = std::ptr::NonNull::new(ptr)
+                .ok_or(StressError::AllocationFailed { bytes: len as u64 })?;
+            return Ok(Buffer::Raw {
+                ptr,
+                layout,
+                touched: 0,
+            });
+        }
+
+        let mut bytes = Vec::new();
+        bytes
+            .try_reserve_exact(len)
+            .map_err(|_| StressError::AllocationFailed { bytes: len as u64 })?;
+        Ok(Buffer::Safe { bytes, len })
+    }
+
+    /// Maps `len` bytes of `protection` memory. Locked pages are only
+    /// locked as they are touched; both kinds count against
+    /// `RLIMIT_MEMLOCK`.
+    pub fn protected(len: usize, protection: Protection) -> Result<Self, StressError> {
+        use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+        let failed = |what: &str| {
+            log::warn!(
+                "{what} failed ({}); raising RLIMIT_MEMLOCK or CAP_IPC_LOCK may help.",
+                std::io::Error::last_os_error()
+            );
+            StressError::AllocationFailed { bytes: len as u64 }
+        };
+        let secret = match protection {
+            Protection::Secret => {
+                // SAFETY: memfd_secret takes only flags.
+                let fd = unsafe { libc::syscall(libc::SYS_memfd_secret, libc::O_CLOEXEC) };
+                if fd < 0 {
+                    return Err(StressError::InvalidInput(format!(
+                        "memfd_secret is unavailable ({}); it needs Linux 5.14+, booted with \
+                         secretmem.enable=1 on older kernels",
+                        std::io::Error::last_os_error()
+                    )));
+                }
+                // SAFETY: the kernel just handed us this descriptor.
+                let fd = unsafe { OwnedFd::from_raw_fd(fd as i32) };
+                // SAFETY: plain syscall on a descriptor we own.
+                if unsafe { libc::ftruncate(fd.as_raw_fd(), len as libc::off_t) } != 0 {
+                    return Err(failed("Sizing the secret memory"));
+                }
+                Some(fd)
+            }
+            Protection::Locked => None,
+        };
+        let (flags, fd) = match &secret {
+            Some(fd) => (libc::MAP_SHARED, fd.as_raw_fd()),
+            None => (libc::MAP_PRIVATE | libc::MAP_ANONYMOUS, -1),
+        };
+        // SAFETY: a fresh mapping aliases nothing.
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                flags,
+                fd,
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(failed("Mapping protected memory"));
+        }
+        // From here on, dropping the buffer unmaps it.
+        let buffer = Buffer::Protected {
+            ptr: std::ptr::NonNull::new(ptr.cast()).expect("mmap succeeded"),
+            len,
+            touched: 0,
+            secret,
+        };
+        if protection == Protection::Locked {
+            // SAFETY: the range is the mapping just made.
+            let failed_to = unsafe {
+                libc::madvise(ptr, len, libc::MADV_DONTDUMP) != 0
+                    || libc::mlock2(ptr, len, libc::MLOCK_ONFAULT) != 0
+            };
+            if failed_to {
+                return Err(failed("Locking memory"));
+            }
+        }
+        Ok(buffer)
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            Buffer::Safe { len, .. } => *len,
+            #[cfg(feature = "raw-alloc")]
+            Buffer::Raw { layout, .. } => layout.size(),
+            Buffer::Protected { len, .. } => *len,
+        }
+    }
+
+    pub fn touched(&self) -> usize {
+        match self {
+            Buffer::Safe { bytes, .. } => bytes.len(),
+            #[cfg(feature = "raw-alloc")]
+            Buffer::Raw { touched, .. } => *touched,
+            Buffer::Protected { touched, .. } => *touched,
+        }
+    }
+
+    /// Writes every byte up to `end` (clamped to the buffer size).
+    pub fn touch_to(&mut self, end: usize) {
+        let end = end.min(self.size());
+        match self {
+            Buffer::Safe { bytes, .. } => {
+                if end > bytes.len() {
+                    bytes.resize(end, 0);
+                }
+            }
+            #[cfg(feature = "raw-alloc")]
+            Buffer::Raw { ptr, touched, .. } => {
+                for i in *touched..end {
+                    // SAFETY: `i` is below the allocation's size.
+                    unsafe { *ptr.as_ptr().add(i) = 0 };
+                }
+                *touched = (*touched).max(end);
+            }
+            Buffer::Protected { ptr, touched, .. } => {
+                if end > *touched {
+                    // SAFETY: `end` is clamped to the mapping's length.
+                    unsafe { ptr.as_ptr().add(*touched).write_bytes(0, end - *touched) };
+                    *touched = end;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        match self {
+            Buffer::Safe { .. } => {}
+            #[cfg(feature = "raw-alloc")]
+            Buffer::Raw { ptr, layout, .. } => {
+                // SAFETY: allocated in `allocate` with this very layout.
+                unsafe { std::alloc::dealloc(ptr.as_ptr(), *layout) };
+            }
+            Buffer::Protected { ptr, len, .. } => {
+                // SAFETY: mapped in `protected` with this length; unmapping
+                // also unlocks it.
+                unsafe { libc::munmap(ptr.as_ptr().cast(), *len) };
+            }
+        }
+    }
+}
+
+// SAFETY: raw and protected buffers are uniquely owned like a `Box<[u8]>`.
+unsafe impl Send for Buffer {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn touch_to_grows_up_to_len() {
+        let mut buffer = Buffer::allocate(4096).unwrap();
+        assert_eq!(buffer.touched(), 0);
+        buffer.touch_to(1000);
+        assert_eq!(buffer.touched(), 1000);
+        buffer.touch_to(10_000);
+        assert_eq!(buffer.touched(), buffer.size());
+    }
+
+    #[test]
+    fn protected_buffers_touch_like_the_others() {
+        let mut locked = Buffer::protected(4 * 4096, Protection::Locked).unwrap();
+        locked.touch_to(5000);
+        assert_eq!(locked.touched(), 5000);
+        locked.touch_to(1 << 20);
+        assert_eq!(locked.touched(), locked.size());
+
+        // Secret memory depends on the kernel; when it's there it must work.
+        match Buffer::protected(4096, Protection::Secret) {
+            Ok(mut secret) => {
+                secret.touch_to(4096);
+                assert_eq!(secret.touched(), 4096);
+            }
+            Err(e) => assert!(matches!(e, StressError::InvalidInput(_))),
+        }
+    }
+}
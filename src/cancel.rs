@@ -0,0 +1,131 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::shutdown;
+
+/// How often cancellable waits re-check the token.
+const POLL: Duration = Duration::from_millis(50);
+
+/// How long before a deadline `spin_until` stops sleeping and spins;
+/// comfortably more than a sleep overshoots by.
+const SPIN: Duration = Duration::from_millis(1);
+
+/// Asks a running stressor to tear down early. Clones share the same state;
+/// a child token is also cancelled when its parent is. Every token reports
+/// cancelled once process shutdown was requested (SIGTERM/SIGINT).
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    parent: Option<CancellationToken>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A token cancelled together with this one, that can also be cancelled
+    /// on its own.
+    pub fn child_token(&self) -> Self {
+        CancellationToken {
+            inner: Arc::new(Inner {
+                cancelled: AtomicBool::new(false),
+                parent: Some(self.clone()),
+            }),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+            || self
+                .inner
+                .parent
+                .as_ref()
+                .is_some_and(CancellationToken::is_cancelled)
+            || shutdown::requested()
+    }
+
+    /// Sleeps until `deadline`, returning early (with `false`) if cancelled.
+    pub fn sleep_until(&self, deadline: Instant) -> bool {
+        loop {
+            if self.is_cancelled() {
+                return false;
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return true;
+            }
+            std::thread::sleep(remaining.min(POLL));
+        }
+    }
+
+    /// Like `sleep_until`, but spins through the last stretch so it
+    /// returns within microseconds of `deadline`, at the cost of a busy CPU.
+    pub fn spin_until(&self, deadline: Instant) -> bool {
+        if !self.sleep_until(deadline.checked_sub(SPIN).unwrap_or(deadline)) {
+            return false;
+        }
+        while Instant::now() < deadline {
+            if self.is_cancelled() {
+                return false;
+            }
+            std::hint::spin_loop();
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancel_propagates_to_children_only() {
+        let parent = CancellationToken::new();
+        let child = parent.child_token();
+        child.cancel();
+        assert!(child.is_cancelled());
+        assert!(!parent.is_cancelled());
+
+        let other = parent.child_token();
+        parent.cancel();
+        assert!(other.is_cancelled());
+    }
+
+    #[test]
+    fn sleep_until_returns_early_when_cancelled() {
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            canceller.cancel();
+        });
+        let started = Instant::now();
+        assert!(!token.sleep_until(started + Duration::from_secs(10)));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(CancellationToken::new().sleep_until(Instant::now()));
+    }
+
+    #[test]
+    fn spin_until_returns_just_after_the_deadline() {
+        let token = CancellationToken::new();
+        for wait in [Duration::from_micros(50), Duration::from_millis(3)] {
+            let deadline = Instant::now() + wait;
+            assert!(token.spin_until(deadline));
+            let late = Instant::now() - deadline;
+            assert!(late < Duration::from_millis(1), "{late:?}");
+        }
+        token.cancel();
+        assert!(!token.spin_until(Instant::now() + Duration::from_secs(10)));
+    }
+}
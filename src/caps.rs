@@ -0,0 +1,129 @@
+//! Privileges that some features need, checked before the features are
+//! used. When a privilege is missing, the feature is skipped and the skip
+//! is logged and recorded in the report, instead of the run failing with
+//! `EPERM` partway through.
+
+use std::sync::Mutex;
+
+use serde_json::json;
+
+use crate::events;
+
+static SKIPPED: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Privilege {
+    /// An effective user ID of 0, for files only root may write.
+    Root,
+    /// Locking memory beyond `RLIMIT_MEMLOCK`.
+    IpcLock,
+    /// Namespaces and the realtime I/O class.
+    SysAdmin,
+    /// Realtime scheduling beyond `RLIMIT_RTPRIO`.
+    SysNice,
+    /// Lowering `oom_score_adj`.
+    SysResource,
+}
+
+impl Privilege {
+    pub fn name(self) -> &'static str {
+        match self {
+            Privilege::Root => "root",
+            Privilege::IpcLock => "CAP_IPC_LOCK",
+            Privilege::SysAdmin => "CAP_SYS_ADMIN",
+            Privilege::SysNice => "CAP_SYS_NICE",
+            Privilege::SysResource => "CAP_SYS_RESOURCE",
+        }
+    }
+
+    /// The capability's bit in `CapEff`; `None` for root, which is a user
+    /// rather than a capability.
+    fn bit(self) -> Option<u32> {
+        match self {
+            Privilege::Root => None,
+            Privilege::IpcLock => Some(14),
+            Privilege::SysAdmin => Some(21),
+            Privilege::SysNice => Some(23),
+            Privilege::SysResource => Some(24),
+        }
+    }
+}
+
+/// Whether this process holds `privilege`.
+pub fn held(privilege: Privilege) -> bool {
+    match privilege.bit() {
+        // SAFETY: geteuid has no preconditions.
+        None => (unsafe { libc::geteuid() }) == 0,
+        Some(bit) => std::fs::read_to_string("/proc/self/status")
+            .ok()
+            .and_then(|status| effective(&status))
+            .is_some_and(|caps| caps >> bit & 1 == 1),
+    }
+}
+
+/// The effective capability set from a /proc/<pid>/status.
+fn effective(status: &str) -> Option<u64> {
+    let caps = status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))?;
+    u64::from_str_radix(caps.trim(), 16).ok()
+}
+
+/// Whether `feature` may go ahead with `privilege`. If the privilege is
+/// missing, this records "<feature>: skipped: missing <privilege>" for the
+/// report and returns false, and the caller leaves the feature out.
+pub fn require(feature: &str, privilege: Privilege) -> bool {
+    if held(privilege) {
+        return true;
+    }
+    skip(feature, privilege);
+    false
+}
+
+/// Records `feature` as skipped for want of `privilege`, held or not, e.g.
+/// because it is about to be dropped.
+pub fn skip(feature: &str, privilege: Privilege) {
+    let entry = format!("{feature}: skipped: missing {}", privilege.name());
+    let mut skipped = SKIPPED.lock().unwrap_or_else(|e| e.into_inner());
+    if !skipped.contains(&entry) {
+        log::warn!("Skipping {feature}: it needs {}.", privilege.name());
+        events::emit(
+            "feature_skipped",
+            json!({ "feature": feature, "missing": privilege.name() }),
+        );
+        skipped.push(entry);
+    }
+}
+
+/// Every feature skipped so far, in the order first skipped.
+pub fn skipped() -> Vec<String> {
+    SKIPPED.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_effective_capabilities() {
+        let status = "Name:\titsmine\nCapInh:\t0000000000000000\nCapEff:\t0000000000804000\n";
+        let caps = effective(status).unwrap();
+        assert_eq!(caps >> Privilege::IpcLock.bit().unwrap() & 1, 1);
+        assert_eq!(caps >> Privilege::SysNice.bit().unwrap() & 1, 1);
+        assert_eq!(caps >> Privilege::SysAdmin.bit().unwrap() & 1, 0);
+        assert_eq!(effective("Name:\titsmine\n"), None);
+    }
+
+    #[test]
+    fn records_each_skip_once() {
+        // Held or not, a root check never leaves a duplicate entry.
+        let root = held(Privilege::Root);
+        assert_eq!(require("--test-feature", Privilege::Root), root);
+        assert_eq!(require("--test-feature", Privilege::Root), root);
+        let entries = skipped()
+            .into_iter()
+            .filter(|entry| entry.starts_with("--test-feature"))
+            .count();
+        assert_eq!(entries, usize::from(!root));
+    }
+}
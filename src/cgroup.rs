@@ -0,0 +1,158 @@
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// v1 reports "unlimited" as a huge page-aligned number.
+const V1_UNLIMITED: u64 = 1 << 62;
+
+/// Resource limits of the cgroup this process runs in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct Limits {
+    pub memory_max: Option<u64>,
+    /// CPU quota in cores (quota / period).
+    pub cpu_max: Option<f64>,
+}
+
+impl Limits {
+    pub fn detect() -> Self {
+        let own = std::fs::read_to_string("/proc/self/cgroup").unwrap_or_default();
+        Self::detect_at(Path::new(CGROUP_ROOT), &own)
+    }
+
+    fn detect_at(root: &Path, proc_self_cgroup: &str) -> Self {
+        if let Some(dir) = own_dir(root, proc_self_cgroup, None) {
+            return Limits {
+                // `max` fails to parse and means unlimited.
+                memory_max: read_u64(&dir.join("memory.max")),
+                cpu_max: read_v2_cpu(&dir.join("cpu.max")),
+            };
+        }
+
+        let memory_max = own_dir(root, proc_self_cgroup, Some("memory"))
+            .and_then(|dir| read_u64(&dir.join("memory.limit_in_bytes")))
+            .filter(|&limit| limit < V1_UNLIMITED);
+        let cpu_max = own_dir(root, proc_self_cgroup, Some("cpu")).and_then(|dir| {
+            let quota: i64 = read_string(&dir.join("cpu.cfs_quota_us"))?.parse().ok()?;
+            let period = read_u64(&dir.join("cpu.cfs_period_us"))?;
+            (quota > 0 && period > 0).then(|| quota as f64 / period as f64)
+        });
+        Limits {
+            memory_max,
+            cpu_max,
+        }
+    }
+}
+
+/// Finds this process's cgroup directory for a v1 `controller`, or the v2
+/// unified hierarchy when `controller` is `None`.
+fn own_dir(root: &Path, proc_self_cgroup: &str, controller: Option<&str>) -> Option<PathBuf> {
+    let relative = proc_self_cgroup.lines().find_map(|line| {
+        let mut fields = line.splitn(3, ':');
+        let (_, controllers, path) = (fields.next()?, fields.next()?, fields.next()?);
+        let matches = match controller {
+            None => controllers.is_empty(),
+            Some(c) => controllers.split(',').any(|name| name == c),
+        };
+        matches.then(|| path.trim_start_matches('/').to_string())
+    })?;
+
+    let base = match controller {
+        None => root.to_path_buf(),
+        Some(c) => root.join(c),
+    };
+    // The unified hierarchy is only in use if it has controller files.
+    let probe = if controller.is_none() {
+        "cgroup.controllers"
+    } else {
+        "cgroup.procs"
+    };
+    // Inside a cgroup namespace the hierarchy root already is our cgroup.
+    [base.join(&relative), base]
+        .into_iter()
+        .find(|dir| dir.join(probe).exists())
+}
+
+fn read_string(path: &Path) -> Option<String> {
+    std::fs::read_to_string(path)
+        .ok()
+        .map(|s| s.trim().to_string())
+}
+
+fn read_u64(path: &Path) -> Option<u64> {
+    read_string(path)?.parse().ok()
+}
+
+/// Reads v2 `cpu.max` (`<quota> <period>` or `max <period>`).
+fn read_v2_cpu(path: &Path) -> Option<f64> {
+    let content = read_string(path)?;
+    let (quota, period) = content.split_once(' ')?;
+    let quota: f64 = quota.parse().ok()?;
+    let period: f64 = period.parse().ok()?;
+    (period > 0.0).then_some(quota / period)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("itsmine-cgroup-{name}"));
+        let _ = std::fs::remove_dir_all(&root);
+        root
+    }
+
+    #[test]
+    fn detect_v2_limits() {
+        let root = fake_root("v2");
+        let dir = root.join("kubepods/pod1");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("cgroup.controllers"), "cpu memory\n").unwrap();
+        std::fs::write(dir.join("memory.max"), "536870912\n").unwrap();
+        std::fs::write(dir.join("cpu.max"), "150000 100000\n").unwrap();
+
+        let limits = Limits::detect_at(&root, "0::/kubepods/pod1\n");
+        assert_eq!(
+            limits,
+            Limits {
+                memory_max: Some(512 * 1024 * 1024),
+                cpu_max: Some(1.5),
+            }
+        );
+    }
+
+    #[test]
+    fn detect_v2_unlimited() {
+        let root = fake_root("v2-max");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("cgroup.controllers"), "cpu memory\n").unwrap();
+        std::fs::write(root.join("memory.max"), "max\n").unwrap();
+        std::fs::write(root.join("cpu.max"), "max 100000\n").unwrap();
+
+        assert_eq!(Limits::detect_at(&root, "0::/\n"), Limits::default());
+    }
+
+    #[test]
+    fn detect_v1_limits_in_namespace() {
+        let root = fake_root("v1");
+        let memory = root.join("memory");
+        let cpu = root.join("cpu");
+        std::fs::create_dir_all(&memory).unwrap();
+        std::fs::create_dir_all(&cpu).unwrap();
+        std::fs::write(memory.join("cgroup.procs"), "").unwrap();
+        std::fs::write(memory.join("memory.limit_in_bytes"), "1073741824\n").unwrap();
+        std::fs::write(cpu.join("cgroup.procs"), "").unwrap();
+        std::fs::write(cpu.join("cpu.cfs_quota_us"), "-1\n").unwrap();
+        std::fs::write(cpu.join("cpu.cfs_period_us"), "100000\n").unwrap();
+
+        let limits = Limits::detect_at(&root, "4:memory:/docker/abc\n1:cpu,cpuacct:/docker/abc\n");
+        assert_eq!(
+            limits,
+            Limits {
+                memory_max: Some(1024 * 1024 * 1024),
+                cpu_max: None,
+            }
+        );
+    }
+}
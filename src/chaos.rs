@@ -0,0 +1,260 @@
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+
+use crate::scenario::Phase;
+use crate::{CancellationToken, Resource, events, parse};
+
+const MIN_EPISODE: Duration = Duration::from_secs(5);
+const MAX_EPISODE: Duration = Duration::from_secs(60);
+
+/// Upper bounds for the load chaos mode may generate at any moment.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Budget {
+    pub cpu_percent: u32,
+    pub mem_bytes: u64,
+}
+
+impl FromStr for Budget {
+    type Err = String;
+
+    /// Parses `cpu=50%,mem=4G`. Omitted resources get a zero budget.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut budget = Budget {
+            cpu_percent: 0,
+            mem_bytes: 0,
+        };
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, got '{part}'"))?;
+            match key {
+                "cpu" => {
+                    let percent = value.strip_suffix('%').unwrap_or(value);
+                    budget.cpu_percent = percent
+                        .parse()
+                        .map_err(|e| format!("invalid cpu budget '{value}': {e}"))?;
+                    if budget.cpu_percent > 100 {
+                        return Err(format!("cpu budget '{value}' exceeds 100%"));
+                    }
+                }
+                "mem" => {
+                    budget.mem_bytes = parse::bytes(value)
+                        .map_err(|e| format!("invalid mem budget '{value}': {e}"))?;
+                }
+                other => return Err(format!("unknown budget resource '{other}'")),
+            }
+        }
+        Ok(budget)
+    }
+}
+
+/// Small deterministic PRNG (splitmix64) so a seed reproduces a chaos run.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in `0..=max`.
+    pub fn up_to(&mut self, max: u64) -> u64 {
+        match max.checked_add(1) {
+            Some(bound) => self.next_u64() % bound,
+            None => self.next_u64(),
+        }
+    }
+
+    pub fn chance(&mut self, percent: u64) -> bool {
+        self.next_u64() % 100 < percent
+    }
+}
+
+/// One randomly shaped slice of a chaos run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Episode {
+    pub threads: u32,
+    pub mem_bytes: u64,
+    pub duration: Duration,
+}
+
+pub struct Chaos {
+    pub budget: Budget,
+    pub duration: Duration,
+    pub seed: u64,
+}
+
+impl Chaos {
+    /// Plans the sequence of episodes for the whole run.
+    pub fn plan(&self, cores: u32) -> Vec<Episode> {
+        let max_threads = (cores * self.budget.cpu_percent).div_ceil(100);
+        let mut rng = Rng::new(self.seed);
+        let mut episodes = vec![];
+        let mut remaining = self.duration;
+
+        while !remaining.is_zero() {
+            let span = (MAX_EPISODE - MIN_EPISODE).as_millis() as u64;
+            let length = (MIN_EPISODE + Duration::from_millis(rng.up_to(span))).min(remaining);
+            remaining -= length;
+
+            // Each stressor sits out roughly a quarter of the episodes.
+            let threads = if rng.chance(25) {
+                0
+            } else {
+                rng.up_to(max_threads as u64) as u32
+            };
+            let mem_bytes = if rng.chance(25) {
+                0
+            } else {
+                // Whole MiB keeps sizes readable in the logs.
+                rng.up_to(self.budget.mem_bytes / (1024 * 1024)) * 1024 * 1024
+            };
+
+            episodes.push(Episode {
+                threads,
+                mem_bytes,
+                duration: length,
+            });
+        }
+        episodes
+    }
+
+    pub fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        let cores = std::thread::available_parallelism().map_or(1, |n| n.get() as u32);
+        log::info!(
+            "Chaos run for {:.0}s within cpu={}% mem={} bytes (seed {}).",
+            self.duration.as_secs_f64(),
+            self.budget.cpu_percent,
+            self.budget.mem_bytes,
+            self.seed
+        );
+
+        let started = Instant::now();
+        for (index, episode) in self.plan(cores).into_iter().enumerate() {
+            if cancel.is_cancelled() {
+                break;
+            }
+            log::info!(
+                "Chaos episode {index}: {} threads, {} MiB for {:.1}s (+{:.0}s).",
+                episode.threads,
+                episode.mem_bytes / (1024 * 1024),
+                episode.duration.as_secs_f64(),
+                started.elapsed().as_secs_f64()
+            );
+            events::emit(
+                "chaos_episode",
+                json!({
+                    "index": index,
+                    "threads": episode.threads,
+                    "mem_bytes": episode.mem_bytes,
+                    "duration_secs": episode.duration.as_secs_f64(),
+                }),
+            );
+
+            let mut stressors = vec![];
+            if episode.threads > 0 {
+                stressors.push(Resource::Thread {
+                    num: episode.threads,
+                });
+            }
+            if episode.mem_bytes > 0 {
+                stressors.push(Resource::Memory {
+                    arg: format!("{}B", episode.mem_bytes),
+                    bench: None,
+                });
+            }
+            Phase {
+                name: format!("chaos-{index}"),
+                duration: episode.duration,
+                stressors,
+                timeouts: Default::default(),
+            }
+            .run(cancel)?;
+        }
+        Ok(())
+    }
+}
+
+/// Picks a seed when none was given; it is logged so the run can be replayed.
+pub fn random_seed() -> u64 {
+    use std::hash::{BuildHasher, RandomState};
+    RandomState::new().hash_one(std::time::SystemTime::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budget_parses_cpu_and_mem() {
+        assert_eq!(
+            "cpu=50%,mem=4G".parse::<Budget>(),
+            Ok(Budget {
+                cpu_percent: 50,
+                mem_bytes: 4 * 1024 * 1024 * 1024,
+            })
+        );
+        assert_eq!(
+            "mem=1M".parse::<Budget>().unwrap(),
+            Budget {
+                cpu_percent: 0,
+                mem_bytes: 1024 * 1024,
+            }
+        );
+    }
+
+    #[test]
+    fn budget_rejects_invalid_input() {
+        assert!("cpu=150%".parse::<Budget>().is_err());
+        assert!("disk=1G".parse::<Budget>().is_err());
+        assert!("cpu".parse::<Budget>().is_err());
+        assert!("mem=4X".parse::<Budget>().is_err());
+    }
+
+    #[test]
+    fn plan_is_reproducible_and_within_budget() {
+        let chaos = Chaos {
+            budget: "cpu=50%,mem=64M".parse().unwrap(),
+            duration: Duration::from_secs(600),
+            seed: 42,
+        };
+        let plan = chaos.plan(8);
+        assert_eq!(plan, chaos.plan(8));
+        assert_eq!(
+            plan.iter().map(|e| e.duration).sum::<Duration>(),
+            chaos.duration
+        );
+        for episode in &plan {
+            assert!(episode.threads <= 4);
+            assert!(episode.mem_bytes <= 64 * 1024 * 1024);
+            assert!(episode.duration <= MAX_EPISODE);
+        }
+    }
+
+    #[test]
+    fn different_seeds_give_different_plans() {
+        let chaos = |seed| Chaos {
+            budget: "cpu=100%,mem=1G".parse().unwrap(),
+            duration: Duration::from_secs(600),
+            seed,
+        };
+        assert_ne!(chaos(1).plan(4), chaos(2).plan(4));
+    }
+
+    #[test]
+    fn rng_up_to_covers_range() {
+        let mut rng = Rng::new(7);
+        let values: Vec<u64> = (0..200).map(|_| rng.up_to(3)).collect();
+        assert!(values.iter().all(|&v| v <= 3));
+        assert!((0..=3).all(|v| values.contains(&v)));
+    }
+}
@@ -0,0 +1,275 @@
+//! `itsmine clocks`: threads call clock_gettime(2) and gettimeofday(2) as
+//! fast as they can while one more thread measures how CLOCK_REALTIME
+//! drifts from CLOCK_MONOTONIC and how late timed sleeps wake. vDSO
+//! fallbacks and unstable TSCs show up exactly under this kind of load, as
+//! slow calls, clocks going backwards or jumps between the two clocks.
+
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+use crate::chaos::{self, Rng};
+use crate::stats::{self, Samples};
+use crate::{CancellationToken, StressError, Stressor, parse, report};
+
+/// Calls between deadline checks.
+const BATCH: u64 = 10_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Call {
+    /// clock_gettime(CLOCK_MONOTONIC)
+    Monotonic,
+    /// clock_gettime(CLOCK_REALTIME)
+    Realtime,
+    /// gettimeofday
+    Gettimeofday,
+    /// All three in turn
+    Mixed,
+}
+
+impl Call {
+    pub fn name(self) -> &'static str {
+        match self {
+            Call::Monotonic => "monotonic",
+            Call::Realtime => "realtime",
+            Call::Gettimeofday => "gettimeofday",
+            Call::Mixed => "mixed",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, clap::Args)]
+pub struct Args {
+    /// Threads reading the clocks
+    #[arg(long, value_name = "N", default_value_t = 4)]
+    pub threads: u32,
+    /// Which calls they make
+    #[arg(long, value_enum, default_value = "mixed")]
+    pub call: Call,
+    /// How long the measuring thread sleeps between samples, e.g. 1ms
+    #[arg(long, value_parser = parse::duration, default_value = "1ms")]
+    pub interval: Duration,
+    /// How long to run, e.g. 10s
+    #[arg(long, value_parser = parse::duration, default_value = "10s")]
+    pub duration: Duration,
+}
+
+/// Reads `clock`.
+fn now(clock: libc::clockid_t) -> Duration {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `time` is valid for writes; every clock used here exists.
+    unsafe { libc::clock_gettime(clock, &mut time) };
+    Duration::new(time.tv_sec as u64, time.tv_nsec as u32)
+}
+
+fn gettimeofday() -> Duration {
+    let mut time = libc::timeval {
+        tv_sec: 0,
+        tv_usec: 0,
+    };
+    // SAFETY: `time` is valid for writes; no timezone is asked for.
+    unsafe { libc::gettimeofday(&mut time, std::ptr::null_mut()) };
+    Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000)
+}
+
+/// What one hammering thread saw.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Hammered {
+    calls: u64,
+    /// Times CLOCK_MONOTONIC read earlier than the read before.
+    backwards: u64,
+    /// Smallest nonzero step between consecutive monotonic reads.
+    finest_step: Option<Duration>,
+    /// Largest step between consecutive monotonic reads.
+    widest_step: Duration,
+}
+
+/// Makes `call` until `deadline`, watching the monotonic clock between
+/// consecutive reads.
+fn hammer(call: Call, deadline: Instant, cancel: &CancellationToken) -> Hammered {
+    let mut hammered = Hammered::default();
+    let mut last = now(libc::CLOCK_MONOTONIC);
+    while Instant::now() < deadline && !cancel.is_cancelled() {
+        for _ in 0..BATCH {
+            match call {
+                Call::Realtime => {
+                    std::hint::black_box(now(libc::CLOCK_REALTIME));
+                }
+                Call::Gettimeofday => {
+                    std::hint::black_box(gettimeofday());
+                }
+                Call::Mixed => {
+                    std::hint::black_box(now(libc::CLOCK_REALTIME));
+                    std::hint::black_box(gettimeofday());
+                }
+                Call::Monotonic => {}
+            }
+            let monotonic = now(libc::CLOCK_MONOTONIC);
+            match monotonic.checked_sub(last) {
+                None => hammered.backwards += 1,
+                Some(Duration::ZERO) => {}
+                Some(step) => {
+                    hammered.finest_step = Some(hammered.finest_step.map_or(step, |f| f.min(step)));
+                    hammered.widest_step = hammered.widest_step.max(step);
+                }
+            }
+            last = monotonic;
+        }
+        hammered.calls += BATCH
+            * match call {
+                Call::Mixed => 3,
+                Call::Monotonic => 1,
+                Call::Realtime | Call::Gettimeofday => 2,
+            };
+    }
+    hammered
+}
+
+/// What the measuring thread saw.
+struct Measured {
+    /// How much later than asked each sleep woke, in nanoseconds.
+    oversleep: Samples,
+    /// CLOCK_REALTIME's gain on CLOCK_MONOTONIC over the run, in
+    /// nanoseconds; negative when it fell behind.
+    drift: f64,
+    /// Largest change in that gain between two samples, e.g. a clock step.
+    largest_jump: f64,
+    elapsed: Duration,
+}
+
+/// Sleeps `interval` at a time until `deadline`, timing each wakeup and
+/// comparing the two clocks.
+fn measure(interval: Duration, deadline: Instant, cancel: &CancellationToken) -> Measured {
+    let mut rng = Rng::new(chaos::random_seed());
+    // In whole nanoseconds: an f64 cannot hold the epoch that finely.
+    let offset = || {
+        now(libc::CLOCK_REALTIME).as_nanos() as i128 - now(libc::CLOCK_MONOTONIC).as_nanos() as i128
+    };
+    let started = Instant::now();
+    let initial = offset();
+    let mut measured = Measured {
+        oversleep: Samples::new(),
+        drift: 0.0,
+        largest_jump: 0.0,
+        elapsed: Duration::ZERO,
+    };
+    while Instant::now() < deadline && !cancel.is_cancelled() {
+        let asleep = Instant::now();
+        std::thread::sleep(interval);
+        let slept = asleep.elapsed();
+        measured
+            .oversleep
+            .record(slept.saturating_sub(interval), &mut rng);
+        let drift = (offset() - initial) as f64;
+        measured.largest_jump = measured.largest_jump.max((drift - measured.drift).abs());
+        measured.drift = drift;
+    }
+    measured.elapsed = started.elapsed();
+    measured
+}
+
+impl Stressor for Args {
+    fn name(&self) -> &'static str {
+        "clocks"
+    }
+
+    fn params(&self) -> Value {
+        json!({
+            "threads": self.threads,
+            "call": self.call.name(),
+            "interval_secs": self.interval.as_secs_f64(),
+            "duration_secs": self.duration.as_secs_f64(),
+        })
+    }
+
+    fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        if self.threads == 0 || self.interval.is_zero() {
+            return Err(StressError::InvalidInput(
+                "--threads and --interval must be greater than 0".to_string(),
+            )
+            .into());
+        }
+        log::info!(
+            "Reading clocks ({}) from {} threads for {:?}.",
+            self.call.name(),
+            self.threads,
+            self.duration
+        );
+        let mut resolution = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // SAFETY: `resolution` is valid for writes.
+        unsafe { libc::clock_getres(libc::CLOCK_MONOTONIC, &mut resolution) };
+        let started = Instant::now();
+        let deadline = started + self.duration;
+        let (hammered, mut measured) = std::thread::scope(|s| {
+            let handles: Vec<_> = (0..self.threads)
+                .map(|_| s.spawn(|| hammer(self.call, deadline, cancel)))
+                .collect();
+            let measured = measure(self.interval, deadline, cancel);
+            let mut total = Hammered::default();
+            for handle in handles {
+                let hammered = handle.join().map_err(|payload| {
+                    StressError::WorkerPanicked(crate::panic_message(&payload))
+                })?;
+                total.calls += hammered.calls;
+                total.backwards += hammered.backwards;
+                total.finest_step = match (total.finest_step, hammered.finest_step) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                };
+                total.widest_step = total.widest_step.max(hammered.widest_step);
+            }
+            Ok::<_, StressError>((total, measured))
+        })?;
+        measured.oversleep.kept.sort_by(f64::total_cmp);
+        let us = |p: f64| stats::percentile(&measured.oversleep.kept, p) / 1e3;
+        let summary = json!({
+            "calls": hammered.calls,
+            "calls_per_sec": hammered.calls as f64 / started.elapsed().as_secs_f64(),
+            "monotonic_backwards": hammered.backwards,
+            "resolution_ns": resolution.tv_nsec,
+            "finest_step_ns": hammered.finest_step.map(|step| step.as_nanos() as u64),
+            "widest_step_us": hammered.widest_step.as_secs_f64() * 1e6,
+            "realtime_drift_us": measured.drift / 1e3,
+            "realtime_drift_ppm": measured.drift / measured.elapsed.as_nanos().max(1) as f64 * 1e6,
+            "largest_jump_us": measured.largest_jump / 1e3,
+            "oversleep_p50_us": us(0.5),
+            "oversleep_p99_us": us(0.99),
+            "oversleep_max_us": us(1.0),
+        });
+        log::info!("Clocks: {summary}.");
+        report::measure(self.name(), summary);
+        match cancel.is_cancelled() {
+            true => Err(StressError::Interrupted.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hammers_and_measures_the_clocks() {
+        let deadline = Instant::now() + Duration::from_millis(50);
+        let cancel = CancellationToken::new();
+        let (hammered, measured) = std::thread::scope(|s| {
+            let hammer = s.spawn(|| hammer(Call::Mixed, deadline, &cancel));
+            let measured = measure(Duration::from_millis(1), deadline, &cancel);
+            (hammer.join().unwrap(), measured)
+        });
+        assert!(hammered.calls >= 3 * BATCH);
+        assert_eq!(hammered.backwards, 0);
+        assert!(hammered.finest_step.is_some());
+        assert!(hammered.widest_step >= hammered.finest_step.unwrap());
+        assert!(measured.oversleep.seen > 0);
+        // Nothing steps the realtime clock by a second in 50ms.
+        assert!(measured.drift.abs() < 1e9);
+    }
+}
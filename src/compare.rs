@@ -0,0 +1,213 @@
+//! Compares a metric across two groups of runs or iterations with
+//! confidence intervals and significance tests, so a gate only trips on a
+//! change the noise cannot explain.
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::stats;
+use crate::trend::Better;
+
+/// One group's samples of a metric.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Summary {
+    pub samples: usize,
+    pub mean: f64,
+    /// Half-width of the confidence interval of the mean, with two or more
+    /// samples.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ci: Option<f64>,
+}
+
+impl Summary {
+    fn of(xs: &[f64], confidence: f64) -> Self {
+        Summary {
+            samples: xs.len(),
+            mean: if xs.is_empty() {
+                f64::NAN
+            } else {
+                stats::mean(xs)
+            },
+            ci: stats::mean_ci(xs, confidence),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    Regression,
+    Improvement,
+    /// The difference is within what the noise explains.
+    Unchanged,
+    /// Fewer than two samples on a side.
+    Insufficient,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Comparison {
+    pub metric: String,
+    pub better: Better,
+    pub a: Summary,
+    pub b: Summary,
+    /// Change of the mean from `a` to `b`, relative to `a`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change_percent: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub t_test_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mann_whitney_p: Option<f64>,
+    pub verdict: Verdict,
+}
+
+/// Parses a significance level, which must lie strictly between 0 and 1.
+pub fn alpha(s: &str) -> Result<f64, String> {
+    match s.trim().parse::<f64>() {
+        Ok(alpha) if alpha > 0.0 && alpha < 1.0 => Ok(alpha),
+        _ => Err(format!("significance level '{s}' must be between 0 and 1")),
+    }
+}
+
+/// Compares samples `a` (the baseline) with `b`. The change only counts as
+/// significant when both Welch's t-test and the Mann-Whitney U test put it
+/// below `alpha`: the t-test alone is swayed by one outlier, the rank test
+/// alone by many small shifts that don't move the mean.
+pub fn compare(metric: &str, a: &[f64], b: &[f64], better: Better, alpha: f64) -> Comparison {
+    let (summary_a, summary_b) = (Summary::of(a, 1.0 - alpha), Summary::of(b, 1.0 - alpha));
+    let t_test_p = stats::welch_t_test(a, b);
+    let mann_whitney_p = stats::mann_whitney_p(a, b);
+    let verdict = match (t_test_p, mann_whitney_p) {
+        (Some(t), Some(u)) if t < alpha && u < alpha => {
+            match (summary_b.mean > summary_a.mean, better) {
+                (true, Better::Higher) | (false, Better::Lower) => Verdict::Improvement,
+                _ => Verdict::Regression,
+            }
+        }
+        (Some(_), _) => Verdict::Unchanged,
+        _ => Verdict::Insufficient,
+    };
+    Comparison {
+        metric: metric.to_string(),
+        better,
+        change_percent: (summary_a.mean != 0.0 && !summary_a.mean.is_nan())
+            .then(|| (summary_b.mean - summary_a.mean) / summary_a.mean.abs() * 100.0)
+            .filter(|change| change.is_finite()),
+        a: summary_a,
+        b: summary_b,
+        t_test_p,
+        mann_whitney_p,
+        verdict,
+    }
+}
+
+/// Holds `comparisons` of several metrics to `alpha` together with Holm's
+/// step-down correction, so one gate over many metrics trips on noise no
+/// more often than a single test would: changes significant only on their
+/// own become `Unchanged`.
+pub fn correct(comparisons: &mut [Comparison], alpha: f64) {
+    // A change needs both tests, so it is as significant as the weaker one.
+    let p = |comparison: &Comparison| match (comparison.t_test_p, comparison.mann_whitney_p) {
+        (Some(t), Some(u)) => t.max(u),
+        _ => 1.0,
+    };
+    let mut tested: Vec<usize> = (0..comparisons.len())
+        .filter(|&i| comparisons[i].verdict != Verdict::Insufficient)
+        .collect();
+    tested.sort_by(|&i, &j| p(&comparisons[i]).total_cmp(&p(&comparisons[j])));
+    let mut rejecting = true;
+    for (rank, &i) in tested.iter().enumerate() {
+        rejecting &= p(&comparisons[i]) < alpha / (tested.len() - rank) as f64;
+        if !rejecting
+            && matches!(
+                comparisons[i].verdict,
+                Verdict::Regression | Verdict::Improvement
+            )
+        {
+            comparisons[i].verdict = Verdict::Unchanged;
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let side = |summary: &Summary| match summary.ci {
+            Some(ci) => format!("{:.3} ±{ci:.3} (n={})", summary.mean, summary.samples),
+            None => format!("{:.3} (n={})", summary.mean, summary.samples),
+        };
+        let change = self
+            .change_percent
+            .map_or_else(|| "-".to_string(), |change| format!("{change:+.1}%"));
+        let p = |p: Option<f64>| p.map_or_else(|| "-".to_string(), |p| format!("{p:.4}"));
+        write!(
+            f,
+            "  {:<36} {:>26} {:>26} {:>8}  t p={} U p={}  {}",
+            self.metric,
+            side(&self.a),
+            side(&self.b),
+            change,
+            p(self.t_test_p),
+            p(self.mann_whitney_p),
+            match self.verdict {
+                Verdict::Regression => "REGRESSION",
+                Verdict::Improvement => "improvement",
+                Verdict::Unchanged => "unchanged",
+                Verdict::Insufficient => "too few samples",
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_significant_changes_count() {
+        let a = [100.0, 102.0, 98.0, 101.0, 99.0, 100.5];
+        let slower = [110.0, 111.0, 109.0, 112.0, 110.5, 109.5];
+        let noisy = [99.0, 103.0, 97.0, 101.5, 100.0, 98.5];
+        let regression = compare("duration_secs", &a, &slower, Better::Lower, 0.05);
+        assert_eq!(regression.verdict, Verdict::Regression);
+        assert!((regression.change_percent.unwrap() - 10.0).abs() < 0.5);
+        assert!(regression.a.ci.unwrap() > 0.0);
+        assert_eq!(
+            compare("ops_per_sec", &a, &slower, Better::Higher, 0.05).verdict,
+            Verdict::Improvement
+        );
+        assert_eq!(
+            compare("duration_secs", &a, &noisy, Better::Lower, 0.05).verdict,
+            Verdict::Unchanged
+        );
+        assert_eq!(
+            compare("duration_secs", &a[..1], &slower, Better::Lower, 0.05).verdict,
+            Verdict::Insufficient
+        );
+    }
+
+    #[test]
+    fn parses_significance_levels() {
+        assert_eq!(alpha("0.05"), Ok(0.05));
+        for bad in ["0", "1", "-0.1", "1.5", "NaN", "inf", "x"] {
+            assert!(alpha(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn corrects_for_many_metrics() {
+        let a = [100.0, 102.0, 98.0, 101.0, 99.0, 100.5];
+        let slower = [110.0, 111.0, 109.0, 112.0, 110.5, 109.5];
+        let slightly = [101.0, 103.0, 99.5, 102.5, 100.5, 101.5];
+        let clear = compare("clear", &a, &slower, Better::Lower, 0.05);
+        let marginal = compare("marginal", &a, &slightly, Better::Lower, 0.2);
+        assert_eq!(marginal.verdict, Verdict::Regression);
+        let unchanged = compare("noise", &a, &a, Better::Lower, 0.2);
+        let mut comparisons = vec![clear, marginal, unchanged];
+        correct(&mut comparisons, 0.2);
+        let verdicts: Vec<_> = comparisons.iter().map(|c| c.verdict).collect();
+        assert_eq!(
+            verdicts,
+            [Verdict::Regression, Verdict::Unchanged, Verdict::Unchanged]
+        );
+    }
+}
@@ -0,0 +1,255 @@
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use crate::report;
+
+const CPUFREQ_ROOT: &str = "/sys/devices/system/cpu/cpufreq";
+
+/// Original contents of every sysfs file changed, restored at exit.
+static SAVED: Mutex<Vec<(PathBuf, String)>> = Mutex::new(vec![]);
+
+/// Frequency scaling to pin for the run.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Settings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub governor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_khz: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_khz: Option<u64>,
+}
+
+impl Settings {
+    pub fn is_empty(&self) -> bool {
+        self.governor.is_none() && self.min_khz.is_none() && self.max_khz.is_none()
+    }
+}
+
+/// Applies `settings` to every cpufreq policy, remembering the previous
+/// values so they are restored when the process exits. Policies the process
+/// may not write to are skipped with a warning; any other failure puts back
+/// what was already changed before returning the error.
+pub fn apply(settings: &Settings) -> Result<(), anyhow::Error> {
+    let changed = apply_to(Path::new(CPUFREQ_ROOT), settings)?;
+    if changed > 0 {
+        report::measure("cpufreq", serde_json::to_value(settings)?);
+        // SAFETY: the handler is a plain function that lives for the whole
+        // process.
+        unsafe { libc::atexit(restore_at_exit) };
+    }
+    Ok(())
+}
+
+fn apply_to(root: &Path, settings: &Settings) -> Result<usize, anyhow::Error> {
+    let mut policies: Vec<PathBuf> = std::fs::read_dir(root)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("policy"))
+        .map(|entry| entry.path())
+        .collect();
+    if policies.is_empty() {
+        // Common in VMs and containers without a cpufreq driver.
+        log::warn!("CPU frequency scaling is not exposed here; leaving it as is.");
+        return Ok(0);
+    }
+    policies.sort();
+    // Check every policy before changing any, so a bad governor leaves none
+    // of them half pinned.
+    if let Some(governor) = &settings.governor {
+        for policy in &policies {
+            let available = std::fs::read_to_string(policy.join("scaling_available_governors"))
+                .unwrap_or_default();
+            if !available.split_whitespace().any(|g| g == governor) {
+                return Err(anyhow::anyhow!(
+                    "Governor '{governor}' is not available on {} (have: {})",
+                    policy.display(),
+                    available.trim()
+                ));
+            }
+        }
+    }
+    let mut changed = 0;
+    let mut denied = false;
+    for policy in &policies {
+        let mut writes = vec![];
+        if let Some(governor) = &settings.governor {
+            writes.push(("scaling_governor", governor.clone()));
+        }
+        // Raising the floor above the current ceiling is rejected, and so
+        // is lowering the ceiling below the floor, so order the writes.
+        let current_max = read_khz(&policy.join("scaling_max_freq"));
+        let limits = [
+            ("scaling_max_freq", settings.max_khz),
+            ("scaling_min_freq", settings.min_khz),
+        ];
+        let raise_floor_first = settings.min_khz > current_max && current_max.is_some();
+        for (file, khz) in match raise_floor_first {
+            true => [limits[1], limits[0]],
+            false => limits,
+        } {
+            if let Some(khz) = khz {
+                writes.push((file, khz.to_string()));
+            }
+        }
+        for (file, value) in writes {
+            match write(&policy.join(file), &value) {
+                Ok(()) => changed += 1,
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        ErrorKind::PermissionDenied | ErrorKind::ReadOnlyFilesystem
+                    ) =>
+                {
+                    denied = true;
+                }
+                Err(e) => {
+                    // `apply` only registers the restore on success.
+                    restore();
+                    return Err(anyhow::anyhow!(
+                        "Failed to set {} to {value}: {e}",
+                        policy.join(file).display()
+                    ));
+                }
+            }
+        }
+    }
+    if denied {
+        log::warn!("Not permitted to change CPU frequency scaling (needs root); leaving it as is.");
+    } else if changed > 0 {
+        log::info!(
+            "Pinned CPU frequency scaling on {} policies; it is restored at exit.",
+            policies.len()
+        );
+    }
+    Ok(changed)
+}
+
+fn read_khz(path: &Path) -> Option<u64> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Writes `value` to a sysfs file, saving what it held first.
+fn write(path: &Path, value: &str) -> std::io::Result<()> {
+    let previous = std::fs::read_to_string(path)?;
+    std::fs::write(path, value)?;
+    let mut saved = SAVED.lock().unwrap_or_else(|e| e.into_inner());
+    if !saved.iter().any(|(saved, _)| saved == path) {
+        saved.push((path.to_path_buf(), previous.trim().to_string()));
+    }
+    Ok(())
+}
+
+/// Puts back every value `apply` changed, latest change first.
+pub fn restore() {
+    let saved = std::mem::take(&mut *SAVED.lock().unwrap_or_else(|e| e.into_inner()));
+    // Limits went in in an order the kernel accepted; undo in reverse.
+    for (path, value) in saved.iter().rev() {
+        if let Err(e) = std::fs::write(path, value) {
+            log::warn!("Failed to restore {} to {value}: {e}", path.display());
+        }
+    }
+}
+
+extern "C" fn restore_at_exit() {
+    restore();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Held by tests that touch `SAVED`, which `restore` empties wholesale.
+    static SERIAL: Mutex<()> = Mutex::new(());
+
+    /// A fake cpufreq tree under a fresh directory named for `test`.
+    fn fake_policies(test: &str, count: usize) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("itsmine-cpufreq-{test}-{}", std::process::id()));
+        for index in 0..count {
+            let policy = root.join(format!("policy{index}"));
+            std::fs::create_dir_all(&policy).unwrap();
+            for (file, value) in [
+                ("scaling_available_governors", "performance powersave\n"),
+                ("scaling_governor", "powersave\n"),
+                ("scaling_min_freq", "800000\n"),
+                ("scaling_max_freq", "3000000\n"),
+            ] {
+                std::fs::write(policy.join(file), value).unwrap();
+            }
+        }
+        root
+    }
+
+    #[test]
+    fn pins_and_restores_policies() {
+        let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+        let root = fake_policies("pins", 1);
+        let policy = root.join("policy0");
+        let read = |file| std::fs::read_to_string(policy.join(file)).unwrap();
+
+        let settings = Settings {
+            governor: Some("performance".to_string()),
+            min_khz: Some(2_000_000),
+            max_khz: None,
+        };
+        assert_eq!(apply_to(&root, &settings).unwrap(), 2);
+        assert_eq!(read("scaling_governor"), "performance");
+        assert_eq!(read("scaling_min_freq"), "2000000");
+        assert!(
+            apply_to(
+                &root,
+                &Settings {
+                    governor: Some("ondemand".to_string()),
+                    ..Default::default()
+                }
+            )
+            .is_err()
+        );
+
+        restore();
+        assert_eq!(apply_to(&root.join("none"), &settings).unwrap(), 0);
+        assert_eq!(read("scaling_governor"), "powersave");
+        assert_eq!(read("scaling_min_freq"), "800000");
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn restores_earlier_policies_when_a_later_one_fails() {
+        let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+        let root = fake_policies("partial", 2);
+        // policy1 offers the governor but rejects the write.
+        let governor = root.join("policy1/scaling_governor");
+        std::fs::remove_file(&governor).unwrap();
+        std::fs::create_dir(&governor).unwrap();
+
+        let settings = Settings {
+            governor: Some("performance".to_string()),
+            ..Default::default()
+        };
+        assert!(apply_to(&root, &settings).is_err());
+        assert_eq!(
+            std::fs::read_to_string(root.join("policy0/scaling_governor")).unwrap(),
+            "powersave"
+        );
+        assert!(SAVED.lock().unwrap().is_empty());
+
+        // An unavailable governor on policy1 stops before policy0 changes.
+        std::fs::remove_dir(&governor).unwrap();
+        std::fs::write(&governor, "powersave\n").unwrap();
+        std::fs::write(
+            root.join("policy1/scaling_available_governors"),
+            "powersave\n",
+        )
+        .unwrap();
+        assert!(apply_to(&root, &settings).is_err());
+        assert_eq!(
+            std::fs::read_to_string(root.join("policy0/scaling_governor")).unwrap(),
+            "powersave"
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,860 @@
+//! `itsmine disk`: loads a filesystem through a scratch file, removed when
+//! the run ends. `write` streams blocks through it, syncing after every
+//! pass; `read` fills it, then reads it back sequentially and at random,
+//! dropping every block from the page cache so reads reach the device;
+//! `sparse` makes it huge and sparse, then punches holes in it and fills
+//! them back in at random offsets.
+
+use std::fs::File;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{FileExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+use crate::caps::{self, Privilege};
+use crate::chaos::{self, Rng};
+use crate::sched::{self, IoClass};
+use crate::stats::{self, Samples};
+use crate::throttle::{Limits, Throttle};
+use crate::{CancellationToken, StressError, Stressor, parse, report};
+
+/// Most data `sparse` keeps filled in at once; past it, it only punches.
+const MAX_FILLED: u64 = 256 << 20;
+/// Most `--fill-until` allocates at once before checking usage again.
+const FILL_STEP: u64 = 64 << 20;
+/// `statfs` filesystem types, as `i64` whatever the width of `f_type`.
+const TMPFS_MAGIC: i64 = 0x01021994;
+const RAMFS_MAGIC: i64 = 0x858458f6;
+/// Size of each probe read, and of the dataset probes read from.
+const PROBE_BLOCK: u64 = 4096;
+const PROBE_DATASET: u64 = 16 << 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Mode {
+    Write,
+    Read,
+    Sparse,
+}
+
+impl Mode {
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Write => "write",
+            Mode::Read => "read",
+            Mode::Sparse => "sparse",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, clap::Args)]
+pub struct Args {
+    /// What to do with the scratch file
+    #[arg(long, value_enum, default_value = "write")]
+    pub mode: Mode,
+    /// Directory on a filesystem to load; repeat it to load several at
+    /// once, each with its own workers [default: the temp dir]
+    #[arg(long = "path", value_name = "PATH")]
+    pub paths: Vec<PathBuf>,
+    /// Size of the scratch file; 1G for write and read, and the apparent
+    /// size for sparse, 1T
+    #[arg(long, value_name = "BYTES", value_parser = parse::bytes)]
+    pub size: Option<u64>,
+    /// Bytes per write, or per hole punched or filled
+    #[arg(long, value_name = "BYTES", value_parser = parse::bytes, default_value = "1M")]
+    pub block: u64,
+    /// Threads reading concurrently in read mode
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    pub jobs: u32,
+    /// I/O scheduling class for the load: idle, best-effort[:N] or rt[:N],
+    /// with N from 0 (first) to 7, 4 if omitted
+    #[arg(long, value_name = "CLASS")]
+    pub io_class: Option<IoClass>,
+    /// Run even where a path is on tmpfs, ramfs or a ramdisk, which
+    /// measures memory rather than a disk
+    #[arg(long)]
+    pub allow_tmpfs: bool,
+    #[command(flatten)]
+    pub limits: Limits,
+    /// Alongside the load, time a cold 4K read this often, e.g. 100ms, and
+    /// report its latency apart from the load's
+    #[arg(long, value_name = "INTERVAL", value_parser = parse::duration)]
+    pub probe_interval: Option<Duration>,
+    /// Instead of a mode, grow the file until the filesystem is this full,
+    /// e.g. 90%, and hold it there
+    #[arg(long, value_name = "PERCENT", value_parser = parse::percent, conflicts_with = "mode")]
+    pub fill_until: Option<f64>,
+    /// How long to run, e.g. 10s
+    #[arg(long, value_parser = parse::duration, default_value = "10s")]
+    pub duration: Duration,
+}
+
+/// A file created for the run and unlinked straight away, so its space goes
+/// back to the filesystem however the process ends, even killed.
+struct Scratch {
+    file: File,
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl Scratch {
+    /// Creates scratch file `name` in `dir`; names keep runs on several
+    /// paths apart, even when they share a directory.
+    fn create(dir: &Path, name: &str) -> std::io::Result<Self> {
+        let path = dir.join(format!("itsmine-disk-{}-{name}", std::process::id()));
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        std::fs::remove_file(&path)?;
+        Ok(Scratch {
+            file,
+            dir: dir.to_path_buf(),
+            path,
+        })
+    }
+
+    /// Evicts `len` bytes at `offset` from the page cache, so the next read
+    /// of them goes to the device.
+    fn drop_cache(&self, offset: u64, len: u64) -> std::io::Result<()> {
+        // SAFETY: plain syscall on a descriptor `self` owns.
+        let advised = unsafe {
+            libc::posix_fadvise(
+                self.file.as_raw_fd(),
+                offset as libc::off_t,
+                len as libc::off_t,
+                libc::POSIX_FADV_DONTNEED,
+            )
+        };
+        match advised {
+            0 => Ok(()),
+            errno => Err(std::io::Error::from_raw_os_error(errno)),
+        }
+    }
+
+    /// Bytes the filesystem has actually allocated to the file.
+    fn allocated(&self) -> std::io::Result<u64> {
+        Ok(self.file.metadata()?.blocks() * 512)
+    }
+
+    fn fallocate(&self, mode: libc::c_int, offset: u64, len: u64) -> std::io::Result<()> {
+        // SAFETY: plain syscall on a descriptor `self` owns.
+        let done = unsafe {
+            libc::fallocate(
+                self.file.as_raw_fd(),
+                mode,
+                offset as libc::off_t,
+                len as libc::off_t,
+            )
+        };
+        match done {
+            -1 => Err(std::io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+
+    fn punch_hole(&self, offset: u64, len: u64) -> std::io::Result<()> {
+        self.fallocate(
+            libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+            offset,
+            len,
+        )
+    }
+
+    /// Allocates `len` bytes at `offset`, writing zeros where the filesystem
+    /// can't preallocate.
+    fn allocate(&self, offset: u64, len: u64) -> std::io::Result<()> {
+        match self.fallocate(0, offset, len) {
+            Err(e) if e.raw_os_error() == Some(libc::EOPNOTSUPP) => {
+                let zeros = vec![0; len.min(1 << 20) as usize];
+                let mut done = 0;
+                while done < len {
+                    let chunk = (len - done).min(zeros.len() as u64) as usize;
+                    self.file.write_all_at(&zeros[..chunk], offset + done)?;
+                    done += chunk as u64;
+                }
+                self.file.sync_data()
+            }
+            allocated => allocated,
+        }
+    }
+}
+
+/// Bytes used on the filesystem holding `path`, and the most it can hold
+/// for unprivileged users: usage the way df reports it.
+fn usage(path: &Path) -> std::io::Result<(u64, u64)> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    // SAFETY: statvfs is plain data, and zero is a valid bit pattern.
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `path` is NUL-terminated and `stat` is a valid out-pointer.
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let block = stat.f_frsize as u64;
+    let used = (stat.f_blocks - stat.f_bfree) as u64 * block;
+    Ok((used, used + stat.f_bavail as u64 * block))
+}
+
+/// The block device holding `path`, e.g. `nvme0n1p2`, or its `major:minor`
+/// numbers when it has no name, as for overlay or network filesystems.
+fn device(path: &Path) -> Option<String> {
+    let dev = std::fs::metadata(path).ok()?.dev();
+    let numbers = format!("{}:{}", libc::major(dev), libc::minor(dev));
+    let uevent =
+        std::fs::read_to_string(format!("/sys/dev/block/{numbers}/uevent")).unwrap_or_default();
+    Some(
+        uevent
+            .lines()
+            .find_map(|line| line.strip_prefix("DEVNAME="))
+            .map_or(numbers, str::to_string),
+    )
+}
+
+/// What holds `path` in memory, if anything: `tmpfs`, `ramfs`, or
+/// `ramdisk` for brd and zram devices.
+fn memory_backed(path: &Path) -> std::io::Result<Option<&'static str>> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    // SAFETY: statfs is plain data, and zero is a valid bit pattern.
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `c_path` is NUL-terminated and `stat` is a valid out-pointer.
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(match stat.f_type as i64 {
+        TMPFS_MAGIC => Some("tmpfs"),
+        RAMFS_MAGIC => Some("ramfs"),
+        _ => device(path)
+            .filter(|name| name.starts_with("ram") || name.starts_with("zram"))
+            .map(|_| "ramdisk"),
+    })
+}
+
+fn percent_of(used: u64, capacity: u64) -> f64 {
+    used as f64 * 100.0 / capacity.max(1) as f64
+}
+
+/// Latencies of the two operations a mode alternates between, and how
+/// many bytes went through.
+struct Outcome {
+    first: Samples,
+    second: Samples,
+    bytes: u64,
+}
+
+/// Counts and latency percentiles in microseconds of a mode's operations,
+/// named `first` and `second`, in the plural and singular.
+fn summarize(outcome: &mut Outcome, names: [(&str, &str); 2], elapsed: Duration) -> Value {
+    let mut summary = json!({
+        "operations_per_sec": (outcome.first.seen + outcome.second.seen) as f64 / elapsed.as_secs_f64(),
+        "bytes_per_sec": outcome.bytes as f64 / elapsed.as_secs_f64(),
+    });
+    for (names, samples) in names
+        .into_iter()
+        .zip([&mut outcome.first, &mut outcome.second])
+    {
+        percentiles(&mut summary, names, samples);
+    }
+    summary
+}
+
+/// Adds the count and latency percentiles in microseconds of `samples` to
+/// `summary`, named in the plural and singular.
+fn percentiles(summary: &mut Value, (plural, name): (&str, &str), samples: &mut Samples) {
+    samples.kept.sort_by(f64::total_cmp);
+    let us = |p: f64| stats::percentile(&samples.kept, p) / 1000.0;
+    summary[plural] = json!(samples.seen);
+    summary[format!("{name}_p50_us")] = json!(us(0.5));
+    summary[format!("{name}_p99_us")] = json!(us(0.99));
+    summary[format!("{name}_max_us")] = json!(us(1.0));
+}
+
+/// Every `interval` until `stop`, times a read of one block of a dataset of
+/// its own in `dir`, evicted from the page cache first so it reaches the
+/// device: the latency anything else on it sees under the load.
+fn probe(
+    dir: &Path,
+    index: usize,
+    interval: Duration,
+    stop: &AtomicBool,
+) -> std::io::Result<Samples> {
+    let scratch = Scratch::create(dir, &format!("probe-{index}"))?;
+    let block = vec![0x5a; 1 << 20];
+    for offset in (0..PROBE_DATASET).step_by(block.len()) {
+        scratch.file.write_all_at(&block, offset)?;
+    }
+    scratch.file.sync_data()?;
+    let mut rng = Rng::new(chaos::random_seed());
+    let mut buffer = vec![0; PROBE_BLOCK as usize];
+    let mut samples = Samples::new();
+    let mut next = Instant::now();
+    while !stop.load(Ordering::Relaxed) {
+        next += interval;
+        std::thread::sleep(next.saturating_duration_since(Instant::now()));
+        let offset = rng.up_to(PROBE_DATASET / PROBE_BLOCK - 1) * PROBE_BLOCK;
+        scratch.drop_cache(offset, PROBE_BLOCK)?;
+        let started = Instant::now();
+        scratch.file.read_exact_at(&mut buffer, offset)?;
+        samples.record(started.elapsed(), &mut rng);
+    }
+    Ok(samples)
+}
+
+impl Args {
+    fn paths(&self) -> Vec<PathBuf> {
+        match self.paths.is_empty() {
+            true => vec![std::env::temp_dir()],
+            false => self.paths.clone(),
+        }
+    }
+
+    fn size(&self) -> u64 {
+        self.size.unwrap_or(match self.mode {
+            Mode::Write | Mode::Read => 1 << 30,
+            Mode::Sparse => 1 << 40,
+        })
+    }
+
+    /// Writes blocks front to back, syncing and starting over at the end.
+    /// Times writes, then syncs.
+    fn write(
+        &self,
+        scratch: &Scratch,
+        throttle: &Throttle,
+        deadline: Instant,
+        cancel: &CancellationToken,
+    ) -> std::io::Result<Outcome> {
+        let mut rng = Rng::new(chaos::random_seed());
+        let block = vec![0x5a; self.block as usize];
+        let (mut writes, mut syncs, mut offset) = (Samples::new(), Samples::new(), 0);
+        while Instant::now() < deadline && throttle.admit(self.block, cancel) {
+            let started = Instant::now();
+            scratch.file.write_all_at(&block, offset)?;
+            writes.record(started.elapsed(), &mut rng);
+            offset += self.block;
+            if offset + self.block > self.size() {
+                let started = Instant::now();
+                scratch.file.sync_data()?;
+                syncs.record(started.elapsed(), &mut rng);
+                offset = 0;
+            }
+        }
+        Ok(Outcome {
+            bytes: writes.seen * self.block,
+            first: writes,
+            second: syncs,
+        })
+    }
+
+    /// Grows the file until the filesystem is `target` percent full or out
+    /// of space, then holds it there until `deadline`.
+    fn fill(
+        &self,
+        scratch: &Scratch,
+        target: f64,
+        deadline: Instant,
+        cancel: &CancellationToken,
+    ) -> std::io::Result<Value> {
+        let started = Instant::now();
+        let (mut filled, mut out_of_space) = (0, false);
+        while !cancel.is_cancelled() {
+            let (used, capacity) = usage(&scratch.dir)?;
+            let wanted = (capacity as f64 * target / 100.0).ceil() as u64;
+            if used >= wanted {
+                break;
+            }
+            let step = (wanted - used).next_multiple_of(4096).min(FILL_STEP);
+            match scratch.allocate(filled, step) {
+                Err(e) if e.raw_os_error() == Some(libc::ENOSPC) => {
+                    out_of_space = true;
+                    break;
+                }
+                allocated => allocated?,
+            }
+            filled += step;
+        }
+        let fill_secs = started.elapsed().as_secs_f64();
+        let (used, capacity) = usage(&scratch.dir)?;
+        log::info!(
+            "Filled {filled} bytes in {fill_secs:.2}s; {} is {:.1}% full.",
+            scratch.dir.display(),
+            percent_of(used, capacity)
+        );
+        cancel.sleep_until(deadline);
+        Ok(json!({
+            "target_percent": target,
+            "reached_percent": percent_of(used, capacity),
+            "filled_bytes": filled,
+            "fill_secs": fill_secs,
+            "out_of_space": out_of_space,
+        }))
+    }
+
+    /// Writes the dataset `read` reads back, and makes sure none of it is
+    /// left in the page cache.
+    fn create_dataset(&self, scratch: &Scratch, cancel: &CancellationToken) -> std::io::Result<()> {
+        let block = vec![0x5a; self.block as usize];
+        let mut offset = 0;
+        while offset + self.block <= self.size() && !cancel.is_cancelled() {
+            scratch.file.write_all_at(&block, offset)?;
+            offset += self.block;
+        }
+        scratch.file.sync_data()?;
+        scratch.drop_cache(0, 0)
+    }
+
+    /// Reads the dataset sequentially until halfway to `deadline`, each job
+    /// its own slice, then at random offsets. Times sequential reads, then
+    /// random ones.
+    fn read(
+        &self,
+        scratch: &Scratch,
+        throttle: &Throttle,
+        deadline: Instant,
+        cancel: &CancellationToken,
+    ) -> Result<Outcome, StressError> {
+        let halfway = Instant::now() + deadline.saturating_duration_since(Instant::now()) / 2;
+        let sequential = self.read_phase(scratch, throttle, false, halfway, cancel)?;
+        let random = self.read_phase(scratch, throttle, true, deadline, cancel)?;
+        Ok(Outcome {
+            bytes: (sequential.seen + random.seen) * self.block,
+            first: sequential,
+            second: random,
+        })
+    }
+
+    fn read_phase(
+        &self,
+        scratch: &Scratch,
+        throttle: &Throttle,
+        random: bool,
+        deadline: Instant,
+        cancel: &CancellationToken,
+    ) -> Result<Samples, StressError> {
+        let blocks = self.size() / self.block;
+        let slice = (blocks / self.jobs as u64).max(1);
+        let job = |index: u64| -> std::io::Result<Samples> {
+            let mut rng = Rng::new(chaos::random_seed());
+            let mut buffer = vec![0; self.block as usize];
+            let (mut samples, mut next) = (Samples::new(), 0);
+            while Instant::now() < deadline && throttle.admit(self.block, cancel) {
+                let block = match random {
+                    true => rng.up_to(blocks - 1),
+                    false => (index * slice + next % slice).min(blocks - 1),
+                };
+                let offset = block * self.block;
+                let started = Instant::now();
+                scratch.file.read_exact_at(&mut buffer, offset)?;
+                samples.record(started.elapsed(), &mut rng);
+                scratch.drop_cache(offset, self.block)?;
+                next += 1;
+            }
+            Ok(samples)
+        };
+        let outcomes = std::thread::scope(|s| {
+            let handles: Vec<_> = (0..self.jobs as u64)
+                .map(|index| s.spawn(move || job(index)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join())
+                .collect::<Vec<_>>()
+        });
+        let mut merged = Samples::new();
+        for outcome in outcomes {
+            let samples = outcome
+                .map_err(|payload| StressError::WorkerPanicked(crate::panic_message(&payload)))?
+                .map_err(|e| {
+                    StressError::WorkloadFailed(format!("{}: {e}", scratch.path.display()))
+                })?;
+            merged.seen += samples.seen;
+            merged.kept.extend(samples.kept);
+        }
+        Ok(merged)
+    }
+
+    /// Sizes the file without allocating it, then at random block offsets
+    /// fills holes in and punches filled blocks out again. Times punches,
+    /// then fills.
+    fn sparse(
+        &self,
+        scratch: &Scratch,
+        throttle: &Throttle,
+        deadline: Instant,
+        cancel: &CancellationToken,
+    ) -> std::io::Result<Outcome> {
+        let mut rng = Rng::new(chaos::random_seed());
+        scratch.file.set_len(self.size())?;
+        let block = vec![0x5a; self.block as usize];
+        let blocks = self.size() / self.block;
+        let mut filled = vec![];
+        let (mut punches, mut fills) = (Samples::new(), Samples::new());
+        while Instant::now() < deadline && !cancel.is_cancelled() {
+            let full = filled.len() as u64 * self.block >= MAX_FILLED;
+            let punch = !filled.is_empty() && (full || rng.chance(50));
+            // Punching moves no data, so only counts against --limit-iops.
+            if !throttle.admit(if punch { 0 } else { self.block }, cancel) {
+                break;
+            }
+            let started = Instant::now();
+            if punch {
+                let offset = filled.swap_remove(rng.up_to(filled.len() as u64 - 1) as usize);
+                scratch.punch_hole(offset, self.block)?;
+                punches.record(started.elapsed(), &mut rng);
+            } else {
+                let offset = rng.up_to(blocks - 1) * self.block;
+                scratch.file.write_all_at(&block, offset)?;
+                fills.record(started.elapsed(), &mut rng);
+                filled.push(offset);
+            }
+        }
+        Ok(Outcome {
+            bytes: fills.seen * self.block,
+            first: punches,
+            second: fills,
+        })
+    }
+
+    /// These arguments less a realtime I/O class this process may not set,
+    /// which is recorded as skipped rather than failing every worker.
+    fn permitted(&self) -> Args {
+        let mut args = self.clone();
+        if let Some(class @ IoClass::Realtime(_)) = args.io_class
+            && !caps::require(&format!("disk --io-class {class}"), Privilege::SysAdmin)
+        {
+            args.io_class = None;
+        }
+        args
+    }
+
+    /// Runs the mode or fill on the filesystem holding `dir`, with scratch
+    /// file `index`, and returns its summary.
+    fn load(
+        &self,
+        dir: &Path,
+        index: usize,
+        throttle: &Throttle,
+        cancel: &CancellationToken,
+    ) -> Result<Value, anyhow::Error> {
+        if let Some(class) = self.io_class {
+            sched::set_io_class(class)
+                .map_err(|e| anyhow::anyhow!("Failed to set I/O class {class}: {e}"))?;
+        }
+        let scratch = Scratch::create(dir, &index.to_string()).map_err(|e| {
+            anyhow::anyhow!("Failed to create a scratch file in {}: {e}", dir.display())
+        })?;
+        match self.fill_until {
+            Some(target) => log::info!(
+                "Filling {} to {target}% and holding it for {:?}.",
+                dir.display(),
+                self.duration
+            ),
+            None => log::info!(
+                "Running {} on {} ({} bytes, {}-byte blocks) for {:?}.",
+                self.mode.name(),
+                scratch.path.display(),
+                self.size(),
+                self.block,
+                self.duration
+            ),
+        }
+        let stop = &AtomicBool::new(false);
+        let (summary, probes) = std::thread::scope(|s| {
+            let probes = self
+                .probe_interval
+                .map(|interval| s.spawn(move || probe(dir, index, interval, stop)));
+            let summary = self.exercise(&scratch, throttle, cancel);
+            stop.store(true, Ordering::Relaxed);
+            (summary, probes.map(|probes| probes.join()))
+        });
+        let mut summary = summary?;
+        if let Some(probes) = probes {
+            let mut probes = probes
+                .map_err(|payload| StressError::WorkerPanicked(crate::panic_message(&payload)))?
+                .map_err(|e| anyhow::anyhow!("Probing {} failed: {e}", dir.display()))?;
+            percentiles(&mut summary, ("probe_reads", "probe_read"), &mut probes);
+        }
+        summary["allocated_bytes"] = json!(scratch.allocated().ok());
+        summary["path"] = json!(dir);
+        summary["device"] = json!(device(dir));
+        summary["io_class"] = json!(sched::io_class().ok().map(|class| class.to_string()));
+        summary["memory_backed"] = json!(memory_backed(dir).ok().flatten());
+        Ok(summary)
+    }
+
+    /// Runs the mode or fill on `scratch` and returns its summary.
+    fn exercise(
+        &self,
+        scratch: &Scratch,
+        throttle: &Throttle,
+        cancel: &CancellationToken,
+    ) -> Result<Value, anyhow::Error> {
+        let started = Instant::now();
+        let deadline = started + self.duration;
+        let failed = |e: std::io::Error| {
+            StressError::WorkloadFailed(format!("{}: {e}", scratch.path.display()))
+        };
+        Ok(match (self.fill_until, self.mode) {
+            (Some(target), _) => self
+                .fill(scratch, target, deadline, cancel)
+                .map_err(failed)?,
+            (None, Mode::Write) => {
+                let mut outcome = self
+                    .write(scratch, throttle, deadline, cancel)
+                    .map_err(failed)?;
+                summarize(
+                    &mut outcome,
+                    [("writes", "write"), ("syncs", "sync")],
+                    started.elapsed(),
+                )
+            }
+            (None, Mode::Read) => {
+                self.create_dataset(scratch, cancel).map_err(failed)?;
+                let started = Instant::now();
+                let mut outcome = self.read(scratch, throttle, started + self.duration, cancel)?;
+                let phase = started.elapsed().as_secs_f64() / 2.0;
+                let block = self.block as f64;
+                let (sequential, random) = (outcome.first.seen as f64, outcome.second.seen as f64);
+                let mut summary = summarize(
+                    &mut outcome,
+                    [
+                        ("sequential_reads", "sequential_read"),
+                        ("random_reads", "random_read"),
+                    ],
+                    started.elapsed(),
+                );
+                summary["sequential_bytes_per_sec"] = json!(sequential * block / phase);
+                summary["random_bytes_per_sec"] = json!(random * block / phase);
+                summary
+            }
+            (None, Mode::Sparse) => {
+                let mut outcome = self
+                    .sparse(scratch, throttle, deadline, cancel)
+                    .map_err(failed)?;
+                summarize(
+                    &mut outcome,
+                    [("punches", "punch"), ("fills", "fill")],
+                    started.elapsed(),
+                )
+            }
+        })
+    }
+}
+
+impl Stressor for Args {
+    fn name(&self) -> &'static str {
+        "disk"
+    }
+
+    fn params(&self) -> Value {
+        json!({
+            "mode": self.mode.name(),
+            "paths": self.paths(),
+            "size": self.size(),
+            "block": self.block,
+            "jobs": self.jobs,
+            "io_class": self.io_class.map(|class| class.to_string()),
+            "allow_tmpfs": self.allow_tmpfs,
+            "limit_bytes_per_sec": self.limits.limit,
+            "limit_iops": self.limits.limit_iops,
+            "probe_interval_secs": self.probe_interval.map(|interval| interval.as_secs_f64()),
+            "fill_until_percent": self.fill_until,
+            "duration_secs": self.duration.as_secs_f64(),
+        })
+    }
+
+    fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        if self.block == 0 || self.size() < self.block {
+            return Err(StressError::InvalidInput(
+                "--block must be greater than 0 and at most --size".to_string(),
+            )
+            .into());
+        }
+        if self.jobs == 0 {
+            return Err(
+                StressError::InvalidInput("--jobs must be greater than 0".to_string()).into(),
+            );
+        }
+        for path in self.paths() {
+            let Some(kind) = memory_backed(&path)? else {
+                continue;
+            };
+            match self.allow_tmpfs {
+                true => log::warn!(
+                    "{} is on {kind}: its numbers are for memory, not a disk.",
+                    path.display()
+                ),
+                false => {
+                    return Err(StressError::InvalidInput(format!(
+                        "{} is on {kind}, so this would load memory rather than a disk; \
+                         pass --allow-tmpfs to run anyway",
+                        path.display()
+                    ))
+                    .into());
+                }
+            }
+        }
+        let args = &self.permitted();
+        let throttle = &Throttle::new(&self.limits);
+        let started = Instant::now();
+        let outcomes = std::thread::scope(|s| {
+            let handles: Vec<_> = self
+                .paths()
+                .into_iter()
+                .enumerate()
+                .map(|(index, dir)| s.spawn(move || args.load(&dir, index, throttle, cancel)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join())
+                .collect::<Vec<_>>()
+        });
+        let mut summaries = vec![];
+        for outcome in outcomes {
+            summaries.push(outcome.map_err(|payload| {
+                StressError::WorkerPanicked(crate::panic_message(&payload))
+            })??);
+        }
+        let summary = match <[Value; 1]>::try_from(summaries) {
+            Ok([summary]) => summary,
+            Err(summaries) => json!({
+                "bytes_per_sec": summaries
+                    .iter()
+                    .filter_map(|summary| summary["bytes_per_sec"].as_f64())
+                    .sum::<f64>(),
+                "elapsed_secs": started.elapsed().as_secs_f64(),
+                "paths": summaries,
+            }),
+        };
+        log::info!("Disk: {summary}.");
+        report::measure(self.name(), summary);
+        match cancel.is_cancelled() {
+            true => Err(StressError::Interrupted.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(mode: Mode, dir: &Path) -> Args {
+        Args {
+            mode,
+            paths: vec![dir.to_path_buf()],
+            size: Some(1 << 20),
+            block: 64 << 10,
+            jobs: 2,
+            io_class: None,
+            allow_tmpfs: false,
+            limits: Limits::default(),
+            probe_interval: None,
+            fill_until: None,
+            duration: Duration::from_millis(50),
+        }
+    }
+
+    #[test]
+    fn writes_reads_and_punches_then_removes_the_file() {
+        let dir = std::env::temp_dir().join(format!("itsmine-disk-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cancel = CancellationToken::new();
+        let throttle = Throttle::new(&Limits::default());
+        for mode in [Mode::Write, Mode::Read, Mode::Sparse] {
+            let args = args(mode, &dir);
+            let scratch = Scratch::create(&dir, "0").unwrap();
+            let deadline = Instant::now() + args.duration;
+            let mut outcome = match mode {
+                Mode::Write => args.write(&scratch, &throttle, deadline, &cancel).unwrap(),
+                Mode::Read => {
+                    args.create_dataset(&scratch, &cancel).unwrap();
+                    let outcome = args.read(&scratch, &throttle, deadline, &cancel).unwrap();
+                    assert!(outcome.second.seen > 0);
+                    outcome
+                }
+                Mode::Sparse => args.sparse(&scratch, &throttle, deadline, &cancel).unwrap(),
+            };
+            assert!(outcome.first.seen > 0, "{mode:?}");
+            let summary = summarize(&mut outcome, [("as", "a"), ("bs", "b")], args.duration);
+            assert!(summary["a_p50_us"].as_f64().unwrap() <= summary["a_max_us"].as_f64().unwrap());
+            drop(scratch);
+        }
+        // A terabyte file only takes up what was filled in.
+        let args = Args {
+            size: Some(1 << 40),
+            ..args(Mode::Sparse, &dir)
+        };
+        let scratch = Scratch::create(&dir, "0").unwrap();
+        let deadline = Instant::now() + args.duration;
+        args.sparse(&scratch, &throttle, deadline, &cancel).unwrap();
+        assert_eq!(scratch.file.metadata().unwrap().len(), 1 << 40);
+        assert!(scratch.allocated().unwrap() <= MAX_FILLED + args.block);
+        drop(scratch);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir(dir).unwrap();
+    }
+
+    #[test]
+    fn loads_every_path_and_names_its_device() {
+        let dir = std::env::temp_dir();
+        let args = Args {
+            paths: vec![],
+            probe_interval: Some(Duration::from_millis(5)),
+            ..args(Mode::Write, &dir)
+        };
+        assert_eq!(args.paths(), vec![dir.clone()]);
+        let summary = args
+            .load(
+                &dir,
+                1,
+                &Throttle::new(&args.limits),
+                &CancellationToken::new(),
+            )
+            .unwrap();
+        assert_eq!(summary["path"], json!(dir));
+        assert!(summary["device"].is_string());
+        assert!(summary["io_class"].is_string());
+        assert!(summary["probe_reads"].as_u64().unwrap() > 0);
+        assert!(summary["probe_read_p50_us"].as_f64().unwrap() > 0.0);
+        if std::path::Path::new("/dev/shm").is_dir() {
+            assert_eq!(memory_backed(Path::new("/dev/shm")).unwrap(), Some("tmpfs"));
+            let args = Args {
+                paths: vec!["/dev/shm".into()],
+                ..args
+            };
+            assert!(args.run(&CancellationToken::new()).is_err());
+        }
+    }
+
+    #[test]
+    fn fills_to_a_watermark_without_leaving_a_file() {
+        let dir = std::env::temp_dir().join(format!("itsmine-fill-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (used, capacity) = usage(&dir).unwrap();
+        let target = percent_of(used + (8 << 20), capacity);
+        let args = Args {
+            fill_until: Some(target),
+            duration: Duration::ZERO,
+            ..args(Mode::Write, &dir)
+        };
+        let scratch = Scratch::create(&dir, "0").unwrap();
+        // Unlinked from the start.
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        let summary = args
+            .fill(&scratch, target, Instant::now(), &CancellationToken::new())
+            .unwrap();
+        assert!(summary["filled_bytes"].as_u64().unwrap() > 0);
+        assert!(scratch.allocated().unwrap() >= summary["filled_bytes"].as_u64().unwrap());
+        drop(scratch);
+        std::fs::remove_dir(dir).unwrap();
+    }
+}
@@ -0,0 +1,415 @@
+//! `itsmine dns`: fires lookups at a resolver at a fixed rate, for random
+//! names that miss every cache or names from a list, and reports how many it
+//! answered, how, and how fast.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+use crate::chaos::{self, Rng};
+use crate::stats::{self, Samples};
+use crate::{CancellationToken, StressError, Stressor, parse, report};
+
+/// How long a blocked receive waits before checking for the end.
+const POLL: Duration = Duration::from_millis(50);
+const NOERROR: u8 = 0;
+const SERVFAIL: u8 = 2;
+const NXDOMAIN: u8 = 3;
+const REFUSED: u8 = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Record {
+    A,
+    Aaaa,
+}
+
+impl Record {
+    pub fn name(self) -> &'static str {
+        match self {
+            Record::A => "A",
+            Record::Aaaa => "AAAA",
+        }
+    }
+
+    fn qtype(self) -> u16 {
+        match self {
+            Record::A => 1,
+            Record::Aaaa => 28,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, clap::Args)]
+pub struct Args {
+    /// Resolver to query, as IP or IP:PORT [default: the first nameserver in
+    /// /etc/resolv.conf]
+    #[arg(long, value_name = "ADDR", value_parser = resolver)]
+    pub resolver: Option<SocketAddr>,
+    /// Lookups per second, e.g. 500/s
+    #[arg(long, value_name = "RATE", value_parser = parse::per_second, default_value = "100/s")]
+    pub rate: f64,
+    /// File of names to look up at random, one per line; without it, random
+    /// names under --domain, which no cache can answer
+    #[arg(long, value_name = "PATH")]
+    pub names: Option<PathBuf>,
+    /// Domain to make random names under
+    #[arg(long, value_name = "DOMAIN", default_value = "example.com")]
+    pub domain: String,
+    /// Record type to ask for
+    #[arg(long, value_enum, default_value = "a")]
+    pub record: Record,
+    /// How long to wait for each answer, e.g. 2s
+    #[arg(long, value_parser = parse::duration, default_value = "2s")]
+    pub timeout: Duration,
+    /// How long to run, e.g. 10s
+    #[arg(long, value_parser = parse::duration, default_value = "10s")]
+    pub duration: Duration,
+}
+
+fn resolver(s: &str) -> Result<SocketAddr, String> {
+    match (s.parse::<SocketAddr>(), s.parse::<IpAddr>()) {
+        (Ok(addr), _) => Ok(addr),
+        (_, Ok(ip)) => Ok(SocketAddr::new(ip, 53)),
+        _ => Err(format!(
+            "invalid resolver '{s}' (e.g. 10.0.0.2 or 10.0.0.2:53)"
+        )),
+    }
+}
+
+/// The first nameserver in `resolv_conf`.
+fn system_resolver(resolv_conf: &str) -> Option<SocketAddr> {
+    resolv_conf.lines().find_map(|line| {
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("nameserver"), Some(ip)) => resolver(ip).ok(),
+            _ => None,
+        }
+    })
+}
+
+/// A recursive query for `name`.
+fn query(id: u16, name: &str, record: Record) -> Result<Vec<u8>, String> {
+    let name = name.trim_end_matches('.');
+    if name.is_empty() || name.len() > 253 {
+        return Err(format!("'{name}' is not a valid DNS name"));
+    }
+    // Header: recursion desired, one question.
+    let mut packet = [id.to_be_bytes(), [1, 0], [0, 1], [0, 0], [0, 0], [0, 0]].concat();
+    for label in name.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(format!("'{name}' is not a valid DNS name"));
+        }
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend_from_slice(&record.qtype().to_be_bytes());
+    packet.extend_from_slice(&1u16.to_be_bytes());
+    Ok(packet)
+}
+
+/// The ID and response code of a response; None for anything else.
+fn response(packet: &[u8]) -> Option<(u16, u8)> {
+    let header = packet.get(..4)?;
+    match header[2] & 0x80 {
+        0 => None,
+        _ => Some((u16::from_be_bytes([header[0], header[1]]), header[3] & 0x0f)),
+    }
+}
+
+/// What came back, by response code, and what never did.
+#[derive(Debug, Default)]
+struct Tally {
+    queries: u64,
+    rcodes: [u64; 16],
+    timeouts: u64,
+    send_errors: u64,
+    /// ICMP errors from the resolver's host, e.g. nothing listening.
+    refused_by_host: u64,
+    /// How long queries went out for.
+    sending: Duration,
+}
+
+impl Tally {
+    fn answered(&self) -> u64 {
+        self.rcodes.iter().sum()
+    }
+}
+
+/// Queries in flight, by ID, with when they were sent.
+struct Pending(Mutex<HashMap<u16, Instant>>);
+
+impl Pending {
+    fn insert(&self, id: u16, sent: Instant) -> bool {
+        let mut pending = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        pending.insert(id, sent).is_none()
+    }
+
+    fn remove(&self, id: u16) -> Option<Instant> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).remove(&id)
+    }
+
+    /// Drops queries sent before `cutoff`, returning how many.
+    fn expire(&self, cutoff: Instant) -> u64 {
+        let mut pending = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let before = pending.len();
+        pending.retain(|_, sent| *sent >= cutoff);
+        (before - pending.len()) as u64
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).is_empty()
+    }
+}
+
+impl Args {
+    fn names(&self) -> Result<Vec<String>, anyhow::Error> {
+        let Some(path) = &self.names else {
+            return Ok(vec![]);
+        };
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {e}", path.display()))?;
+        let names: Vec<_> = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect();
+        match names.is_empty() {
+            true => Err(anyhow::anyhow!("{} lists no names", path.display())),
+            false => Ok(names),
+        }
+    }
+
+    /// Sends queries to `resolver` at the set rate while a second thread
+    /// collects the answers, then waits out the stragglers.
+    fn storm(
+        &self,
+        resolver: SocketAddr,
+        names: &[String],
+        cancel: &CancellationToken,
+    ) -> Result<(Tally, Samples), anyhow::Error> {
+        let local: SocketAddr = match resolver {
+            SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+            SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(resolver)?;
+        socket.set_read_timeout(Some(POLL))?;
+        let pending = Pending(Mutex::new(HashMap::new()));
+        let stop = AtomicBool::new(false);
+        let mut tally = Tally::default();
+        let mut latencies = Samples::new();
+        std::thread::scope(|s| {
+            let receiver = s.spawn(|| {
+                let mut rng = Rng::new(chaos::random_seed());
+                let mut buffer = [0; 4096];
+                let mut received = Tally::default();
+                while !stop.load(Ordering::Relaxed) {
+                    match socket.recv(&mut buffer) {
+                        Ok(len) => {
+                            let Some((id, rcode)) = response(&buffer[..len]) else {
+                                continue;
+                            };
+                            if let Some(sent) = pending.remove(id) {
+                                latencies.record(sent.elapsed(), &mut rng);
+                                received.rcodes[rcode as usize] += 1;
+                            }
+                        }
+                        Err(e) if e.raw_os_error() == Some(libc::ECONNREFUSED) => {
+                            received.refused_by_host += 1
+                        }
+                        Err(_) => {}
+                    }
+                    received.timeouts += pending.expire(Instant::now() - self.timeout);
+                }
+                received
+            });
+
+            let mut rng = Rng::new(chaos::random_seed());
+            let interval = Duration::from_secs_f64(1.0 / self.rate);
+            let started = Instant::now();
+            let deadline = started + self.duration;
+            let mut next = started;
+            let mut id = rng.next_u64() as u16;
+            while !cancel.is_cancelled() && Instant::now() < deadline {
+                let name = match names.is_empty() {
+                    true => format!("{:012x}.{}", rng.next_u64() >> 16, self.domain),
+                    false => names[rng.up_to(names.len() as u64 - 1) as usize].clone(),
+                };
+                id = id.wrapping_add(1);
+                let packet = query(id, &name, self.record).map_err(anyhow::Error::msg)?;
+                // An ID still in flight after 65536 more queries is dropped.
+                if pending.insert(id, Instant::now()) {
+                    tally.queries += 1;
+                } else {
+                    tally.timeouts += 1;
+                }
+                if socket.send(&packet).is_err() {
+                    pending.remove(id);
+                    tally.send_errors += 1;
+                }
+                next = (next + interval).max(Instant::now() - interval);
+                cancel.sleep_until(next.min(deadline));
+            }
+            tally.sending = started.elapsed();
+            let waited = Instant::now() + self.timeout;
+            while !pending.is_empty() && Instant::now() < waited && !cancel.is_cancelled() {
+                std::thread::sleep(POLL.min(waited.saturating_duration_since(Instant::now())));
+            }
+            stop.store(true, Ordering::Relaxed);
+            let received = receiver
+                .join()
+                .map_err(|payload| StressError::WorkerPanicked(crate::panic_message(&payload)))?;
+            tally.rcodes = received.rcodes;
+            tally.refused_by_host = received.refused_by_host;
+            tally.timeouts += received.timeouts + pending.expire(Instant::now());
+            Ok::<_, anyhow::Error>(())
+        })?;
+        Ok((tally, latencies))
+    }
+}
+
+impl Stressor for Args {
+    fn name(&self) -> &'static str {
+        "dns"
+    }
+
+    fn params(&self) -> Value {
+        json!({
+            "resolver": self.resolver.map(|addr| addr.to_string()),
+            "rate_per_sec": self.rate,
+            "names": self.names,
+            "domain": self.domain,
+            "record": self.record.name(),
+            "timeout_secs": self.timeout.as_secs_f64(),
+            "duration_secs": self.duration.as_secs_f64(),
+        })
+    }
+
+    fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        let resolver = match self.resolver {
+            Some(resolver) => resolver,
+            None => std::fs::read_to_string("/etc/resolv.conf")
+                .ok()
+                .and_then(|conf| system_resolver(&conf))
+                .ok_or_else(|| {
+                    StressError::InvalidInput(
+                        "no nameserver in /etc/resolv.conf; pass --resolver".to_string(),
+                    )
+                })?,
+        };
+        let names = self.names()?;
+        log::info!(
+            "Sending {} {} lookups/s to {resolver} for {:?}.",
+            self.rate,
+            self.record.name(),
+            self.duration
+        );
+        let (tally, mut latencies) = self.storm(resolver, &names, cancel)?;
+        latencies.kept.sort_by(f64::total_cmp);
+        let ms = |p: f64| stats::percentile(&latencies.kept, p) / 1e6;
+        let resolved = tally.rcodes[NOERROR as usize] + tally.rcodes[NXDOMAIN as usize];
+        let summary = json!({
+            "resolver": resolver.to_string(),
+            "queries": tally.queries,
+            "queries_per_sec": tally.queries as f64 / tally.sending.as_secs_f64(),
+            "answered": tally.answered(),
+            "success_rate": resolved as f64 / tally.queries.max(1) as f64,
+            "noerror": tally.rcodes[NOERROR as usize],
+            "nxdomain": tally.rcodes[NXDOMAIN as usize],
+            "servfail": tally.rcodes[SERVFAIL as usize],
+            "refused": tally.rcodes[REFUSED as usize],
+            "other_rcodes": tally.answered() - resolved
+                - tally.rcodes[SERVFAIL as usize]
+                - tally.rcodes[REFUSED as usize],
+            "timeouts": tally.timeouts,
+            "send_errors": tally.send_errors,
+            "refused_by_host": tally.refused_by_host,
+            "latency_p50_ms": ms(0.5),
+            "latency_p99_ms": ms(0.99),
+            "latency_max_ms": ms(1.0),
+        });
+        log::info!("DNS: {summary}.");
+        report::measure(self.name(), summary);
+        match cancel.is_cancelled() {
+            true => Err(StressError::Interrupted.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_queries_and_reads_responses() {
+        let packet = query(0x1234, "www.example.com.", Record::Aaaa).unwrap();
+        assert_eq!(&packet[..2], &[0x12, 0x34]);
+        assert_eq!(&packet[12..17], b"\x03www\x07");
+        assert_eq!(&packet[packet.len() - 4..], &[0, 28, 0, 1]);
+        // A query is not a response; with QR set and NXDOMAIN, it is.
+        assert_eq!(response(&packet), None);
+        let mut answer = packet.clone();
+        answer[2] |= 0x80;
+        answer[3] = NXDOMAIN;
+        assert_eq!(response(&answer), Some((0x1234, NXDOMAIN)));
+        assert!(query(1, "a..b", Record::A).is_err());
+        assert!(query(1, &"x".repeat(64), Record::A).is_err());
+
+        assert_eq!(resolver("10.0.0.2"), Ok("10.0.0.2:53".parse().unwrap()));
+        assert_eq!(resolver("[::1]:5353"), Ok("[::1]:5353".parse().unwrap()));
+        let conf = "# generated\nsearch lan\nnameserver 192.168.1.1\nnameserver 1.1.1.1\n";
+        assert_eq!(
+            system_resolver(conf),
+            Some("192.168.1.1:53".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn tallies_answers_from_a_resolver() {
+        // A resolver that knows no names, and ignores every fourth query.
+        let fake = UdpSocket::bind("127.0.0.1:0").unwrap();
+        fake.set_read_timeout(Some(Duration::from_millis(500)))
+            .unwrap();
+        let resolver = fake.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let mut buffer = [0; 512];
+            let mut seen = 0;
+            while let Ok((len, from)) = fake.recv_from(&mut buffer) {
+                seen += 1;
+                if seen % 4 != 0 {
+                    buffer[2] |= 0x80;
+                    buffer[3] = NXDOMAIN;
+                    fake.send_to(&buffer[..len], from).unwrap();
+                }
+            }
+        });
+        let args = Args {
+            resolver: Some(resolver),
+            rate: 200.0,
+            names: None,
+            domain: "example.com".to_string(),
+            record: Record::A,
+            timeout: Duration::from_millis(100),
+            duration: Duration::from_millis(200),
+        };
+        let (tally, latencies) = args
+            .storm(resolver, &[], &CancellationToken::new())
+            .unwrap();
+        assert!(tally.queries >= 20, "{tally:?}");
+        assert_eq!(tally.rcodes[NXDOMAIN as usize], latencies.seen);
+        assert_eq!(
+            tally.answered() + tally.timeouts,
+            tally.queries,
+            "{tally:?}"
+        );
+        assert!(tally.timeouts >= tally.queries / 5, "{tally:?}");
+    }
+}
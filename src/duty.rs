@@ -0,0 +1,116 @@
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::CancellationToken;
+use crate::parse::Duty;
+
+static DUTY: OnceLock<Duty> = OnceLock::new();
+static THROTTLEABLE: AtomicBool = AtomicBool::new(false);
+static PAUSED: AtomicBool = AtomicBool::new(false);
+/// Fraction of each period's busy share workers may use, as `f64` bits.
+static THROTTLE: AtomicU64 = AtomicU64::new(1.0f64.to_bits());
+
+/// Period workers pace themselves on when only throttling asks for a cycle.
+const THROTTLE_PERIOD: Duration = Duration::from_millis(100);
+
+/// Makes every thread stressor worker alternate between busy and idle as
+/// `duty` describes, instead of running flat out.
+pub fn configure(duty: Duty) {
+    let _ = DUTY.set(duty);
+}
+
+pub fn configured() -> Option<Duty> {
+    DUTY.get().copied()
+}
+
+/// Makes workers pace themselves even without `--duty`, so `set_throttle`
+/// can slow them down later.
+pub fn enable_throttle() {
+    THROTTLEABLE.store(true, Ordering::SeqCst);
+}
+
+/// Scales every worker's busy share by `fraction` (1.0 is unthrottled).
+pub fn set_throttle(fraction: f64) {
+    THROTTLE.store(fraction.clamp(0.0, 1.0).to_bits(), Ordering::SeqCst);
+}
+
+pub fn throttle() -> f64 {
+    f64::from_bits(THROTTLE.load(Ordering::SeqCst))
+}
+
+/// Stops workers' busy time altogether until unpaused; they still wake up
+/// every period to notice cancellation.
+pub fn pause(paused: bool) {
+    PAUSED.store(paused, Ordering::SeqCst);
+}
+
+/// A new worker's duty cycle: the configured one, or flat out while
+/// throttling is enabled, otherwise none.
+pub fn cycle() -> Option<Cycle> {
+    let duty = configured().or_else(|| {
+        THROTTLEABLE.load(Ordering::SeqCst).then_some(Duty {
+            busy: THROTTLE_PERIOD,
+            period: THROTTLE_PERIOD,
+        })
+    })?;
+    Some(Cycle::start(duty))
+}
+
+/// One worker's position in its duty cycle.
+pub struct Cycle {
+    duty: Duty,
+    period_start: Instant,
+}
+
+impl Cycle {
+    pub fn start(duty: Duty) -> Self {
+        Cycle {
+            duty,
+            period_start: Instant::now(),
+        }
+    }
+
+    /// Called after each unit of work: once the period's busy share is used
+    /// up, sleeps out the rest of the period, but not past `deadline`.
+    /// Returns `false` if cancelled while idle.
+    pub fn pace(&mut self, cancel: &CancellationToken, deadline: Option<Instant>) -> bool {
+        let now = Instant::now();
+        let busy = match PAUSED.load(Ordering::SeqCst) {
+            true => Duration::ZERO,
+            false => self.duty.busy.mul_f64(throttle()),
+        };
+        if now < self.period_start + busy {
+            return true;
+        }
+        let period_end = self.period_start + self.duty.period;
+        // A unit of work longer than the period starts the next one late.
+        self.period_start = period_end.max(now);
+        cancel.sleep_until(deadline.map_or(period_end, |d| d.min(period_end)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn idles_for_the_rest_of_each_period() {
+        let mut cycle = Cycle::start(Duty {
+            busy: Duration::from_millis(20),
+            period: Duration::from_millis(100),
+        });
+        let cancel = CancellationToken::new();
+        let started = Instant::now();
+        let mut busy = Duration::ZERO;
+        while started.elapsed() < Duration::from_millis(300) {
+            let unit = Instant::now();
+            std::thread::sleep(Duration::from_millis(5));
+            busy += unit.elapsed();
+            assert!(cycle.pace(&cancel, None));
+        }
+        let share = busy.as_secs_f64() / started.elapsed().as_secs_f64();
+        assert!((0.1..0.45).contains(&share), "busy share {share}");
+    }
+}
@@ -0,0 +1,227 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::StressError;
+
+const EDAC_MC_ROOT: &str = "/sys/devices/system/edac/mc";
+
+/// Error counters of one DIMM (or csrow channel on older drivers).
+#[derive(Clone, Debug, PartialEq)]
+struct Dimm {
+    label: String,
+    ce_path: PathBuf,
+    ue_path: PathBuf,
+}
+
+/// Errors that appeared on one DIMM while the monitor was running.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EdacErrors {
+    pub dimm: String,
+    pub correctable: u64,
+    pub uncorrectable: u64,
+}
+
+/// Polls EDAC memory controller counters while a memory stressor runs.
+pub struct EdacMonitor {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<Vec<EdacErrors>>,
+}
+
+impl EdacMonitor {
+    /// Starts polling, or returns `None` if no EDAC driver is loaded.
+    pub fn start(interval: Duration) -> Option<Self> {
+        Self::start_at(Path::new(EDAC_MC_ROOT), interval)
+    }
+
+    fn start_at(root: &Path, interval: Duration) -> Option<Self> {
+        let dimms = discover_dimms(root);
+        if dimms.is_empty() {
+            log::debug!(
+                "No EDAC counters under {}, ECC monitor disabled.",
+                root.display()
+            );
+            return None;
+        }
+        log::debug!("Monitoring ECC counters of {} DIMMs.", dimms.len());
+
+        let baseline: Vec<(u64, u64)> = dimms.iter().map(read_counts).collect();
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                let mut last = baseline.clone();
+                loop {
+                    let stopping = stop.load(Ordering::Relaxed);
+                    let current: Vec<(u64, u64)> = dimms.iter().map(read_counts).collect();
+                    for (dimm, (before, now)) in dimms.iter().zip(last.iter().zip(&current)) {
+                        if now != before {
+                            log::warn!(
+                                "ECC errors on {}: {} correctable, {} uncorrectable",
+                                dimm.label,
+                                now.0.saturating_sub(before.0),
+                                now.1.saturating_sub(before.1)
+                            );
+                        }
+                    }
+                    last = current;
+                    if stopping {
+                        break;
+                    }
+                    std::thread::sleep(interval);
+                }
+                diff(&dimms, &baseline, &last)
+            })
+        };
+
+        Some(EdacMonitor { stop, handle })
+    }
+
+    /// Stops polling and fails if any DIMM reported new errors.
+    pub fn stop(self) -> Result<(), StressError> {
+        self.stop.store(true, Ordering::Relaxed);
+        let errors = self.handle.join().unwrap_or_default();
+        if errors.is_empty() {
+            log::info!("No ECC errors detected.");
+            return Ok(());
+        }
+
+        let summary: Vec<String> = errors
+            .iter()
+            .map(|e| format!("{} ({} CE, {} UE)", e.dimm, e.correctable, e.uncorrectable))
+            .collect();
+        Err(StressError::HardwareErrors(summary.join(", ")))
+    }
+}
+
+fn discover_dimms(root: &Path) -> Vec<Dimm> {
+    let mut dimms = vec![];
+    for mc in sorted_entries(root, "mc") {
+        let before = dimms.len();
+        // Modern drivers expose one directory per DIMM.
+        for dimm in sorted_entries(&mc, "dimm") {
+            dimms.push(Dimm {
+                label: read_label(&dimm.join("dimm_label"), &dimm),
+                ce_path: dimm.join("dimm_ce_count"),
+                ue_path: dimm.join("dimm_ue_count"),
+            });
+        }
+        if dimms.len() > before {
+            continue;
+        }
+        // Older drivers only expose csrows, with per-channel CE counts.
+        for csrow in sorted_entries(&mc, "csrow") {
+            for ch in 0.. {
+                let ce_path = csrow.join(format!("ch{ch}_ce_count"));
+                if !ce_path.exists() {
+                    break;
+                }
+                dimms.push(Dimm {
+                    label: read_label(&csrow.join(format!("ch{ch}_dimm_label")), &csrow),
+                    ce_path,
+                    ue_path: csrow.join("ue_count"),
+                });
+            }
+        }
+    }
+    dimms
+}
+
+fn sorted_entries(dir: &Path, prefix: &str) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut paths: Vec<(u32, PathBuf)> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let name = e.file_name().into_string().ok()?;
+            let index = name.strip_prefix(prefix)?.parse().ok()?;
+            Some((index, e.path()))
+        })
+        .collect();
+    paths.sort();
+    paths.into_iter().map(|(_, p)| p).collect()
+}
+
+fn read_label(path: &Path, fallback: &Path) -> String {
+    std::fs::read_to_string(path)
+        .map(|s| s.trim().to_string())
+        .ok()
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| fallback.display().to_string())
+}
+
+fn read_count(path: &Path) -> u64 {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+fn read_counts(dimm: &Dimm) -> (u64, u64) {
+    (read_count(&dimm.ce_path), read_count(&dimm.ue_path))
+}
+
+fn diff(dimms: &[Dimm], before: &[(u64, u64)], after: &[(u64, u64)]) -> Vec<EdacErrors> {
+    dimms
+        .iter()
+        .zip(before.iter().zip(after))
+        .filter(|(_, (b, a))| a != b)
+        .map(|(dimm, (b, a))| EdacErrors {
+            dimm: dimm.label.clone(),
+            correctable: a.0.saturating_sub(b.0),
+            uncorrectable: a.1.saturating_sub(b.1),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake_mc(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("itsmine-edac-{name}"));
+        let _ = std::fs::remove_dir_all(&root);
+        let dimm = root.join("mc0/dimm0");
+        std::fs::create_dir_all(&dimm).unwrap();
+        std::fs::write(dimm.join("dimm_label"), "CPU_SrcID#0_Ha#0_Chan#0_DIMM#0\n").unwrap();
+        std::fs::write(dimm.join("dimm_ce_count"), "0\n").unwrap();
+        std::fs::write(dimm.join("dimm_ue_count"), "0\n").unwrap();
+        root
+    }
+
+    #[test]
+    fn discover_dimms_reads_labels() {
+        let root = fake_mc("discover");
+        let dimms = discover_dimms(&root);
+        assert_eq!(dimms.len(), 1);
+        assert_eq!(dimms[0].label, "CPU_SrcID#0_Ha#0_Chan#0_DIMM#0");
+    }
+
+    #[test]
+    fn stop_fails_when_counts_increase() {
+        let root = fake_mc("increase");
+        let monitor = EdacMonitor::start_at(&root, Duration::from_millis(5)).unwrap();
+        std::fs::write(root.join("mc0/dimm0/dimm_ce_count"), "3\n").unwrap();
+        let err = monitor.stop().unwrap_err().to_string();
+        assert!(
+            err.contains("CPU_SrcID#0_Ha#0_Chan#0_DIMM#0 (3 CE, 0 UE)"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn stop_succeeds_without_new_errors() {
+        let root = fake_mc("clean");
+        let monitor = EdacMonitor::start_at(&root, Duration::from_millis(5)).unwrap();
+        assert!(monitor.stop().is_ok());
+    }
+
+    #[test]
+    fn start_without_edac_is_disabled() {
+        let root = std::env::temp_dir().join("itsmine-edac-missing");
+        assert!(EdacMonitor::start_at(&root, Duration::from_millis(5)).is_none());
+    }
+}
@@ -0,0 +1,239 @@
+//! `itsmine entropy`: threads read the kernel's random number generator as
+//! fast as it gives, through getrandom(2) or /dev/random and /dev/urandom,
+//! to see how entropy-hungry neighbours fare while it is drained. On
+//! kernels before 5.6 /dev/random blocks once `entropy_avail` runs low.
+
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::fs::OpenOptionsExt;
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+use crate::{CancellationToken, StressError, Stressor, parse, report};
+
+/// The kernel's estimate of the entropy it holds, in bits.
+const ENTROPY_AVAIL: &str = "/proc/sys/kernel/random/entropy_avail";
+/// How often `entropy_avail` is sampled.
+const SAMPLE: Duration = Duration::from_millis(100);
+/// How long a reader waits after the source had nothing to give.
+const BACKOFF: Duration = Duration::from_millis(1);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Source {
+    /// The getrandom(2) system call
+    Getrandom,
+    /// /dev/random
+    Random,
+    /// /dev/urandom
+    Urandom,
+}
+
+impl Source {
+    pub fn name(self) -> &'static str {
+        match self {
+            Source::Getrandom => "getrandom",
+            Source::Random => "random",
+            Source::Urandom => "urandom",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, clap::Args)]
+pub struct Args {
+    /// Threads reading at once
+    #[arg(long, value_name = "N", default_value_t = 4)]
+    pub threads: u32,
+    /// Where the random bytes come from
+    #[arg(long, value_enum, default_value = "getrandom")]
+    pub source: Source,
+    /// Bytes asked for by each read, e.g. 4K
+    #[arg(long, value_name = "BYTES", value_parser = parse::bytes, default_value = "4K")]
+    pub chunk: u64,
+    /// How long to run, e.g. 10s
+    #[arg(long, value_parser = parse::duration, default_value = "10s")]
+    pub duration: Duration,
+}
+
+/// What one reader got.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Drained {
+    reads: u64,
+    bytes: u64,
+    /// Reads that returned fewer bytes than asked for.
+    short_reads: u64,
+    /// Reads that found nothing to give without blocking.
+    starved: u64,
+}
+
+/// An open source, read without blocking so the end of the run is seen.
+enum Reader {
+    Getrandom,
+    Device(File),
+}
+
+impl Reader {
+    fn open(source: Source) -> std::io::Result<Self> {
+        let path = match source {
+            Source::Getrandom => return Ok(Reader::Getrandom),
+            Source::Random => "/dev/random",
+            Source::Urandom => "/dev/urandom",
+        };
+        let file = File::options()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path)?;
+        Ok(Reader::Device(file))
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Reader::Getrandom => {
+                // SAFETY: `buffer` is valid for writes of its length.
+                let read = unsafe {
+                    libc::getrandom(
+                        buffer.as_mut_ptr().cast(),
+                        buffer.len(),
+                        libc::GRND_NONBLOCK,
+                    )
+                };
+                match read {
+                    -1 => Err(std::io::Error::last_os_error()),
+                    read => Ok(read as usize),
+                }
+            }
+            Reader::Device(file) => file.read(buffer),
+        }
+    }
+}
+
+/// Reads `chunk` bytes at a time from `source` until `deadline`.
+fn drain(
+    source: Source,
+    chunk: usize,
+    deadline: Instant,
+    cancel: &CancellationToken,
+) -> std::io::Result<Drained> {
+    let mut reader = Reader::open(source)?;
+    let mut buffer = vec![0; chunk];
+    let mut drained = Drained::default();
+    while Instant::now() < deadline && !cancel.is_cancelled() {
+        match reader.read(&mut buffer) {
+            Ok(read) => {
+                drained.reads += 1;
+                drained.bytes += read as u64;
+                drained.short_reads += u64::from(read < chunk);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                drained.starved += 1;
+                std::thread::sleep(BACKOFF);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(drained)
+}
+
+fn entropy_avail() -> Option<u64> {
+    std::fs::read_to_string(ENTROPY_AVAIL)
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+impl Stressor for Args {
+    fn name(&self) -> &'static str {
+        "entropy"
+    }
+
+    fn params(&self) -> Value {
+        json!({
+            "threads": self.threads,
+            "source": self.source.name(),
+            "chunk": self.chunk,
+            "duration_secs": self.duration.as_secs_f64(),
+        })
+    }
+
+    fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        if self.threads == 0 || self.chunk == 0 {
+            return Err(StressError::InvalidInput(
+                "--threads and --chunk must be greater than 0".to_string(),
+            )
+            .into());
+        }
+        let chunk = usize::try_from(self.chunk)
+            .ok()
+            .filter(|chunk| *chunk <= 1 << 30)
+            .ok_or_else(|| StressError::InvalidInput("--chunk must be at most 1G".to_string()))?;
+        log::info!(
+            "Draining {} from {} threads, {} bytes a read, for {:?}.",
+            self.source.name(),
+            self.threads,
+            chunk,
+            self.duration
+        );
+        let before = entropy_avail();
+        let started = Instant::now();
+        let deadline = started + self.duration;
+        let mut lowest = before;
+        let drained = std::thread::scope(|s| {
+            let handles: Vec<_> = (0..self.threads)
+                .map(|_| s.spawn(|| drain(self.source, chunk, deadline, cancel)))
+                .collect();
+            while Instant::now() < deadline && !cancel.is_cancelled() {
+                cancel.sleep_until((Instant::now() + SAMPLE).min(deadline));
+                lowest = lowest.min(entropy_avail()).or(lowest);
+            }
+            let mut total = Drained::default();
+            for handle in handles {
+                let drained = handle
+                    .join()
+                    .map_err(|payload| StressError::WorkerPanicked(crate::panic_message(&payload)))?
+                    .map_err(|e| {
+                        StressError::WorkloadFailed(format!("reading {}: {e}", self.source.name()))
+                    })?;
+                total.reads += drained.reads;
+                total.bytes += drained.bytes;
+                total.short_reads += drained.short_reads;
+                total.starved += drained.starved;
+            }
+            Ok::<_, StressError>(total)
+        })?;
+        let elapsed = started.elapsed().as_secs_f64();
+        let summary = json!({
+            "reads": drained.reads,
+            "reads_per_sec": drained.reads as f64 / elapsed,
+            "bytes": drained.bytes,
+            "bytes_per_sec": drained.bytes as f64 / elapsed,
+            "short_reads": drained.short_reads,
+            "starved_reads": drained.starved,
+            "entropy_avail_before": before,
+            "entropy_avail_lowest": lowest,
+            "entropy_avail_after": entropy_avail(),
+        });
+        log::info!("Entropy: {summary}.");
+        report::measure(self.name(), summary);
+        match cancel.is_cancelled() {
+            true => Err(StressError::Interrupted.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drains_every_source() {
+        for source in [Source::Getrandom, Source::Random, Source::Urandom] {
+            let deadline = Instant::now() + Duration::from_millis(20);
+            let drained = drain(source, 64, deadline, &CancellationToken::new()).unwrap();
+            assert!(drained.reads > 0, "{source:?}");
+            assert!(drained.bytes >= drained.reads, "{source:?}");
+        }
+    }
+}
@@ -0,0 +1,108 @@
+/// Failures of the built-in stressors. Higher layers carry these inside
+/// `anyhow::Error`; `exit_code` digs them back out for the process status.
+#[derive(Debug, thiserror::Error)]
+pub enum StressError {
+    #[error("Expected {expected} resource, got {got} resource")]
+    WrongResource {
+        expected: &'static str,
+        got: &'static str,
+    },
+    #[error("{0}")]
+    InvalidSize(String),
+    #[error("{0}")]
+    InvalidInput(String),
+    #[error("Memory allocation of {bytes} bytes failed")]
+    AllocationFailed { bytes: u64 },
+    #[error("Failed to spawn worker thread: {0}")]
+    SpawnFailed(std::io::Error),
+    #[error("Inconsistent results from threads")]
+    WorkloadMismatch,
+    #[error("{stressor} stressor did not stop within {grace_secs}s of its limit")]
+    Hung {
+        stressor: &'static str,
+        grace_secs: u64,
+    },
+    #[error("Worker thread panicked: {0}")]
+    WorkerPanicked(String),
+    #[error("Workload failed: {0}")]
+    WorkloadFailed(String),
+    #[error("ECC errors detected during memory stress: {0}")]
+    HardwareErrors(String),
+    #[error("Interrupted before completion")]
+    Interrupted,
+    /// A failure an isolated child reported, with the exit code and
+    /// retryability of the `StressError` behind it.
+    #[error("{message}")]
+    Isolated {
+        message: String,
+        code: i32,
+        transient: bool,
+    },
+}
+
+impl StressError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            StressError::WrongResource { .. }
+            | StressError::InvalidSize(_)
+            | StressError::InvalidInput(_) => 2,
+            StressError::AllocationFailed { .. } | StressError::SpawnFailed(_) => 3,
+            StressError::WorkloadMismatch => 4,
+            StressError::WorkerPanicked(_)
+            | StressError::WorkloadFailed(_)
+            | StressError::Hung { .. } => 5,
+            StressError::HardwareErrors(_) => 6,
+            // As if killed by SIGINT, like a shell would report.
+            StressError::Interrupted => 130,
+            StressError::Isolated { code, .. } => *code,
+        }
+    }
+
+    /// Whether retrying may succeed: the host was briefly out of memory or
+    /// threads (ENOMEM, EAGAIN).
+    pub fn is_transient(&self) -> bool {
+        match self {
+            StressError::AllocationFailed { .. } => true,
+            StressError::Isolated { transient, .. } => *transient,
+            StressError::SpawnFailed(e) => {
+                matches!(e.raw_os_error(), Some(libc::EAGAIN | libc::ENOMEM))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Exit code for a failed run: that of the first `StressError` in the chain,
+/// or 1 for anything else.
+pub fn exit_code(error: &anyhow::Error) -> i32 {
+    error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<StressError>())
+        .map_or(1, StressError::exit_code)
+}
+
+/// Whether the run was cut short by cancellation rather than failing.
+pub fn is_interrupted(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .any(|cause| matches!(cause.downcast_ref(), Some(StressError::Interrupted)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_code_finds_stress_error_in_context() {
+        let error = anyhow::Error::new(StressError::AllocationFailed { bytes: 1 })
+            .context("Phase 'peak' failed");
+        assert_eq!(exit_code(&error), 3);
+        assert_eq!(
+            format!("{error:#}"),
+            "Phase 'peak' failed: Memory allocation of 1 bytes failed"
+        );
+        assert_eq!(exit_code(&anyhow::anyhow!("Invalid scenario")), 1);
+        assert!(!is_interrupted(&error));
+        assert!(is_interrupted(&StressError::Interrupted.into()));
+    }
+}
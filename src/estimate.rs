@@ -0,0 +1,185 @@
+//! `itsmine estimate`: predicts what a scenario will demand of this machine
+//! before anything runs.
+
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::scenario::{Phase, Scenario};
+use crate::sysinfo::SystemInfo;
+use crate::{Memory, Resource};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Estimate {
+    pub peak_memory_bytes: u64,
+    pub peak_threads: u32,
+    /// Peak host CPU utilization, 0-100.
+    pub peak_cpu_percent: f64,
+    pub disk_bytes: u64,
+    /// Wall-clock time of all runs; `None` when the schedule is unbounded.
+    pub duration_secs: Option<f64>,
+    pub warnings: Vec<String>,
+}
+
+/// Predicts the scenario's peak demands against `system`, with
+/// `disk_available` the free space where stressors write, if known.
+pub fn estimate(scenario: &Scenario, system: &SystemInfo, disk_available: Option<u64>) -> Estimate {
+    let mut estimate = Estimate::default();
+    for phase in &scenario.phases {
+        let (memory, threads, disk) = demands(phase);
+        estimate.peak_memory_bytes = estimate.peak_memory_bytes.max(memory);
+        estimate.peak_threads = estimate.peak_threads.max(threads);
+        estimate.disk_bytes = estimate.disk_bytes.max(disk);
+    }
+
+    let mut cores = system.cpus.logical.max(1) as f64;
+    if let Some(quota) = system.cgroup.cpu_max {
+        cores = cores.min(quota);
+    }
+    estimate.peak_cpu_percent = (estimate.peak_threads as f64 / cores * 100.0).min(100.0);
+
+    let once: Duration = scenario.phases.iter().map(|p| p.duration).sum();
+    estimate.duration_secs = scenario.schedule.runs().map(|runs| {
+        let runs = runs.max(1);
+        match scenario.schedule.every {
+            // Runs start on a fixed cadence; the last one still runs in full.
+            Some(every) => (every * (runs - 1)).as_secs_f64() + once.as_secs_f64(),
+            None => once.as_secs_f64() * runs as f64,
+        }
+    });
+
+    estimate.warnings = warnings(&estimate, system, disk_available);
+    estimate
+}
+
+/// Memory, threads and disk one phase needs while all its stressors run.
+fn demands(phase: &Phase) -> (u64, u32, u64) {
+    let mut memory = 0u64;
+    let mut threads = 0u32;
+    for stressor in &phase.stressors {
+        match stressor {
+            Resource::Memory { .. } => {
+                if let Ok(m) = Memory::from_resource(stressor.clone()) {
+                    memory = memory.saturating_add(m.size.saturating_mul(m.multiplier));
+                }
+            }
+            Resource::Thread { num } => threads = threads.saturating_add(*num),
+            _ => {}
+        }
+    }
+    (memory, threads, 0)
+}
+
+fn warnings(estimate: &Estimate, system: &SystemInfo, disk_available: Option<u64>) -> Vec<String> {
+    let mut warnings = vec![];
+    let peak = estimate.peak_memory_bytes;
+    if let Some(limit) = system.cgroup.memory_max
+        && peak >= limit
+    {
+        warnings.push(format!(
+            "Peak memory of {peak} bytes exceeds the cgroup limit of {limit} bytes; expect an OOM kill."
+        ));
+    } else if let Some(available) = system.memory.available_bytes
+        && peak >= available
+    {
+        warnings.push(format!(
+            "Peak memory of {peak} bytes exceeds the {available} bytes available; likely OOM."
+        ));
+    }
+    if let Some(available) = disk_available
+        && estimate.disk_bytes > available
+    {
+        warnings.push(format!(
+            "Needs {} bytes of disk but only {available} are free; likely ENOSPC.",
+            estimate.disk_bytes
+        ));
+    }
+    if estimate.duration_secs.is_none() {
+        warnings.push("The schedule repeats until interrupted.".to_string());
+    }
+    warnings
+}
+
+/// Free bytes on the filesystem holding `path`.
+pub fn disk_available(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    // SAFETY: statvfs is plain data, and zero is a valid bit pattern.
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `path` is NUL-terminated and `stat` is a valid out-pointer.
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Peak memory: {} bytes", self.peak_memory_bytes)?;
+        writeln!(
+            f,
+            "Peak CPU:    {:.0}% ({} threads)",
+            self.peak_cpu_percent, self.peak_threads
+        )?;
+        writeln!(f, "Disk:        {} bytes", self.disk_bytes)?;
+        match self.duration_secs {
+            Some(secs) => writeln!(f, "Duration:    {secs:.0}s")?,
+            None => writeln!(f, "Duration:    unbounded")?,
+        }
+        for warning in &self.warnings {
+            writeln!(f, "Warning: {warning}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sysinfo::{Memory as SystemMemory, Topology};
+
+    fn system(logical: usize, available: u64) -> SystemInfo {
+        SystemInfo {
+            cpus: Topology {
+                logical,
+                cores: logical,
+                packages: 1,
+            },
+            memory: SystemMemory {
+                total_bytes: Some(available),
+                available_bytes: Some(available),
+            },
+            ..SystemInfo::default()
+        }
+    }
+
+    #[test]
+    fn peaks_and_duration() {
+        let scenario = Scenario::parse(
+            "schedule every 1h times 3\n\
+             phase ramp 2m\n  memory 512M\n  thread 2\n\
+             phase peak 10m\n  memory 1G\n  memory 1G\n  thread 8\n",
+        )
+        .unwrap();
+        let estimate = estimate(&scenario, &system(4, 8 << 30), None);
+        assert_eq!(estimate.peak_memory_bytes, 2 << 30);
+        assert_eq!(estimate.peak_threads, 8);
+        assert_eq!(estimate.peak_cpu_percent, 100.0);
+        assert_eq!(estimate.duration_secs, Some(2.0 * 3600.0 + 720.0));
+        assert!(estimate.warnings.is_empty());
+    }
+
+    #[test]
+    fn warns_about_oom_and_unbounded_runs() {
+        let scenario =
+            Scenario::parse("schedule every 1h\nphase a 1m\n  memory 2G\n  thread 1\n").unwrap();
+        let estimate = estimate(&scenario, &system(2, 1 << 30), Some(0));
+        assert_eq!(estimate.peak_cpu_percent, 50.0);
+        assert_eq!(estimate.duration_secs, None);
+        assert_eq!(estimate.warnings.len(), 2);
+        assert!(estimate.warnings[0].contains("likely OOM"));
+    }
+}
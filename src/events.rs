@@ -0,0 +1,68 @@
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::{Map, Value, json};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Turns on the NDJSON lifecycle event stream on stdout.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Prints one lifecycle event as a single JSON line, if the stream is enabled.
+pub fn emit(event: &str, fields: Value) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let line = to_line(event, fields, SystemTime::now());
+    let mut stdout = std::io::stdout().lock();
+    // A closed pipe on the consumer side must not bring the stressor down.
+    let _ = writeln!(stdout, "{line}").and_then(|_| stdout.flush());
+}
+
+fn to_line(event: &str, fields: Value, at: SystemTime) -> String {
+    let mut object = Map::new();
+    object.insert(
+        "ts".to_string(),
+        json!(
+            at.duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64()
+        ),
+    );
+    object.insert("event".to_string(), json!(event));
+    if let Some(label) = crate::telemetry::label() {
+        object.insert("label".to_string(), json!(label));
+    }
+    let tags = crate::telemetry::tags();
+    if !tags.is_empty() {
+        let tags: Map<_, _> = tags.iter().map(|(k, v)| (k.clone(), json!(v))).collect();
+        object.insert("tags".to_string(), Value::Object(tags));
+    }
+    if let Value::Object(fields) = fields {
+        object.extend(fields);
+    }
+    Value::Object(object).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn to_line_puts_timestamp_and_event_first() {
+        let at = UNIX_EPOCH + Duration::from_millis(1500);
+        let line = to_line("thread_finished", json!({ "thread": 3 }), at);
+        assert_eq!(line, r#"{"ts":1.5,"event":"thread_finished","thread":3}"#);
+    }
+
+    #[test]
+    fn to_line_is_single_line() {
+        let line = to_line("stressor_started", json!({ "arg": "a\nb" }), UNIX_EPOCH);
+        assert!(!line.contains('\n'));
+    }
+}
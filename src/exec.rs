@@ -0,0 +1,275 @@
+//! `itsmine exec-churn`: launches a trivial helper binary over and over at a
+//! fixed rate, the way CI runners and CGI-style servers do, timing how long
+//! each launch and each whole run takes. Launches can carry padded argv and
+//! environments to measure exec-time copying and find where `E2BIG` starts.
+
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+use crate::{CancellationToken, StressError, Stressor, parse, report, stats};
+
+/// Padding goes in strings this long, well under the kernel's 128K limit
+/// on any single argument or variable.
+const PAD_STRING: usize = 64 << 10;
+/// How finely `--probe-limit` pins down the largest accepted size.
+const PROBE_RESOLUTION: u64 = 4 << 10;
+const PROBE_CEILING: u64 = 1 << 30;
+
+#[derive(Clone, Debug, PartialEq, clap::Args)]
+pub struct Args {
+    /// Binary to launch
+    #[arg(long, value_name = "PATH", default_value = "/bin/true")]
+    pub binary: PathBuf,
+    /// Argument to pass it (repeatable)
+    #[arg(long = "arg", value_name = "ARG", allow_hyphen_values = true)]
+    pub args: Vec<String>,
+    /// Launches per second, e.g. 200/s; fewer if launches take longer
+    #[arg(long, value_name = "RATE", value_parser = parse::per_second, default_value = "100/s")]
+    pub rate: f64,
+    /// How long to run, e.g. 10s
+    #[arg(long, value_parser = parse::duration, default_value = "10s")]
+    pub duration: Duration,
+    /// Extra argv to pass every launch, e.g. 512K
+    #[arg(long, value_name = "BYTES", value_parser = parse::bytes, default_value = "0")]
+    pub argv_bytes: u64,
+    /// Extra environment to pass every launch, e.g. 512K
+    #[arg(long, value_name = "BYTES", value_parser = parse::bytes, default_value = "0")]
+    pub env_bytes: u64,
+    /// First find the largest argv padding exec accepts before E2BIG
+    #[arg(long, default_value_t = false)]
+    pub probe_limit: bool,
+}
+
+/// Filler argv and environment strings.
+#[derive(Default)]
+struct Padding {
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+}
+
+impl Padding {
+    fn new(argv_bytes: u64, env_bytes: u64) -> Self {
+        let strings = |bytes: u64| {
+            let bytes = bytes as usize;
+            (0..bytes.div_ceil(PAD_STRING))
+                .map(move |i| "x".repeat(PAD_STRING.min(bytes - i * PAD_STRING)))
+        };
+        Padding {
+            args: strings(argv_bytes).collect(),
+            env: strings(env_bytes)
+                .enumerate()
+                .map(|(i, value)| (format!("ITSMINE_PAD{i}"), value))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Churn {
+    /// Nanoseconds until the child had exec'd the binary.
+    launches: Vec<f64>,
+    /// Nanoseconds from launch until the child was reaped.
+    lifetimes: Vec<f64>,
+    failed: u64,
+}
+
+impl Args {
+    fn command(&self, padding: &Padding) -> Command {
+        let mut command = Command::new(&self.binary);
+        command
+            .args(&self.args)
+            .args(&padding.args)
+            .envs(padding.env.iter().map(|(key, value)| (key, value)))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        command
+    }
+
+    /// Launches and reaps one child, recording its timings.
+    fn launch(&self, padding: &Padding, churn: &mut Churn) -> std::io::Result<()> {
+        let started = Instant::now();
+        // `spawn` returns once the exec succeeded or failed.
+        let mut child = self.command(padding).spawn()?;
+        churn.launches.push(started.elapsed().as_nanos() as f64);
+        child.wait()?;
+        churn.lifetimes.push(started.elapsed().as_nanos() as f64);
+        Ok(())
+    }
+
+    /// Whether exec accepts `bytes` of argv padding, alongside the
+    /// configured environment padding.
+    fn accepts(&self, bytes: u64) -> std::io::Result<bool> {
+        let padding = Padding::new(bytes, self.env_bytes);
+        match self.launch(&padding, &mut Churn::default()) {
+            Ok(()) => Ok(true),
+            Err(e) if e.raw_os_error() == Some(libc::E2BIG) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The largest argv padding exec accepts, doubling until it fails and
+    /// then bisecting; `None` if even 1G goes through.
+    fn probe_limit(&self) -> std::io::Result<Option<u64>> {
+        let (mut accepted, mut refused) = (0, PROBE_RESOLUTION);
+        while self.accepts(refused)? {
+            accepted = refused;
+            refused *= 2;
+            if refused > PROBE_CEILING {
+                return Ok(None);
+            }
+        }
+        while refused - accepted > PROBE_RESOLUTION {
+            let middle = (accepted + refused) / 2;
+            match self.accepts(middle)? {
+                true => accepted = middle,
+                false => refused = middle,
+            }
+        }
+        Ok(Some(accepted))
+    }
+
+    fn churn(&self, cancel: &CancellationToken) -> Result<Churn, anyhow::Error> {
+        let mut churn = Churn::default();
+        let padding = Padding::new(self.argv_bytes, self.env_bytes);
+        // A binary that can't start at all is a usage error, not load.
+        self.launch(&padding, &mut churn)
+            .map_err(|e| anyhow::anyhow!("Failed to launch {}: {e}", self.binary.display()))?;
+        let interval = Duration::from_secs_f64(1.0 / self.rate);
+        let started = Instant::now();
+        let deadline = started + self.duration;
+        let mut next = started;
+        while !cancel.is_cancelled() && Instant::now() < deadline {
+            if let Err(e) = self.launch(&padding, &mut churn) {
+                log::debug!("Launch failed: {e}");
+                churn.failed += 1;
+            }
+            next = (next + interval).max(Instant::now() - interval);
+            cancel.sleep_until(next.min(deadline));
+        }
+        Ok(churn)
+    }
+}
+
+impl Stressor for Args {
+    fn name(&self) -> &'static str {
+        "exec-churn"
+    }
+
+    fn params(&self) -> Value {
+        json!({
+            "binary": self.binary,
+            "args": self.args,
+            "rate_per_sec": self.rate,
+            "duration_secs": self.duration.as_secs_f64(),
+            "argv_bytes": self.argv_bytes,
+            "env_bytes": self.env_bytes,
+        })
+    }
+
+    fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        log::info!(
+            "Launching {} {}/s for {:?}.",
+            self.binary.display(),
+            self.rate,
+            self.duration
+        );
+        let largest_accepted = match self.probe_limit {
+            true => {
+                let limit = self.probe_limit()?;
+                match limit {
+                    Some(bytes) => log::info!("exec accepts up to {bytes} bytes of argv padding."),
+                    None => log::warn!("exec accepted {PROBE_CEILING} bytes of argv padding."),
+                }
+                limit
+            }
+            false => None,
+        };
+        let mut churn = self.churn(cancel)?;
+        churn.launches.sort_by(f64::total_cmp);
+        churn.lifetimes.sort_by(f64::total_cmp);
+        let us = |sorted: &[f64], p| stats::percentile(sorted, p) / 1000.0;
+        let mut summary = json!({
+            "launches": churn.launches.len(),
+            "launches_per_sec": churn.launches.len() as f64 / self.duration.as_secs_f64(),
+            "failed": churn.failed,
+            "exec_p50_us": us(&churn.launches, 0.5),
+            "exec_p99_us": us(&churn.launches, 0.99),
+            "lifetime_p50_us": us(&churn.lifetimes, 0.5),
+            "lifetime_p99_us": us(&churn.lifetimes, 0.99),
+        });
+        if self.probe_limit {
+            summary["largest_accepted_argv_bytes"] = json!(largest_accepted);
+        }
+        if churn.failed > 0 {
+            log::warn!("{} launches failed.", churn.failed);
+        }
+        log::info!("Exec churn: {summary}.");
+        report::measure(self.name(), summary);
+        match cancel.is_cancelled() {
+            true => Err(StressError::Interrupted.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(binary: &str) -> Args {
+        Args {
+            binary: binary.into(),
+            args: vec![],
+            rate: 200.0,
+            duration: Duration::from_millis(100),
+            argv_bytes: 0,
+            env_bytes: 0,
+            probe_limit: false,
+        }
+    }
+
+    #[test]
+    fn times_every_launch() {
+        let churn = args("/bin/true").churn(&CancellationToken::new()).unwrap();
+        assert!(churn.launches.len() > 1);
+        assert_eq!(churn.launches.len(), churn.lifetimes.len());
+        assert_eq!(churn.failed, 0);
+        assert!(
+            churn
+                .launches
+                .iter()
+                .zip(&churn.lifetimes)
+                .all(|(l, t)| l <= t)
+        );
+    }
+
+    #[test]
+    fn pads_in_bounded_strings() {
+        let padding = Padding::new(PAD_STRING as u64 * 2 + 10, 100);
+        assert_eq!(padding.args.len(), 3);
+        assert_eq!(padding.args[2].len(), 10);
+        assert_eq!(padding.env, [("ITSMINE_PAD0".to_string(), "x".repeat(100))]);
+    }
+
+    #[test]
+    fn probes_the_e2big_limit() {
+        let args = args("/bin/true");
+        let limit = args.probe_limit().unwrap().unwrap();
+        assert!(limit >= 64 << 10);
+        assert!(args.accepts(limit).unwrap());
+        assert!(!args.accepts(limit + 2 * PROBE_RESOLUTION).unwrap());
+    }
+
+    #[test]
+    fn missing_binary_is_an_error() {
+        assert!(
+            args("/nonexistent/helper")
+                .churn(&CancellationToken::new())
+                .is_err()
+        );
+    }
+}
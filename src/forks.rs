@@ -0,0 +1,225 @@
+//! `itsmine fork-rate`: forks short-lived children at a fixed rate with a cap
+//! on how many are alive at once, so process limits such as `pids.max` can be
+//! exercised without a runaway fork bomb.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+use crate::caps::{self, Privilege};
+use crate::{CancellationToken, StressError, Stressor, parse, report, stats};
+
+#[derive(Clone, Debug, PartialEq, clap::Args)]
+pub struct Args {
+    /// Forks per second, e.g. 500/s or 2k/s
+    #[arg(long, value_name = "RATE", value_parser = parse::per_second, default_value = "100/s")]
+    pub rate: f64,
+    /// Children alive at once; forking pauses while this many are
+    #[arg(long, value_name = "N", default_value_t = 1000)]
+    pub max_live: u32,
+    /// How long each child lives before exiting, e.g. 100ms
+    #[arg(long, value_parser = parse::duration, default_value = "100ms")]
+    pub lifetime: Duration,
+    /// How long to run, e.g. 10s
+    #[arg(long, value_parser = parse::duration, default_value = "10s")]
+    pub duration: Duration,
+    /// Fork into a new PID namespace, so every child dies with the run
+    #[arg(long, default_value_t = false)]
+    pub pid_namespace: bool,
+}
+
+/// A child forked at `at`.
+struct Child {
+    pid: libc::pid_t,
+    at: Instant,
+}
+
+#[derive(Default)]
+struct Storm {
+    forks: u64,
+    /// Forks the kernel refused, e.g. at `pids.max`.
+    refused: u64,
+    /// Fork slots skipped because `--max-live` children were alive.
+    throttled: u64,
+    peak_live: usize,
+    /// Nanoseconds each successful fork took in the parent.
+    latencies: Vec<f64>,
+}
+
+/// Forks a child that lives `lifetime` and exits.
+fn fork_child(lifetime: &libc::timespec) -> libc::pid_t {
+    // SAFETY: the child only makes async-signal-safe calls before `_exit`.
+    unsafe {
+        let pid = libc::fork();
+        if pid == 0 {
+            libc::nanosleep(lifetime, std::ptr::null_mut());
+            libc::_exit(0);
+        }
+        pid
+    }
+}
+
+fn reap(pid: libc::pid_t, flags: libc::c_int) -> bool {
+    // SAFETY: plain syscall on our own child.
+    unsafe { libc::waitpid(pid, std::ptr::null_mut(), flags) == pid }
+}
+
+impl Args {
+    fn storm(&self, cancel: &CancellationToken) -> Storm {
+        let lifetime = libc::timespec {
+            tv_sec: self.lifetime.as_secs() as libc::time_t,
+            tv_nsec: self.lifetime.subsec_nanos() as libc::c_long,
+        };
+        let interval = Duration::from_secs_f64(1.0 / self.rate);
+        let mut storm = Storm::default();
+        let mut live: VecDeque<Child> = VecDeque::new();
+        let started = Instant::now();
+        let deadline = started + self.duration;
+        let mut next = started;
+        while !cancel.is_cancelled() && Instant::now() < deadline {
+            // Children exit in the order they were forked, give or take.
+            while live.front().is_some_and(|child| {
+                child.at.elapsed() >= self.lifetime && reap(child.pid, libc::WNOHANG)
+            }) {
+                live.pop_front();
+            }
+            if live.len() >= self.max_live as usize {
+                storm.throttled += 1;
+            } else {
+                let at = Instant::now();
+                match fork_child(&lifetime) {
+                    -1 => storm.refused += 1,
+                    pid => {
+                        storm.latencies.push(at.elapsed().as_nanos() as f64);
+                        storm.forks += 1;
+                        live.push_back(Child { pid, at });
+                        storm.peak_live = storm.peak_live.max(live.len());
+                    }
+                }
+            }
+            // Catch up after a stall, but not by bursting through a backlog.
+            next = (next + interval).max(Instant::now() - interval);
+            cancel.sleep_until(next.min(deadline));
+        }
+        for child in live {
+            // SAFETY: plain syscalls on our own child.
+            unsafe { libc::kill(child.pid, libc::SIGKILL) };
+            reap(child.pid, 0);
+        }
+        storm
+    }
+}
+
+/// Moves the calling thread's future children into a new PID namespace and
+/// forks its init, which must outlive them. Killing it kills them all.
+fn enter_pid_namespace() -> std::io::Result<libc::pid_t> {
+    // SAFETY: affects only the calling thread's later children.
+    if unsafe { libc::unshare(libc::CLONE_NEWPID) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: the child only makes async-signal-safe calls.
+    match unsafe { libc::fork() } {
+        -1 => Err(std::io::Error::last_os_error()),
+        0 => loop {
+            // SAFETY: as above.
+            unsafe { libc::pause() };
+        },
+        init => Ok(init),
+    }
+}
+
+impl Stressor for Args {
+    fn name(&self) -> &'static str {
+        "fork-rate"
+    }
+
+    fn params(&self) -> Value {
+        json!({
+            "rate_per_sec": self.rate,
+            "max_live": self.max_live,
+            "lifetime_secs": self.lifetime.as_secs_f64(),
+            "duration_secs": self.duration.as_secs_f64(),
+            "pid_namespace": self.pid_namespace,
+        })
+    }
+
+    fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        if self.max_live == 0 {
+            return Err(
+                StressError::InvalidInput("--max-live must be greater than 0".to_string()).into(),
+            );
+        }
+        log::info!(
+            "Forking {}/s with at most {} alive for {:?}.",
+            self.rate,
+            self.max_live,
+            self.duration
+        );
+        // A thread of its own, since entering a PID namespace is for good.
+        let storm = std::thread::scope(|s| {
+            s.spawn(|| {
+                let namespace = self.pid_namespace
+                    && caps::require("fork-rate --pid-namespace", Privilege::SysAdmin);
+                let init = match namespace {
+                    true => Some(enter_pid_namespace().map_err(|e| {
+                        anyhow::anyhow!("Failed to enter a new PID namespace: {e}")
+                    })?),
+                    false => None,
+                };
+                let storm = self.storm(cancel);
+                if let Some(init) = init {
+                    // SAFETY: plain syscalls on our own child.
+                    unsafe { libc::kill(init, libc::SIGKILL) };
+                    reap(init, 0);
+                }
+                Ok::<_, anyhow::Error>(storm)
+            })
+            .join()
+            .map_err(|payload| StressError::WorkerPanicked(crate::panic_message(&payload)))
+        })??;
+
+        let mut latencies = storm.latencies;
+        latencies.sort_by(f64::total_cmp);
+        let us = |p| stats::percentile(&latencies, p) / 1000.0;
+        let summary = json!({
+            "forks": storm.forks,
+            "forks_per_sec": storm.forks as f64 / self.duration.as_secs_f64(),
+            "refused": storm.refused,
+            "throttled": storm.throttled,
+            "peak_live": storm.peak_live,
+            "fork_p50_us": us(0.5),
+            "fork_p99_us": us(0.99),
+        });
+        if storm.refused > 0 {
+            log::warn!("The kernel refused {} forks.", storm.refused);
+        }
+        log::info!("Fork rate: {summary}.");
+        report::measure(self.name(), summary);
+        match cancel.is_cancelled() {
+            true => Err(StressError::Interrupted.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn never_exceeds_max_live() {
+        let args = Args {
+            rate: 2000.0,
+            max_live: 4,
+            lifetime: Duration::from_millis(50),
+            duration: Duration::from_millis(200),
+            pid_namespace: false,
+        };
+        let storm = args.storm(&CancellationToken::new());
+        assert!(storm.forks >= 4);
+        assert_eq!(storm.peak_live, 4);
+        assert!(storm.throttled > 0);
+        assert_eq!(storm.latencies.len() as u64, storm.forks);
+    }
+}
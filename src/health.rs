@@ -0,0 +1,64 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static READY: AtomicBool = AtomicBool::new(false);
+
+/// Marks the process as ready (stressors running) or not.
+pub fn set_ready(ready: bool) {
+    READY.store(ready, Ordering::Relaxed);
+}
+
+/// Serves `/healthz` (always OK while the process is alive) and `/readyz`
+/// (OK while a run is in progress and no shutdown was requested) on `addr`.
+pub fn serve(addr: &str) -> Result<(), anyhow::Error> {
+    let listener = TcpListener::bind(addr)
+        .map_err(|e| anyhow::anyhow!("Failed to bind health endpoint {addr}: {e}"))?;
+    log::info!(
+        "Serving /healthz and /readyz on {}.",
+        listener.local_addr()?
+    );
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            if let Err(e) = respond(stream) {
+                log::debug!("Health request failed: {e}");
+            }
+        }
+    });
+    Ok(())
+}
+
+fn respond(mut stream: TcpStream) -> std::io::Result<()> {
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+    let ready = READY.load(Ordering::Relaxed) && !crate::shutdown::requested();
+    let (status, body) = status_for(path, ready);
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+fn status_for(path: &str, ready: bool) -> (&'static str, &'static str) {
+    match path {
+        "/healthz" => ("200 OK", "ok\n"),
+        "/readyz" if ready => ("200 OK", "ready\n"),
+        "/readyz" => ("503 Service Unavailable", "not ready\n"),
+        _ => ("404 Not Found", "not found\n"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_for_paths() {
+        assert_eq!(status_for("/healthz", false).0, "200 OK");
+        assert_eq!(status_for("/readyz", true).0, "200 OK");
+        assert_eq!(status_for("/readyz", false).0, "503 Service Unavailable");
+        assert_eq!(status_for("/metrics", true).0, "404 Not Found");
+    }
+}
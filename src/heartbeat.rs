@@ -0,0 +1,135 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde_json::{Value, json};
+
+/// Workload iterations completed so far, so a beat shows whether the run is
+/// making progress and not merely alive.
+static ITERATIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Where heartbeats go.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Target {
+    /// A line on standard error (`-`).
+    Stderr,
+    /// Overwrites the file with the latest beat, refreshing its mtime.
+    File(PathBuf),
+    /// POSTs each beat as JSON.
+    Url(String),
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" => Err("empty heartbeat target".to_string()),
+            "-" => Ok(Target::Stderr),
+            url if url.starts_with("http://") || url.starts_with("https://") => {
+                Ok(Target::Url(url.to_string()))
+            }
+            path => Ok(Target::File(PathBuf::from(path))),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Stderr => f.write_str("-"),
+            Target::File(path) => write!(f, "{}", path.display()),
+            Target::Url(url) => f.write_str(url),
+        }
+    }
+}
+
+/// Counts one completed workload iteration.
+pub fn tick() {
+    ITERATIONS.fetch_add(1, Ordering::Relaxed);
+}
+
+fn beat(run: &str, started: Instant) -> Value {
+    json!({
+        "heartbeat": SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64(),
+        "pid": std::process::id(),
+        "run": run,
+        "uptime_secs": started.elapsed().as_secs(),
+        "iterations": ITERATIONS.load(Ordering::Relaxed),
+    })
+}
+
+fn send(target: &Target, beat: &Value) -> Result<(), anyhow::Error> {
+    match target {
+        Target::Stderr => eprintln!("{beat}"),
+        Target::File(path) => std::fs::write(path, format!("{beat}\n"))?,
+        Target::Url(url) => crate::http::post(url, "application/json", beat.to_string())?,
+    }
+    Ok(())
+}
+
+/// Signals liveness to every target each `interval` for as long as the
+/// process runs, so an external watchdog can tell a hung soak from a busy
+/// one.
+pub fn start(interval: Duration, targets: Vec<Target>, run: &str) {
+    let run = run.to_string();
+    let started = Instant::now();
+    log::info!(
+        "Sending a heartbeat every {:.1}s to {}.",
+        interval.as_secs_f64(),
+        targets
+            .iter()
+            .map(Target::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    std::thread::spawn(move || {
+        loop {
+            let beat = beat(&run, started);
+            for target in &targets {
+                if let Err(e) = send(target, &beat) {
+                    log::warn!("Heartbeat to {target} failed: {e}");
+                }
+            }
+            std::thread::sleep(interval);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_targets() {
+        assert_eq!("-".parse(), Ok(Target::Stderr));
+        assert_eq!(
+            "https://hc.example/ping".parse(),
+            Ok(Target::Url("https://hc.example/ping".to_string()))
+        );
+        assert_eq!(
+            "/run/itsmine.alive".parse(),
+            Ok(Target::File(PathBuf::from("/run/itsmine.alive")))
+        );
+        assert!("".parse::<Target>().is_err());
+    }
+
+    #[test]
+    fn file_target_holds_the_latest_beat() {
+        let path = std::env::temp_dir().join(format!("itsmine-heartbeat-{}", std::process::id()));
+        let target = Target::File(path.clone());
+        tick();
+        send(&target, &beat("thread", Instant::now())).unwrap();
+        let written: Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written["run"], "thread");
+        assert_eq!(written["pid"], std::process::id());
+        assert!(written["iterations"].as_u64().unwrap() >= 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
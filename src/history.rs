@@ -0,0 +1,620 @@
+//! Run history in a local SQLite database: every recorded run's report and
+//! summary metrics, for listing, comparing and trend analysis across runs.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::compare::{self, Comparison};
+use crate::report::Report;
+use crate::trend::{Better, Point};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS runs (
+        id INTEGER PRIMARY KEY,
+        started_at REAL NOT NULL,
+        stressor TEXT NOT NULL,
+        label TEXT,
+        status TEXT NOT NULL,
+        duration_secs REAL NOT NULL,
+        report TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS metrics (
+        run_id INTEGER NOT NULL REFERENCES runs(id) ON DELETE CASCADE,
+        name TEXT NOT NULL,
+        value REAL NOT NULL,
+        PRIMARY KEY (run_id, name)
+    );
+    CREATE INDEX IF NOT EXISTS metrics_by_name ON metrics(name, run_id);
+";
+
+/// `$XDG_DATA_HOME/itsmine/history.db`, falling back to `~/.local/share`.
+pub fn default_path() -> PathBuf {
+    let data = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .unwrap_or_else(std::env::temp_dir);
+    data.join("itsmine").join("history.db")
+}
+
+/// The summary metrics of a serialized report: its duration, retries,
+/// verification counts and every numeric measurement, keyed by dotted path
+/// (e.g. `priority-inversion.wait_p99_us`).
+pub fn metrics(report: &Value) -> BTreeMap<String, f64> {
+    let mut metrics = BTreeMap::new();
+    for key in ["duration_secs", "retries"] {
+        if let Some(value) = report[key].as_f64() {
+            metrics.insert(key.to_string(), value);
+        }
+    }
+    flatten("verification", &report["verification"], &mut metrics);
+    if let Value::Object(measurements) = &report["measurements"] {
+        for (key, value) in measurements {
+            flatten(key, value, &mut metrics);
+        }
+    }
+    metrics
+}
+
+fn flatten(prefix: &str, value: &Value, into: &mut BTreeMap<String, f64>) {
+    match value {
+        Value::Number(n) => {
+            if let Some(n) = n.as_f64() {
+                into.insert(prefix.to_string(), n);
+            }
+        }
+        Value::Object(fields) => {
+            for (key, value) in fields {
+                flatten(&format!("{prefix}.{key}"), value, into);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// A group of recorded runs: `7`, `3,5,8`, `10..20` or a tag such as
+/// `build=1234`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Selection {
+    Ids(Vec<i64>),
+    Range(i64, i64),
+    Tag(String, String),
+}
+
+impl FromStr for Selection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains('=') {
+            let (key, value) = crate::parse::tag(s)?;
+            return Ok(Selection::Tag(key, value));
+        }
+        let id = |id: &str| {
+            id.trim()
+                .parse::<i64>()
+                .map_err(|_| format!("invalid run id '{id}' in '{s}'"))
+        };
+        if let Some((first, last)) = s.split_once("..") {
+            let (first, last) = (id(first)?, id(last)?);
+            if first > last {
+                return Err(format!("run range '{s}' is reversed"));
+            }
+            return Ok(Selection::Range(first, last));
+        }
+        Ok(Selection::Ids(
+            s.split(',').map(id).collect::<Result<_, _>>()?,
+        ))
+    }
+}
+
+/// One row of `history list`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Run {
+    pub id: i64,
+    pub started_at: f64,
+    /// `started_at` as UTC `YYYY-MM-DD HH:MM:SS`.
+    pub started: String,
+    pub stressor: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub status: String,
+    pub duration_secs: f64,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub tags: Map<String, Value>,
+}
+
+/// One metric of two runs side by side.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct MetricDelta {
+    pub name: String,
+    pub a: Option<f64>,
+    pub b: Option<f64>,
+}
+
+impl MetricDelta {
+    /// Relative change from `a` to `b` in percent, when both exist and `a`
+    /// is non-zero.
+    pub fn change_percent(&self) -> Option<f64> {
+        match (self.a, self.b) {
+            (Some(a), Some(b)) if a != 0.0 => Some((b - a) / a.abs() * 100.0),
+            _ => None,
+        }
+    }
+}
+
+/// A parameter that differs between two runs.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ParameterDelta {
+    pub name: String,
+    pub a: Option<Value>,
+    pub b: Option<Value>,
+}
+
+/// What changed between two recorded runs.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Diff {
+    pub a: Run,
+    pub b: Run,
+    pub parameters: Vec<ParameterDelta>,
+    pub metrics: Vec<MetricDelta>,
+}
+
+pub struct Store(Connection);
+
+impl Store {
+    /// Opens the database at `path`, creating it and its directory if needed.
+    pub fn open(path: &Path) -> Result<Self, anyhow::Error> {
+        if let Some(dir) = path.parent()
+            && !dir.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| anyhow::anyhow!("Failed to create {}: {e}", dir.display()))?;
+        }
+        let connection = Connection::open(path)
+            .map_err(|e| anyhow::anyhow!("Failed to open {}: {e}", path.display()))?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(Store(connection))
+    }
+
+    /// Stores `report` and its summary metrics, returning the run's id.
+    pub fn record(&mut self, report: &Report) -> Result<i64, anyhow::Error> {
+        let value = serde_json::to_value(report)?;
+        let status = value["status"].as_str().unwrap_or_default().to_string();
+        let tx = self.0.transaction()?;
+        tx.execute(
+            "INSERT INTO runs (started_at, stressor, label, status, duration_secs, report)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                report.started_at,
+                report.stressor,
+                report.label,
+                status,
+                report.duration_secs,
+                value.to_string()
+            ],
+        )?;
+        let id = tx.last_insert_rowid();
+        for (name, metric) in metrics(&value) {
+            tx.execute(
+                "INSERT INTO metrics (run_id, name, value) VALUES (?1, ?2, ?3)",
+                params![id, name, metric],
+            )?;
+        }
+        tx.commit()?;
+        Ok(id)
+    }
+
+    /// The `last` most recent runs, oldest first.
+    pub fn list(&self, last: usize) -> Result<Vec<Run>, anyhow::Error> {
+        let mut statement = self.0.prepare(
+            "SELECT * FROM (
+                 SELECT id, started_at, datetime(started_at, 'unixepoch'), stressor, label,
+                        status, duration_secs, report
+                 FROM runs ORDER BY id DESC LIMIT ?1
+             ) ORDER BY id",
+        )?;
+        let runs = statement
+            .query_map(params![last as i64], run_from_row)?
+            .collect::<Result<_, _>>()?;
+        Ok(runs)
+    }
+
+    fn run(&self, id: i64) -> Result<Run, anyhow::Error> {
+        self.0
+            .query_row(
+                "SELECT id, started_at, datetime(started_at, 'unixepoch'), stressor, label,
+                        status, duration_secs, report
+                 FROM runs WHERE id = ?1",
+                params![id],
+                run_from_row,
+            )
+            .optional()?
+            .ok_or_else(|| anyhow::anyhow!("No run with id {id} in the history"))
+    }
+
+    /// The full report stored for run `id`.
+    pub fn show(&self, id: i64) -> Result<Value, anyhow::Error> {
+        let report: Option<String> = self
+            .0
+            .query_row(
+                "SELECT report FROM runs WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let report = report.ok_or_else(|| anyhow::anyhow!("No run with id {id} in the history"))?;
+        Ok(serde_json::from_str(&report)?)
+    }
+
+    /// The summary metrics stored for run `id`.
+    pub fn metrics(&self, id: i64) -> Result<BTreeMap<String, f64>, anyhow::Error> {
+        let mut statement = self
+            .0
+            .prepare("SELECT name, value FROM metrics WHERE run_id = ?1")?;
+        let metrics = statement
+            .query_map(params![id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+        Ok(metrics)
+    }
+
+    /// The `last` most recent values of `metric`, oldest first, optionally
+    /// only from runs of `stressor`.
+    pub fn series(
+        &self,
+        metric: &str,
+        last: usize,
+        stressor: Option<&str>,
+    ) -> Result<Vec<Point>, anyhow::Error> {
+        let mut statement = self.0.prepare(
+            "SELECT * FROM (
+                 SELECT runs.id, runs.started_at, metrics.value
+                 FROM metrics JOIN runs ON runs.id = metrics.run_id
+                 WHERE metrics.name = ?1 AND (?2 IS NULL OR runs.stressor = ?2)
+                 ORDER BY runs.id DESC LIMIT ?3
+             ) ORDER BY id",
+        )?;
+        let points = statement
+            .query_map(params![metric, stressor, last as i64], |row| {
+                Ok(Point {
+                    run: row.get(0)?,
+                    started_at: row.get(1)?,
+                    value: row.get(2)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(points)
+    }
+
+    /// Ids of the runs `selection` picks, oldest first.
+    pub fn select(&self, selection: &Selection) -> Result<Vec<i64>, anyhow::Error> {
+        let ids = match selection {
+            Selection::Ids(ids) => {
+                for &id in ids {
+                    self.run(id)?;
+                }
+                ids.clone()
+            }
+            Selection::Range(first, last) => self
+                .0
+                .prepare("SELECT id FROM runs WHERE id BETWEEN ?1 AND ?2 ORDER BY id")?
+                .query_map(params![first, last], |row| row.get(0))?
+                .collect::<Result<_, _>>()?,
+            Selection::Tag(key, value) => self
+                .0
+                .prepare(
+                    "SELECT id FROM runs WHERE json_extract(report, '$.tags.' || json_quote(?1)) = ?2
+                     ORDER BY id",
+                )?
+                .query_map(params![key, value], |row| row.get(0))?
+                .collect::<Result<_, _>>()?,
+        };
+        Ok(ids)
+    }
+
+    /// Compares every summary metric of the runs in `a` (the baseline) with
+    /// those in `b`, holding them all together to significance `alpha`.
+    pub fn compare(
+        &self,
+        a: &Selection,
+        b: &Selection,
+        alpha: f64,
+    ) -> Result<Vec<Comparison>, anyhow::Error> {
+        if !(alpha > 0.0 && alpha < 1.0) {
+            return Err(anyhow::anyhow!(
+                "Significance level {alpha} must be between 0 and 1"
+            ));
+        }
+        let samples = |selection| -> Result<BTreeMap<String, Vec<f64>>, anyhow::Error> {
+            let mut samples: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+            for id in self.select(selection)? {
+                for (name, value) in self.metrics(id)? {
+                    samples.entry(name).or_default().push(value);
+                }
+            }
+            Ok(samples)
+        };
+        let (a, b) = (samples(a)?, samples(b)?);
+        let names: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+        let mut comparisons: Vec<Comparison> = names
+            .into_iter()
+            .map(|name| {
+                let (a, b) = (
+                    a.get(name).map_or(&[][..], Vec::as_slice),
+                    b.get(name).map_or(&[][..], Vec::as_slice),
+                );
+                compare::compare(name, a, b, Better::guess(name), alpha)
+            })
+            .collect();
+        compare::correct(&mut comparisons, alpha);
+        Ok(comparisons)
+    }
+
+    /// Compares runs `a` and `b`: parameters that differ and every summary
+    /// metric either of them has.
+    pub fn diff(&self, a: i64, b: i64) -> Result<Diff, anyhow::Error> {
+        let (run_a, run_b) = (self.run(a)?, self.run(b)?);
+        let (params_a, params_b) = (
+            self.show(a)?["parameters"].take(),
+            self.show(b)?["parameters"].take(),
+        );
+        let empty = Map::new();
+        let (params_a, params_b) = (
+            params_a.as_object().unwrap_or(&empty),
+            params_b.as_object().unwrap_or(&empty),
+        );
+        let names: BTreeSet<&String> = params_a.keys().chain(params_b.keys()).collect();
+        let parameters = names
+            .into_iter()
+            .filter(|name| params_a.get(*name) != params_b.get(*name))
+            .map(|name| ParameterDelta {
+                name: name.clone(),
+                a: params_a.get(name).cloned(),
+                b: params_b.get(name).cloned(),
+            })
+            .collect();
+
+        let (metrics_a, metrics_b) = (self.metrics(a)?, self.metrics(b)?);
+        let names: BTreeSet<&String> = metrics_a.keys().chain(metrics_b.keys()).collect();
+        let metrics = names
+            .into_iter()
+            .map(|name| MetricDelta {
+                name: name.clone(),
+                a: metrics_a.get(name).copied(),
+                b: metrics_b.get(name).copied(),
+            })
+            .collect();
+        Ok(Diff {
+            a: run_a,
+            b: run_b,
+            parameters,
+            metrics,
+        })
+    }
+}
+
+fn run_from_row(row: &rusqlite::Row) -> rusqlite::Result<Run> {
+    let report: String = row.get(7)?;
+    let tags = serde_json::from_str::<Value>(&report)
+        .ok()
+        .and_then(|mut report| match report["tags"].take() {
+            Value::Object(tags) => Some(tags),
+            _ => None,
+        })
+        .unwrap_or_default();
+    Ok(Run {
+        id: row.get(0)?,
+        started_at: row.get(1)?,
+        started: row.get(2)?,
+        stressor: row.get(3)?,
+        label: row.get(4)?,
+        status: row.get(5)?,
+        duration_secs: row.get(6)?,
+        tags,
+    })
+}
+
+/// Renders `history list` as a table.
+pub fn render(runs: &[Run]) -> String {
+    let mut out = format!(
+        "{:>5}  {:<19}  {:<18}  {:<8}  {:>9}  Tags\n",
+        "ID", "Started (UTC)", "Stressor", "Status", "Duration"
+    );
+    for run in runs {
+        let mut tags: Vec<String> = run
+            .tags
+            .iter()
+            .map(|(key, value)| format!("{key}={}", value.as_str().unwrap_or_default()))
+            .collect();
+        if let Some(label) = &run.label {
+            tags.insert(0, format!("label={label}"));
+        }
+        out.push_str(&format!(
+            "{:>5}  {:<19}  {:<18}  {:<8}  {:>8.1}s  {}\n",
+            run.id,
+            run.started,
+            run.stressor,
+            run.status,
+            run.duration_secs,
+            tags.join(" ")
+        ));
+    }
+    out
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let missing = || "-".to_string();
+        writeln!(
+            f,
+            "#{} {} ({}, {})  vs  #{} {} ({}, {})",
+            self.a.id,
+            self.a.stressor,
+            self.a.started,
+            self.a.status,
+            self.b.id,
+            self.b.stressor,
+            self.b.started,
+            self.b.status
+        )?;
+        if !self.parameters.is_empty() {
+            writeln!(f, "Parameters:")?;
+            for parameter in &self.parameters {
+                writeln!(
+                    f,
+                    "  {}: {} -> {}",
+                    parameter.name,
+                    parameter.a.as_ref().map_or_else(missing, Value::to_string),
+                    parameter.b.as_ref().map_or_else(missing, Value::to_string)
+                )?;
+            }
+        }
+        writeln!(f, "Metrics:")?;
+        for metric in &self.metrics {
+            let value = |v: Option<f64>| v.map_or_else(missing, |v| format!("{v:.3}"));
+            let change = metric
+                .change_percent()
+                .map_or_else(missing, |change| format!("{change:+.1}%"));
+            writeln!(
+                f,
+                "  {:<36} {:>14} {:>14} {:>9}",
+                metric.name,
+                value(metric.a),
+                value(metric.b),
+                change
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::SystemTime;
+
+    fn report(threads: u32, wait_p99_us: u64) -> Report {
+        let mut report = Report::new(
+            "thread",
+            json!({ "threads": threads }),
+            SystemTime::now(),
+            &Ok(()),
+        );
+        report.measurements.insert(
+            "priority-inversion".to_string(),
+            json!({ "wait_p99_us": wait_p99_us, "realtime": false }),
+        );
+        report
+    }
+
+    #[test]
+    fn parses_selections() {
+        assert_eq!("7".parse(), Ok(Selection::Ids(vec![7])));
+        assert_eq!("3,5,8".parse(), Ok(Selection::Ids(vec![3, 5, 8])));
+        assert_eq!("10..20".parse(), Ok(Selection::Range(10, 20)));
+        assert_eq!(
+            "build=1234".parse(),
+            Ok(Selection::Tag("build".to_string(), "1234".to_string()))
+        );
+        assert!("20..10".parse::<Selection>().is_err());
+        assert!("seven".parse::<Selection>().is_err());
+    }
+
+    #[test]
+    fn flattens_numeric_measurements() {
+        let value = serde_json::to_value(report(2, 40)).unwrap();
+        let metrics = metrics(&value);
+        assert_eq!(metrics["priority-inversion.wait_p99_us"], 40.0);
+        assert_eq!(metrics["retries"], 0.0);
+        assert!(metrics.contains_key("duration_secs"));
+        assert!(!metrics.contains_key("priority-inversion.realtime"));
+    }
+
+    #[test]
+    fn records_lists_and_diffs_runs() {
+        let path = std::env::temp_dir().join(format!("itsmine-history-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut store = Store::open(&path).unwrap();
+        let a = store.record(&report(2, 40)).unwrap();
+        let b = store.record(&report(4, 50)).unwrap();
+
+        let runs = store.list(10).unwrap();
+        assert_eq!(runs.iter().map(|run| run.id).collect::<Vec<_>>(), [a, b]);
+        assert_eq!(runs[0].status, "ok");
+        assert_eq!(store.list(1).unwrap()[0].id, b);
+        assert_eq!(store.show(a).unwrap()["parameters"]["threads"], 2);
+        assert!(store.show(99).is_err());
+
+        let diff = store.diff(a, b).unwrap();
+        assert_eq!(
+            diff.parameters,
+            [ParameterDelta {
+                name: "threads".to_string(),
+                a: Some(json!(2)),
+                b: Some(json!(4)),
+            }]
+        );
+        let wait = diff
+            .metrics
+            .iter()
+            .find(|metric| metric.name == "priority-inversion.wait_p99_us")
+            .unwrap();
+        assert_eq!(wait.change_percent(), Some(25.0));
+        assert!(diff.to_string().contains("+25.0%"));
+
+        let series = store
+            .series("priority-inversion.wait_p99_us", 30, Some("thread"))
+            .unwrap();
+        assert_eq!(
+            series.iter().map(|point| point.value).collect::<Vec<_>>(),
+            [40.0, 50.0]
+        );
+        assert!(
+            store
+                .series("retries", 30, Some("memory"))
+                .unwrap()
+                .is_empty()
+        );
+
+        for wait in [41, 39, 40, 42] {
+            store.record(&report(2, wait)).unwrap();
+        }
+        let mut tagged = report(2, 80);
+        tagged.tags.insert("build".to_string(), json!("2"));
+        let tagged = [
+            store.record(&tagged).unwrap(),
+            store.record(&tagged).unwrap(),
+        ];
+        assert_eq!(
+            store
+                .select(&Selection::Tag("build".to_string(), "2".to_string()))
+                .unwrap(),
+            tagged
+        );
+        assert!(store.select(&Selection::Ids(vec![99])).is_err());
+        let comparisons = store
+            .compare(&"3..6".parse().unwrap(), &"build=2".parse().unwrap(), 0.05)
+            .unwrap();
+        let wait = comparisons
+            .iter()
+            .find(|comparison| comparison.metric == "priority-inversion.wait_p99_us")
+            .unwrap();
+        assert_eq!((wait.a.samples, wait.b.samples), (4, 2));
+        assert!(wait.change_percent.unwrap() > 90.0);
+        assert!(
+            store
+                .compare(
+                    &"3..6".parse().unwrap(),
+                    &"build=2".parse().unwrap(),
+                    f64::NAN
+                )
+                .is_err()
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
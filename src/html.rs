@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+use crate::telemetry::Series;
+
+const CHART_WIDTH: f64 = 640.0;
+const CHART_HEIGHT: f64 = 180.0;
+
+/// Renders a self-contained HTML page with the run configuration and one
+/// chart per recorded metric.
+pub fn render(
+    title: &str,
+    config: &[(String, String)],
+    series: &BTreeMap<String, Series>,
+) -> String {
+    let mut page = String::new();
+    let _ = write!(
+        page,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+         <style>\n\
+         body {{ font-family: sans-serif; margin: 2em; color: #222; }}\n\
+         table {{ border-collapse: collapse; }}\n\
+         td, th {{ border: 1px solid #ccc; padding: 4px 8px; text-align: left; }}\n\
+         svg {{ background: #fafafa; border: 1px solid #ddd; }}\n\
+         polyline {{ fill: none; stroke: #1f77b4; stroke-width: 2; }}\n\
+         </style>\n</head>\n<body>\n<h1>{title}</h1>\n",
+        title = escape(title)
+    );
+
+    page.push_str("<h2>Configuration</h2>\n<table>\n");
+    for (key, value) in config {
+        let _ = writeln!(
+            page,
+            "<tr><th>{}</th><td>{}</td></tr>",
+            escape(key),
+            escape(value)
+        );
+    }
+    page.push_str("</table>\n<h2>Metrics</h2>\n");
+
+    if series.is_empty() {
+        page.push_str("<p>No metrics were recorded.</p>\n");
+    }
+    for (name, points) in series {
+        page.push_str(&chart(name, points));
+    }
+
+    page.push_str("</body>\n</html>\n");
+    page
+}
+
+/// Converts a monotonically increasing counter into a per-second rate.
+pub fn rate(points: &Series) -> Series {
+    points
+        .windows(2)
+        .filter_map(|w| {
+            let elapsed = (w[1].0 - w[0].0).as_secs_f64();
+            (elapsed > 0.0).then(|| {
+                let delta = w[1].1.saturating_sub(w[0].1) as f64;
+                (w[1].0, (delta / elapsed) as u64)
+            })
+        })
+        .collect()
+}
+
+fn chart(name: &str, points: &Series) -> String {
+    let end = points.last().map(|p| p.0).unwrap_or(Duration::ZERO);
+    let max = points.iter().map(|p| p.1).max().unwrap_or(0);
+
+    let coords: Vec<String> = points
+        .iter()
+        .map(|&(at, value)| {
+            let x = if end.is_zero() {
+                0.0
+            } else {
+                at.as_secs_f64() / end.as_secs_f64() * CHART_WIDTH
+            };
+            let y = if max == 0 {
+                CHART_HEIGHT
+            } else {
+                CHART_HEIGHT - value as f64 / max as f64 * CHART_HEIGHT
+            };
+            format!("{x:.1},{y:.1}")
+        })
+        .collect();
+
+    format!(
+        "<h3>{name}</h3>\n<p>max {max}, {count} samples over {secs:.1}s</p>\n\
+         <svg width=\"{CHART_WIDTH}\" height=\"{CHART_HEIGHT}\" viewBox=\"0 0 {CHART_WIDTH} {CHART_HEIGHT}\">\
+         <polyline points=\"{points}\"/></svg>\n",
+        name = escape(name),
+        count = points.len(),
+        secs = end.as_secs_f64(),
+        points = coords.join(" ")
+    )
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(s: u64) -> Duration {
+        Duration::from_secs(s)
+    }
+
+    #[test]
+    fn rate_differentiates_counter() {
+        let points = vec![(secs(0), 0), (secs(1), 10), (secs(3), 30)];
+        assert_eq!(rate(&points), vec![(secs(1), 10), (secs(3), 10)]);
+    }
+
+    #[test]
+    fn render_escapes_configuration() {
+        let config = vec![("arg".to_string(), "<script>".to_string())];
+        let page = render("run", &config, &BTreeMap::new());
+        assert!(page.contains("&lt;script&gt;"));
+        assert!(!page.contains("<script>"));
+    }
+
+    #[test]
+    fn render_draws_one_chart_per_metric() {
+        let mut series = BTreeMap::new();
+        series.insert("a".to_string(), vec![(secs(0), 1), (secs(1), 2)]);
+        series.insert("b".to_string(), vec![(secs(0), 0)]);
+        let page = render("run", &[], &series);
+        assert_eq!(page.matches("<polyline").count(), 2);
+        assert!(page.contains("points=\"0.0,90.0 640.0,0.0\""));
+    }
+}
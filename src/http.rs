@@ -0,0 +1,16 @@
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// POSTs `body` to `url`, failing on transport errors and non-2xx responses.
+pub fn post(url: &str, content_type: &str, body: String) -> Result<(), anyhow::Error> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(TIMEOUT))
+        .build()
+        .into();
+    agent
+        .post(url)
+        .header("Content-Type", content_type)
+        .send(body)?;
+    Ok(())
+}
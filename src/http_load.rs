@@ -0,0 +1,953 @@
+//! `itsmine http`: sends requests at a URL at a fixed rate from several
+//! connections, over HTTP/1.1 on TCP or, with `--http3`, HTTP/3 on QUIC,
+//! and reports status codes with handshake and request latency apart, so a
+//! proxy can be compared under either transport from the same tool.
+
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, ClientConnection, DigitallySignedStruct, SignatureScheme, StreamOwned};
+use serde_json::{Value, json};
+
+use crate::chaos::{self, Rng};
+use crate::stats::{self, Samples};
+use crate::throttle::{Limits, Throttle};
+use crate::{CancellationToken, StressError, Stressor, parse, report};
+
+/// Longest a response's headers may be.
+const MAX_HEAD: usize = 64 << 10;
+
+/// Where requests go: `http://` or `https://`, a host, a port and a path.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Url {
+    pub tls: bool,
+    /// The host name or address, without an IPv6 address's brackets.
+    pub host: String,
+    pub port: u16,
+    /// The path and query, starting with `/`.
+    pub path: String,
+}
+
+impl FromStr for Url {
+    type Err = String;
+
+    /// Parses `http://host[:port][/path]` or the same with `https://`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid URL '{s}' (e.g. https://proxy.lan:8443/health)");
+        let (tls, rest) = match s.split_once("://") {
+            Some(("http", rest)) => (false, rest),
+            Some(("https", rest)) => (true, rest),
+            _ => return Err(invalid()),
+        };
+        let (authority, path) = match rest.find(['/', '?']) {
+            Some(at) if rest[at..].starts_with('/') => (&rest[..at], rest[at..].to_string()),
+            Some(at) => (&rest[..at], format!("/{}", &rest[at..])),
+            None => (rest, "/".to_string()),
+        };
+        let (host, port) = match authority.strip_prefix('[') {
+            Some(bracketed) => {
+                let (host, port) = bracketed.split_once(']').ok_or_else(invalid)?;
+                (host, port.strip_prefix(':'))
+            }
+            None => match authority.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            },
+        };
+        if host.is_empty() || host.contains('@') {
+            return Err(invalid());
+        }
+        let port = match port {
+            Some(port) => port.parse().map_err(|_| invalid())?,
+            None if tls => 443,
+            None => 80,
+        };
+        Ok(Url {
+            tls,
+            host: host.to_string(),
+            port,
+            path,
+        })
+    }
+}
+
+impl fmt::Display for Url {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scheme = if self.tls { "https" } else { "http" };
+        match self.host.contains(':') {
+            true => write!(f, "{scheme}://[{}]:{}{}", self.host, self.port, self.path),
+            false => write!(f, "{scheme}://{}:{}{}", self.host, self.port, self.path),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, clap::Args)]
+pub struct Args {
+    /// URL to request, e.g. https://proxy.lan:8443/health
+    #[arg(value_name = "URL")]
+    pub url: Url,
+    /// Requests per second across all connections, e.g. 500/s
+    #[arg(long, value_name = "RATE", value_parser = parse::per_second, default_value = "100/s")]
+    pub rate: f64,
+    /// Connections taking turns to send, each from its own thread
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..), default_value_t = 4)]
+    pub connections: u32,
+    /// Open a fresh connection for every request, so each one pays for a
+    /// handshake
+    #[arg(long)]
+    pub new_connections: bool,
+    /// Accept any certificate, e.g. a test proxy's self-signed one
+    #[arg(long)]
+    pub insecure: bool,
+    /// Send HTTP/3 over QUIC instead of HTTP/1.1 over TCP; needs an https URL
+    #[cfg(feature = "http3")]
+    #[arg(long)]
+    pub http3: bool,
+    /// How long to wait for each handshake and each response, e.g. 5s
+    #[arg(long, value_parser = parse::duration, default_value = "5s")]
+    pub timeout: Duration,
+    /// How long to run, e.g. 10s
+    #[arg(long, value_parser = parse::duration, default_value = "10s")]
+    pub duration: Duration,
+}
+
+/// What came back, by status class, and what never did.
+struct Tally {
+    requests: u64,
+    /// Responses by the first digit of their status, 1xx to 5xx.
+    statuses: [u64; 5],
+    /// Connections that failed to open or broke off mid-request.
+    errors: u64,
+    timeouts: u64,
+    handshakes: Samples,
+    latencies: Samples,
+}
+
+impl Tally {
+    fn new() -> Self {
+        Tally {
+            requests: 0,
+            statuses: [0; 5],
+            errors: 0,
+            timeouts: 0,
+            handshakes: Samples::new(),
+            latencies: Samples::new(),
+        }
+    }
+
+    fn responses(&self) -> u64 {
+        self.statuses.iter().sum()
+    }
+
+    fn status(&mut self, status: u16) {
+        match status {
+            100..=599 => self.statuses[status as usize / 100 - 1] += 1,
+            _ => self.errors += 1,
+        }
+    }
+
+    fn failed(&mut self, e: &io::Error) {
+        match matches!(
+            e.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        ) {
+            true => self.timeouts += 1,
+            false => self.errors += 1,
+        }
+    }
+
+    fn add(&mut self, other: Tally) {
+        self.requests += other.requests;
+        for (total, count) in self.statuses.iter_mut().zip(other.statuses) {
+            *total += count;
+        }
+        self.errors += other.errors;
+        self.timeouts += other.timeouts;
+        self.handshakes.kept.extend(other.handshakes.kept);
+        self.handshakes.seen += other.handshakes.seen;
+        self.latencies.kept.extend(other.latencies.kept);
+        self.latencies.seen += other.latencies.seen;
+    }
+}
+
+/// Accepts any certificate for `--insecure`, while still checking that the
+/// server holds the key of the one it sent.
+#[derive(Debug)]
+struct AnyCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _: &CertificateDer<'_>,
+        _: &[CertificateDer<'_>],
+        _: &ServerName<'_>,
+        _: &[u8],
+        _: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// A connection for HTTP/1.1, in the clear or over TLS.
+enum Stream {
+    Plain(TcpStream),
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+}
+
+impl Read for Stream {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.read(buffer),
+            Stream::Tls(stream) => stream.read(buffer),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.write(buffer),
+            Stream::Tls(stream) => stream.write(buffer),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Plain(stream) => stream.flush(),
+            Stream::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+/// How the body of a response ends.
+enum Body {
+    Length(u64),
+    Chunked,
+    /// When the server closes the connection.
+    Close,
+}
+
+/// Reads one response from `stream`, draining its body. Returns the status
+/// and whether the connection may carry another request.
+fn response(stream: &mut Stream) -> io::Result<(u16, bool)> {
+    let mut head = vec![];
+    let mut chunk = [0; 8192];
+    let (status, body, keep_alive, parsed) = loop {
+        let read = stream.read(&mut chunk)?;
+        if read == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed before the response",
+            ));
+        }
+        head.extend_from_slice(&chunk[..read]);
+        let mut headers = [httparse::EMPTY_HEADER; 64];
+        let mut response = httparse::Response::new(&mut headers);
+        let parsed = match response.parse(&head).map_err(io::Error::other)? {
+            httparse::Status::Complete(parsed) => parsed,
+            httparse::Status::Partial if head.len() < MAX_HEAD => continue,
+            httparse::Status::Partial => return Err(io::Error::other("response head too long")),
+        };
+        let status = response.code.unwrap_or_default();
+        let header = |name: &str| {
+            response
+                .headers
+                .iter()
+                .find(|header| header.name.eq_ignore_ascii_case(name))
+                .map(|header| String::from_utf8_lossy(header.value).to_ascii_lowercase())
+        };
+        let body = match (
+            status,
+            header("transfer-encoding"),
+            header("content-length"),
+        ) {
+            (100..=199 | 204 | 304, _, _) => Body::Length(0),
+            (_, Some(encoding), _) if encoding.ends_with("chunked") => Body::Chunked,
+            (_, _, Some(length)) => Body::Length(
+                length
+                    .trim()
+                    .parse()
+                    .map_err(|_| io::Error::other("invalid Content-Length"))?,
+            ),
+            _ => Body::Close,
+        };
+        let keep_alive = response.version == Some(1)
+            && !header("connection").is_some_and(|value| value.contains("close"));
+        break (status, body, keep_alive, parsed);
+    };
+    let mut reader = BufReader::new(io::Cursor::new(head.split_off(parsed)).chain(stream));
+    let drain = |reader: &mut dyn BufRead, length: u64| match io::copy(
+        &mut reader.take(length),
+        &mut io::sink(),
+    )? == length
+    {
+        true => Ok(()),
+        false => Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "connection closed mid-body",
+        )),
+    };
+    match body {
+        Body::Length(length) => drain(&mut reader, length)?,
+        Body::Chunked => loop {
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            let size = line.split(';').next().unwrap_or_default().trim();
+            let size = u64::from_str_radix(size, 16)
+                .map_err(|_| io::Error::other("invalid chunk size"))?;
+            if size == 0 {
+                // Trailers, up to the blank line.
+                while reader.read_line(&mut line)? > 0 && !line.ends_with("\r\n\r\n") {
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    line.clear();
+                }
+                break;
+            }
+            drain(&mut reader, size + 2)?;
+        },
+        Body::Close => {
+            io::copy(&mut reader, &mut io::sink())?;
+        }
+    }
+    Ok((status, keep_alive && !matches!(body, Body::Close)))
+}
+
+impl Args {
+    fn http3(&self) -> bool {
+        #[cfg(feature = "http3")]
+        return self.http3;
+        #[cfg(not(feature = "http3"))]
+        false
+    }
+
+    fn protocol(&self) -> &'static str {
+        match self.http3() {
+            true => "http/3",
+            false => "http/1.1",
+        }
+    }
+
+    fn target(&self) -> Result<SocketAddr, anyhow::Error> {
+        (self.url.host.as_str(), self.url.port)
+            .to_socket_addrs()
+            .map_err(|e| anyhow::anyhow!("Failed to resolve {}: {e}", self.url.host))?
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("{} resolves to no address", self.url.host))
+    }
+
+    /// Client TLS settings offering `alpn`, checking certificates against
+    /// the bundled web roots unless `--insecure`.
+    fn tls_config(&self, alpn: &[u8]) -> Result<ClientConfig, anyhow::Error> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?;
+        let mut config = match self.insecure {
+            true => builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(AnyCertificate(provider)))
+                .with_no_client_auth(),
+            false => builder
+                .with_root_certificates(rustls::RootCertStore {
+                    roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+                })
+                .with_no_client_auth(),
+        };
+        config.alpn_protocols = vec![alpn.to_vec()];
+        Ok(config)
+    }
+
+    /// Opens a connection to `target`, with the TLS handshake done if
+    /// `tls` is given.
+    fn connect(&self, target: SocketAddr, tls: Option<&Arc<ClientConfig>>) -> io::Result<Stream> {
+        let stream = TcpStream::connect_timeout(&target, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        stream.set_nodelay(true)?;
+        let Some(tls) = tls else {
+            return Ok(Stream::Plain(stream));
+        };
+        let name = ServerName::try_from(self.url.host.clone()).map_err(io::Error::other)?;
+        let mut connection = ClientConnection::new(tls.clone(), name).map_err(io::Error::other)?;
+        let mut stream = stream;
+        while connection.is_handshaking() {
+            connection.complete_io(&mut stream)?;
+        }
+        Ok(Stream::Tls(Box::new(StreamOwned::new(connection, stream))))
+    }
+
+    /// Sends HTTP/1.1 requests over one connection at a time, opening
+    /// another whenever the last one closes, until `deadline`.
+    fn tcp_connection(
+        &self,
+        target: SocketAddr,
+        tls: Option<&Arc<ClientConfig>>,
+        throttle: &Throttle,
+        deadline: Instant,
+        cancel: &CancellationToken,
+    ) -> Tally {
+        let mut rng = Rng::new(chaos::random_seed());
+        let mut tally = Tally::new();
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: itsmine\r\n{}\r\n",
+            self.url.path,
+            self.host_header(),
+            if self.new_connections {
+                "Connection: close\r\n"
+            } else {
+                ""
+            }
+        );
+        let mut connection = None;
+        loop {
+            let at = throttle.reserve(0);
+            if at >= deadline || !cancel.sleep_until(at) {
+                break;
+            }
+            tally.requests += 1;
+            let stream = match &mut connection {
+                Some(stream) => stream,
+                None => {
+                    let started = Instant::now();
+                    match self.connect(target, tls) {
+                        Ok(stream) => {
+                            tally.handshakes.record(started.elapsed(), &mut rng);
+                            connection.insert(stream)
+                        }
+                        Err(e) => {
+                            log::debug!("Failed to connect to {}: {e}", self.url);
+                            tally.failed(&e);
+                            continue;
+                        }
+                    }
+                }
+            };
+            let started = Instant::now();
+            let exchanged = stream
+                .write_all(request.as_bytes())
+                .and_then(|()| stream.flush())
+                .and_then(|()| response(stream));
+            match exchanged {
+                Ok((status, keep_alive)) => {
+                    tally.latencies.record(started.elapsed(), &mut rng);
+                    tally.status(status);
+                    if !keep_alive || self.new_connections {
+                        connection = None;
+                    }
+                }
+                Err(e) => {
+                    log::debug!("Request to {} failed: {e}", self.url);
+                    tally.failed(&e);
+                    connection = None;
+                }
+            }
+        }
+        tally
+    }
+
+    /// The `Host` header: the host, with the port unless it is the
+    /// scheme's default.
+    fn host_header(&self) -> String {
+        let host = match self.url.host.contains(':') {
+            true => format!("[{}]", self.url.host),
+            false => self.url.host.clone(),
+        };
+        match (self.url.tls, self.url.port) {
+            (false, 80) | (true, 443) => host,
+            (_, port) => format!("{host}:{port}"),
+        }
+    }
+
+    /// Runs every connection on its own thread, sharing the rate, and
+    /// adds up what they saw.
+    fn load(&self, target: SocketAddr, cancel: &CancellationToken) -> Result<Tally, anyhow::Error> {
+        let throttle = Throttle::new(&Limits {
+            limit: None,
+            limit_iops: Some(self.rate),
+        });
+        let deadline = Instant::now() + self.duration;
+        #[cfg(feature = "http3")]
+        let quic = match self.http3 {
+            true => Some(quic::client_config(self.tls_config(b"h3")?)?),
+            false => None,
+        };
+        let tls = match self.url.tls && !self.http3() {
+            true => Some(Arc::new(self.tls_config(b"http/1.1")?)),
+            false => None,
+        };
+        std::thread::scope(|s| {
+            let workers: Vec<_> = (0..self.connections)
+                .map(|_| {
+                    s.spawn(|| {
+                        #[cfg(feature = "http3")]
+                        if let Some(config) = &quic {
+                            return quic::connection(
+                                self,
+                                target,
+                                config.clone(),
+                                &throttle,
+                                deadline,
+                                cancel,
+                            );
+                        }
+                        Ok::<_, anyhow::Error>(self.tcp_connection(
+                            target,
+                            tls.as_ref(),
+                            &throttle,
+                            deadline,
+                            cancel,
+                        ))
+                    })
+                })
+                .collect();
+            let mut total = Tally::new();
+            for worker in workers {
+                let tally = worker.join().map_err(|payload| {
+                    StressError::WorkerPanicked(crate::panic_message(&payload))
+                })??;
+                total.add(tally);
+            }
+            Ok(total)
+        })
+    }
+}
+
+/// HTTP/3 over QUIC, each connection driven by its own single-threaded
+/// runtime.
+#[cfg(feature = "http3")]
+mod quic {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use bytes::Bytes;
+    use h3::client::SendRequest;
+    use rustls::ClientConfig;
+
+    use super::{Args, Tally};
+    use crate::CancellationToken;
+    use crate::chaos::{self, Rng};
+    use crate::throttle::Throttle;
+
+    /// How often a wait for the next request checks for the end.
+    const POLL: Duration = Duration::from_millis(50);
+
+    type Requests = SendRequest<h3_quinn::OpenStreams, Bytes>;
+
+    /// QUIC settings speaking `tls`, which must offer TLS 1.3.
+    pub(super) fn client_config(tls: ClientConfig) -> Result<quinn::ClientConfig, anyhow::Error> {
+        let crypto = quinn::crypto::rustls::QuicClientConfig::try_from(tls)
+            .map_err(|e| anyhow::anyhow!("TLS settings unusable for QUIC: {e}"))?;
+        Ok(quinn::ClientConfig::new(Arc::new(crypto)))
+    }
+
+    /// Waits until `at`, letting the connection's tasks run meanwhile.
+    /// Returns false if cancelled first.
+    async fn sleep_until(at: Instant, cancel: &CancellationToken) -> bool {
+        loop {
+            if cancel.is_cancelled() {
+                return false;
+            }
+            let remaining = at.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return true;
+            }
+            tokio::time::sleep(remaining.min(POLL)).await;
+        }
+    }
+
+    /// Completes the QUIC handshake and starts HTTP/3 on top, leaving a
+    /// task to drive the connection.
+    async fn open(
+        endpoint: &quinn::Endpoint,
+        target: SocketAddr,
+        host: &str,
+    ) -> Result<(tokio::task::JoinHandle<()>, Requests), anyhow::Error> {
+        let connection = endpoint.connect(target, host)?.await?;
+        let (mut driver, requests) = h3::client::new(h3_quinn::Connection::new(connection)).await?;
+        let driver = tokio::spawn(async move {
+            std::future::poll_fn(|cx| driver.poll_close(cx)).await;
+        });
+        Ok((driver, requests))
+    }
+
+    /// Sends one request and reads its whole response.
+    async fn get(requests: &mut Requests, uri: &http::Uri) -> Result<u16, anyhow::Error> {
+        let mut stream = requests
+            .send_request(http::Request::get(uri.clone()).body(())?)
+            .await?;
+        stream.finish().await?;
+        let status = stream.recv_response().await?.status().as_u16();
+        while stream.recv_data().await?.is_some() {}
+        Ok(status)
+    }
+
+    /// Sends HTTP/3 requests over one QUIC connection at a time, opening
+    /// another whenever the last one breaks, until `deadline`.
+    pub(super) fn connection(
+        args: &Args,
+        target: SocketAddr,
+        config: quinn::ClientConfig,
+        throttle: &Throttle,
+        deadline: Instant,
+        cancel: &CancellationToken,
+    ) -> Result<Tally, anyhow::Error> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let local: SocketAddr = match target {
+            SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+            SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+        };
+        let uri: http::Uri = args.url.to_string().parse()?;
+        runtime.block_on(async {
+            let mut endpoint = quinn::Endpoint::client(local)?;
+            endpoint.set_default_client_config(config);
+            let mut rng = Rng::new(chaos::random_seed());
+            let mut tally = Tally::new();
+            let mut connection: Option<(tokio::task::JoinHandle<()>, Requests)> = None;
+            loop {
+                let at = throttle.reserve(0);
+                if at >= deadline || !sleep_until(at, cancel).await {
+                    break;
+                }
+                tally.requests += 1;
+                let requests = match &mut connection {
+                    Some((_, requests)) => requests,
+                    None => {
+                        let started = Instant::now();
+                        let opened = tokio::time::timeout(
+                            args.timeout,
+                            open(&endpoint, target, &args.url.host),
+                        )
+                        .await;
+                        match opened {
+                            Ok(Ok(opened)) => {
+                                tally.handshakes.record(started.elapsed(), &mut rng);
+                                &mut connection.insert(opened).1
+                            }
+                            Ok(Err(e)) => {
+                                log::debug!("Failed to connect to {}: {e:#}", args.url);
+                                tally.errors += 1;
+                                continue;
+                            }
+                            Err(_) => {
+                                tally.timeouts += 1;
+                                continue;
+                            }
+                        }
+                    }
+                };
+                let started = Instant::now();
+                match tokio::time::timeout(args.timeout, get(requests, &uri)).await {
+                    Ok(Ok(status)) => {
+                        tally.latencies.record(started.elapsed(), &mut rng);
+                        tally.status(status);
+                        if args.new_connections {
+                            close(connection.take());
+                        }
+                    }
+                    Ok(Err(e)) => {
+                        log::debug!("Request to {} failed: {e:#}", args.url);
+                        tally.errors += 1;
+                        close(connection.take());
+                    }
+                    Err(_) => {
+                        tally.timeouts += 1;
+                        close(connection.take());
+                    }
+                }
+            }
+            close(connection);
+            endpoint.close(0u32.into(), b"done");
+            let _ = tokio::time::timeout(args.timeout, endpoint.wait_idle()).await;
+            Ok(tally)
+        })
+    }
+
+    /// Drops a connection, which closes it once its driver stops.
+    fn close(connection: Option<(tokio::task::JoinHandle<()>, Requests)>) {
+        if let Some((driver, _)) = connection {
+            driver.abort();
+        }
+    }
+}
+
+impl Stressor for Args {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    fn params(&self) -> Value {
+        json!({
+            "url": self.url.to_string(),
+            "protocol": self.protocol(),
+            "rate_per_sec": self.rate,
+            "connections": self.connections,
+            "new_connections": self.new_connections,
+            "insecure": self.insecure,
+            "timeout_secs": self.timeout.as_secs_f64(),
+            "duration_secs": self.duration.as_secs_f64(),
+        })
+    }
+
+    fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        if self.http3() && !self.url.tls {
+            return Err(StressError::InvalidInput("--http3 needs an https URL".to_string()).into());
+        }
+        let target = self.target()?;
+        log::info!(
+            "Sending {} requests/s to {} ({target}) over {} from {} connections for {:?}.",
+            self.rate,
+            self.url,
+            self.protocol(),
+            self.connections,
+            self.duration
+        );
+        let started = Instant::now();
+        let mut tally = self.load(target, cancel)?;
+        let elapsed = started.elapsed();
+        tally.handshakes.kept.sort_by(f64::total_cmp);
+        tally.latencies.kept.sort_by(f64::total_cmp);
+        let ms = |samples: &Samples, p: f64| stats::percentile(&samples.kept, p) / 1e6;
+        let summary = json!({
+            "url": self.url.to_string(),
+            "protocol": self.protocol(),
+            "requests": tally.requests,
+            "requests_per_sec": tally.requests as f64 / elapsed.as_secs_f64(),
+            "responses": tally.responses(),
+            "success_rate": tally.statuses[1] as f64 / tally.requests.max(1) as f64,
+            "status_1xx": tally.statuses[0],
+            "status_2xx": tally.statuses[1],
+            "status_3xx": tally.statuses[2],
+            "status_4xx": tally.statuses[3],
+            "status_5xx": tally.statuses[4],
+            "errors": tally.errors,
+            "timeouts": tally.timeouts,
+            "handshakes": tally.handshakes.seen,
+            "handshake_p50_ms": ms(&tally.handshakes, 0.5),
+            "handshake_p99_ms": ms(&tally.handshakes, 0.99),
+            "handshake_max_ms": ms(&tally.handshakes, 1.0),
+            "request_p50_ms": ms(&tally.latencies, 0.5),
+            "request_p99_ms": ms(&tally.latencies, 0.99),
+            "request_max_ms": ms(&tally.latencies, 1.0),
+        });
+        log::info!("HTTP: {summary}.");
+        report::measure(self.name(), summary);
+        match cancel.is_cancelled() {
+            true => Err(StressError::Interrupted.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use clap::{FromArgMatches, Parser};
+
+    use super::*;
+
+    fn args(command: &[&str]) -> Args {
+        #[derive(Parser)]
+        struct Command {
+            #[command(flatten)]
+            args: Args,
+        }
+        let matches = <Command as clap::CommandFactory>::command()
+            .try_get_matches_from(["http"].iter().chain(command))
+            .unwrap();
+        Command::from_arg_matches(&matches).unwrap().args
+    }
+
+    #[test]
+    fn parses_urls() {
+        assert_eq!(
+            "https://proxy.lan:8443/health?full=1".parse(),
+            Ok(Url {
+                tls: true,
+                host: "proxy.lan".to_string(),
+                port: 8443,
+                path: "/health?full=1".to_string(),
+            })
+        );
+        let url: Url = "http://[::1]".parse().unwrap();
+        assert_eq!((url.host.as_str(), url.port), ("::1", 80));
+        assert_eq!(url.to_string(), "http://[::1]:80/");
+        assert_eq!("https://a?b".parse::<Url>().unwrap().path, "/?b");
+        for bad in [
+            "ftp://a/",
+            "proxy.lan",
+            "http://",
+            "http://a:x/",
+            "http://u@a/",
+        ] {
+            assert!(bad.parse::<Url>().is_err(), "{bad}");
+        }
+    }
+
+    /// Serves every request on every connection with 200, alternating
+    /// sized and chunked bodies, until the client closes.
+    fn serve(listener: TcpListener) {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                return;
+            };
+            std::thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                for served in 0.. {
+                    let mut close = false;
+                    loop {
+                        let mut line = String::new();
+                        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                            return;
+                        }
+                        close |= line.eq_ignore_ascii_case("connection: close\r\n");
+                        if line == "\r\n" {
+                            break;
+                        }
+                    }
+                    let response: &[u8] = match served % 2 {
+                        0 => b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok",
+                        _ => b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nok\r\n0\r\n\r\n",
+                    };
+                    if stream.write_all(response).is_err() || close {
+                        return;
+                    }
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn counts_handshakes_and_responses() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        std::thread::spawn(move || serve(listener));
+        let command = [
+            "--rate",
+            "200/s",
+            "--connections",
+            "2",
+            "--duration",
+            "200ms",
+        ];
+
+        let reused = args(&[&[url.as_str()][..], &command].concat());
+        let target = reused.target().unwrap();
+        let tally = reused.load(target, &CancellationToken::new()).unwrap();
+        assert!(tally.requests >= 20, "{:?}", tally.statuses);
+        assert_eq!(tally.statuses[1], tally.requests, "{:?}", tally.statuses);
+        assert_eq!(tally.handshakes.seen, 2);
+        assert_eq!(tally.latencies.seen, tally.requests);
+
+        let fresh = args(&[&[url.as_str(), "--new-connections"][..], &command].concat());
+        let tally = fresh.load(target, &CancellationToken::new()).unwrap();
+        assert_eq!(tally.statuses[1], tally.requests, "{:?}", tally.statuses);
+        assert_eq!(tally.handshakes.seen, tally.requests);
+    }
+
+    #[cfg(feature = "http3")]
+    #[test]
+    fn sends_http3_over_quic() {
+        let key = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let certificate = key.cert.der().clone();
+        let private_key =
+            rustls::pki_types::PrivatePkcs8KeyDer::from(key.signing_key.serialize_der());
+        let mut tls = rustls::ServerConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(vec![certificate], private_key.into())
+        .unwrap();
+        tls.alpn_protocols = vec![b"h3".to_vec()];
+        let config = quinn::ServerConfig::with_crypto(Arc::new(
+            quinn::crypto::rustls::QuicServerConfig::try_from(tls).unwrap(),
+        ));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let endpoint = runtime
+            .block_on(async { quinn::Endpoint::server(config, "127.0.0.1:0".parse().unwrap()) })
+            .unwrap();
+        let port = endpoint.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            runtime.block_on(async move {
+                while let Some(incoming) = endpoint.accept().await {
+                    tokio::spawn(async move {
+                        let connection = h3_quinn::Connection::new(incoming.await.unwrap());
+                        let mut server: h3::server::Connection<_, bytes::Bytes> =
+                            h3::server::Connection::new(connection).await.unwrap();
+                        while let Ok(Some(resolver)) = server.accept().await {
+                            let (_, mut stream) = resolver.resolve_request().await.unwrap();
+                            let response = http::Response::builder().status(204).body(()).unwrap();
+                            stream.send_response(response).await.unwrap();
+                            stream.finish().await.unwrap();
+                        }
+                    });
+                }
+            });
+        });
+
+        let args = args(&[
+            &format!("https://localhost:{port}/"),
+            "--http3",
+            "--insecure",
+            "--rate",
+            "100/s",
+            "--connections",
+            "2",
+            "--duration",
+            "200ms",
+        ]);
+        let target = SocketAddr::from(([127, 0, 0, 1], port));
+        let tally = args.load(target, &CancellationToken::new()).unwrap();
+        assert!(tally.requests >= 10, "{:?}", tally.statuses);
+        assert_eq!(tally.statuses[1], tally.requests, "{:?}", tally.statuses);
+        assert_eq!(tally.handshakes.seen, 2);
+    }
+}
@@ -0,0 +1,312 @@
+//! Fault injection into thread stressor workers for `--inject`, to check
+//! that `--on-worker-panic`, isolation and reporting cope with workers
+//! dying mid-run, and to add churn to chaos runs. Faults strike between
+//! workload iterations, at a rate shared by every worker or with a chance
+//! on each iteration.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+use crate::chaos::{self, Rng};
+use crate::{CancellationToken, events, parse};
+
+static FAULTS: OnceLock<Faults> = OnceLock::new();
+/// When each rate-triggered fault next strikes, by its index in `FAULTS`.
+static DUE: Mutex<Vec<Option<Instant>>> = Mutex::new(vec![]);
+static STRUCK: [AtomicU64; 3] = [const { AtomicU64::new(0) }; 3];
+static IN_WORKER_PROCESS: AtomicBool = AtomicBool::new(false);
+/// Longest gap drawn between rate-triggered strikes, longer than any run
+/// and far from overflowing an `Instant`.
+const LONGEST_GAP: Duration = Duration::from_secs(365 * 86_400);
+
+/// What happens to the worker a fault strikes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Effect {
+    /// Ends an isolated worker's process with SIGKILL, or otherwise the
+    /// worker thread, as a panic would.
+    Kill,
+    /// Stops the worker for this long.
+    Stall(Duration),
+    /// Panics the worker thread.
+    Panic,
+}
+
+impl Effect {
+    pub fn name(self) -> &'static str {
+        match self {
+            Effect::Kill => "kill",
+            Effect::Stall(_) => "stall",
+            Effect::Panic => "panic",
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            Effect::Kill => 0,
+            Effect::Stall(_) => 1,
+            Effect::Panic => 2,
+        }
+    }
+}
+
+/// When a fault strikes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Trigger {
+    /// On average this many times a second across all workers, at random.
+    Rate(f64),
+    /// With this probability after each iteration of each worker.
+    Chance(f64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fault {
+    pub effect: Effect,
+    pub trigger: Trigger,
+}
+
+impl FromStr for Fault {
+    type Err = String;
+
+    /// Parses `kill:1/min`, `stall:500ms:p=0.01` or `panic:p=0.001`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().split(':');
+        let effect = match parts.next() {
+            Some("kill") => Effect::Kill,
+            Some("panic") => Effect::Panic,
+            Some("stall") => Effect::Stall(parse::duration(
+                parts
+                    .next()
+                    .ok_or_else(|| format!("stall fault '{s}' needs a duration"))?,
+            )?),
+            _ => {
+                return Err(format!(
+                    "unknown fault '{s}' (use kill, stall:DURATION or panic)"
+                ));
+            }
+        };
+        let trigger = match (parts.next(), parts.next()) {
+            (Some(trigger), None) => match trigger.strip_prefix("p=") {
+                Some(p) => match p.parse::<f64>() {
+                    Ok(p) if (0.0..=1.0).contains(&p) && p > 0.0 => Trigger::Chance(p),
+                    _ => return Err(format!("probability in '{s}' must be in (0, 1]")),
+                },
+                None => Trigger::Rate(parse::per_second(trigger)?),
+            },
+            _ => {
+                return Err(format!(
+                    "fault '{s}' needs one trigger, a rate (e.g. 1/min) or a probability (e.g. p=0.01)"
+                ));
+            }
+        };
+        Ok(Fault { effect, trigger })
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.effect.name())?;
+        if let Effect::Stall(duration) = self.effect {
+            write!(f, ":{}ms", duration.as_millis())?;
+        }
+        match self.trigger {
+            Trigger::Rate(rate) => write!(f, ":{rate}/s"),
+            Trigger::Chance(p) => write!(f, ":p={p}"),
+        }
+    }
+}
+
+/// Faults to inject, e.g. `kill:1/min,stall:500ms:p=0.01`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Faults(Vec<Fault>);
+
+impl FromStr for Faults {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map(Faults)
+    }
+}
+
+impl fmt::Display for Faults {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let faults: Vec<_> = self.0.iter().map(Fault::to_string).collect();
+        f.write_str(&faults.join(","))
+    }
+}
+
+/// Makes every thread stressor worker suffer `faults` from now on.
+pub fn configure(faults: Faults) {
+    if FAULTS.set(faults).is_ok() {
+        log::warn!("Injecting faults into workers: {}.", FAULTS.get().unwrap());
+    }
+}
+
+pub fn configured() -> Option<&'static Faults> {
+    FAULTS.get()
+}
+
+/// Marks this process as an isolated worker, whose kill fault ends the
+/// whole process for its supervisor to notice.
+pub fn set_in_worker_process() {
+    IN_WORKER_PROCESS.store(true, Ordering::SeqCst);
+}
+
+/// A new worker's fault injector, if faults are configured.
+pub fn armed() -> Option<Injector> {
+    let faults = configured()?;
+    Some(Injector {
+        faults,
+        rng: Rng::new(chaos::random_seed()),
+    })
+}
+
+/// Faults seen by one worker, with its own random numbers.
+pub struct Injector {
+    faults: &'static Faults,
+    rng: Rng,
+}
+
+impl Injector {
+    /// A uniform value in `(0, 1]`.
+    fn unit(&mut self) -> f64 {
+        ((self.rng.next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Whether fault `index` with `trigger` strikes now.
+    fn strikes(&mut self, index: usize, trigger: Trigger) -> bool {
+        match trigger {
+            Trigger::Chance(p) => self.unit() <= p,
+            Trigger::Rate(rate) => {
+                let now = Instant::now();
+                // Exponential gaps make the strikes a Poisson process.
+                let gap = Duration::try_from_secs_f64(-self.unit().ln() / rate)
+                    .map_or(LONGEST_GAP, |gap| gap.min(LONGEST_GAP));
+                let mut due = DUE.lock().unwrap_or_else(|e| e.into_inner());
+                if due.len() <= index {
+                    due.resize(index + 1, None);
+                }
+                match due[index] {
+                    Some(at) if now >= at => {
+                        due[index] = Some(now + gap);
+                        true
+                    }
+                    Some(_) => false,
+                    None => {
+                        due[index] = Some(now + gap);
+                        false
+                    }
+                }
+            }
+        }
+    }
+
+    /// Called by worker `thread` after each iteration: applies whichever
+    /// fault strikes, if any. Returns `false` if cancelled while stalled.
+    pub fn strike(&mut self, thread: u32, cancel: &CancellationToken) -> bool {
+        for (index, fault) in self.faults.0.iter().enumerate() {
+            if !self.strikes(index, fault.trigger) {
+                continue;
+            }
+            STRUCK[fault.effect.index()].fetch_add(1, Ordering::Relaxed);
+            log::warn!("Injecting {} into thread {thread}.", fault.effect.name());
+            events::emit(
+                "fault_injected",
+                json!({ "thread": thread, "fault": fault.to_string() }),
+            );
+            match fault.effect {
+                Effect::Stall(duration) => {
+                    if !cancel.sleep_until(Instant::now() + duration) {
+                        return false;
+                    }
+                }
+                Effect::Kill if IN_WORKER_PROCESS.load(Ordering::SeqCst) => {
+                    // SAFETY: raising a signal has no memory safety
+                    // requirements; SIGKILL ends the process here.
+                    unsafe { libc::raise(libc::SIGKILL) };
+                }
+                Effect::Kill => panic!("thread {thread} killed by fault injection"),
+                Effect::Panic => panic!("thread {thread} panicked by fault injection"),
+            }
+        }
+        true
+    }
+}
+
+/// How many faults of each kind struck, for the report.
+pub fn struck() -> Value {
+    json!({
+        "kill": STRUCK[Effect::Kill.index()].load(Ordering::Relaxed),
+        "stall": STRUCK[Effect::Stall(Duration::ZERO).index()].load(Ordering::Relaxed),
+        "panic": STRUCK[Effect::Panic.index()].load(Ordering::Relaxed),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_prints_faults() {
+        let faults: Faults = "kill:1/min,stall:500ms:p=0.01,panic:p=1".parse().unwrap();
+        assert_eq!(
+            faults.0,
+            [
+                Fault {
+                    effect: Effect::Kill,
+                    trigger: Trigger::Rate(1.0 / 60.0),
+                },
+                Fault {
+                    effect: Effect::Stall(Duration::from_millis(500)),
+                    trigger: Trigger::Chance(0.01),
+                },
+                Fault {
+                    effect: Effect::Panic,
+                    trigger: Trigger::Chance(1.0),
+                },
+            ]
+        );
+        assert_eq!(faults.to_string().parse::<Faults>().unwrap(), faults);
+        assert!("stall:p=0.5".parse::<Faults>().is_err());
+        assert!("panic".parse::<Faults>().is_err());
+        assert!("panic:p=2".parse::<Faults>().is_err());
+        assert!("explode:1/s".parse::<Faults>().is_err());
+        assert!("kill:1e-300/s".parse::<Faults>().is_err());
+    }
+
+    #[test]
+    fn chance_faults_strike_their_share_of_iterations() {
+        let faults = Box::leak(Box::new(Faults(vec![Fault {
+            effect: Effect::Stall(Duration::ZERO),
+            trigger: Trigger::Chance(0.25),
+        }])));
+        let mut injector = Injector {
+            faults,
+            rng: Rng::new(7),
+        };
+        let struck = (0..10_000)
+            .filter(|_| injector.strikes(0, Trigger::Chance(0.25)))
+            .count();
+        assert!((2_000..3_000).contains(&struck), "{struck}");
+        assert!(injector.strike(0, &CancellationToken::new()));
+    }
+
+    #[test]
+    fn rates_too_slow_to_strike_never_do() {
+        let faults = Box::leak(Box::new(Faults(vec![])));
+        let mut injector = Injector {
+            faults,
+            rng: Rng::new(7),
+        };
+        // Past every other test's faults in `DUE`.
+        assert!(!injector.strikes(9, Trigger::Rate(1e-300)));
+        assert!(!injector.strikes(9, Trigger::Rate(1e-300)));
+    }
+}
@@ -0,0 +1,182 @@
+//! `itsmine interference`: a victim thread measures memory latency on one
+//! CPU, first alone and then while an aggressor thread thrashes the cache from
+//! an SMT sibling or a core sharing the last-level cache, and the slowdown is
+//! reported as the interference penalty.
+
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+use crate::chaos::{self, Rng};
+use crate::membench::Chain;
+use crate::{CancellationToken, StressError, Stressor, parse, placement, report, stats};
+
+const LINE: usize = 64;
+/// Loads timed together as one latency sample.
+const STEPS: usize = 10_000;
+
+/// Where the aggressor runs relative to the victim.
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum Pairing {
+    /// The other hardware thread of the victim's core.
+    Smt,
+    /// Another core sharing the victim's last-level cache.
+    Llc,
+}
+
+impl Pairing {
+    fn name(self) -> &'static str {
+        match self {
+            Pairing::Smt => "smt",
+            Pairing::Llc => "llc",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, clap::Args)]
+pub struct Args {
+    /// How long to run, half alone and half next to the aggressor, e.g. 10s
+    #[arg(long, value_parser = parse::duration, default_value = "10s")]
+    pub duration: Duration,
+    /// Where the aggressor runs relative to the victim
+    #[arg(long, value_enum, default_value_t = Pairing::Smt)]
+    pub pair: Pairing,
+    /// Working set the victim chases pointers through, e.g. 256K
+    #[arg(long, value_name = "BYTES", value_parser = parse::bytes, default_value = "256K")]
+    pub victim_bytes: u64,
+    /// Buffer the aggressor streams writes over, e.g. 64M
+    #[arg(long, value_name = "BYTES", value_parser = parse::bytes, default_value = "64M")]
+    pub aggressor_bytes: u64,
+}
+
+/// Nanoseconds per load, one sample per `STEPS` loads, until `until`.
+fn sample(chain: &Chain, until: Instant, cancel: &CancellationToken) -> Vec<f64> {
+    let mut samples = vec![];
+    let mut at = 0;
+    while Instant::now() < until && !cancel.is_cancelled() {
+        let started = Instant::now();
+        at = chain.walk(at, STEPS);
+        samples.push(started.elapsed().as_nanos() as f64 / STEPS as f64);
+    }
+    samples
+}
+
+/// Streams writes over `buffer`, a cache line apart, until `stop`.
+fn thrash(buffer: &mut [u8], stop: &CancellationToken) {
+    while !stop.is_cancelled() {
+        for line in buffer.chunks_mut(LINE) {
+            line[0] = line[0].wrapping_add(1);
+        }
+        std::hint::black_box(&buffer);
+    }
+}
+
+/// Latency percentiles alone and contended, and how much worse the median
+/// got.
+fn summarize(alone: &mut [f64], contended: &mut [f64]) -> Value {
+    alone.sort_by(f64::total_cmp);
+    contended.sort_by(f64::total_cmp);
+    let (alone_p50, contended_p50) = (
+        stats::percentile(alone, 0.5),
+        stats::percentile(contended, 0.5),
+    );
+    let penalty = (contended_p50 / alone_p50 - 1.0) * 100.0;
+    json!({
+        "samples": alone.len() + contended.len(),
+        "alone_p50_ns": alone_p50,
+        "alone_p99_ns": stats::percentile(alone, 0.99),
+        "contended_p50_ns": contended_p50,
+        "contended_p99_ns": stats::percentile(contended, 0.99),
+        "penalty_percent": penalty.is_finite().then_some(penalty),
+    })
+}
+
+impl Stressor for Args {
+    fn name(&self) -> &'static str {
+        "interference"
+    }
+
+    fn params(&self) -> Value {
+        json!({
+            "duration_secs": self.duration.as_secs_f64(),
+            "pair": self.pair.name(),
+            "victim_bytes": self.victim_bytes,
+            "aggressor_bytes": self.aggressor_bytes,
+        })
+    }
+
+    fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        let pair = match self.pair {
+            Pairing::Smt => placement::smt_siblings(),
+            Pairing::Llc => placement::llc_neighbours(),
+        };
+        let (victim, aggressor) = pair.unwrap_or_else(|| {
+            let allowed = placement::allowed();
+            let first = allowed.first().copied().unwrap_or(0);
+            let fallback = (first, allowed.get(1).copied().unwrap_or(first));
+            log::warn!(
+                "No {} CPU pair found; running the victim on CPU {} and the aggressor on CPU {}.",
+                self.pair.name(),
+                fallback.0,
+                fallback.1
+            );
+            fallback
+        });
+        let mut rng = Rng::new(chaos::random_seed());
+        let chain = Chain::new(self.victim_bytes, &mut rng);
+        let mut buffer = vec![0u8; self.aggressor_bytes as usize];
+        let stop = cancel.child_token();
+        let half = self.duration / 2;
+        log::info!(
+            "Measuring interference: victim on CPU {victim}, aggressor on CPU {aggressor}, {half:?} each way."
+        );
+
+        let (mut alone, mut contended) = std::thread::scope(|s| {
+            let measuring = s.spawn(|| {
+                let _ = placement::pin_current(victim);
+                let alone = sample(&chain, Instant::now() + half, cancel);
+                // Let the aggressor fill the cache before measuring again.
+                let started = Instant::now() + Duration::from_millis(50);
+                while Instant::now() < started {
+                    chain.walk(0, STEPS);
+                }
+                let contended = sample(&chain, Instant::now() + half, cancel);
+                stop.cancel();
+                (alone, contended)
+            });
+            if stop.sleep_until(Instant::now() + half) {
+                s.spawn(|| {
+                    let _ = placement::pin_current(aggressor);
+                    thrash(&mut buffer, &stop);
+                });
+            }
+            measuring.join()
+        })
+        .map_err(|payload| StressError::WorkerPanicked(crate::panic_message(&payload)))?;
+
+        let mut summary = summarize(&mut alone, &mut contended);
+        summary["victim_cpu"] = json!(victim);
+        summary["aggressor_cpu"] = json!(aggressor);
+        summary["paired"] = json!(pair.is_some());
+        log::info!("Interference: {summary}.");
+        report::measure(self.name(), summary);
+        match cancel.is_cancelled() {
+            true => Err(StressError::Interrupted.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn penalty_compares_medians() {
+        let summary = summarize(&mut [10.0, 12.0, 11.0], &mut [15.0, 16.5, 16.0]);
+        assert_eq!(summary["alone_p50_ns"], 11.0);
+        assert_eq!(summary["contended_p50_ns"], 16.0);
+        assert!((summary["penalty_percent"].as_f64().unwrap() - 45.45).abs() < 0.01);
+        assert!(summarize(&mut [], &mut [])["penalty_percent"].is_null());
+    }
+}
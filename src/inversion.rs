@@ -0,0 +1,233 @@
+//! `itsmine priority-inversion`: a low-priority thread holds a lock the
+//! high-priority thread needs while medium-priority threads spin on the same
+//! CPU, and the high-priority thread's wait for the lock is measured.
+
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+use crate::sched::{self, Policy};
+use crate::{CancellationToken, StressError, Stressor, parse, placement, report};
+
+const LOW: i32 = 10;
+const MEDIUM: i32 = 20;
+const HIGH: i32 = 30;
+
+/// How long a medium-priority thread spins before sleeping as long again.
+const SPIN_BURST: Duration = Duration::from_millis(5);
+/// Pause between the high-priority thread's lock acquisitions.
+const PERIOD: Duration = Duration::from_millis(5);
+
+#[derive(Clone, Debug, PartialEq, clap::Args)]
+pub struct Args {
+    /// How long to run, e.g. 10s
+    #[arg(long, value_parser = parse::duration, default_value = "10s")]
+    pub duration: Duration,
+    /// How long the low-priority thread holds the lock each time
+    #[arg(long, value_parser = parse::duration, default_value = "1ms")]
+    pub hold: Duration,
+    /// Medium-priority threads spinning on the shared CPU
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    pub spinners: u32,
+    /// Use a priority-inheritance mutex, which bounds the inversion
+    #[arg(long, default_value_t = false)]
+    pub inherit: bool,
+}
+
+/// A pthread mutex, so the priority-inheritance protocol can be chosen.
+struct RtMutex(Box<UnsafeCell<libc::pthread_mutex_t>>);
+
+// SAFETY: pthread mutexes are designed to be shared between threads, and the
+// box keeps the mutex at a stable address.
+unsafe impl Sync for RtMutex {}
+unsafe impl Send for RtMutex {}
+
+impl RtMutex {
+    fn new(inherit: bool) -> Result<Self, StressError> {
+        let protocol = match inherit {
+            true => libc::PTHREAD_PRIO_INHERIT,
+            false => libc::PTHREAD_PRIO_NONE,
+        };
+        let mutex = Box::new(UnsafeCell::new(libc::PTHREAD_MUTEX_INITIALIZER));
+        let mut attr = MaybeUninit::<libc::pthread_mutexattr_t>::uninit();
+        // SAFETY: `attr` is initialised before use and destroyed after
+        // `mutex` copies what it needs from it.
+        let rc = unsafe {
+            libc::pthread_mutexattr_init(attr.as_mut_ptr());
+            let rc = match libc::pthread_mutexattr_setprotocol(attr.as_mut_ptr(), protocol) {
+                0 => libc::pthread_mutex_init(mutex.get(), attr.as_ptr()),
+                rc => rc,
+            };
+            libc::pthread_mutexattr_destroy(attr.as_mut_ptr());
+            rc
+        };
+        match rc {
+            0 => Ok(RtMutex(mutex)),
+            rc => Err(StressError::InvalidInput(format!(
+                "Failed to create lock: {}",
+                std::io::Error::from_raw_os_error(rc)
+            ))),
+        }
+    }
+
+    fn with<R>(&self, f: impl FnOnce() -> R) -> R {
+        // SAFETY: the mutex was initialised in `new` and is unlocked by the
+        // thread that locked it.
+        unsafe { libc::pthread_mutex_lock(self.0.get()) };
+        let result = f();
+        unsafe { libc::pthread_mutex_unlock(self.0.get()) };
+        result
+    }
+}
+
+impl Drop for RtMutex {
+    fn drop(&mut self) {
+        // SAFETY: no thread holds the mutex once it can be dropped.
+        unsafe { libc::pthread_mutex_destroy(self.0.get()) };
+    }
+}
+
+fn spin_for(duration: Duration) {
+    let until = Instant::now() + duration;
+    while Instant::now() < until {
+        std::hint::spin_loop();
+    }
+}
+
+/// Pins the calling thread to `cpu` and, while `realtime` holds, gives it
+/// a realtime `priority`, clearing `realtime` if it could not be set.
+fn enter(cpu: usize, priority: i32, realtime: &AtomicBool) {
+    let _ = placement::pin_current(cpu);
+    if realtime.load(Ordering::SeqCst) && sched::set_current(Policy::Fifo(priority)).is_err() {
+        realtime.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Percentiles of the high-priority thread's waits next to what the lock
+/// hold time alone would explain.
+fn summarize(waits: &mut [Duration], hold: Duration) -> Value {
+    waits.sort();
+    let percentile = |p: f64| {
+        waits
+            .get(((waits.len() as f64 * p) as usize).min(waits.len().saturating_sub(1)))
+            .map_or(0, |d| d.as_micros() as u64)
+    };
+    let max = waits.last().copied().unwrap_or_default();
+    json!({
+        "samples": waits.len(),
+        "hold_us": hold.as_micros() as u64,
+        "wait_p50_us": percentile(0.5),
+        "wait_p99_us": percentile(0.99),
+        "wait_max_us": max.as_micros() as u64,
+        "inversion_us": max.saturating_sub(hold).as_micros() as u64,
+    })
+}
+
+impl Stressor for Args {
+    fn name(&self) -> &'static str {
+        "priority-inversion"
+    }
+
+    fn params(&self) -> Value {
+        json!({
+            "duration_secs": self.duration.as_secs_f64(),
+            "hold_secs": self.hold.as_secs_f64(),
+            "spinners": self.spinners,
+            "inherit": self.inherit,
+        })
+    }
+
+    fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        // SAFETY: sched_getcpu has no preconditions.
+        let cpu = unsafe { libc::sched_getcpu() }.max(0) as usize;
+        let lock = RtMutex::new(self.inherit)?;
+        let permitted = Policy::Fifo(HIGH).permitted("priority-inversion realtime priorities");
+        let realtime = AtomicBool::new(permitted);
+        let stop = cancel.child_token();
+        let deadline = Instant::now() + self.duration;
+        log::info!(
+            "Running priority inversion on CPU {cpu} with {} spinners for {:?}.",
+            self.spinners,
+            self.duration
+        );
+
+        let mut waits = std::thread::scope(|s| {
+            s.spawn(|| {
+                enter(cpu, LOW, &realtime);
+                while !stop.is_cancelled() {
+                    lock.with(|| spin_for(self.hold));
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+            });
+            for _ in 0..self.spinners {
+                s.spawn(|| {
+                    enter(cpu, MEDIUM, &realtime);
+                    while !stop.is_cancelled() {
+                        spin_for(SPIN_BURST);
+                        std::thread::sleep(SPIN_BURST);
+                    }
+                });
+            }
+            let high = s.spawn(|| {
+                enter(cpu, HIGH, &realtime);
+                let mut waits = vec![];
+                while Instant::now() < deadline && !stop.is_cancelled() {
+                    std::thread::sleep(PERIOD);
+                    let asked = Instant::now();
+                    waits.push(lock.with(|| asked.elapsed()));
+                }
+                waits
+            });
+            let waits = high.join();
+            stop.cancel();
+            waits
+        })
+        .map_err(|payload| StressError::WorkerPanicked(crate::panic_message(&payload)))?;
+
+        let realtime = realtime.load(Ordering::SeqCst);
+        if !realtime {
+            log::warn!(
+                "Realtime priorities unavailable{}; waits reflect fair scheduling, not inversion.",
+                match permitted {
+                    true => "",
+                    false => " (needs CAP_SYS_NICE)",
+                }
+            );
+        }
+        let mut summary = summarize(&mut waits, self.hold);
+        summary["realtime"] = json!(realtime);
+        summary["priority_inheritance"] = json!(self.inherit);
+        log::info!("Priority inversion: {summary}.");
+        report::measure(self.name(), summary);
+        match cancel.is_cancelled() {
+            true => Err(StressError::Interrupted.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_waits() {
+        let mut waits: Vec<_> = (1..=100).rev().map(Duration::from_micros).collect();
+        let summary = summarize(&mut waits, Duration::from_micros(40));
+        assert_eq!(summary["samples"], 100);
+        assert_eq!(summary["wait_p50_us"], 51);
+        assert_eq!(summary["wait_p99_us"], 100);
+        assert_eq!(summary["wait_max_us"], 100);
+        assert_eq!(summary["inversion_us"], 60);
+        assert_eq!(summarize(&mut [], Duration::ZERO)["wait_max_us"], 0);
+    }
+
+    #[test]
+    fn inheritance_mutex_locks() {
+        let lock = RtMutex::new(true).unwrap();
+        assert_eq!(lock.with(|| 7), 7);
+    }
+}
@@ -0,0 +1,311 @@
+use std::io::Read;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::verify::Verification;
+use crate::{CancellationToken, Resource, StressError, report, retry, verify};
+
+/// Name of the hidden subcommand a supervised child runs.
+pub const WORKER_COMMAND: &str = "isolated-worker";
+
+/// How long a cancelled child gets to exit after SIGTERM before SIGKILL.
+const GRACE: Duration = Duration::from_secs(5);
+const POLL: Duration = Duration::from_millis(50);
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static FORWARDED: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+/// Runs memory and thread stressors in child processes from now on, so an
+/// OOM kill or crash of one takes down neither its siblings nor reporting.
+pub fn set(enabled: bool) {
+    ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+/// Global options children need to behave like the parent, such as the
+/// workload script or `--sandbox`, passed ahead of the worker subcommand.
+pub fn forward(args: impl IntoIterator<Item = String>) {
+    FORWARDED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .extend(args);
+}
+
+/// The worker arguments that recreate `resource` in a child, or `None` for
+/// stressors that only orchestrate others.
+fn worker_args(resource: &Resource, deadline: Option<Instant>) -> Option<Vec<String>> {
+    let (stressor, value) = match resource {
+        // Benchmarks measure rather than load, so there's nothing to contain.
+        Resource::Memory { bench: Some(_), .. } => return None,
+        Resource::Memory { arg, .. } => ("memory", arg.clone()),
+        Resource::Thread { num } => ("thread", num.to_string()),
+        _ => return None,
+    };
+    let mut args: Vec<String> = verbosity().map(str::to_string).into_iter().collect();
+    args.extend(
+        FORWARDED
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .cloned(),
+    );
+    args.push(WORKER_COMMAND.to_string());
+    if let Some(deadline) = deadline {
+        let hold = deadline.saturating_duration_since(Instant::now());
+        args.push(format!("--hold-ms={}", hold.as_millis()));
+    }
+    args.extend([stressor.to_string(), value]);
+    Some(args)
+}
+
+/// What a child sends back on its stdout as one line of JSON when it ends,
+/// for the supervisor's report and exit code.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Outcome {
+    measurements: Map<String, Value>,
+    degradations: Vec<String>,
+    retries: u64,
+    verification: Option<Verification>,
+    failure: Option<Failure>,
+}
+
+/// The `StressError` a child failed with, as far as the supervisor needs it.
+#[derive(Debug, Serialize, Deserialize)]
+struct Failure {
+    message: String,
+    code: i32,
+    transient: bool,
+}
+
+impl Outcome {
+    /// Reads the last line of a child's stdout, or an empty outcome if it
+    /// died before writing one.
+    fn parse(stdout: &str) -> Self {
+        stdout
+            .lines()
+            .next_back()
+            .and_then(|line| serde_json::from_str(line).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Passes the parent's log level on so the child's logs match.
+fn verbosity() -> Option<&'static str> {
+    match log::max_level() {
+        log::LevelFilter::Off => Some("--quiet"),
+        log::LevelFilter::Error | log::LevelFilter::Warn => None,
+        log::LevelFilter::Info => Some("-v"),
+        log::LevelFilter::Debug => Some("-vv"),
+        log::LevelFilter::Trace => Some("-vvv"),
+    }
+}
+
+/// Runs `resource` in a child process and supervises it: the child is
+/// terminated when `cancel` fires, and a crash or kill becomes an error here
+/// instead of ending the whole run.
+pub fn execute(
+    resource: &Resource,
+    deadline: Option<Instant>,
+    cancel: &CancellationToken,
+) -> Option<Result<(), anyhow::Error>> {
+    let args = worker_args(resource, deadline)?;
+    Some(supervise(resource.name(), &args, cancel))
+}
+
+fn supervise(name: &str, args: &[String], cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+    let mut child = Command::new(std::env::current_exe()?)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to start isolated {name} stressor: {e}"))?;
+    log::info!("Started {name} stressor in child process {}.", child.id());
+    // Drained alongside the wait, so a full pipe never blocks the child.
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let reader = std::thread::spawn(move || {
+        let mut output = String::new();
+        let _ = stdout.read_to_string(&mut output);
+        output
+    });
+    let status = wait(&mut child, cancel)?;
+    let outcome = Outcome::parse(&reader.join().unwrap_or_default());
+    for (key, value) in outcome.measurements {
+        report::measure(&key, value);
+    }
+    for reason in outcome.degradations {
+        report::degrade(reason);
+    }
+    retry::add(outcome.retries);
+    if let Some(verification) = outcome.verification {
+        verify::record(verification);
+    }
+    if cancel.is_cancelled() && !status.success() {
+        return Err(StressError::Interrupted.into());
+    }
+    check(name, status, outcome.failure)
+}
+
+fn wait(child: &mut Child, cancel: &CancellationToken) -> Result<ExitStatus, anyhow::Error> {
+    let mut terminated_at = None;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        match terminated_at {
+            None if cancel.is_cancelled() => {
+                // SAFETY: `kill` with a valid pid and signal has no memory
+                // safety requirements; the child has not been reaped yet.
+                unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM) };
+                terminated_at = Some(Instant::now());
+            }
+            Some(at) if at.elapsed() >= GRACE => {
+                child.kill()?;
+                return Ok(child.wait()?);
+            }
+            _ => {}
+        }
+        std::thread::sleep(POLL);
+    }
+}
+
+/// Turns how a child ended into the error it stands for: the child's own
+/// `StressError` when it reported one, so exit codes and `--retry` see
+/// through isolation.
+fn check(name: &str, status: ExitStatus, failure: Option<Failure>) -> Result<(), anyhow::Error> {
+    use std::os::unix::process::ExitStatusExt;
+
+    match (status.code(), status.signal()) {
+        (Some(0), _) => Ok(()),
+        (Some(code), _) => Err(match failure {
+            Some(failure) => StressError::Isolated {
+                message: format!("Isolated {name} stressor failed: {}", failure.message),
+                code: failure.code,
+                transient: failure.transient,
+            },
+            None => StressError::Isolated {
+                message: format!("Isolated {name} stressor exited with code {code}"),
+                code,
+                transient: false,
+            },
+        }
+        .into()),
+        (None, Some(signal)) => Err(anyhow::anyhow!(
+            "Isolated {name} stressor was killed by signal {signal}{}",
+            if signal == libc::SIGKILL {
+                " (possibly by the OOM killer)"
+            } else {
+                ""
+            }
+        )),
+        (None, None) => Err(anyhow::anyhow!("Isolated {name} stressor ended abnormally")),
+    }
+}
+
+/// Entry point of the hidden worker subcommand: runs one stressor in this
+/// process, holding it for `hold` if given, sends its outcome to the
+/// supervisor on stdout and returns the exit code.
+pub fn worker(resource: Resource, hold: Option<Duration>) -> i32 {
+    crate::shutdown::install_handlers();
+    let deadline = hold.map(|hold| Instant::now() + hold);
+    let result = crate::execute(resource, deadline, &CancellationToken::new());
+    let failure = result.as_ref().err().map(|e| {
+        log::error!("Error: {e:#}");
+        Failure {
+            message: format!("{e:#}"),
+            code: crate::error::exit_code(e),
+            transient: e
+                .chain()
+                .find_map(|cause| cause.downcast_ref::<StressError>())
+                .is_some_and(StressError::is_transient),
+        }
+    });
+    let code = failure.as_ref().map_or(0, |failure| failure.code);
+    let outcome = Outcome {
+        measurements: report::take_measurements(),
+        degradations: report::take_degradations(),
+        retries: retry::take(),
+        verification: verify::take(),
+        failure,
+    };
+    println!(
+        "{}",
+        serde_json::to_string(&outcome).expect("outcome serializes")
+    );
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_leaf_stressors_are_isolated() {
+        let args = worker_args(&Resource::Thread { num: 2 }, None).unwrap();
+        assert_eq!(args.last().unwrap(), "2");
+        assert!(args.contains(&WORKER_COMMAND.to_string()));
+        let args = worker_args(
+            &Resource::Memory {
+                arg: "1K".to_string(),
+                bench: None,
+            },
+            Some(Instant::now() + Duration::from_secs(60)),
+        )
+        .unwrap();
+        assert!(args.iter().any(|arg| arg.starts_with("--hold-ms=")));
+        assert_eq!(args[args.len() - 2..], ["memory", "1K"]);
+        assert!(
+            worker_args(
+                &Resource::Run {
+                    scenario: "s.txt".into()
+                },
+                None
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn signals_become_errors() {
+        use std::os::unix::process::ExitStatusExt;
+
+        assert!(check("memory", ExitStatus::from_raw(0), None).is_ok());
+        let e = check("memory", ExitStatus::from_raw(libc::SIGKILL), None).unwrap_err();
+        assert!(e.to_string().contains("OOM killer"));
+        let e = check("thread", ExitStatus::from_raw(3 << 8), None).unwrap_err();
+        assert_eq!(e.to_string(), "Isolated thread stressor exited with code 3");
+        assert_eq!(crate::error::exit_code(&e), 3);
+    }
+
+    #[test]
+    fn child_failures_keep_their_exit_code_and_retryability() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let outcome = Outcome::parse(concat!(
+            "stray output\n",
+            r#"{"measurements":{"memory":{"bytes":1024}},"degradations":["slow"],"retries":2,"#,
+            r#""verification":{"checked":3,"mismatch_count":0,"passed":true},"#,
+            r#""failure":{"message":"Memory allocation of 1024 bytes failed","code":3,"transient":true}}"#,
+        ));
+        assert_eq!(outcome.measurements["memory"]["bytes"], 1024);
+        assert_eq!(outcome.degradations, ["slow"]);
+        assert_eq!(outcome.retries, 2);
+        assert_eq!(outcome.verification.unwrap().checked, 3);
+        let e = check("memory", ExitStatus::from_raw(3 << 8), outcome.failure).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Isolated memory stressor failed: Memory allocation of 1024 bytes failed"
+        );
+        assert_eq!(crate::error::exit_code(&e), 3);
+        assert!(e.downcast_ref::<StressError>().unwrap().is_transient());
+        assert!(Outcome::parse("").failure.is_none());
+    }
+}
@@ -0,0 +1,124 @@
+use std::sync::OnceLock;
+
+use crate::cgroup;
+
+static LIMITS: OnceLock<PodLimits> = OnceLock::new();
+
+/// Requests and limits of the container, as exposed through the downward API
+/// or, failing that, the cgroup the process runs in.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PodLimits {
+    pub cpu_request: Option<f64>,
+    pub cpu_limit: Option<f64>,
+    pub memory_request: Option<u64>,
+    pub memory_limit: Option<u64>,
+}
+
+/// Reads the limits from `ITSMINE_{CPU,MEMORY}_{REQUEST,LIMIT}` (wired up via
+/// `resourceFieldRef` in the pod spec), falling back to the cgroup limits,
+/// and keeps them for the stressors to check against.
+pub fn init() -> PodLimits {
+    let var = |name| std::env::var(name).ok();
+    let cgroup = cgroup::Limits::detect();
+    let limits = PodLimits {
+        cpu_request: var("ITSMINE_CPU_REQUEST").and_then(|v| parse_cpu(&v)),
+        cpu_limit: var("ITSMINE_CPU_LIMIT")
+            .and_then(|v| parse_cpu(&v))
+            .or(cgroup.cpu_max),
+        memory_request: var("ITSMINE_MEMORY_REQUEST").and_then(|v| parse_memory(&v)),
+        memory_limit: var("ITSMINE_MEMORY_LIMIT")
+            .and_then(|v| parse_memory(&v))
+            .or(cgroup.memory_max),
+    };
+    log::info!(
+        "Container limits: cpu request={:?} limit={:?}, memory request={:?} limit={:?}.",
+        limits.cpu_request,
+        limits.cpu_limit,
+        limits.memory_request,
+        limits.memory_limit
+    );
+    LIMITS.get_or_init(|| limits);
+    limits
+}
+
+/// Warns when a memory stressor would push the container past its limit,
+/// which gets it OOM-killed rather than reported.
+pub fn check_memory(bytes: u64) {
+    let Some(limits) = LIMITS.get() else { return };
+    if let Some(limit) = limits.memory_limit
+        && bytes >= limit
+    {
+        log::warn!(
+            "Allocating {bytes} bytes exceeds the container memory limit of {limit} bytes; expect an OOM kill."
+        );
+    } else if let Some(request) = limits.memory_request
+        && bytes > request
+    {
+        log::warn!(
+            "Allocating {bytes} bytes exceeds the container memory request of {request} bytes; the pod may be evicted under node pressure."
+        );
+    }
+}
+
+/// Warns when more busy threads are started than the CPU limit allows, so
+/// the results reflect CFS throttling rather than the node.
+pub fn check_threads(threads: u32) {
+    let Some(limits) = LIMITS.get() else { return };
+    if let Some(limit) = limits.cpu_limit
+        && f64::from(threads) > limit
+    {
+        log::warn!(
+            "Running {threads} threads under a CPU limit of {limit:.2} cores; expect throttling."
+        );
+    }
+}
+
+/// Parses a Kubernetes CPU quantity: cores (`2`, `0.5`) or millicores (`500m`).
+fn parse_cpu(s: &str) -> Option<f64> {
+    let s = s.trim();
+    let cores = match s.strip_suffix('m') {
+        Some(millis) => millis.parse::<f64>().ok()? / 1000.0,
+        None => s.parse().ok()?,
+    };
+    (cores > 0.0).then_some(cores)
+}
+
+/// Parses a Kubernetes memory quantity: bytes, or a decimal (`k`, `M`, `G`)
+/// or binary (`Ki`, `Mi`, `Gi`) suffix.
+fn parse_memory(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (value, suffix) = s.split_at(split);
+    let multiplier: u64 = match suffix {
+        "" => 1,
+        "k" => 1000,
+        "M" => 1000 * 1000,
+        "G" => 1000 * 1000 * 1000,
+        "Ki" => 1024,
+        "Mi" => 1024 * 1024,
+        "Gi" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+    value.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_cpu_quantities() {
+        assert_eq!(parse_cpu("2"), Some(2.0));
+        assert_eq!(parse_cpu("500m"), Some(0.5));
+        assert_eq!(parse_cpu("0"), None);
+        assert_eq!(parse_cpu("lots"), None);
+    }
+
+    #[test]
+    fn parse_memory_quantities() {
+        assert_eq!(parse_memory("1048576"), Some(1024 * 1024));
+        assert_eq!(parse_memory("512Mi"), Some(512 * 1024 * 1024));
+        assert_eq!(parse_memory("1G"), Some(1_000_000_000));
+        assert_eq!(parse_memory("1X"), None);
+    }
+}
@@ -0,0 +1,146 @@
+use std::fs::File;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::os::unix::fs::OpenOptionsExt;
+
+use serde::Serialize;
+
+const KMSG_PATH: &str = "/dev/kmsg";
+
+/// Largest record the kernel hands out in one read.
+const RECORD_MAX: usize = 8192;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KernelEventKind {
+    OomKill,
+    HungTask,
+    MachineCheck,
+}
+
+/// A kernel log line relevant to a stress run.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct KernelEvent {
+    /// Microseconds since boot, as stamped by the kernel.
+    pub timestamp_us: u64,
+    pub kind: KernelEventKind,
+    pub message: String,
+}
+
+/// Follows the kernel log from the moment it is opened, so only messages
+/// emitted during the run are collected.
+pub struct KmsgWatcher {
+    file: File,
+}
+
+impl KmsgWatcher {
+    /// Opens the kernel log, or returns `None` if it is not readable (e.g. when
+    /// unprivileged with `kernel.dmesg_restrict=1`).
+    pub fn open() -> Option<Self> {
+        let mut file = match std::fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(KMSG_PATH)
+        {
+            Ok(file) => file,
+            Err(e) => {
+                log::debug!("Cannot read {KMSG_PATH}, kernel events disabled: {e}");
+                return None;
+            }
+        };
+        // Skip everything logged before the run started.
+        file.seek(SeekFrom::End(0)).ok()?;
+        Some(KmsgWatcher { file })
+    }
+
+    /// Returns the relevant events logged since the last call.
+    pub fn drain(&mut self) -> Vec<KernelEvent> {
+        let mut events = vec![];
+        let mut buf = vec![0u8; RECORD_MAX];
+        loop {
+            match self.file.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    let record = String::from_utf8_lossy(&buf[..n]);
+                    events.extend(parse_record(&record));
+                }
+                // Records were overwritten before we read them; carry on.
+                Err(e) if e.kind() == ErrorKind::BrokenPipe => continue,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    log::debug!("Failed to read {KMSG_PATH}: {e}");
+                    break;
+                }
+            }
+        }
+        events
+    }
+}
+
+/// Parses a `/dev/kmsg` record (`prio,seq,usec,flags;message`).
+fn parse_record(record: &str) -> Option<KernelEvent> {
+    let (header, message) = record.split_once(';')?;
+    let timestamp_us = header.split(',').nth(2)?.parse().ok()?;
+    // Continuation lines (dictionary entries) start with a space.
+    let message = message.lines().next()?.trim().to_string();
+    let kind = classify(&message)?;
+    Some(KernelEvent {
+        timestamp_us,
+        kind,
+        message,
+    })
+}
+
+fn classify(message: &str) -> Option<KernelEventKind> {
+    let lower = message.to_lowercase();
+    if lower.contains("out of memory")
+        || lower.contains("oom-kill")
+        || lower.contains("killed process")
+    {
+        Some(KernelEventKind::OomKill)
+    } else if lower.contains("blocked for more than") {
+        Some(KernelEventKind::HungTask)
+    } else if lower.contains("machine check") || lower.starts_with("mce:") {
+        Some(KernelEventKind::MachineCheck)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_record_detects_oom_kill() {
+        let record =
+            "3,1234,5678901,-;Out of memory: Killed process 4242 (itsmine) total-vm:1024kB\n";
+        let event = parse_record(record).unwrap();
+        assert_eq!(event.kind, KernelEventKind::OomKill);
+        assert_eq!(event.timestamp_us, 5678901);
+        assert!(event.message.starts_with("Out of memory"));
+    }
+
+    #[test]
+    fn parse_record_detects_hung_task() {
+        let record = "3,1,10,-;INFO: task kworker:12 blocked for more than 120 seconds.";
+        assert_eq!(
+            parse_record(record).unwrap().kind,
+            KernelEventKind::HungTask
+        );
+    }
+
+    #[test]
+    fn parse_record_detects_machine_check() {
+        let record = "2,1,10,-;mce: [Hardware Error]: Machine check events logged";
+        assert_eq!(
+            parse_record(record).unwrap().kind,
+            KernelEventKind::MachineCheck
+        );
+    }
+
+    #[test]
+    fn parse_record_ignores_unrelated_and_malformed() {
+        assert!(parse_record("6,1,10,-;eth0: link up").is_none());
+        assert!(parse_record("no header here").is_none());
+    }
+}
@@ -0,0 +1,1039 @@
+use clap::Subcommand;
+use serde_json::json;
+use std::time::Instant;
+
+pub use cancel::CancellationToken;
+pub use error::StressError;
+pub use registry::Stressor;
+
+pub mod address_space;
+#[cfg(feature = "async")]
+pub mod async_api;
+pub mod balloon;
+pub mod bank;
+pub mod barrier;
+pub mod buffer;
+pub mod cancel;
+pub mod caps;
+pub mod cgroup;
+pub mod chaos;
+pub mod clocks;
+pub mod compare;
+pub mod cpufreq;
+pub mod disk;
+pub mod dns;
+pub mod duty;
+pub mod edac;
+pub mod entropy;
+pub mod error;
+pub mod estimate;
+pub mod events;
+pub mod exec;
+pub mod forks;
+pub mod health;
+pub mod heartbeat;
+#[cfg(feature = "history")]
+pub mod history;
+pub mod html;
+pub mod http;
+pub mod http_load;
+pub mod inject;
+pub mod interference;
+pub mod inversion;
+pub mod isolate;
+pub mod k8s;
+pub mod kmsg;
+pub mod links;
+pub mod locks;
+pub mod logging;
+pub mod membench;
+pub mod mix;
+pub mod mmap_churn;
+pub mod monitor;
+pub mod mq;
+pub mod namespace;
+pub mod net;
+pub mod ng;
+pub mod oom;
+pub mod parse;
+pub mod perf;
+pub mod placement;
+#[cfg(feature = "plugins")]
+pub mod plugin;
+pub mod power;
+pub mod privileges;
+pub mod probe_limits;
+#[cfg(feature = "profiling")]
+pub mod profile;
+pub mod pty;
+pub mod ramp;
+pub mod registry;
+pub mod replay;
+pub mod report;
+pub mod retry;
+pub mod sandbox;
+pub mod scenario;
+pub mod sched;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod selftest;
+pub mod sem;
+pub mod shutdown;
+pub mod stats;
+pub mod sysinfo;
+pub mod systemd;
+pub mod telemetry;
+pub mod template;
+pub mod thermal;
+pub mod throttle;
+pub mod tlb;
+pub mod trend;
+pub mod udp_flood;
+pub mod until;
+pub mod validate;
+pub mod verify;
+pub mod watches;
+pub mod websocket;
+pub mod zombies;
+
+#[derive(Clone, Debug, PartialEq, Subcommand)]
+pub enum Resource {
+    /// Allocate a block of memory and touch every page
+    Memory {
+        /// Size with a B, K, M, G or T suffix, e.g. 512M; with --bench, the
+        /// largest buffer measured (256M if omitted)
+        #[arg(
+            required_unless_present = "bench",
+            default_value = "256M",
+            hide_default_value = true
+        )]
+        arg: String,
+        /// Measure the memory subsystem instead of loading it
+        #[arg(long, value_enum)]
+        bench: Option<membench::Bench>,
+    },
+    /// Run the thread workload on several threads and compare results
+    Thread {
+        /// Number of threads
+        num: u32,
+    },
+    /// Run a scenario file of sequential phases
+    Run {
+        /// Path to the scenario file
+        scenario: std::path::PathBuf,
+    },
+    /// Randomly start, stop and reshape stressors within a budget
+    Chaos {
+        /// Upper bounds, e.g. cpu=50%,mem=4G
+        #[arg(long, env = "ITSMINE_CHAOS_BUDGET")]
+        budget: chaos::Budget,
+        /// How long to run, e.g. 10m
+        #[arg(long, value_parser = parse::duration, env = "ITSMINE_CHAOS_DURATION")]
+        duration: std::time::Duration,
+        /// Seed for reproducing a previous run
+        #[arg(long, env = "ITSMINE_CHAOS_SEED")]
+        seed: Option<u64>,
+    },
+    /// Reproduce a timestamped CPU%/memory trace (CSV)
+    ReplayTrace {
+        /// Path to the CSV trace
+        trace: std::path::PathBuf,
+    },
+    /// A registered stressor in a scenario phase, built from the registry
+    /// by name when the phase starts
+    #[command(skip)]
+    Registered {
+        name: &'static str,
+        args: Vec<String>,
+    },
+}
+
+impl Resource {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Resource::Memory { .. } => "memory",
+            Resource::Thread { .. } => "thread",
+            Resource::Run { .. } => "scenario",
+            Resource::Chaos { .. } => "chaos",
+            Resource::ReplayTrace { .. } => "replay-trace",
+            Resource::Registered { name, .. } => name,
+        }
+    }
+
+    /// The stressor's parameters as recorded in events and reports.
+    pub fn params(&self) -> serde_json::Value {
+        match self {
+            Resource::Memory { arg, bench: None } => json!({ "size": arg }),
+            Resource::Memory {
+                arg,
+                bench: Some(bench),
+            } => json!({ "size": arg, "bench": bench.name() }),
+            Resource::Thread { num } => json!({ "threads": num }),
+            Resource::Run { scenario } => json!({ "path": scenario }),
+            Resource::Chaos {
+                budget,
+                duration,
+                seed,
+            } => json!({
+                "cpu_percent": budget.cpu_percent,
+                "mem_bytes": budget.mem_bytes,
+                "duration_secs": duration.as_secs_f64(),
+                "seed": seed,
+            }),
+            Resource::ReplayTrace { trace } => json!({ "trace": trace }),
+            Resource::Registered { args, .. } => json!({ "args": args }),
+        }
+    }
+}
+
+/// How many bytes the memory stressor touches between progress and
+/// cancellation checks.
+const TOUCH_CHUNK_BYTES: usize = 1024 * 1024;
+
+#[derive(Clone)]
+pub struct Memory {
+    pub size: u64,
+    pub multiplier: u64,
+}
+
+pub struct Thread(pub u32);
+
+impl Memory {
+    pub fn from_resource(res: Resource) -> Result<Self, StressError> {
+        match res {
+            Resource::Memory { .. } => {}
+            other => {
+                return Err(StressError::WrongResource {
+                    expected: "Memory",
+                    got: other.name(),
+                });
+            }
+        }
+
+        let size_str = match res {
+            Resource::Memory { arg, .. } => arg,
+            _ => unreachable!(),
+        };
+
+        let Some((suffix, multiplier)) = size_str
+            .chars()
+            .last()
+            .and_then(|suffix| Some((suffix, parse::size_multiplier(suffix)?)))
+        else {
+            return Err(StressError::InvalidSize(
+                "Invalid memory size suffix. Use B, K, M, G or T.".to_string(),
+            ));
+        };
+
+        let size = size_str
+            .strip_suffix(suffix)
+            .ok_or_else(|| StressError::InvalidSize(format!("Invalid memory size {size_str}")))
+            .and_then(|s| {
+                s.parse::<u64>().map_err(|e| {
+                    StressError::InvalidSize(format!("Failed to parse memory size '{s}': {e}"))
+                })
+            })?;
+
+        // drop(res);
+
+        Ok(Memory { size, multiplier })
+    }
+
+    /// The size in bytes, failing if it overflows.
+    pub fn total(&self) -> Result<u64, StressError> {
+        self.size
+            .checked_mul(self.multiplier)
+            .ok_or_else(|| StressError::InvalidSize("Memory size is too large".to_string()))
+    }
+
+    pub fn execute(self, cancel: &CancellationToken) -> Result<(), StressError> {
+        self.run(None, cancel)
+    }
+
+    /// Like `execute`, but holds the memory until `deadline` before releasing
+    /// it.
+    pub fn execute_until(
+        self,
+        deadline: Instant,
+        cancel: &CancellationToken,
+    ) -> Result<(), StressError> {
+        self.run(Some(deadline), cancel)
+    }
+
+    fn run(self, deadline: Option<Instant>, cancel: &CancellationToken) -> Result<(), StressError> {
+        let total_size = self.total()?;
+        if total_size == 0 {
+            return Err(StressError::InvalidSize(
+                "Memory size must be greater than 0".to_string(),
+            ));
+        }
+        let total_size = usize::try_from(total_size)
+            .map_err(|_| StressError::InvalidSize("Memory size is too large".to_string()))?;
+        log::info!("Allocating {} bytes of memory.", total_size);
+
+        let allocate = telemetry::span("memory.allocate");
+        let mut buffer = retry::run("allocate memory", cancel, || {
+            buffer::Buffer::allocate(total_size)
+        })?;
+        drop(allocate);
+        telemetry::gauge("memory.allocated_bytes", "By", total_size as u64);
+        events::emit("allocation_complete", json!({ "bytes": total_size }));
+
+        // dummy usage of allocated memory
+        log::info!("Dummy usage of allocated memory...");
+        let touch = telemetry::span("memory.touch");
+        let report_every = (total_size / 10).max(1);
+        let mut interrupted = false;
+        while buffer.touched() < total_size {
+            let before = buffer.touched();
+            buffer.touch_to(before + TOUCH_CHUNK_BYTES);
+            let touched = buffer.touched();
+            if touched / report_every > before / report_every {
+                telemetry::gauge("memory.touched_bytes", "By", touched as u64);
+            }
+            if log::log_enabled!(log::Level::Debug) {
+                eprint!("used byte {}\r", touched - 1);
+            }
+            if cancel.is_cancelled() {
+                interrupted = true;
+                break;
+            }
+        }
+        drop(touch);
+        log::info!("Memory allocation and usage complete.");
+
+        if let Some(deadline) = deadline
+            && !interrupted
+        {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            log::info!(
+                "Holding {total_size} bytes for {:.1}s.",
+                remaining.as_secs_f64()
+            );
+            interrupted = !cancel.sleep_until(deadline);
+        }
+
+        let free = telemetry::span("memory.free");
+        drop(buffer);
+        drop(free);
+        events::emit("memory_released", json!({ "bytes": total_size }));
+        match interrupted {
+            true => Err(StressError::Interrupted),
+            false => Ok(()),
+        }
+    }
+}
+
+impl Thread {
+    pub fn new(num: u32) -> Self {
+        Thread(num)
+    }
+
+    pub fn from_resource(res: Resource) -> Result<Self, StressError> {
+        match res {
+            Resource::Thread { num } => Ok(Thread::new(num)),
+            other => Err(StressError::WrongResource {
+                expected: "Thread",
+                got: other.name(),
+            }),
+        }
+    }
+
+    pub fn execute(self, cancel: &CancellationToken) -> Result<(), StressError> {
+        self.run(None, cancel)
+    }
+
+    /// Like `execute`, but each thread repeats the workload until `deadline`.
+    pub fn execute_until(
+        self,
+        deadline: Instant,
+        cancel: &CancellationToken,
+    ) -> Result<(), StressError> {
+        self.run(Some(deadline), cancel)
+    }
+
+    fn run(self, deadline: Option<Instant>, cancel: &CancellationToken) -> Result<(), StressError> {
+        if self.0 == 0 {
+            return Err(StressError::InvalidInput(
+                "Thread count must be greater than 0".to_string(),
+            ));
+        }
+        log::info!("Spawning {} threads.", self.0);
+        if let Some(slots) = placement::slots()
+            && self.0 as usize > slots
+        {
+            log::warn!(
+                "{} threads but the placement uses {slots} CPUs; some threads share one.",
+                self.0
+            );
+        }
+        let freq_monitor = monitor::FreqMonitor::start(std::time::Duration::from_millis(100));
+        let mut handles = vec![];
+        telemetry::gauge("thread.count", "{thread}", self.0 as u64);
+
+        let kinds = mix::configured().map(|mix| mix.assign(self.0));
+        let started = Instant::now();
+        let (tx, rx) = std::sync::mpsc::channel::<verify::Observation>();
+        let spawn = telemetry::span("thread.spawn");
+        // Lets a failed spawn wind down the workers already running.
+        let workers = cancel.child_token();
+        let sync = barrier::policy();
+        let barrier = sync
+            .is_enabled()
+            .then(|| std::sync::Arc::new(barrier::Barrier::new(self.0 as usize)));
+
+        for i in 0..self.0 {
+            let spawned = retry::run("spawn thread", cancel, || {
+                let tx = tx.clone();
+                let cancel = workers.clone();
+                let barrier = barrier.clone();
+                let kind = kinds.as_ref().map(|kinds| kinds[i as usize]);
+                std::thread::Builder::new()
+                    .spawn(move || {
+                        worker(i, kind, deadline, &cancel, &tx, barrier.as_deref(), sync)
+                    })
+                    .map_err(StressError::SpawnFailed)
+            });
+            match spawned {
+                Ok(handle) => handles.push(handle),
+                Err(e) => {
+                    workers.cancel();
+                    return Err(e);
+                }
+            }
+        }
+        drop(tx);
+        drop(spawn);
+
+        let join = telemetry::span("thread.join");
+        let kind_of = |thread: u32| kinds.as_ref().map(|kinds| kinds[thread as usize]);
+        let default_expectation = expectation(None);
+        let mut verifier = verify::Verifier::new();
+        let mut iterations = std::collections::BTreeMap::<Option<mix::Kind>, u64>::new();
+        for seen in rx {
+            let kind = kind_of(seen.thread);
+            let (workload, expectation) = match kind {
+                Some(kind) => (kind.name(), expectation(Some(kind))),
+                None => (default_workload_name(), default_expectation),
+            };
+            verifier.check(workload, expectation, seen);
+            *iterations.entry(kind).or_default() += 1;
+            telemetry::gauge("thread.completed", "{thread}", iterations.values().sum());
+            log::debug!("Received from thread {}: {}", seen.thread, seen.result);
+        }
+        let mut outcome = Ok(());
+        for handle in handles {
+            match handle.join() {
+                Ok(Ok(false)) => {}
+                Ok(Ok(true)) => outcome = outcome.and(Err(StressError::Interrupted)),
+                Ok(Err(e)) => outcome = Err(e),
+                Err(payload) => {
+                    outcome = Err(StressError::WorkerPanicked(panic_message(&payload)));
+                }
+            }
+        }
+
+        drop(join);
+        if inject::configured().is_some() {
+            report::measure("injected_faults", inject::struck());
+        }
+
+        if let Some(freq_monitor) = freq_monitor {
+            freq_monitor.stop().record();
+        }
+        outcome?;
+
+        let _verify = telemetry::span("thread.verify");
+        if !verifier.finish() {
+            return Err(StressError::WorkloadMismatch);
+        }
+        if let Some(kinds) = &kinds {
+            let elapsed = started.elapsed().as_secs_f64();
+            let mut by_kind = serde_json::Map::new();
+            for kind in kinds.iter().collect::<std::collections::BTreeSet<_>>() {
+                let threads = kinds.iter().filter(|k| *k == kind).count();
+                let iterations = iterations.get(&Some(*kind)).copied().unwrap_or(0);
+                by_kind.insert(
+                    kind.name().to_string(),
+                    json!({
+                        "threads": threads,
+                        "iterations": iterations,
+                        "iterations_per_sec": iterations as f64 / elapsed,
+                    }),
+                );
+            }
+            report::measure("mix", serde_json::Value::Object(by_kind));
+        }
+        log::info!("All threads completed.");
+        Ok(())
+    }
+}
+
+/// Body of thread stressor worker `i`: repeats the workload until
+/// `deadline` (or once without one), sending each result to `tx`. With a
+/// `barrier`, lines up with the other workers as `sync` asks. Returns whether
+/// it was interrupted.
+fn worker(
+    i: u32,
+    kind: Option<mix::Kind>,
+    deadline: Option<Instant>,
+    cancel: &CancellationToken,
+    tx: &std::sync::mpsc::Sender<verify::Observation>,
+    barrier: Option<&barrier::Barrier>,
+    sync: barrier::SyncPolicy,
+) -> Result<bool, StressError> {
+    log::debug!("Thread {i} started.");
+    placement::pin(i);
+    let party = barrier.map(barrier::Barrier::party);
+    if let Some(party) = &party
+        && sync.start
+        && !party.wait(cancel)
+    {
+        return Ok(true);
+    }
+    let mut iterations = 0u64;
+    survive_panics(i, on_worker_panic(), deadline, cancel, || {
+        work(
+            i,
+            kind,
+            deadline,
+            cancel,
+            tx,
+            party.as_ref(),
+            sync,
+            &mut iterations,
+        )
+    })
+}
+
+/// How often `--on-worker-panic restart` restarts one worker.
+const MAX_WORKER_RESTARTS: u32 = 5;
+
+static ON_WORKER_PANIC: std::sync::OnceLock<OnWorkerPanic> = std::sync::OnceLock::new();
+
+/// What a thread stressor does when one of its workers panics.
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub enum OnWorkerPanic {
+    /// Fail the run.
+    #[default]
+    Fail,
+    /// Restart the worker, up to a few times, then degrade.
+    Restart,
+    /// Let the worker end and mark the run degraded.
+    Degrade,
+}
+
+pub fn set_on_worker_panic(policy: OnWorkerPanic) {
+    let _ = ON_WORKER_PANIC.set(policy);
+}
+
+fn on_worker_panic() -> OnWorkerPanic {
+    ON_WORKER_PANIC.get().copied().unwrap_or_default()
+}
+
+/// Runs worker `i`'s `body`, handling a panic in it as `policy` says.
+fn survive_panics(
+    i: u32,
+    policy: OnWorkerPanic,
+    deadline: Option<Instant>,
+    cancel: &CancellationToken,
+    mut body: impl FnMut() -> Result<bool, StressError>,
+) -> Result<bool, StressError> {
+    let mut restarts = 0;
+    loop {
+        let payload = match std::panic::catch_unwind(std::panic::AssertUnwindSafe(&mut body)) {
+            Ok(outcome) => return outcome,
+            Err(payload) => panic_message(&payload),
+        };
+        log::warn!("Thread {i} panicked: {payload}");
+        let running = !cancel.is_cancelled() && deadline.is_none_or(|d| Instant::now() < d);
+        match policy {
+            OnWorkerPanic::Fail => return Err(StressError::WorkerPanicked(payload)),
+            OnWorkerPanic::Restart if running && restarts < MAX_WORKER_RESTARTS => {
+                restarts += 1;
+                log::warn!("Restarting thread {i} ({restarts}/{MAX_WORKER_RESTARTS}).");
+                events::emit(
+                    "thread_restarted",
+                    json!({ "thread": i, "restarts": restarts, "panic": payload }),
+                );
+            }
+            OnWorkerPanic::Restart | OnWorkerPanic::Degrade => {
+                report::degrade(format!("thread {i} panicked: {payload}"));
+                return Ok(false);
+            }
+        }
+    }
+}
+
+/// The workload loop of worker `i`, counting `iterations` across restarts.
+#[allow(clippy::too_many_arguments)]
+fn work(
+    i: u32,
+    kind: Option<mix::Kind>,
+    deadline: Option<Instant>,
+    cancel: &CancellationToken,
+    tx: &std::sync::mpsc::Sender<verify::Observation>,
+    party: Option<&barrier::Party>,
+    sync: barrier::SyncPolicy,
+    iterations: &mut u64,
+) -> Result<bool, StressError> {
+    let mut workload = workload(i, kind)?;
+    let mut cycle = duty::cycle();
+    let mut injector = inject::armed();
+    loop {
+        let fib = workload()?;
+        *iterations += 1;
+        heartbeat::tick();
+        // The receiver outlives every worker.
+        let _ = tx.send(verify::Observation::here(i, *iterations, fib));
+        let interrupted = deadline.is_some() && cancel.is_cancelled();
+        let finished = deadline.is_none_or(|d| Instant::now() >= d) || interrupted;
+        if let Some(party) = party
+            && !finished
+            && sync.resync_after(*iterations)
+            && !party.wait(cancel)
+        {
+            return Ok(deadline.is_some());
+        }
+        if let Some(cycle) = &mut cycle
+            && !finished
+            && !cycle.pace(cancel, deadline)
+        {
+            return Ok(deadline.is_some());
+        }
+        if let Some(injector) = &mut injector
+            && !finished
+            && !injector.strike(i, cancel)
+        {
+            return Ok(deadline.is_some());
+        }
+        if finished {
+            log::debug!("Thread {i} finished. Result = {fib}");
+            events::emit(
+                "thread_finished",
+                json!({ "thread": i, "result": fib, "iterations": iterations }),
+            );
+            return Ok(interrupted);
+        }
+    }
+}
+
+type Workload = Box<dyn FnMut() -> Result<u32, StressError>>;
+
+/// One iteration of a thread stressor: the thread's `--mix` kind if one was
+/// assigned, the user's plugin or script when `--plugin` or
+/// `--workload-script` is given, otherwise Fibonacci(30).
+#[cfg_attr(
+    not(any(feature = "scripting", feature = "plugins")),
+    allow(unused_variables)
+)]
+fn workload(thread: u32, kind: Option<mix::Kind>) -> Result<Workload, StressError> {
+    if let Some(kind) = kind {
+        let mut iterate = kind.workload();
+        return Ok(Box::new(move || Ok(iterate())));
+    }
+    #[cfg(feature = "plugins")]
+    if let Some(plugin) = plugin::loaded() {
+        let mut runner = plugin
+            .runner(thread)
+            .map_err(|e| StressError::WorkloadFailed(format!("plugin setup: {e}")))?;
+        return Ok(Box::new(move || runner.iterate()));
+    }
+    #[cfg(feature = "scripting")]
+    if let Some(script) = script::loaded() {
+        let mut runner = script.runner();
+        return Ok(Box::new(move || runner.iterate(thread)));
+    }
+    Ok(Box::new(|| Ok(fibonacci(30))))
+}
+
+/// What a thread running `kind`, or the default workload, must compute.
+fn expectation(kind: Option<mix::Kind>) -> verify::Expectation {
+    if let Some(kind) = kind {
+        return verify::Expectation::Value(kind.expected());
+    }
+    match default_workload_name() {
+        "fibonacci" => verify::Expectation::Value(FIBONACCI_30),
+        _ => verify::Expectation::Agreement,
+    }
+}
+
+fn default_workload_name() -> &'static str {
+    #[cfg(feature = "plugins")]
+    if plugin::loaded().is_some() {
+        return "plugin";
+    }
+    #[cfg(feature = "scripting")]
+    if script::loaded().is_some() {
+        return "script";
+    }
+    "fibonacci"
+}
+
+/// Fibonacci(30), the built-in workload's answer.
+pub(crate) const FIBONACCI_30: u32 = 832_040;
+
+pub(crate) fn fibonacci(n: u32) -> u32 {
+    if n <= 1 {
+        return n;
+    }
+    fibonacci(n - 1) + fibonacci(n - 2)
+}
+
+/// Runs the requested stressor, turning a panic into an error so the run can
+/// still be reported.
+pub fn run_stressor(resource: Resource, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+    std::panic::catch_unwind(move || match resource {
+        Resource::Run { scenario } => scenario::Scenario::load(&scenario)?.run(cancel),
+        Resource::Chaos {
+            budget,
+            duration,
+            seed,
+        } => chaos::Chaos {
+            budget,
+            duration,
+            seed: seed.unwrap_or_else(chaos::random_seed),
+        }
+        .run(cancel),
+        Resource::ReplayTrace { trace } => replay::Replay::load(&trace)?.run(cancel),
+        resource => execute(resource, None, cancel),
+    })
+    .map_err(|payload| anyhow::anyhow!("Stressor panicked: {}", panic_message(&payload)))?
+}
+
+/// Executes a single stressor, holding it until `deadline` if one is given
+/// or until `cancel` fires.
+pub fn execute(
+    resource: Resource,
+    deadline: Option<Instant>,
+    cancel: &CancellationToken,
+) -> Result<(), anyhow::Error> {
+    if isolate::enabled()
+        && let Some(outcome) = isolate::execute(&resource, deadline, cancel)
+    {
+        return outcome;
+    }
+    match resource {
+        Resource::Memory {
+            arg,
+            bench: Some(bench),
+        } => {
+            let bytes = parse::bytes(&arg).map_err(|e| StressError::InvalidSize(e.to_string()))?;
+            membench::run(bench, bytes, cancel)
+        }
+        Resource::Memory { .. } => {
+            let memory = Memory::from_resource(resource)?;
+            k8s::check_memory(memory.total()?);
+            let edac = edac::EdacMonitor::start(std::time::Duration::from_secs(1));
+            let outcome = match deadline {
+                Some(deadline) => memory.execute_until(deadline, cancel),
+                None => memory.execute(cancel),
+            };
+            // Stop the monitor even if the stressor failed.
+            let ecc = edac.map_or(Ok(()), edac::EdacMonitor::stop);
+            outcome.and(ecc)?;
+            Ok(())
+        }
+        Resource::Thread { .. } => {
+            let thread = Thread::from_resource(resource)?;
+            k8s::check_threads(thread.0);
+            match deadline {
+                Some(deadline) => thread.execute_until(deadline, cancel)?,
+                None => thread.execute(cancel)?,
+            }
+            Ok(())
+        }
+        Resource::Registered { name, args } => {
+            let stressor = registry::stressor(name, &args)?;
+            let Some(deadline) = deadline else {
+                return registry::run(&*stressor, cancel);
+            };
+            // Stopped at the deadline like memory and thread stressors are,
+            // which counts as finishing rather than being interrupted.
+            let stop = cancel.child_token();
+            let finished = stop.child_token();
+            let outcome = std::thread::scope(|s| {
+                s.spawn(|| {
+                    if finished.sleep_until(deadline) {
+                        stop.cancel();
+                    }
+                });
+                let outcome = registry::run(&*stressor, &stop);
+                finished.cancel();
+                outcome
+            });
+            match outcome {
+                Err(e)
+                    if !cancel.is_cancelled()
+                        && matches!(e.downcast_ref(), Some(StressError::Interrupted)) =>
+                {
+                    Ok(())
+                }
+                outcome => outcome,
+            }
+        }
+        other => Err(anyhow::anyhow!(
+            "{} cannot run inside a scenario",
+            other.name()
+        )),
+    }
+}
+
+pub fn panic_message(payload: &Box<dyn std::any::Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// A stressor running on a background thread, for driving itsmine from other
+/// programs instead of the command line.
+pub struct Stress {
+    handle: std::thread::JoinHandle<Result<(), anyhow::Error>>,
+    cancel: CancellationToken,
+}
+
+impl Stress {
+    /// Starts `resource`, holding it for `duration` if one is given.
+    pub fn start(resource: Resource, duration: Option<std::time::Duration>) -> Self {
+        let deadline = duration.map(|d| Instant::now() + d);
+        let cancel = CancellationToken::new();
+        let token = cancel.clone();
+        let handle = std::thread::spawn(move || {
+            std::panic::catch_unwind(move || match deadline {
+                Some(deadline) => execute(resource, Some(deadline), &token),
+                None => run_stressor(resource, &token),
+            })
+            .map_err(|payload| anyhow::anyhow!("Stressor panicked: {}", panic_message(&payload)))?
+        });
+        Stress { handle, cancel }
+    }
+
+    /// Asks the stressor to tear down early; `wait` then returns promptly.
+    pub fn stop(&self) {
+        self.cancel.cancel();
+    }
+
+    /// Token cancelling this stressor, e.g. to hand to other components.
+    pub fn cancel_token(&self) -> &CancellationToken {
+        &self.cancel
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Blocks until the stressor completes and returns its outcome. Being
+    /// interrupted by `stop` counts as success.
+    pub fn wait(self) -> Result<(), anyhow::Error> {
+        let outcome = self.handle.join().unwrap_or_else(|payload| {
+            Err(anyhow::anyhow!(
+                "Stressor panicked: {}",
+                panic_message(&payload)
+            ))
+        });
+        match outcome {
+            Err(e) if self.cancel.is_cancelled() && error::is_interrupted(&e) => Ok(()),
+            outcome => outcome,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn worker_panics_follow_the_policy() {
+        let cancel = CancellationToken::new();
+        let deadline = Some(Instant::now() + std::time::Duration::from_secs(60));
+        let panicking = |times: u32| {
+            let mut calls = 0;
+            move || {
+                calls += 1;
+                if calls <= times {
+                    panic!("worker bug");
+                }
+                Ok(false)
+            }
+        };
+        assert!(matches!(
+            survive_panics(0, OnWorkerPanic::Fail, deadline, &cancel, panicking(1)),
+            Err(StressError::WorkerPanicked(message)) if message == "worker bug"
+        ));
+        assert!(
+            !survive_panics(0, OnWorkerPanic::Restart, deadline, &cancel, panicking(2)).unwrap()
+        );
+        survive_panics(7, OnWorkerPanic::Degrade, deadline, &cancel, panicking(1)).unwrap();
+        assert!(
+            report::take_degradations()
+                .iter()
+                .any(|reason| reason == "thread 7 panicked: worker bug")
+        );
+    }
+
+    // Memory tests
+    #[test]
+    fn test_memory_allocation() {
+        let memory = Memory {
+            size: 1,
+            multiplier: 1024,
+        };
+        memory.execute(&CancellationToken::new()).unwrap();
+    }
+
+    #[test]
+    fn memory_from_resource_valid_b() {
+        let res = Resource::Memory {
+            arg: "100B".to_string(),
+            bench: None,
+        };
+        let memory = Memory::from_resource(res).unwrap();
+        assert_eq!(memory.size, 100);
+        assert_eq!(memory.multiplier, 1);
+    }
+
+    #[test]
+    fn memory_from_resource_valid_g() {
+        let res = Resource::Memory {
+            arg: "2G".to_string(),
+            bench: None,
+        };
+        let memory = Memory::from_resource(res).unwrap();
+        assert_eq!(memory.size, 2);
+        assert_eq!(memory.multiplier, 1024 * 1024 * 1024);
+    }
+
+    #[test]
+    fn memory_from_resource_invalid_no_suffix() {
+        let res = Resource::Memory {
+            arg: "10".to_string(),
+            bench: None,
+        };
+        let result = Memory::from_resource(res);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn memory_from_resource_invalid_wrong_suffix() {
+        let res = Resource::Memory {
+            arg: "10X".to_string(),
+            bench: None,
+        };
+        let result = Memory::from_resource(res);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn memory_from_resource_invalid_non_numeric() {
+        let res = Resource::Memory {
+            arg: "abcK".to_string(),
+            bench: None,
+        };
+        let result = Memory::from_resource(res);
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Failed to parse memory size 'abc': invalid digit found in string"
+        );
+    }
+
+    #[test]
+    fn memory_from_resource_zero_size() {
+        let res = Resource::Memory {
+            arg: "0K".to_string(),
+            bench: None,
+        };
+        let memory = Memory::from_resource(res).unwrap();
+        assert_eq!(memory.size, 0);
+        assert_eq!(memory.multiplier, 1024);
+    }
+
+    #[test]
+    fn test_memory_execute_zero_size() {
+        let memory = Memory {
+            size: 0,
+            multiplier: 1,
+        };
+        let result = memory.execute(&CancellationToken::new());
+        assert!(matches!(result, Err(StressError::InvalidSize(_))));
+    }
+
+    #[test]
+    fn overflowing_memory_size_is_an_error() {
+        let resource = Resource::Memory {
+            arg: "99999999999T".to_string(),
+            bench: None,
+        };
+        let error = execute(resource, None, &CancellationToken::new()).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<StressError>(),
+            Some(StressError::InvalidSize(_))
+        ));
+    }
+
+    #[test]
+    fn test_memory_execute_large() {
+        let memory = Memory {
+            size: 1,
+            multiplier: 1024 * 1024, // 1M
+        };
+        memory.execute(&CancellationToken::new()).unwrap();
+    }
+
+    #[test]
+    fn memory_from_resource_invalid() {
+        let res = Resource::Thread { num: 4 };
+        let result = Memory::from_resource(res);
+        assert!(result.is_err());
+    }
+
+    // Thread tests
+    #[test]
+    fn thread_from_resource_valid() {
+        let res = Resource::Thread { num: 4 };
+        let thread = Thread::from_resource(res).unwrap();
+        assert_eq!(thread.0, 4);
+    }
+
+    #[test]
+    fn stress_runs_in_background() {
+        let stress = Stress::start(
+            Resource::Thread { num: 1 },
+            Some(std::time::Duration::from_millis(50)),
+        );
+        stress.wait().unwrap();
+        let held = Stress::start(
+            Resource::Memory {
+                arg: "1K".to_string(),
+                bench: None,
+            },
+            Some(std::time::Duration::from_secs(60)),
+        );
+        let started = Instant::now();
+        held.stop();
+        held.wait().unwrap();
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+
+        let failing = Stress::start(
+            Resource::Memory {
+                arg: "0K".to_string(),
+                bench: None,
+            },
+            None,
+        );
+        assert!(failing.wait().is_err());
+    }
+
+    #[test]
+    fn thread_from_resource_invalid() {
+        let res = Resource::Memory {
+            arg: "100K".to_string(),
+            bench: None,
+        };
+        let result = Thread::from_resource(res);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,232 @@
+//! `itsmine links`: builds a deep tree full of symlink chains, symlinks
+//! looping back up, and hardlinks to one file, then walks and resolves it
+//! over and over, the way backup and scanning software would.
+
+use std::collections::HashSet;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+use crate::{CancellationToken, StressError, Stressor, parse, report};
+
+#[derive(Clone, Debug, PartialEq, clap::Args)]
+pub struct Args {
+    /// Directory to build the tree in
+    #[arg(long, value_name = "PATH", default_value_os_t = std::env::temp_dir())]
+    pub dir: PathBuf,
+    /// Nesting depth of the tree
+    #[arg(long, value_name = "N", default_value_t = 32)]
+    pub depth: u32,
+    /// Length of the symlink chain in each directory
+    #[arg(long, value_name = "N", default_value_t = 16)]
+    pub width: u32,
+    /// Hardlinks to one file, spread over the tree
+    #[arg(long, value_name = "N", default_value_t = 1000)]
+    pub hardlinks: u32,
+    /// How long to walk it, e.g. 10s
+    #[arg(long, value_parser = parse::duration, default_value = "10s")]
+    pub duration: Duration,
+}
+
+/// Counts from one walk of the tree.
+#[derive(Debug, Default, PartialEq)]
+struct Walk {
+    entries: u64,
+    /// Symlinks that led somewhere already visited, or nowhere.
+    loops: u64,
+}
+
+/// The tree, removed on drop; removal never follows its symlinks.
+struct Tree {
+    root: PathBuf,
+    /// The deepest directory.
+    bottom: PathBuf,
+}
+
+impl Drop for Tree {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+impl Args {
+    /// Builds the tree: a chain of `depth` directories, each holding `up`
+    /// and `top` symlinks that loop back, a chain of `width` symlinks ending
+    /// at the shared file, and its share of the hardlinks. `loop-a` and `loop-b`
+    /// at the top point at each other.
+    fn build(&self) -> std::io::Result<Tree> {
+        use std::os::unix::fs::symlink;
+
+        let root = self
+            .dir
+            .join(format!("itsmine-links-{}", std::process::id()));
+        std::fs::create_dir_all(&root)?;
+        let mut tree = Tree {
+            root: root.clone(),
+            bottom: root.clone(),
+        };
+        let origin = root.join("origin");
+        std::fs::write(&origin, b"itsmine")?;
+        symlink("loop-b", root.join("loop-a"))?;
+        symlink("loop-a", root.join("loop-b"))?;
+        let mut levels = vec![root.clone()];
+        for level in 1..=self.depth {
+            let dir = levels[level as usize - 1].join(format!("d{level}"));
+            std::fs::create_dir(&dir)?;
+            symlink("..", dir.join("up"))?;
+            symlink(&root, dir.join("top"))?;
+            levels.push(dir);
+        }
+        for (i, dir) in levels
+            .iter()
+            .cycle()
+            .take(self.hardlinks as usize)
+            .enumerate()
+        {
+            std::fs::hard_link(&origin, dir.join(format!("h{i}")))?;
+        }
+        for dir in &levels {
+            let mut target = origin.clone();
+            for link in 0..self.width {
+                symlink(&target, dir.join(format!("s{link}")))?;
+                target = PathBuf::from(format!("s{link}"));
+            }
+        }
+        tree.bottom = levels.pop().expect("at least the root");
+        Ok(tree)
+    }
+}
+
+/// Walks everything under `root`, following symlinks but entering each
+/// directory only once.
+fn walk(root: &Path) -> std::io::Result<Walk> {
+    let mut result = Walk::default();
+    let mut seen = HashSet::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let meta = std::fs::metadata(&dir)?;
+        if !seen.insert((meta.dev(), meta.ino())) {
+            result.loops += 1;
+            continue;
+        }
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            result.entries += 1;
+            // Follows symlinks; ELOOP and dangling links count as loops.
+            match std::fs::metadata(&path) {
+                Ok(meta) if meta.is_dir() => pending.push(path),
+                Ok(_) => {}
+                Err(_) => result.loops += 1,
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Resolves the long ways through the tree: the bottom's symlink chain,
+/// and `up` all the way from the bottom to the top.
+fn resolve(tree: &Tree, depth: u32, width: u32) -> std::io::Result<u64> {
+    let mut paths = vec![tree.bottom.join(vec!["up"; depth as usize].join("/"))];
+    if width > 0 {
+        paths.push(tree.bottom.join(format!("s{}", width - 1)));
+    }
+    for path in &paths {
+        std::fs::canonicalize(path)?;
+    }
+    Ok(paths.len() as u64)
+}
+
+impl Stressor for Args {
+    fn name(&self) -> &'static str {
+        "links"
+    }
+
+    fn params(&self) -> Value {
+        json!({
+            "depth": self.depth,
+            "width": self.width,
+            "hardlinks": self.hardlinks,
+            "duration_secs": self.duration.as_secs_f64(),
+        })
+    }
+
+    fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        // Beyond 40 symlinks the kernel gives up resolving with ELOOP.
+        if self.width > 40 {
+            return Err(StressError::InvalidInput("--width must be at most 40".to_string()).into());
+        }
+        let started = Instant::now();
+        let tree = self
+            .build()
+            .map_err(|e| anyhow::anyhow!("Failed to build the link tree: {e}"))?;
+        let link_count = std::fs::metadata(tree.root.join("origin"))?.nlink();
+        log::info!(
+            "Built a tree {} deep with {link_count} links to one file in {:.2}s; walking it for {:?}.",
+            self.depth,
+            started.elapsed().as_secs_f64(),
+            self.duration
+        );
+
+        let started = Instant::now();
+        let deadline = started + self.duration;
+        let (mut walks, mut entries, mut loops, mut resolutions) = (0u64, 0, 0, 0);
+        while Instant::now() < deadline && !cancel.is_cancelled() {
+            let walk = walk(&tree.root)?;
+            (walks, entries, loops) = (walks + 1, entries + walk.entries, loops + walk.loops);
+            resolutions += resolve(&tree, self.depth, self.width)?;
+        }
+        let elapsed = started.elapsed().as_secs_f64();
+        drop(tree);
+        let summary = json!({
+            "link_count": link_count,
+            "walks": walks,
+            "entries_per_sec": entries as f64 / elapsed,
+            "loops_skipped": loops,
+            "resolutions_per_sec": resolutions as f64 / elapsed,
+        });
+        log::info!("Links: {summary}.");
+        report::measure(self.name(), summary);
+        match cancel.is_cancelled() {
+            true => Err(StressError::Interrupted.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_walks_and_removes_a_looping_tree() {
+        let args = Args {
+            dir: std::env::temp_dir().join(format!("itsmine-links-test-{}", std::process::id())),
+            depth: 3,
+            width: 4,
+            hardlinks: 10,
+            duration: Duration::ZERO,
+        };
+        let tree = args.build().unwrap();
+        let root = tree.root.clone();
+        assert_eq!(std::fs::metadata(root.join("origin")).unwrap().nlink(), 11);
+
+        let walk = walk(&root).unwrap();
+        // The top: origin, loop-a, loop-b and 4 symlinks. Each of the 3
+        // levels: up, top and 4 symlinks, plus an entry for itself. Then
+        // the hardlinks.
+        assert_eq!(walk.entries, 7 + 3 * (6 + 1) + 10);
+        // Every `up` and `top` leads back, and loop-a/loop-b go nowhere.
+        assert_eq!(walk.loops, 3 * 2 + 2);
+
+        assert_eq!(resolve(&tree, args.depth, args.width).unwrap(), 2);
+        assert_eq!(
+            std::fs::canonicalize(tree.bottom.join("s3")).unwrap(),
+            std::fs::canonicalize(root.join("origin")).unwrap()
+        );
+        drop(tree);
+        assert!(!root.exists());
+        std::fs::remove_dir_all(args.dir).unwrap();
+    }
+}
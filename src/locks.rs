@@ -0,0 +1,358 @@
+//! `itsmine locks`: forked processes contend for advisory locks on shared
+//! files, holding each for a while, and report how long every acquisition
+//! took. Point `--dir` at an NFS mount to load its lock manager.
+
+use std::ffi::CString;
+use std::io::Read;
+use std::os::fd::FromRawFd;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+use crate::chaos::{self, Rng};
+use crate::{CancellationToken, StressError, Stressor, parse, report, stats};
+
+/// Acquisition latencies each process keeps; later ones are only counted.
+const MAX_SAMPLES: usize = 100_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Kind {
+    /// Whole-file `flock` locks
+    Flock,
+    /// POSIX record locks via `fcntl(F_SETLKW)`
+    Fcntl,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Flock => "flock",
+            Kind::Fcntl => "fcntl",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, clap::Args)]
+pub struct Args {
+    /// Processes contending for the locks
+    #[arg(long, value_name = "N", default_value_t = 4)]
+    pub processes: u32,
+    /// Shared files to lock, each picked at random per acquisition
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    pub files: u32,
+    /// How long each lock is held, e.g. 1ms
+    #[arg(long, value_parser = parse::duration, default_value = "1ms")]
+    pub hold: Duration,
+    /// Lock API to use
+    #[arg(long, value_enum, default_value = "flock")]
+    pub kind: Kind,
+    /// Directory for the lock files
+    #[arg(long, value_name = "PATH", default_value_os_t = std::env::temp_dir())]
+    pub dir: PathBuf,
+    /// How long to run, e.g. 10s
+    #[arg(long, value_parser = parse::duration, default_value = "10s")]
+    pub duration: Duration,
+}
+
+/// A flag in memory shared with forked children, unmapped on drop.
+struct SharedFlag(*mut AtomicBool);
+
+impl SharedFlag {
+    fn new() -> std::io::Result<Self> {
+        // SAFETY: a fresh anonymous mapping aliases nothing; zeroed memory
+        // is a valid `false`.
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                size_of::<AtomicBool>(),
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        match ptr == libc::MAP_FAILED {
+            true => Err(std::io::Error::last_os_error()),
+            false => Ok(SharedFlag(ptr.cast())),
+        }
+    }
+
+    fn get(&self) -> &AtomicBool {
+        // SAFETY: the mapping lives as long as `self`.
+        unsafe { &*self.0 }
+    }
+}
+
+impl Drop for SharedFlag {
+    fn drop(&mut self) {
+        // SAFETY: mapped in `new` and unmapped once.
+        unsafe { libc::munmap(self.0.cast(), size_of::<AtomicBool>()) };
+    }
+}
+
+/// One process's results.
+#[derive(Default)]
+struct Tally {
+    acquisitions: u64,
+    /// Nanoseconds each of the first acquisitions waited.
+    latencies: Vec<f64>,
+}
+
+impl Tally {
+    /// Reads what a child wrote: the acquisition count, then each latency.
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (acquisitions, latencies) = bytes.split_first_chunk::<8>()?;
+        Some(Tally {
+            acquisitions: u64::from_ne_bytes(*acquisitions),
+            latencies: latencies
+                .chunks_exact(8)
+                .map(|ns| f64::from_ne_bytes(ns.try_into().expect("8 bytes")))
+                .collect(),
+        })
+    }
+}
+
+fn check(result: libc::c_int) -> std::io::Result<()> {
+    match result {
+        -1 => Err(std::io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+/// Takes (`lock`) or releases the whole-file lock of `kind` on `fd`.
+fn set_lock(fd: libc::c_int, kind: Kind, lock: bool) -> std::io::Result<()> {
+    // SAFETY: plain syscalls on a descriptor we own.
+    unsafe {
+        match kind {
+            Kind::Flock => check(libc::flock(
+                fd,
+                if lock { libc::LOCK_EX } else { libc::LOCK_UN },
+            )),
+            Kind::Fcntl => {
+                let mut range: libc::flock = std::mem::zeroed();
+                range.l_type = if lock { libc::F_WRLCK } else { libc::F_UNLCK } as _;
+                range.l_whence = libc::SEEK_SET as _;
+                check(libc::fcntl(fd, libc::F_SETLKW, &range))
+            }
+        }
+    }
+}
+
+impl Args {
+    /// A child's whole life: opens every file itself (so `flock` locks
+    /// contend too) and locks them at random until `deadline` or `stop`.
+    /// Makes no allocations, as it runs right after fork; `tally` comes with
+    /// room for every sample.
+    fn contend(
+        &self,
+        paths: &[CString],
+        tally: &mut Tally,
+        deadline: Instant,
+        stop: &AtomicBool,
+    ) -> std::io::Result<()> {
+        let mut fds = [-1; 64];
+        for (fd, path) in fds.iter_mut().zip(paths) {
+            // SAFETY: `path` is NUL-terminated and outlives the call.
+            *fd = unsafe { libc::open(path.as_ptr(), libc::O_RDWR | libc::O_CLOEXEC) };
+            check(*fd)?;
+        }
+        let hold = libc::timespec {
+            tv_sec: self.hold.as_secs() as libc::time_t,
+            tv_nsec: self.hold.subsec_nanos() as libc::c_long,
+        };
+        let mut rng = Rng::new(chaos::random_seed());
+        while Instant::now() < deadline && !stop.load(Ordering::Relaxed) {
+            let fd = fds[rng.up_to(paths.len() as u64 - 1) as usize];
+            let started = Instant::now();
+            set_lock(fd, self.kind, true)?;
+            if tally.latencies.len() < tally.latencies.capacity() {
+                tally.latencies.push(started.elapsed().as_nanos() as f64);
+            }
+            tally.acquisitions += 1;
+            // SAFETY: `hold` outlives the call.
+            unsafe { libc::nanosleep(&hold, std::ptr::null_mut()) };
+            set_lock(fd, self.kind, false)?;
+        }
+        Ok(())
+    }
+
+    /// Forks the contending processes and gathers their tallies.
+    fn contest(&self, paths: &[CString], cancel: &CancellationToken) -> anyhow::Result<Vec<Tally>> {
+        let stop = SharedFlag::new()?;
+        let deadline = Instant::now() + self.duration;
+        let mut children = vec![];
+        for _ in 0..self.processes {
+            let mut tally = Tally {
+                acquisitions: 0,
+                latencies: Vec::with_capacity(MAX_SAMPLES),
+            };
+            let mut ends = [0; 2];
+            // SAFETY: `ends` is a valid out-array of two descriptors.
+            check(unsafe { libc::pipe2(ends.as_mut_ptr(), libc::O_CLOEXEC) })?;
+            // SAFETY: the child allocates nothing and only makes syscalls
+            // before `_exit`, which is safe after fork in a threaded process.
+            match unsafe { libc::fork() } {
+                -1 => return Err(std::io::Error::last_os_error().into()),
+                0 => unsafe {
+                    libc::close(ends[0]);
+                    let status = match self.contend(paths, &mut tally, deadline, stop.get()) {
+                        Ok(()) => 0,
+                        Err(_) => 1,
+                    };
+                    let acquisitions = tally.acquisitions.to_ne_bytes();
+                    let latencies = std::slice::from_raw_parts(
+                        tally.latencies.as_ptr().cast::<u8>(),
+                        tally.latencies.len() * 8,
+                    );
+                    for mut bytes in [&acquisitions[..], latencies] {
+                        while !bytes.is_empty() {
+                            let written = libc::write(ends[1], bytes.as_ptr().cast(), bytes.len());
+                            if written <= 0 {
+                                libc::_exit(1);
+                            }
+                            bytes = &bytes[written as usize..];
+                        }
+                    }
+                    libc::_exit(status);
+                },
+                pid => {
+                    // SAFETY: our end of the pipe, closed once.
+                    unsafe { libc::close(ends[1]) };
+                    // SAFETY: the read end is ours alone from here.
+                    children.push((pid, unsafe { std::fs::File::from_raw_fd(ends[0]) }));
+                }
+            }
+        }
+        if !cancel.sleep_until(deadline) {
+            stop.get().store(true, Ordering::Relaxed);
+        }
+        let mut tallies = vec![];
+        let mut failed = 0;
+        for (pid, mut results) in children {
+            let mut bytes = vec![];
+            results.read_to_end(&mut bytes)?;
+            let mut status = 0;
+            // SAFETY: plain syscall on our own child.
+            unsafe { libc::waitpid(pid, &mut status, 0) };
+            if !libc::WIFEXITED(status) || libc::WEXITSTATUS(status) != 0 {
+                failed += 1;
+            }
+            tallies.extend(Tally::from_bytes(&bytes));
+        }
+        if failed > 0 {
+            return Err(StressError::WorkloadFailed(format!(
+                "{failed} of {} processes failed to lock",
+                self.processes
+            ))
+            .into());
+        }
+        Ok(tallies)
+    }
+}
+
+impl Stressor for Args {
+    fn name(&self) -> &'static str {
+        "locks"
+    }
+
+    fn params(&self) -> Value {
+        json!({
+            "processes": self.processes,
+            "files": self.files,
+            "hold_secs": self.hold.as_secs_f64(),
+            "kind": self.kind.name(),
+            "dir": self.dir,
+            "duration_secs": self.duration.as_secs_f64(),
+        })
+    }
+
+    fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        if self.processes == 0 || !(1..=64).contains(&self.files) {
+            return Err(StressError::InvalidInput(
+                "--processes must be greater than 0 and --files from 1 to 64".to_string(),
+            )
+            .into());
+        }
+        let dir = self
+            .dir
+            .join(format!("itsmine-locks-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let paths = (0..self.files)
+            .map(|i| {
+                let path = dir.join(i.to_string());
+                std::fs::File::create(&path)?;
+                Ok(CString::new(path.as_os_str().as_bytes())?)
+            })
+            .collect::<anyhow::Result<Vec<_>>>();
+        log::info!(
+            "{} processes contending for {} {} locks for {:?}.",
+            self.processes,
+            self.files,
+            self.kind.name(),
+            self.duration
+        );
+        let tallies = paths.and_then(|paths| self.contest(&paths, cancel));
+        let _ = std::fs::remove_dir_all(&dir);
+        let tallies = tallies?;
+
+        let acquisitions: u64 = tallies.iter().map(|tally| tally.acquisitions).sum();
+        let mut latencies: Vec<f64> = tallies.into_iter().flat_map(|t| t.latencies).collect();
+        latencies.sort_by(f64::total_cmp);
+        let us = |p| stats::percentile(&latencies, p) / 1000.0;
+        let summary = json!({
+            "acquisitions": acquisitions,
+            "acquisitions_per_sec": acquisitions as f64 / self.duration.as_secs_f64(),
+            "acquire_p50_us": us(0.5),
+            "acquire_p99_us": us(0.99),
+            "acquire_max_us": us(1.0),
+        });
+        log::info!("Locks: {summary}.");
+        report::measure(self.name(), summary);
+        match cancel.is_cancelled() {
+            true => Err(StressError::Interrupted.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tallies_round_trip() {
+        let bytes: Vec<u8> =
+            [7u64.to_ne_bytes(), 1.5f64.to_ne_bytes(), 2f64.to_ne_bytes()].concat();
+        let back = Tally::from_bytes(&bytes).unwrap();
+        assert_eq!((back.acquisitions, back.latencies), (7, vec![1.5, 2.0]));
+        assert!(Tally::from_bytes(&[1, 2]).is_none());
+    }
+
+    #[test]
+    fn processes_contend_for_both_kinds() {
+        let dir = std::env::temp_dir().join(format!("itsmine-locks-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("lock");
+        std::fs::File::create(&path).unwrap();
+        let paths = [CString::new(path.as_os_str().as_bytes()).unwrap()];
+        for kind in [Kind::Flock, Kind::Fcntl] {
+            let args = Args {
+                processes: 2,
+                files: 1,
+                hold: Duration::from_millis(2),
+                kind,
+                dir: dir.clone(),
+                duration: Duration::from_millis(100),
+            };
+            let tallies = args.contest(&paths, &CancellationToken::new()).unwrap();
+            assert_eq!(tallies.len(), 2);
+            // One lock held 2ms at a time fits at most ~50 acquisitions.
+            let acquisitions: u64 = tallies.iter().map(|t| t.acquisitions).sum();
+            assert!((2..=60).contains(&acquisitions), "{kind:?}: {acquisitions}");
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
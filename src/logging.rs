@@ -0,0 +1,171 @@
+use std::os::unix::net::UnixDatagram;
+
+use log::{LevelFilter, Log, Metadata, Record};
+use simple_logger::SimpleLogger;
+
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Prefixes every log line with the job label so interleaved output from
+/// several jobs stays attributable.
+struct LabeledLogger {
+    inner: SimpleLogger,
+    label: String,
+}
+
+impl Log for LabeledLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        self.inner.log(
+            &Record::builder()
+                .args(format_args!("[{}] {}", self.label, record.args()))
+                .metadata(record.metadata().clone())
+                .module_path(record.module_path())
+                .file(record.file())
+                .line(record.line())
+                .build(),
+        );
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Sends records to journald over its native protocol, keeping the level as
+/// the entry priority and the label as a structured field.
+struct JournaldLogger {
+    level: LevelFilter,
+    label: Option<String>,
+    socket: UnixDatagram,
+}
+
+impl Log for JournaldLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let entry = journal_entry(record, self.label.as_deref());
+        if self.socket.send(&entry).is_err() {
+            eprintln!("{} {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn journal_entry(record: &Record, label: Option<&str>) -> Vec<u8> {
+    let priority = match record.level() {
+        log::Level::Error => "3",
+        log::Level::Warn => "4",
+        log::Level::Info => "6",
+        log::Level::Debug | log::Level::Trace => "7",
+    };
+    let mut entry = vec![];
+    push_field(&mut entry, "MESSAGE", &record.args().to_string());
+    push_field(&mut entry, "PRIORITY", priority);
+    push_field(&mut entry, "SYSLOG_IDENTIFIER", "itsmine");
+    push_field(&mut entry, "CODE_MODULE", record.target());
+    if let Some(label) = label {
+        push_field(&mut entry, "ITSMINE_LABEL", label);
+    }
+    entry
+}
+
+/// Appends one field; values containing newlines use the length-prefixed
+/// binary form of the protocol.
+fn push_field(entry: &mut Vec<u8>, key: &str, value: &str) {
+    entry.extend_from_slice(key.as_bytes());
+    if value.contains('\n') {
+        entry.push(b'\n');
+        entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        entry.push(b'=');
+    }
+    entry.extend_from_slice(value.as_bytes());
+    entry.push(b'\n');
+}
+
+/// Maps `-v` occurrences to a log level: warnings and errors by default,
+/// then info, debug and trace. Quiet mode turns logging off entirely.
+pub fn level(verbose: u8, quiet: bool) -> LevelFilter {
+    match (quiet, verbose) {
+        (true, _) => LevelFilter::Off,
+        (false, 0) => LevelFilter::Warn,
+        (false, 1) => LevelFilter::Info,
+        (false, 2) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    }
+}
+
+pub fn init_journald(level: LevelFilter, label: Option<&str>) -> Result<(), anyhow::Error> {
+    let socket = UnixDatagram::unbound()?;
+    socket
+        .connect(JOURNAL_SOCKET)
+        .map_err(|e| anyhow::anyhow!("Failed to reach journald at {JOURNAL_SOCKET}: {e}"))?;
+    log::set_max_level(level);
+    log::set_boxed_logger(Box::new(JournaldLogger {
+        level,
+        label: label.map(str::to_string),
+        socket,
+    }))?;
+    Ok(())
+}
+
+pub fn init(level: LevelFilter, label: Option<&str>) -> Result<(), log::SetLoggerError> {
+    let logger = SimpleLogger::new().with_level(level);
+    match label {
+        None => logger.init(),
+        Some(label) => {
+            log::set_max_level(level);
+            log::set_boxed_logger(Box::new(LabeledLogger {
+                inner: logger,
+                label: label.to_string(),
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn journal_entry_fields() {
+        let entry = journal_entry(
+            &Record::builder()
+                .args(format_args!("Done!"))
+                .level(log::Level::Warn)
+                .target("itsmine")
+                .build(),
+            Some("nightly"),
+        );
+        assert_eq!(
+            String::from_utf8(entry).unwrap(),
+            "MESSAGE=Done!\nPRIORITY=4\nSYSLOG_IDENTIFIER=itsmine\nCODE_MODULE=itsmine\nITSMINE_LABEL=nightly\n"
+        );
+    }
+
+    #[test]
+    fn verbosity_levels() {
+        assert_eq!(level(0, false), LevelFilter::Warn);
+        assert_eq!(level(1, false), LevelFilter::Info);
+        assert_eq!(level(2, false), LevelFilter::Debug);
+        assert_eq!(level(3, false), LevelFilter::Trace);
+        assert_eq!(level(5, false), LevelFilter::Trace);
+        assert_eq!(level(2, true), LevelFilter::Off);
+    }
+
+    #[test]
+    fn multiline_values_are_length_prefixed() {
+        let mut entry = vec![];
+        push_field(&mut entry, "MESSAGE", "a\nb");
+        assert_eq!(entry, b"MESSAGE\n\x03\0\0\0\0\0\0\0a\nb\n");
+    }
+}
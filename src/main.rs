@@ -1,304 +1,1262 @@
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use itsmine::caps::Privilege;
+#[cfg(feature = "history")]
+use itsmine::compare;
+#[cfg(feature = "history")]
+use itsmine::history;
+#[cfg(feature = "plugins")]
+use itsmine::plugin;
+#[cfg(feature = "profiling")]
+use itsmine::profile;
+#[cfg(feature = "scripting")]
+use itsmine::script;
+#[cfg(feature = "history")]
+use itsmine::trend;
+use itsmine::{
+    CancellationToken, Resource, Stressor, barrier, buffer, caps, cpufreq, duty, error, estimate,
+    events, health, heartbeat, html, inject, isolate, k8s, kmsg, logging, mix, monitor, namespace,
+    oom, parse, placement, power, privileges, probe_limits, registry, report, retry, sandbox,
+    scenario, sched, selftest, shutdown, sysinfo, systemd, telemetry, thermal, until, validate,
+};
+use serde_json::json;
+use std::time::Instant;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Cli {
     #[command(subcommand)]
-    resource: Resource,
-    #[arg(short, long, default_value_t = false)]
-    verbose: bool,
+    command: Option<Command>,
+    /// Scenario file to run when no subcommand is given
+    #[arg(long, value_name = "PATH", env = "ITSMINE_CONFIG")]
+    config: Option<std::path::PathBuf>,
+    /// Log more: -v for progress, -vv for debug, -vvv for trace
+    #[arg(short, long, action = clap::ArgAction::Count, env = "ITSMINE_VERBOSE")]
+    verbose: u8,
+    /// Log nothing; print only the final JSON report to stdout
+    #[arg(
+        short,
+        long,
+        default_value_t = false,
+        conflicts_with = "verbose",
+        env = "ITSMINE_QUIET"
+    )]
+    quiet: bool,
+    /// Label keying this job's metrics, events, log lines and report
+    #[arg(long, env = "ITSMINE_LABEL")]
+    label: Option<String>,
+    /// Tag this run's report and metrics with key=value (repeatable), e.g.
+    /// --tag build=1234 --tag host-class=m6i; values may contain commas
+    #[arg(long, value_name = "KEY=VALUE", value_parser = parse::tag, env = "ITSMINE_TAG")]
+    tag: Vec<(String, String)>,
+    /// OTLP/HTTP collector to export run spans and metrics to
+    #[arg(long, value_name = "URL", env = "ITSMINE_OTLP_ENDPOINT")]
+    otlp_endpoint: Option<String>,
+    /// statsd daemon to push live metrics to
+    #[arg(long, value_name = "HOST:PORT", env = "ITSMINE_STATSD")]
+    statsd: Option<String>,
+    /// InfluxDB write URL to push live metrics to in line protocol
+    #[arg(long, value_name = "URL", env = "ITSMINE_INFLUX")]
+    influx: Option<String>,
+    /// Print lifecycle events to stdout as newline-delimited JSON
+    #[arg(long, default_value_t = false, env = "ITSMINE_EVENTS")]
+    events: bool,
+    /// Write a self-contained HTML report with metric charts to this file
+    #[arg(long, value_name = "PATH", env = "ITSMINE_REPORT_HTML")]
+    report_html: Option<std::path::PathBuf>,
+    /// POST the final JSON report to this URL when the run ends
+    #[arg(long, value_name = "URL", env = "ITSMINE_NOTIFY_URL")]
+    notify_url: Option<String>,
+    /// Profile the stressor itself and write a flamegraph SVG to this file
+    #[cfg(feature = "profiling")]
+    #[arg(long, value_name = "PATH", env = "ITSMINE_PROFILE_SELF")]
+    profile_self: Option<std::path::PathBuf>,
+    /// Rhai script defining `fn workload(id)` to run in thread stressors
+    #[cfg(feature = "scripting")]
+    #[arg(long, value_name = "PATH", env = "ITSMINE_WORKLOAD_SCRIPT")]
+    workload_script: Option<std::path::PathBuf>,
+    /// WASM module exporting setup/iterate/teardown to run in thread stressors
+    #[cfg(feature = "plugins")]
+    #[arg(long, value_name = "PATH", env = "ITSMINE_PLUGIN")]
+    plugin: Option<std::path::PathBuf>,
+    /// Repeat the run at this interval (e.g. 6h)
+    #[arg(long, value_name = "DURATION", value_parser = parse::duration, env = "ITSMINE_EVERY")]
+    every: Option<std::time::Duration>,
+    /// Number of runs to perform (unbounded with --every if omitted)
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..), env = "ITSMINE_TIMES")]
+    times: Option<u32>,
+    /// Record each run's report and summary metrics in the history database
+    #[cfg(feature = "history")]
+    #[arg(long, default_value_t = false, env = "ITSMINE_HISTORY")]
+    history: bool,
+    /// SQLite history database (default: $XDG_DATA_HOME/itsmine/history.db)
+    #[cfg(feature = "history")]
+    #[arg(long, value_name = "PATH", env = "ITSMINE_HISTORY_DB")]
+    history_db: Option<std::path::PathBuf>,
+    /// Signal liveness at this interval (e.g. 10s) for external watchdogs
+    #[arg(long, value_name = "DURATION", value_parser = parse::duration, env = "ITSMINE_HEARTBEAT")]
+    heartbeat: Option<std::time::Duration>,
+    /// Where heartbeats go: `-` for stderr, a file to overwrite, or an
+    /// http(s) URL to POST to (repeatable; stderr if omitted)
+    #[arg(
+        long,
+        value_name = "TARGET",
+        requires = "heartbeat",
+        env = "ITSMINE_HEARTBEAT_TO"
+    )]
+    heartbeat_to: Vec<heartbeat::Target>,
+    /// Directory to write each run's JSON report to
+    #[arg(long, value_name = "DIR", env = "ITSMINE_RESULTS_DIR")]
+    results_dir: Option<std::path::PathBuf>,
+    /// Read CPU/memory requests and limits from the downward API or cgroup
+    /// and warn when a stressor would exceed them
+    #[arg(long, default_value_t = false, env = "ITSMINE_DOWNWARD_API")]
+    downward_api: bool,
+    /// Serve /healthz and /readyz on this address
+    #[arg(long, value_name = "HOST:PORT", env = "ITSMINE_HEALTH_ADDR")]
+    health_addr: Option<String>,
+    /// Back memory stressors with `Vec` even in builds with raw allocation
+    #[arg(long, default_value_t = false, env = "ITSMINE_SAFE_ALLOC")]
+    safe_alloc: bool,
+    /// Back memory stressors with secret (memfd_secret) or locked,
+    /// undumpable memory
+    #[arg(
+        long,
+        value_enum,
+        value_name = "KIND",
+        env = "ITSMINE_PROTECTED_MEMORY"
+    )]
+    protected_memory: Option<buffer::Protection>,
+    /// End the run once a condition holds, e.g. swap-used>1G, load>16 or
+    /// file-exists=/tmp/stop (repeatable; paths may contain commas)
+    #[arg(long, value_name = "CONDITION", env = "ITSMINE_UNTIL")]
+    until: Vec<until::Condition>,
+    /// Stop everything, including repeated runs, after this long (e.g. 2h)
+    #[arg(long, value_name = "DURATION", value_parser = parse::duration, env = "ITSMINE_DEADLINE")]
+    deadline: Option<std::time::Duration>,
+    /// Run each memory and thread stressor in its own supervised child
+    /// process, so an OOM kill or crash doesn't end the whole run
+    #[arg(long, default_value_t = false, env = "ITSMINE_ISOLATE")]
+    isolate: bool,
+    /// Run stressors in child processes confined by a seccomp filter, with
+    /// root dropped to nobody (implies --isolate)
+    #[arg(long, default_value_t = false, env = "ITSMINE_SANDBOX")]
+    sandbox: bool,
+    /// Switch to this user, by name or UID, once privileged setup such as
+    /// --sched, --protected-memory and --oom-score-adj is done and before
+    /// any workload runs; not with the CPU frequency options, which only
+    /// root can restore at exit
+    #[arg(
+        long,
+        value_name = "USER",
+        env = "ITSMINE_USER",
+        conflicts_with_all = ["governor", "min_freq", "max_freq"]
+    )]
+    user: Option<String>,
+    /// Drop every capability once privileged setup is done, before any
+    /// workload runs, staying the same user
+    #[arg(
+        long,
+        default_value_t = false,
+        env = "ITSMINE_DROP_CAPS",
+        conflicts_with_all = ["governor", "min_freq", "max_freq"]
+    )]
+    drop_caps: bool,
+    /// Run workloads in fresh namespaces, e.g. mount,pid,net, leaving the
+    /// host's mounts, process table and network untouched; telemetry and
+    /// --notify-url are sent from inside, so a new network namespace cuts
+    /// them off
+    #[arg(
+        long,
+        value_name = "NS,...",
+        value_enum,
+        env = "ITSMINE_NEW_NS",
+        value_delimiter = ','
+    )]
+    new_ns: Vec<namespace::Namespace>,
+    /// oom_score_adj for the stressors, from -1000 (never killed) to 1000
+    /// (killed first)
+    #[arg(
+        long,
+        value_name = "N",
+        allow_negative_numbers = true,
+        value_parser = clap::value_parser!(i32).range(-1000..=1000),
+        env = "ITSMINE_OOM_SCORE_ADJ"
+    )]
+    oom_score_adj: Option<i32>,
+    /// Exempt the supervising process from the OOM killer so reporting
+    /// survives (implies --isolate)
+    #[arg(long, default_value_t = false, env = "ITSMINE_OOM_PROTECT")]
+    oom_protect: bool,
+    /// Scheduling class for the stressors: fifo:PRIO, rr:PRIO, batch or idle
+    #[arg(long, value_name = "POLICY", env = "ITSMINE_SCHED")]
+    sched: Option<sched::Policy>,
+    /// Hold thread stressor workers on a barrier so they all start the
+    /// workload at the same instant
+    #[arg(long, default_value_t = false, env = "ITSMINE_SYNC_START")]
+    sync_start: bool,
+    /// Line thread stressor workers up again every N iterations
+    #[arg(long, value_name = "N", env = "ITSMINE_RESYNC")]
+    resync: Option<u64>,
+    /// Make each thread stressor worker busy for a share of every period,
+    /// e.g. 30%:100ms
+    #[arg(long, value_name = "PERCENT:PERIOD", value_parser = parse::duty, env = "ITSMINE_DUTY")]
+    duty: Option<parse::Duty>,
+    /// Split thread stressor workers across workload kinds by weight, e.g.
+    /// fib:4,sha256:2,memcpy:2
+    #[arg(long, value_name = "KIND:WEIGHT,...", env = "ITSMINE_MIX")]
+    mix: Option<mix::Mix>,
+    /// Randomly kill, stall or panic thread stressor workers, each at a
+    /// rate across all of them or with a chance per iteration, e.g.
+    /// kill:1/min,stall:500ms:p=0.01,panic:p=0.001
+    #[arg(long, value_name = "FAULT:TRIGGER,...", env = "ITSMINE_INJECT")]
+    inject: Option<inject::Faults>,
+    /// What a thread stressor does when one of its workers panics
+    #[arg(long, value_enum, default_value_t = itsmine::OnWorkerPanic::Fail, env = "ITSMINE_ON_WORKER_PANIC")]
+    on_worker_panic: itsmine::OnWorkerPanic,
+    /// Pin thread stressor workers to CPUs chosen from the detected topology
+    #[arg(long, value_enum, env = "ITSMINE_PLACEMENT")]
+    placement: Option<placement::Placement>,
+    /// Throttle thread stressors' duty cycle while the hottest sensor is at
+    /// or above this temperature, e.g. 90C
+    #[arg(long, value_name = "TEMP", value_parser = parse::celsius, env = "ITSMINE_THERMAL_LIMIT")]
+    thermal_limit: Option<f64>,
+    /// How far below --thermal-limit the temperature must fall before load
+    /// is restored
+    #[arg(
+        long,
+        value_name = "TEMP",
+        value_parser = parse::celsius,
+        default_value = "5C",
+        env = "ITSMINE_THERMAL_HYSTERESIS"
+    )]
+    thermal_hysteresis: f64,
+    /// Pause thread stressors' CPU load while the machine runs on battery
+    #[arg(long, default_value_t = false, env = "ITSMINE_PAUSE_ON_BATTERY")]
+    pause_on_battery: bool,
+    /// CPU frequency governor to switch every core to for the run, e.g.
+    /// performance; the previous one is restored at exit
+    #[arg(long, value_name = "NAME", env = "ITSMINE_GOVERNOR")]
+    governor: Option<String>,
+    /// Lowest frequency cores may scale down to for the run, e.g. 2.4GHz
+    #[arg(long, value_name = "FREQ", value_parser = parse::frequency, env = "ITSMINE_MIN_FREQ")]
+    min_freq: Option<u64>,
+    /// Highest frequency cores may scale up to for the run, e.g. 3GHz
+    #[arg(long, value_name = "FREQ", value_parser = parse::frequency, env = "ITSMINE_MAX_FREQ")]
+    max_freq: Option<u64>,
+    /// What a scenario phase does when one of its stressors fails
+    #[arg(long, value_enum, default_value_t = scenario::OnError::AbortAll, env = "ITSMINE_ON_ERROR")]
+    on_error: scenario::OnError,
+    /// Retry transient allocation and thread spawn failures this many times
+    #[arg(long, value_name = "N", default_value_t = 0, env = "ITSMINE_RETRY")]
+    retry: u32,
+    /// Delay growth between retries
+    #[arg(long, value_enum, default_value_t = retry::Backoff::Exp, env = "ITSMINE_BACKOFF")]
+    backoff: retry::Backoff,
+    /// Send log records to the systemd journal instead of stderr
+    #[arg(long, default_value_t = false, env = "ITSMINE_LOG_JOURNALD")]
+    log_journald: bool,
 }
 
-#[derive(Clone, Subcommand)]
-enum Resource {
-    Memory { arg: String },
-    Thread { num: u32 },
+// Subcommands other than stressors, which come from the registry.
+#[derive(Subcommand)]
+enum Command {
+    /// Print CPU topology, caches, memory, NUMA, cgroup and rlimit details
+    #[command(display_order = 100)]
+    Info {
+        /// Print as JSON instead of text
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// List the available stressors and thread workloads
+    #[command(display_order = 101)]
+    List {
+        /// Print as JSON instead of text
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// Run every stressor briefly and verify its side effects
+    #[command(display_order = 102)]
+    Selftest,
+    /// Predict a scenario's peak memory, CPU, disk and duration
+    #[command(display_order = 103)]
+    Estimate {
+        /// Path to the scenario file
+        scenario: std::path::PathBuf,
+        /// Print as JSON instead of text
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// Browse and compare runs recorded with --history
+    #[cfg(feature = "history")]
+    #[command(display_order = 104)]
+    History {
+        #[command(subcommand)]
+        command: HistoryCommand,
+    },
+    /// Fit a trend through a metric's recorded history and flag significant
+    /// degradation (exits 1 if degrading)
+    #[cfg(feature = "history")]
+    #[command(display_order = 105)]
+    Trend {
+        /// Summary metric to analyse, as listed by `history diff`
+        #[arg(long)]
+        metric: String,
+        /// How many of the most recent runs to fit
+        #[arg(long, value_name = "N", default_value_t = 30)]
+        last: usize,
+        /// Only use runs of this stressor
+        #[arg(long)]
+        stressor: Option<String>,
+        /// Which direction is an improvement (guessed from the name if omitted)
+        #[arg(long, value_enum)]
+        better: Option<trend::Better>,
+        /// Significance level of the slope test
+        #[arg(long, default_value_t = 0.05)]
+        alpha: f64,
+        /// Print as JSON instead of text
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// Run stressors sized from a cgroup v2 group's limits inside it and
+    /// check that each limit holds (exits 1 if any fails)
+    #[command(display_order = 106)]
+    ValidateCgroup {
+        /// The group, e.g. /sys/fs/cgroup/test
+        #[arg(long, value_name = "PATH")]
+        path: std::path::PathBuf,
+        /// How long to run each check's stressor
+        #[arg(long, value_parser = parse::duration, default_value = "5s")]
+        duration: std::time::Duration,
+        /// Directory on the io.max-limited device to write to (default: the
+        /// temporary directory)
+        #[arg(long, value_name = "DIR")]
+        io_dir: Option<std::path::PathBuf>,
+        /// Print as JSON instead of text
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// Find how many open files, threads, bytes of address space and bytes
+    /// of file this process really gets, next to what the rlimits claim
+    #[command(display_order = 107)]
+    ProbeLimits {
+        /// Stop counting open files and threads here
+        #[arg(long, value_name = "N", default_value_t = 32768)]
+        cap: u64,
+        /// Directory to probe file size in (default: the temporary directory)
+        #[arg(long, value_name = "DIR")]
+        dir: Option<std::path::PathBuf>,
+        /// Print as JSON instead of text
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    // Runs one stressor on behalf of an `--isolate` parent.
+    #[command(name = isolate::WORKER_COMMAND, hide = true)]
+    IsolatedWorker {
+        #[arg(long)]
+        hold_ms: Option<u64>,
+        stressor: String,
+        value: String,
+    },
 }
 
-#[derive(Clone)]
-struct Memory {
-    size: u64,
-    multiplier: u64,
+#[cfg(feature = "history")]
+#[derive(Subcommand)]
+enum HistoryCommand {
+    /// List the most recent runs
+    List {
+        /// How many runs to list
+        #[arg(long, value_name = "N", default_value_t = 20)]
+        last: usize,
+        /// Print as JSON instead of text
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// Print a run's full report
+    Show { id: i64 },
+    /// Compare two groups of runs metric by metric, with confidence intervals
+    /// and significance tests (exits 1 on a significant regression)
+    Compare {
+        /// Baseline runs: an id, ids `3,5,8`, a range `10..20` or a tag `build=1234`
+        a: history::Selection,
+        /// Candidate runs, selected the same way
+        b: history::Selection,
+        /// Significance level of the tests, held across all metrics at once
+        /// with Holm's correction
+        #[arg(long, default_value_t = 0.05, value_parser = compare::alpha)]
+        alpha: f64,
+        /// Print as JSON instead of text
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// Compare two runs' parameters and summary metrics
+    Diff {
+        a: i64,
+        b: i64,
+        /// Print as JSON instead of text
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
 }
 
-struct Thread(u32);
-
-impl Memory {
-    fn from_resource(res: Resource) -> Result<Self, anyhow::Error> {
-        match res {
-            Resource::Memory { .. } => {}
-            Resource::Thread { .. } => {
-                return Err(anyhow::anyhow!(
-                    "Expected Memory resource, got Thread resource"
-                ));
+fn main() {
+    let registry = registry::registry();
+    let matches = registry
+        .iter()
+        .fold(Cli::command(), |command, registration| {
+            command.subcommand(registration.command.clone())
+        })
+        .get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let level = logging::level(cli.verbose, cli.quiet);
+    if cli.log_journald {
+        if let Err(e) = logging::init_journald(level, cli.label.as_deref()) {
+            eprintln!("Error: {e:#}");
+            std::process::exit(1);
+        }
+    } else {
+        logging::init(level, cli.label.as_deref()).unwrap();
+    }
+    match &cli.command {
+        Some(Command::Info { json }) => {
+            let info = sysinfo::SystemInfo::collect();
+            match json {
+                true => println!("{}", serde_json::to_string_pretty(&info).unwrap()),
+                false => print!("{info}"),
             }
+            return;
         }
+        Some(Command::List { json }) => {
+            match json {
+                true => println!(
+                    "{}",
+                    json!({ "stressors": registry::stressors(), "workloads": registry::workloads() })
+                ),
+                false => print!("{}", registry::render()),
+            }
+            return;
+        }
+        Some(Command::Estimate {
+            scenario: path,
+            json,
+        }) => {
+            let scenario = scenario::Scenario::load(path).unwrap_or_else(|e| {
+                log::error!("Error: {e}");
+                std::process::exit(1);
+            });
+            let estimate = estimate::estimate(
+                &scenario,
+                &sysinfo::SystemInfo::collect(),
+                estimate::disk_available(&std::env::temp_dir()),
+            );
+            match json {
+                true => println!("{}", serde_json::to_string_pretty(&estimate).unwrap()),
+                false => print!("{estimate}"),
+            }
+            return;
+        }
+        Some(Command::IsolatedWorker {
+            hold_ms,
+            stressor,
+            value,
+        }) => {
+            let resource = match stressor.as_str() {
+                "memory" => Resource::Memory {
+                    arg: value.clone(),
+                    bench: None,
+                },
+                "thread" => match value.parse() {
+                    Ok(num) => Resource::Thread { num },
+                    Err(e) => {
+                        log::error!("Error: Invalid thread count '{value}': {e}");
+                        std::process::exit(1);
+                    }
+                },
+                other => {
+                    log::error!("Error: Unknown isolated stressor '{other}'");
+                    std::process::exit(1);
+                }
+            };
+            let hold = hold_ms.map(std::time::Duration::from_millis);
+            check_privileges(&mut cli);
+            if cli.safe_alloc {
+                buffer::set_safe(true);
+            }
+            if let Some(protection) = cli.protected_memory {
+                buffer::set_protection(protection);
+            }
+            barrier::configure(barrier::SyncPolicy {
+                start: cli.sync_start,
+                every: cli.resync,
+            });
+            if let Some(duty) = cli.duty {
+                duty::configure(duty);
+            }
+            if let Some(mix) = cli.mix.clone() {
+                mix::configure(mix);
+            }
+            if let Some(faults) = cli.inject.clone() {
+                inject::configure(faults);
+                inject::set_in_worker_process();
+            }
+            if let Some(placement) = cli.placement {
+                placement::configure(placement);
+            }
+            itsmine::set_on_worker_panic(cli.on_worker_panic);
+            retry::configure(retry::Policy {
+                retries: cli.retry,
+                backoff: cli.backoff,
+            });
+            if let Some(limit) = cli.thermal_limit {
+                thermal::start(limit, cli.thermal_hysteresis);
+            }
+            if cli.pause_on_battery {
+                power::start(true);
+            }
+            load_workloads(&cli);
+            set_oom_score_adj(cli.oom_score_adj);
+            set_sched(cli.sched);
+            if cli.sandbox
+                && let Err(e) = sandbox::apply()
+            {
+                log::error!("Error: {e:#}");
+                std::process::exit(1);
+            }
+            std::process::exit(isolate::worker(resource, hold));
+        }
+        #[cfg(feature = "history")]
+        Some(Command::History { command }) => {
+            if let Err(e) = run_history(command, &history_db(&cli)) {
+                log::error!("Error: {e}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        #[cfg(feature = "history")]
+        Some(Command::Trend {
+            metric,
+            last,
+            stressor,
+            better,
+            alpha,
+            json,
+        }) => {
+            let points = history::Store::open(&history_db(&cli))
+                .and_then(|store| store.series(metric, *last, stressor.as_deref()))
+                .unwrap_or_else(|e| {
+                    log::error!("Error: {e}");
+                    std::process::exit(1);
+                });
+            let better = better.unwrap_or_else(|| trend::Better::guess(metric));
+            let trend = trend::analyze(metric, &points, better, *alpha);
+            match json {
+                true => println!("{}", serde_json::to_string_pretty(&trend).unwrap()),
+                false => print!("{trend}"),
+            }
+            std::process::exit(i32::from(trend.verdict == trend::Verdict::Degrading));
+        }
+        Some(Command::Selftest) => {
+            shutdown::install_handlers();
+            std::process::exit(run_selftest());
+        }
+        Some(Command::ValidateCgroup {
+            path,
+            duration,
+            io_dir,
+            json,
+        }) => {
+            shutdown::install_handlers();
+            let io_dir = io_dir.clone().unwrap_or_else(std::env::temp_dir);
+            std::process::exit(run_validate_cgroup(path, *duration, &io_dir, *json));
+        }
+        Some(Command::ProbeLimits { cap, dir, json }) => {
+            let dir = dir.clone().unwrap_or_else(std::env::temp_dir);
+            let probes = probe_limits::run(&dir, *cap).unwrap_or_else(|e| {
+                log::error!("Error: {e:#}");
+                std::process::exit(1);
+            });
+            match json {
+                true => println!("{}", serde_json::to_string_pretty(&probes).unwrap()),
+                false => {
+                    let or_unlimited = |value: Option<u64>| {
+                        value.map_or("unlimited".to_string(), |v| v.to_string())
+                    };
+                    for probe in &probes {
+                        println!(
+                            "{:<14} found {}{}; {} soft {}, hard {}",
+                            probe.limit,
+                            match probe.capped {
+                                true => "at least ",
+                                false => "",
+                            },
+                            probe.found,
+                            probe.rlimit,
+                            or_unlimited(probe.soft),
+                            or_unlimited(probe.hard)
+                        );
+                        if let Some(error) = &probe.stopped_by {
+                            println!("{:<14} stopped by: {error}", "");
+                        }
+                    }
+                }
+            }
+            return;
+        }
+        None => {}
+    }
+    let stressor: Box<dyn Stressor> = match matches.subcommand_name() {
+        Some(name) => registry
+            .iter()
+            .find(|registration| registration.name == name)
+            .expect("only registered subcommands parse")
+            .build(&matches)
+            .unwrap_or_else(|e| e.exit()),
+        None => match cli.config.clone() {
+            Some(scenario) => Box::new(Resource::Run { scenario }),
+            None => Cli::command()
+                .error(
+                    clap::error::ErrorKind::MissingSubcommand,
+                    "a subcommand or --config (ITSMINE_CONFIG) is required",
+                )
+                .exit(),
+        },
+    };
+    if let Some(label) = &cli.label {
+        telemetry::set_label(label);
+    }
+    if !cli.tag.is_empty() {
+        telemetry::set_tags(cli.tag.clone());
+    }
+    log::info!("Hello, world!");
+    check_privileges(&mut cli);
+    if let Err(e) = namespace::enter(&cli.new_ns) {
+        log::error!("Error: {e}");
+        std::process::exit(1);
+    }
+    shutdown::install_handlers();
+    report::install_flush();
+    if cli.safe_alloc {
+        buffer::set_safe(true);
+    }
+    if let Some(protection) = cli.protected_memory {
+        buffer::set_protection(protection);
+    }
+    let isolated = cli.isolate || cli.sandbox || cli.oom_protect;
+    barrier::configure(barrier::SyncPolicy {
+        start: cli.sync_start,
+        every: cli.resync,
+    });
+    if let Some(duty) = cli.duty {
+        duty::configure(duty);
+    }
+    if let Some(mix) = cli.mix.clone() {
+        mix::configure(mix);
+    }
+    if let Some(faults) = cli.inject.clone() {
+        inject::configure(faults);
+    }
+    if let Some(placement) = cli.placement {
+        placement::configure(placement);
+    }
+    itsmine::set_on_worker_panic(cli.on_worker_panic);
+    isolate::set(isolated);
+    isolate::forward(forwarded_args(&cli));
+    if cli.oom_protect {
+        set_oom_score_adj(Some(oom::PROTECT));
+    }
+    if !isolated {
+        set_oom_score_adj(cli.oom_score_adj);
+        set_sched(cli.sched);
+        if let Some(limit) = cli.thermal_limit {
+            thermal::start(limit, cli.thermal_hysteresis);
+        }
+    }
+    // Isolated workers pause themselves; the supervisor only reports.
+    power::start(cli.pause_on_battery && !isolated);
+    let cpufreq = cpufreq::Settings {
+        governor: cli.governor.clone(),
+        min_khz: cli.min_freq,
+        max_khz: cli.max_freq,
+    };
+    if !cpufreq.is_empty()
+        && let Err(e) = cpufreq::apply(&cpufreq)
+    {
+        log::error!("Error: {e}");
+        std::process::exit(1);
+    }
+    scenario::set_on_error(cli.on_error);
+    retry::configure(retry::Policy {
+        retries: cli.retry,
+        backoff: cli.backoff,
+    });
 
-        let size_str = match res {
-            Resource::Memory { arg } => arg,
-            _ => unreachable!(),
-        };
-
-        let (multiplier, suffix) = if size_str.ends_with('B') {
-            (1, 'B')
-        } else if size_str.ends_with('K') {
-            (1024, 'K')
-        } else if size_str.ends_with('M') {
-            (1024 * 1024, 'M')
-        } else if size_str.ends_with('G') {
-            (1024 * 1024 * 1024, 'G')
-        } else {
-            return Err(anyhow::anyhow!(
-                "Invalid memory size suffix. Use B, K, M, or G."
-            ));
-        };
+    load_workloads(&cli);
 
-        let size = size_str
-            .strip_suffix(suffix)
-            .ok_or_else(|| anyhow::anyhow!("Invalid memory size {size_str}"))
-            .and_then(|s| {
-                s.parse::<u64>()
-                    .map_err(|e| anyhow::anyhow!("Failed to parse memory size '{s}': {e}"))
-            })
-            .expect("Failed to parse memory size");
+    if cli.downward_api {
+        k8s::init();
+    }
+    if let Some(addr) = &cli.health_addr
+        && let Err(e) = health::serve(addr)
+    {
+        log::error!("Error: {e}");
+        std::process::exit(1);
+    }
 
-        // drop(res);
+    if let Some(endpoint) = &cli.otlp_endpoint {
+        telemetry::init(endpoint);
+    }
+    if let Some(addr) = &cli.statsd {
+        telemetry::init_statsd(addr).unwrap_or_else(|e| {
+            log::error!("Error: {e}");
+            std::process::exit(1);
+        });
+    }
+    if let Some(url) = &cli.influx {
+        telemetry::init_influx(url);
+    }
 
-        Ok(Memory { size, multiplier })
+    if cli.events {
+        events::enable();
     }
 
-    fn execute(self) {
-        let total_size = self.size * self.multiplier;
-        assert!(total_size > 0, "Memory size must be greater than 0");
-        log::info!("Allocating {} bytes of memory.", total_size);
+    if cli.report_html.is_some() {
+        telemetry::record_history();
+    }
+    let sampler = (cli.report_html.is_some()
+        || cli.otlp_endpoint.is_some()
+        || cli.statsd.is_some()
+        || cli.influx.is_some())
+    .then(|| monitor::SystemSampler::start(std::time::Duration::from_millis(500)));
 
-        unsafe {
-            let layout = std::alloc::Layout::from_size_align(total_size as usize, 8).unwrap();
-            let ptr = std::alloc::alloc(layout);
-            if ptr.is_null() {
-                panic!("Memory allocation failed");
-            }
+    if cli.user.is_some() || cli.drop_caps {
+        drop_privileges(&cli, isolated);
+    }
 
-            // dummy usage of allocated memory
-            log::info!("Dummy usage of allocated memory...");
-            for i in 0..total_size as usize {
-                *ptr.add(i) = 0;
-                if log::log_enabled!(log::Level::Debug) {
-                    print!("used byte {i}\r");
-                }
-            }
-            log::info!("Memory allocation and usage complete.");
+    let run = stressor.name();
+    let params = stressor.params();
 
-            std::alloc::dealloc(ptr, layout);
-        }
+    let mut schedule = stressor.schedule();
+    if let Some(every) = cli.every {
+        schedule.every = Some(every);
     }
-}
-
-impl Thread {
-    fn new(num: u32) -> Self {
-        Thread(num)
+    if let Some(times) = cli.times {
+        schedule.times = Some(times);
     }
-
-    fn from_resource(res: Resource) -> Result<Self, anyhow::Error> {
-        match res {
-            Resource::Thread { num } => Ok(Thread::new(num)),
-            Resource::Memory { .. } => Err(anyhow::anyhow!(
-                "Expected Thread resource, got Memory resource"
-            )),
-        }
+    if let Some(dir) = &cli.results_dir
+        && let Err(e) = std::fs::create_dir_all(dir)
+    {
+        log::error!("Error: Failed to create {}: {e}", dir.display());
+        std::process::exit(1);
     }
 
-    fn execute(self) {
-        log::info!("Spawning {} threads.", self.0);
-        let mut handles = vec![];
+    #[cfg(feature = "profiling")]
+    let profiler = cli.profile_self.as_deref().map(|path| {
+        profile::SelfProfiler::start(path).unwrap_or_else(|e| {
+            log::error!("Error: {e}");
+            std::process::exit(1);
+        })
+    });
 
-        let (tx, rx) = std::sync::mpsc::channel::<u32>();
+    let runs = schedule.runs();
+    let mut kernel_events = vec![];
+    // Exit code of the most recent failed run, if any.
+    let mut exit_code = 0;
+    let mut iteration = 0;
+    health::set_ready(true);
+    systemd::notify("READY=1");
+    systemd::start_watchdog();
+    if let Some(interval) = cli.heartbeat {
+        let targets = match cli.heartbeat_to.is_empty() {
+            true => vec![heartbeat::Target::Stderr],
+            false => cli.heartbeat_to.clone(),
+        };
+        heartbeat::start(interval, targets, run);
+    }
+    let deadline = cli.deadline.map(|d| Instant::now() + d);
+    let expired = || deadline.is_some_and(|d| Instant::now() >= d);
+    while runs.is_none_or(|runs| iteration < runs) && !shutdown::requested() && !expired() {
+        iteration += 1;
+        let started = std::time::SystemTime::now();
+        let progress = format!(
+            "{iteration}{}",
+            runs.map(|runs| format!("/{runs}")).unwrap_or_default()
+        );
+        if schedule.is_recurring() {
+            log::info!("Starting run {progress}.");
+        }
+        systemd::notify(&format!("STATUS=Running {run} ({progress})"));
 
-        for i in 0..self.0 {
-            let tx = tx.clone();
-            let handle = std::thread::spawn(move || {
-                log::debug!("Thread {i} started.");
-                let fib = fibonacci(30); // Example workload
-                tx.send(fib).unwrap();
-                log::debug!("Thread {i} finished. Fibonacci(30) = {fib}");
-            });
-            handles.push(handle);
+        let path = cli.results_dir.as_ref().map(|dir| {
+            let secs = started
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            dir.join(format!("run-{iteration:04}-{secs}.json"))
+        });
+        report::begin(run, params.clone(), started, path.clone(), cli.quiet);
+        let report = run_once(
+            stressor.as_ref(),
+            run,
+            &params,
+            &cli.until,
+            deadline,
+            started,
+        );
+        report::finish();
+        kernel_events.extend(report.kernel_events.iter().cloned());
+        if report.status == report::Status::Failed {
+            exit_code = report.exit_code;
         }
 
-        let mut results: Vec<u32> = vec![];
+        if let Some(path) = &path {
+            match std::fs::write(path, report.to_json()) {
+                Ok(()) => log::info!("Wrote report to {}.", path.display()),
+                Err(e) => log::error!("Failed to write report to {}: {e}", path.display()),
+            }
+        }
 
-        for handle in handles {
-            handle.join().expect("Thread panicked");
-            let result = rx.recv().unwrap();
-            results.push(result);
-            log::debug!("Received from thread: {}", result);
+        #[cfg(feature = "history")]
+        if cli.history {
+            match history::Store::open(&history_db(&cli))
+                .and_then(|mut store| store.record(&report))
+            {
+                Ok(id) => log::info!("Recorded run #{id} in the history."),
+                Err(e) => log::warn!("Failed to record the run in the history: {e}"),
+            }
         }
 
-        let first = results[0];
+        if let Some(url) = &cli.notify_url
+            && let Err(e) = report.notify(url)
+        {
+            log::warn!("{e}");
+        }
 
-        for &x in results.iter() {
-            if x != first {
-                panic!("Inconsistent results from threads");
-            }
+        if let Some(error) = &report.error {
+            log::error!("Error: {error}");
+        }
+        if cli.quiet {
+            println!("{}", report.to_json());
         }
-        log::info!("All threads completed.");
-    }
-}
 
-fn fibonacci(n: u32) -> u32 {
-    if n <= 1 {
-        return n;
+        let last = runs.is_some_and(|runs| iteration >= runs);
+        if let Some(every) = schedule.every
+            && !last
+        {
+            let next = started + every;
+            let wait = next
+                .duration_since(std::time::SystemTime::now())
+                .unwrap_or_default();
+            log::info!("Next run in {:.0}s.", wait.as_secs_f64());
+            let wake = Instant::now() + wait;
+            shutdown::sleep_until(deadline.map_or(wake, |d| d.min(wake)));
+        }
+    }
+    health::set_ready(false);
+    systemd::notify("STOPPING=1");
+    if shutdown::requested() {
+        log::info!("Shutdown requested; stopped after {iteration} runs.");
+    } else if expired() {
+        log::info!("Deadline reached; stopped after {iteration} runs.");
     }
-    fibonacci(n - 1) + fibonacci(n - 2)
-}
 
-fn main() {
-    let cli = Cli::parse();
-    match cli.verbose {
-        true => simple_logger::init_with_level(log::Level::Debug).unwrap(),
-        false => simple_logger::init_with_level(log::Level::Info).unwrap(),
+    #[cfg(feature = "profiling")]
+    if let Some(profiler) = profiler
+        && let Err(e) = profiler.finish()
+    {
+        log::warn!("{e}");
     }
-    log::info!("Hello, world!");
 
-    match cli.resource {
-        Resource::Memory { .. } => {
-            Memory::from_resource(cli.resource)
-                .unwrap_or_else(|e| {
-                    log::error!("Error: {e}");
-                    std::process::exit(1);
-                })
-                .execute();
+    if let Some(sampler) = sampler {
+        sampler.stop();
+    }
 
-            log::info!("Done!");
+    if let Some(path) = &cli.report_html {
+        let mut config = vec![
+            (
+                "command".to_string(),
+                std::env::args().collect::<Vec<_>>().join(" "),
+            ),
+            ("stressor".to_string(), run.to_string()),
+            ("parameters".to_string(), params.to_string()),
+        ];
+        for event in &kernel_events {
+            config.push((
+                format!("kernel {:?} @ {}us", event.kind, event.timestamp_us),
+                event.message.clone(),
+            ));
         }
-
-        Resource::Thread { .. } => {
-            Thread::from_resource(cli.resource)
-                .unwrap_or_else(|e| {
-                    log::error!("Error: {e}");
-                    std::process::exit(1);
-                })
-                .execute();
-
-            log::info!("Done!");
+        let title = match &cli.label {
+            Some(label) => format!("itsmine run report: {label}"),
+            None => "itsmine run report".to_string(),
+        };
+        let mut series = telemetry::history();
+        if let Some(completed) = series.get("thread.completed") {
+            let ops = html::rate(completed);
+            series.insert("thread.ops_per_sec".to_string(), ops);
+        }
+        match std::fs::write(path, html::render(&title, &config, &series)) {
+            Ok(()) => log::info!("Wrote HTML report to {}.", path.display()),
+            Err(e) => log::error!("Failed to write HTML report to {}: {e}", path.display()),
         }
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
 
-    // Memory tests
-    #[test]
-    fn test_memory_allocation() {
-        let memory = Memory {
-            size: 1,
-            multiplier: 1024,
-        };
-        memory.execute();
+    if let Err(e) = telemetry::shutdown(run) {
+        log::warn!("{e}");
     }
 
-    #[test]
-    fn memory_from_resource_valid_b() {
-        let res = Resource::Memory {
-            arg: "100B".to_string(),
-        };
-        let memory = Memory::from_resource(res).unwrap();
-        assert_eq!(memory.size, 100);
-        assert_eq!(memory.multiplier, 1);
+    if exit_code != 0 {
+        std::process::exit(exit_code);
     }
+}
 
-    #[test]
-    fn memory_from_resource_valid_g() {
-        let res = Resource::Memory {
-            arg: "2G".to_string(),
-        };
-        let memory = Memory::from_resource(res).unwrap();
-        assert_eq!(memory.size, 2);
-        assert_eq!(memory.multiplier, 1024 * 1024 * 1024);
+/// Loads the `--plugin` and `--workload-script` thread workloads, if given.
+fn load_workloads(cli: &Cli) {
+    #[cfg(feature = "plugins")]
+    if let Some(path) = &cli.plugin
+        && let Err(e) = plugin::load(path)
+    {
+        log::error!("Error: {e}");
+        std::process::exit(1);
+    }
+    #[cfg(feature = "scripting")]
+    if let Some(path) = &cli.workload_script
+        && let Err(e) = script::load(path)
+    {
+        log::error!("Error: {e}");
+        std::process::exit(1);
     }
+    #[cfg(not(any(feature = "plugins", feature = "scripting")))]
+    let _ = cli;
+}
 
-    #[test]
-    fn memory_from_resource_invalid_no_suffix() {
-        let res = Resource::Memory {
-            arg: "10".to_string(),
+/// Checks that this process holds the privileges the options given need
+/// before anything runs, leaving out each option it cannot honour so the
+/// log and the report list it as skipped.
+fn check_privileges(cli: &mut Cli) {
+    // Setup keeps realtime priorities and locked memory past --user and
+    // --drop-caps only through rlimits raised now.
+    let dropping = cli.user.is_some() || cli.drop_caps;
+    if dropping {
+        privileges::keep_limits(
+            cli.sched.and_then(sched::Policy::realtime_priority),
+            cli.protected_memory.is_some(),
+        );
+    }
+    if let Some(policy) = cli.sched
+        && !policy.permitted(&format!("--sched {policy}"))
+    {
+        cli.sched = None;
+    }
+    // Raising oom_score_adj is always allowed; lowering it is not.
+    let current = oom::score_adj().unwrap_or(0);
+    if cli.oom_protect && !caps::require("--oom-protect", Privilege::SysResource) {
+        cli.oom_protect = false;
+    }
+    if let Some(value) = cli.oom_score_adj
+        && value < current
+        && !caps::require(&format!("--oom-score-adj {value}"), Privilege::SysResource)
+    {
+        cli.oom_score_adj = None;
+    }
+    if let Some(protection) = cli.protected_memory
+        && !buffer::memlock_unlimited()
+    {
+        let feature = format!("--protected-memory {}", protection.name());
+        let kept = match dropping {
+            true => {
+                caps::skip(&feature, Privilege::IpcLock);
+                false
+            }
+            false => caps::require(&feature, Privilege::IpcLock),
         };
-        let result = Memory::from_resource(res);
-        assert!(result.is_err());
+        if !kept {
+            cli.protected_memory = None;
+        }
+    }
+    if (cli.governor.is_some() || cli.min_freq.is_some() || cli.max_freq.is_some())
+        && !caps::require("--governor, --min-freq and --max-freq", Privilege::Root)
+    {
+        (cli.governor, cli.min_freq, cli.max_freq) = (None, None, None);
     }
+}
 
-    #[test]
-    fn memory_from_resource_invalid_wrong_suffix() {
-        let res = Resource::Memory {
-            arg: "10X".to_string(),
-        };
-        let result = Memory::from_resource(res);
-        assert!(result.is_err());
+fn set_oom_score_adj(value: Option<i32>) {
+    if let Some(value) = value
+        && let Err(e) = oom::set_score_adj(value)
+    {
+        log::error!("Error: {e}");
+        std::process::exit(1);
     }
+}
 
-    #[test]
-    #[should_panic(
-        expected = "Failed to parse memory size: Failed to parse memory size 'abc': invalid digit found in string"
-    )]
-    fn memory_from_resource_invalid_non_numeric() {
-        let res = Resource::Memory {
-            arg: "abcK".to_string(),
-        };
-        let result = Memory::from_resource(res);
-        assert!(result.is_err());
+/// Sheds privileges for `--user` and `--drop-caps`. Isolated workers
+/// inherit the dropped user and the rlimits `check_privileges` raised, and
+/// take their oom_score_adj from this process while it still may lower it.
+fn drop_privileges(cli: &Cli, isolated: bool) {
+    let user = cli.user.as_deref().map(|user| {
+        privileges::User::lookup(user).unwrap_or_else(|e| {
+            log::error!("Error: {e}");
+            std::process::exit(1);
+        })
+    });
+    if isolated && !cli.oom_protect {
+        set_oom_score_adj(cli.oom_score_adj);
+    }
+    if let Err(e) = privileges::drop_privileges(user.as_ref(), cli.drop_caps) {
+        log::error!("Error: {e}");
+        std::process::exit(1);
     }
+}
 
-    #[test]
-    fn memory_from_resource_zero_size() {
-        let res = Resource::Memory {
-            arg: "0K".to_string(),
-        };
-        let memory = Memory::from_resource(res).unwrap();
-        assert_eq!(memory.size, 0);
-        assert_eq!(memory.multiplier, 1024);
+fn set_sched(policy: Option<sched::Policy>) {
+    if let Some(policy) = policy
+        && let Err(e) = sched::apply(policy)
+    {
+        log::error!("Error: {e}");
+        std::process::exit(1);
     }
+}
 
-    #[test]
-    #[should_panic(expected = "Memory size must be greater than 0")]
-    fn test_memory_execute_zero_size() {
-        let memory = Memory {
-            size: 0,
-            multiplier: 1,
-        };
-        memory.execute();
+/// The global options isolated workers must share with this process.
+fn forwarded_args(cli: &Cli) -> Vec<String> {
+    let mut args = vec![];
+    #[cfg(feature = "plugins")]
+    if let Some(path) = &cli.plugin {
+        args.push(format!("--plugin={}", path.display()));
+    }
+    #[cfg(feature = "scripting")]
+    if let Some(path) = &cli.workload_script {
+        args.push(format!("--workload-script={}", path.display()));
     }
+    if cli.safe_alloc {
+        args.push("--safe-alloc".to_string());
+    }
+    if let Some(protection) = cli.protected_memory {
+        args.push(format!("--protected-memory={}", protection.name()));
+    }
+    // Children would otherwise inherit the protected supervisor's score.
+    let oom_score_adj = match cli.oom_protect {
+        true => Some(cli.oom_score_adj.unwrap_or(0)),
+        false => cli.oom_score_adj,
+    };
+    if let Some(value) = oom_score_adj {
+        args.push(format!("--oom-score-adj={value}"));
+    }
+    if cli.sync_start {
+        args.push("--sync-start".to_string());
+    }
+    if let Some(n) = cli.resync {
+        args.push(format!("--resync={n}"));
+    }
+    if let Some(duty) = cli.duty {
+        args.push(format!(
+            "--duty={}%:{}ms",
+            duty.busy.as_secs_f64() / duty.period.as_secs_f64() * 100.0,
+            duty.period.as_millis()
+        ));
+    }
+    if let Some(mix) = &cli.mix {
+        args.push(format!("--mix={mix}"));
+    }
+    if let Some(faults) = &cli.inject {
+        args.push(format!("--inject={faults}"));
+    }
+    if let Some(placement) = cli.placement
+        && let Some(value) = placement.to_possible_value()
+    {
+        args.push(format!("--placement={}", value.get_name()));
+    }
+    if cli.on_worker_panic != itsmine::OnWorkerPanic::Fail {
+        args.push(format!(
+            "--on-worker-panic={}",
+            cli.on_worker_panic
+                .to_possible_value()
+                .expect("no variant is skipped")
+                .get_name()
+        ));
+    }
+    if cli.retry > 0 {
+        args.push(format!("--retry={}", cli.retry));
+        args.push(format!(
+            "--backoff={}",
+            cli.backoff
+                .to_possible_value()
+                .expect("no variant is skipped")
+                .get_name()
+        ));
+    }
+    if let Some(limit) = cli.thermal_limit {
+        args.push(format!("--thermal-limit={limit}"));
+        args.push(format!("--thermal-hysteresis={}", cli.thermal_hysteresis));
+    }
+    if cli.pause_on_battery {
+        args.push("--pause-on-battery".to_string());
+    }
+    if let Some(policy) = cli.sched {
+        args.push(format!("--sched={policy}"));
+    }
+    if cli.sandbox {
+        args.push("--sandbox".to_string());
+    }
+    args
+}
 
-    #[test]
-    fn test_memory_execute_large() {
-        let memory = Memory {
-            size: 1,
-            multiplier: 1024 * 1024, // 1M
-        };
-        memory.execute();
+#[cfg(feature = "history")]
+fn history_db(cli: &Cli) -> std::path::PathBuf {
+    cli.history_db.clone().unwrap_or_else(history::default_path)
+}
+
+#[cfg(feature = "history")]
+fn run_history(command: &HistoryCommand, path: &std::path::Path) -> Result<(), anyhow::Error> {
+    let store = history::Store::open(path)?;
+    match command {
+        HistoryCommand::List { last, json } => {
+            let runs = store.list(*last)?;
+            match json {
+                true => println!("{}", serde_json::to_string_pretty(&runs)?),
+                false => print!("{}", history::render(&runs)),
+            }
+        }
+        HistoryCommand::Show { id } => {
+            println!("{}", serde_json::to_string_pretty(&store.show(*id)?)?)
+        }
+        HistoryCommand::Compare { a, b, alpha, json } => {
+            let comparisons = store.compare(a, b, *alpha)?;
+            match json {
+                true => println!("{}", serde_json::to_string_pretty(&comparisons)?),
+                false => {
+                    for comparison in &comparisons {
+                        println!("{comparison}");
+                    }
+                }
+            }
+            if comparisons
+                .iter()
+                .any(|comparison| comparison.verdict == compare::Verdict::Regression)
+            {
+                std::process::exit(1);
+            }
+        }
+        HistoryCommand::Diff { a, b, json } => {
+            let diff = store.diff(*a, *b)?;
+            match json {
+                true => println!("{}", serde_json::to_string_pretty(&diff)?),
+                false => print!("{diff}"),
+            }
+        }
     }
+    Ok(())
+}
 
-    #[test]
-    fn memory_from_resource_invalid() {
-        let res = Resource::Thread { num: 4 };
-        let result = Memory::from_resource(res);
-        assert!(result.is_err());
+/// Runs `itsmine selftest`, printing one line per check, and returns the
+/// exit code.
+fn run_selftest() -> i32 {
+    let checks = match selftest::run(&CancellationToken::new()) {
+        Ok(checks) => checks,
+        Err(e) => {
+            log::error!("Error: {e}");
+            return 1;
+        }
+    };
+    let mut failed = 0;
+    for check in &checks {
+        let elapsed = check.elapsed.as_secs_f64();
+        match &check.verdict {
+            selftest::Verdict::Pass => println!("PASS {:<13} {elapsed:.2}s", check.name),
+            selftest::Verdict::Skip => println!("SKIP {:<13} no self-test", check.name),
+            selftest::Verdict::Fail(e) => {
+                failed += 1;
+                println!("FAIL {:<13} {elapsed:.2}s: {e:#}", check.name);
+            }
+        }
     }
+    println!("{} checks, {failed} failed.", checks.len());
+    i32::from(failed > 0)
+}
 
-    // Thread tests
-    #[test]
-    fn thread_from_resource_valid() {
-        let res = Resource::Thread { num: 4 };
-        let thread = Thread::from_resource(res).unwrap();
-        assert_eq!(thread.0, 4);
+/// Runs `itsmine validate-cgroup`, printing one line per limit, and returns
+/// the exit code.
+fn run_validate_cgroup(
+    path: &std::path::Path,
+    duration: std::time::Duration,
+    io_dir: &std::path::Path,
+    json: bool,
+) -> i32 {
+    let checks = match validate::run(path, duration, io_dir, &CancellationToken::new()) {
+        Ok(checks) => checks,
+        Err(e) => {
+            log::error!("Error: {e:#}");
+            return 1;
+        }
+    };
+    let failed = checks
+        .iter()
+        .filter(|check| check.verdict == validate::Verdict::Fail)
+        .count();
+    match json {
+        true => println!("{}", serde_json::to_string_pretty(&checks).unwrap()),
+        false => {
+            for check in &checks {
+                let verdict = match check.verdict {
+                    validate::Verdict::Pass => "PASS",
+                    validate::Verdict::Fail => "FAIL",
+                    validate::Verdict::Skip => "SKIP",
+                };
+                println!("{verdict} {:<11} {}", check.limit, check.detail);
+            }
+            println!("{} limits, {failed} failed.", checks.len());
+        }
     }
+    i32::from(failed > 0)
+}
 
-    #[test]
-    fn thread_from_resource_invalid() {
-        let res = Resource::Memory {
-            arg: "100K".to_string(),
-        };
-        let result = Thread::from_resource(res);
-        assert!(result.is_err());
+/// Runs the stressor once, watching the kernel log, and summarizes the run.
+fn run_once(
+    stressor: &dyn Stressor,
+    run: &str,
+    params: &serde_json::Value,
+    until: &[until::Condition],
+    deadline: Option<Instant>,
+    started: std::time::SystemTime,
+) -> report::Report {
+    events::emit(
+        "stressor_started",
+        json!({ "stressor": run, "params": params }),
+    );
+
+    let mut kmsg = kmsg::KmsgWatcher::open();
+    let cancel = CancellationToken::new();
+    let watcher = (!until.is_empty() || deadline.is_some())
+        .then(|| until::Watcher::start(until.to_vec(), deadline, cancel.clone()));
+    let mut outcome = registry::run(stressor, &cancel);
+    let stopped_by = watcher.and_then(until::Watcher::stop);
+    // Reaching an --until condition or the deadline is the intended end,
+    // not a failure.
+    if stopped_by.is_some() && outcome.as_ref().is_err_and(error::is_interrupted) {
+        outcome = Ok(());
     }
+    let kernel_events = kmsg.as_mut().map(|k| k.drain()).unwrap_or_default();
+    for event in &kernel_events {
+        log::warn!(
+            "Kernel reported {:?} at {:.3}s: {}",
+            event.kind,
+            event.timestamp_us as f64 / 1e6,
+            event.message
+        );
+    }
+    match &outcome {
+        Ok(()) => {
+            log::info!("Done!");
+            events::emit("stressor_finished", json!({ "stressor": run }));
+        }
+        Err(e) => events::emit(
+            "stressor_failed",
+            json!({ "stressor": run, "error": format!("{e:#}") }),
+        ),
+    }
+
+    let mut report = report::Report::new(run, params.clone(), started, &outcome);
+    report.collect();
+    report.stop_reason = stopped_by.map(|trigger| trigger.to_string());
+    report.kernel_events = kernel_events;
+    report
 }
@@ -0,0 +1,378 @@
+//! `itsmine memory --bench`: measurements of the memory subsystem rather
+//! than load on it.
+
+use std::fmt;
+use std::time::Instant;
+
+use serde::Serialize;
+
+use crate::chaos::{self, Rng};
+use crate::{CancellationToken, StressError, parse, placement, report, sysinfo};
+
+const LINE: usize = 64;
+/// Loads followed per latency measurement.
+const CHASE_STEPS: usize = 1 << 20;
+/// Read passes over the buffer per bandwidth measurement; the best counts.
+const PASSES: usize = 3;
+/// Smallest working set on the latency curve, one page.
+const MIN_WORKING_SET: u64 = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum Bench {
+    /// Bandwidth and latency from every NUMA node's CPUs to every node's
+    /// memory
+    NumaMatrix,
+    /// Pointer-chase latency over working sets from 4K to the full size
+    Latency,
+}
+
+impl Bench {
+    pub fn name(self) -> &'static str {
+        match self {
+            Bench::NumaMatrix => "numa-matrix",
+            Bench::Latency => "latency",
+        }
+    }
+}
+
+/// Runs `bench` over buffers of up to `bytes`.
+pub fn run(bench: Bench, bytes: u64, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+    match bench {
+        Bench::NumaMatrix => {
+            let matrix = numa_matrix(bytes, cancel)?;
+            print!("{matrix}");
+            report::measure(bench.name(), serde_json::to_value(&matrix)?);
+            Ok(())
+        }
+        Bench::Latency => {
+            let curve = latency_curve(bytes, cancel)?;
+            print!("{curve}");
+            let points: serde_json::Map<_, _> = curve
+                .0
+                .iter()
+                .map(|(size, ns)| (size.to_string(), serde_json::json!(ns)))
+                .collect();
+            report::measure(bench.name(), serde_json::json!({ "ns_per_access": points }));
+            Ok(())
+        }
+    }
+}
+
+/// A cyclic walk through every cache line of a buffer in random order, so
+/// the prefetchers can't hide the latency of each load.
+pub(crate) struct Chain(Vec<[usize; LINE / size_of::<usize>()]>);
+
+impl Chain {
+    pub(crate) fn new(bytes: u64, rng: &mut Rng) -> Self {
+        let lines = (bytes as usize / LINE).max(2);
+        let mut next: Vec<usize> = (0..lines).collect();
+        // Sattolo's shuffle leaves a single cycle through every line.
+        for i in (1..lines).rev() {
+            next.swap(i, rng.up_to(i as u64 - 1) as usize);
+        }
+        let mut slots = vec![[0; LINE / size_of::<usize>()]; lines];
+        for (slot, next) in slots.iter_mut().zip(next) {
+            slot[0] = next;
+        }
+        Chain(slots)
+    }
+
+    /// Follows `steps` links from line `at` and returns where it ended.
+    pub(crate) fn walk(&self, mut at: usize, steps: usize) -> usize {
+        for _ in 0..steps {
+            at = std::hint::black_box(self.0[at][0]);
+        }
+        at
+    }
+}
+
+/// Nanoseconds per dependent load for each working-set size in bytes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Curve(pub Vec<(u64, f64)>);
+
+/// Powers of two from one page up to `bytes`, then `bytes` itself.
+fn working_sets(bytes: u64) -> Vec<u64> {
+    let bytes = bytes.max(MIN_WORKING_SET);
+    let mut sizes: Vec<u64> =
+        std::iter::successors(Some(MIN_WORKING_SET), |size| size.checked_mul(2))
+            .take_while(|&size| size < bytes)
+            .collect();
+    sizes.push(bytes);
+    sizes
+}
+
+fn latency_curve(bytes: u64, cancel: &CancellationToken) -> Result<Curve, anyhow::Error> {
+    let sizes = working_sets(bytes);
+    log::info!(
+        "Chasing pointers through {} working sets up to {bytes} bytes.",
+        sizes.len()
+    );
+    let mut rng = Rng::new(chaos::random_seed());
+    let mut points = vec![];
+    for size in sizes {
+        if cancel.is_cancelled() {
+            return Err(StressError::Interrupted.into());
+        }
+        let chain = Chain::new(size, &mut rng);
+        // One lap first, so small sets are measured from the cache they fit.
+        chain.walk(0, chain.0.len().min(CHASE_STEPS));
+        let started = Instant::now();
+        chain.walk(0, CHASE_STEPS);
+        points.push((
+            size,
+            started.elapsed().as_nanos() as f64 / CHASE_STEPS as f64,
+        ));
+    }
+    Ok(Curve(points))
+}
+
+/// `bytes` with the largest binary suffix that divides it, e.g. `32K`.
+fn size_label(bytes: u64) -> String {
+    let suffixes = [(1u64 << 30, "G"), (1 << 20, "M"), (1 << 10, "K")];
+    match suffixes
+        .iter()
+        .find(|(unit, _)| bytes.is_multiple_of(*unit))
+    {
+        Some((unit, suffix)) => format!("{}{suffix}", bytes / unit),
+        None => format!("{bytes}B"),
+    }
+}
+
+impl fmt::Display for Curve {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:>12} {:>12}", "working set", "ns/access")?;
+        for (size, ns) in &self.0 {
+            writeln!(f, "{:>12} {ns:>12.2}", size_label(*size))?;
+        }
+        Ok(())
+    }
+}
+
+/// One CPU node to memory node measurement.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Cell {
+    pub cpu_node: u32,
+    pub memory_node: u32,
+    pub bandwidth_gbps: f64,
+    pub latency_ns: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Matrix {
+    pub bytes: u64,
+    pub cells: Vec<Cell>,
+}
+
+fn numa_matrix(bytes: u64, cancel: &CancellationToken) -> Result<Matrix, anyhow::Error> {
+    let allowed = placement::allowed();
+    let mut nodes: Vec<(u32, Vec<usize>)> = sysinfo::numa_nodes()
+        .into_iter()
+        .filter(|node| node.memory_bytes != Some(0))
+        .map(|node| {
+            let cpus = parse::cpu_list(&node.cpus).unwrap_or_default();
+            let cpus = cpus.into_iter().filter(|cpu| allowed.contains(cpu));
+            (node.id, cpus.collect())
+        })
+        .collect();
+    if nodes.is_empty() {
+        nodes.push((0, allowed));
+    }
+    // With one node there's nothing to bind to, and containers often forbid
+    // set_mempolicy anyway.
+    let bind = nodes.len() > 1;
+    log::info!(
+        "Measuring {} CPU node x memory node pairs over {bytes} bytes each.",
+        nodes.iter().filter(|(_, cpus)| !cpus.is_empty()).count() * nodes.len()
+    );
+    let mut cells = vec![];
+    for (cpu_node, cpus) in &nodes {
+        // Memory-only nodes (e.g. CXL expanders) have no CPUs to measure from.
+        let Some(&cpu) = cpus.first() else {
+            continue;
+        };
+        for (memory_node, _) in &nodes {
+            if cancel.is_cancelled() {
+                return Err(StressError::Interrupted.into());
+            }
+            let (bandwidth_gbps, latency_ns) = std::thread::scope(|s| {
+                s.spawn(|| measure(cpu, bind.then_some(*memory_node), bytes))
+                    .join()
+            })
+            .map_err(|payload| StressError::WorkerPanicked(crate::panic_message(&payload)))??;
+            cells.push(Cell {
+                cpu_node: *cpu_node,
+                memory_node: *memory_node,
+                bandwidth_gbps,
+                latency_ns,
+            });
+        }
+    }
+    Ok(Matrix { bytes, cells })
+}
+
+/// Read bandwidth (GB/s) and load latency (ns) seen from `cpu` over `bytes`
+/// of memory on `node`, or wherever the kernel puts it without one. Must run
+/// on a thread of its own, which it pins and binds.
+fn measure(cpu: usize, node: Option<u32>, bytes: u64) -> Result<(f64, f64), anyhow::Error> {
+    placement::pin_current(cpu).map_err(|e| anyhow::anyhow!("Failed to pin to CPU {cpu}: {e}"))?;
+    if let Some(node) = node {
+        bind_memory(Some(node))
+            .map_err(|e| anyhow::anyhow!("Failed to bind memory to node {node}: {e}"))?;
+    }
+    // Pages land on the bound node when first written, which both of these
+    // do before anything is timed.
+    let mut buffer = vec![0u64; (bytes as usize / size_of::<u64>()).max(1)];
+    buffer.iter_mut().for_each(|word| *word = 1);
+    let chain = Chain::new(bytes, &mut Rng::new(chaos::random_seed()));
+    if node.is_some() {
+        bind_memory(None)?;
+    }
+
+    let mut best = f64::INFINITY;
+    for _ in 0..PASSES {
+        let started = Instant::now();
+        std::hint::black_box(
+            buffer
+                .iter()
+                .fold(0u64, |sum, word| sum.wrapping_add(*word)),
+        );
+        best = best.min(started.elapsed().as_secs_f64());
+    }
+    let bandwidth_gbps = buffer.len() as f64 * size_of::<u64>() as f64 / best / 1e9;
+
+    let started = Instant::now();
+    chain.walk(0, CHASE_STEPS);
+    let latency_ns = started.elapsed().as_nanos() as f64 / CHASE_STEPS as f64;
+    Ok((bandwidth_gbps, latency_ns))
+}
+
+/// Restricts the calling thread's new allocations to `node`, or lifts the
+/// restriction.
+fn bind_memory(node: Option<u32>) -> std::io::Result<()> {
+    let mut mask = [0 as libc::c_ulong; 16];
+    let bits = libc::c_ulong::BITS as usize;
+    let mode = match node {
+        Some(node) => {
+            let node = node as usize;
+            if node >= mask.len() * bits {
+                return Err(std::io::ErrorKind::InvalidInput.into());
+            }
+            mask[node / bits] |= 1 << (node % bits);
+            libc::MPOL_BIND
+        }
+        None => libc::MPOL_DEFAULT,
+    };
+    // SAFETY: `mask` outlives the call and holds `maxnode` bits.
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_set_mempolicy,
+            mode,
+            mask.as_ptr(),
+            mask.len() * bits,
+        )
+    };
+    match result {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+impl fmt::Display for Matrix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut cpu_nodes: Vec<u32> = self.cells.iter().map(|cell| cell.cpu_node).collect();
+        let mut memory_nodes: Vec<u32> = self.cells.iter().map(|cell| cell.memory_node).collect();
+        cpu_nodes.dedup();
+        memory_nodes.sort();
+        memory_nodes.dedup();
+        let table = |f: &mut fmt::Formatter<'_>, title: &str, value: fn(&Cell) -> String| {
+            writeln!(f, "{title}")?;
+            write!(f, "{:>10}", "cpu\\mem")?;
+            for node in &memory_nodes {
+                write!(f, "{:>10}", format!("node {node}"))?;
+            }
+            writeln!(f)?;
+            for cpu_node in &cpu_nodes {
+                write!(f, "{:>10}", format!("node {cpu_node}"))?;
+                for memory_node in &memory_nodes {
+                    let cell = self.cells.iter().find(|cell| {
+                        cell.cpu_node == *cpu_node && cell.memory_node == *memory_node
+                    });
+                    write!(f, "{:>10}", cell.map_or_else(|| "-".to_string(), value))?;
+                }
+                writeln!(f)?;
+            }
+            Ok(())
+        };
+        table(f, "Read bandwidth (GB/s)", |cell| {
+            format!("{:.2}", cell.bandwidth_gbps)
+        })?;
+        writeln!(f)?;
+        table(f, "Load latency (ns)", |cell| {
+            format!("{:.1}", cell.latency_ns)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chain_visits_every_line_once() {
+        let chain = Chain::new(64 * 1000, &mut Rng::new(7));
+        let mut seen = vec![false; 1000];
+        let mut at = 0;
+        for _ in 0..1000 {
+            assert!(!seen[at]);
+            seen[at] = true;
+            at = chain.walk(at, 1);
+        }
+        assert_eq!(at, 0);
+    }
+
+    #[test]
+    fn prints_a_matrix_per_metric() {
+        let cell = |cpu_node, memory_node, bandwidth_gbps, latency_ns| Cell {
+            cpu_node,
+            memory_node,
+            bandwidth_gbps,
+            latency_ns,
+        };
+        let matrix = Matrix {
+            bytes: 1 << 20,
+            cells: vec![
+                cell(0, 0, 20.0, 90.0),
+                cell(0, 1, 11.5, 140.0),
+                cell(1, 0, 11.0, 145.0),
+                cell(1, 1, 19.5, 91.0),
+            ],
+        };
+        let text = matrix.to_string();
+        assert!(text.contains("    node 0     20.00     11.50\n"));
+        assert!(text.contains("    node 1     145.0      91.0\n"));
+    }
+
+    #[test]
+    fn latency_curve_doubles_up_to_the_full_size() {
+        assert_eq!(working_sets(32 << 10), [4096, 8192, 16384, 32768]);
+        assert_eq!(working_sets(5000), [4096, 5000]);
+        assert_eq!(working_sets(0), [4096]);
+        let curve = latency_curve(16 << 10, &CancellationToken::new()).unwrap();
+        assert_eq!(curve.0.len(), 3);
+        assert!(curve.0.iter().all(|(_, ns)| *ns > 0.0));
+        assert!(curve.to_string().contains("         16K"));
+        assert_eq!(size_label(5000), "5000B");
+        assert_eq!(size_label(3 << 30), "3G");
+    }
+
+    #[test]
+    fn measures_local_memory() {
+        let cpu = placement::allowed().first().copied().unwrap_or(0);
+        let (bandwidth_gbps, latency_ns) = std::thread::spawn(move || measure(cpu, None, 1 << 20))
+            .join()
+            .unwrap()
+            .unwrap();
+        assert!(bandwidth_gbps > 0.0);
+        assert!(latency_ns > 0.0);
+    }
+}
@@ -0,0 +1,275 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+static MIX: OnceLock<Mix> = OnceLock::new();
+
+/// Bytes hashed per `sha256` iteration.
+const SHA256_INPUT: usize = 64 * 1024;
+/// Bytes copied per `memcpy` iteration.
+const MEMCPY_BYTES: usize = 4 * 1024 * 1024;
+
+/// A built-in thread workload selectable with `--mix`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind {
+    /// Recursive Fibonacci(30): branchy, call-heavy integer work.
+    Fib,
+    /// SHA-256 over a 64 KiB buffer: ALU-bound hashing.
+    Sha256,
+    /// 4 MiB buffer copies: memory-bandwidth bound.
+    Memcpy,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Fib => "fib",
+            Kind::Sha256 => "sha256",
+            Kind::Memcpy => "memcpy",
+        }
+    }
+
+    /// The result every iteration of this kind must produce.
+    pub fn expected(self) -> u32 {
+        match self {
+            Kind::Fib => crate::FIBONACCI_30,
+            Kind::Sha256 => {
+                let input: Vec<u8> = (0..SHA256_INPUT).map(|i| i as u8).collect();
+                let digest = sha256(&input);
+                u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]])
+            }
+            Kind::Memcpy => ((MEMCPY_BYTES - 1) % 251) as u32,
+        }
+    }
+
+    /// A fresh iteration function; results are deterministic per kind so
+    /// they can be checked against `expected`.
+    pub fn workload(self) -> Box<dyn FnMut() -> u32 + Send> {
+        match self {
+            Kind::Fib => Box::new(|| crate::fibonacci(30)),
+            Kind::Sha256 => {
+                let input: Vec<u8> = (0..SHA256_INPUT).map(|i| i as u8).collect();
+                Box::new(move || {
+                    let digest = sha256(std::hint::black_box(&input));
+                    u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]])
+                })
+            }
+            Kind::Memcpy => {
+                let source: Vec<u8> = (0..MEMCPY_BYTES).map(|i| (i % 251) as u8).collect();
+                let mut target = vec![0u8; MEMCPY_BYTES];
+                Box::new(move || {
+                    target.copy_from_slice(std::hint::black_box(&source));
+                    let target = std::hint::black_box(&target);
+                    u32::from(target[0]) + u32::from(target[MEMCPY_BYTES - 1])
+                })
+            }
+        }
+    }
+}
+
+impl FromStr for Kind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fib" => Ok(Kind::Fib),
+            "sha256" => Ok(Kind::Sha256),
+            "memcpy" => Ok(Kind::Memcpy),
+            other => Err(format!(
+                "unknown workload '{other}' (use fib, sha256 or memcpy)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Workload kinds and their relative weights, e.g. `fib:4,sha256:2`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mix(Vec<(Kind, u32)>);
+
+impl FromStr for Mix {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = vec![];
+        for part in s.split(',') {
+            let (kind, weight) = part.trim().split_once(':').unwrap_or((part.trim(), "1"));
+            let weight: u32 = weight
+                .parse()
+                .map_err(|_| format!("invalid weight in '{part}'"))?;
+            if weight == 0 {
+                return Err(format!("weight of '{kind}' must be positive"));
+            }
+            parts.push((kind.parse()?, weight));
+        }
+        Ok(Mix(parts))
+    }
+}
+
+impl fmt::Display for Mix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<_> = self
+            .0
+            .iter()
+            .map(|(kind, weight)| format!("{kind}:{weight}"))
+            .collect();
+        f.write_str(&parts.join(","))
+    }
+}
+
+impl Mix {
+    /// The kind each of `threads` workers runs, split by weight with the
+    /// rounding remainder going to the kinds that lost the most to it.
+    pub fn assign(&self, threads: u32) -> Vec<Kind> {
+        let total: u64 = self.0.iter().map(|(_, weight)| u64::from(*weight)).sum();
+        let mut counts: Vec<(Kind, u64, u64)> = self
+            .0
+            .iter()
+            .map(|&(kind, weight)| {
+                let share = u64::from(threads) * u64::from(weight);
+                (kind, share / total, share % total)
+            })
+            .collect();
+        let assigned: u64 = counts.iter().map(|(_, count, _)| count).sum();
+        let mut by_remainder: Vec<usize> = (0..counts.len()).collect();
+        by_remainder.sort_by_key(|&i| std::cmp::Reverse(counts[i].2));
+        for &i in by_remainder
+            .iter()
+            .take((u64::from(threads) - assigned) as usize)
+        {
+            counts[i].1 += 1;
+        }
+        counts
+            .into_iter()
+            .flat_map(|(kind, count, _)| std::iter::repeat_n(kind, count as usize))
+            .collect()
+    }
+}
+
+/// Makes thread stressors split their workers across `mix` instead of all
+/// running the same workload.
+pub fn configure(mix: Mix) {
+    let _ = MIX.set(mix);
+}
+
+pub fn configured() -> Option<&'static Mix> {
+    MIX.get()
+}
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// SHA-256 (FIPS 180-4), kept here so the workload needs no dependency.
+fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut h: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut hh] = h;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = hh
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            hh = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e, f, g, hh]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+    let mut digest = [0u8; 32];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(h) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_assigns_by_weight() {
+        let mix: Mix = "fib:4,sha256:2,memcpy:2".parse().unwrap();
+        assert_eq!(mix.to_string(), "fib:4,sha256:2,memcpy:2");
+        let kinds = mix.assign(16);
+        let count = |kind| kinds.iter().filter(|&&k| k == kind).count();
+        assert_eq!(
+            (count(Kind::Fib), count(Kind::Sha256), count(Kind::Memcpy)),
+            (8, 4, 4)
+        );
+        assert_eq!(mix.assign(3).len(), 3);
+        assert_eq!(
+            "sha256".parse::<Mix>().unwrap().assign(2),
+            [Kind::Sha256; 2]
+        );
+        assert!("fib:0".parse::<Mix>().is_err());
+        assert!("bogo:1".parse::<Mix>().is_err());
+    }
+
+    #[test]
+    fn workloads_produce_their_expected_results() {
+        for kind in [Kind::Fib, Kind::Sha256, Kind::Memcpy] {
+            assert_eq!(kind.workload()(), kind.expected(), "{kind}");
+        }
+    }
+
+    #[test]
+    fn sha256_matches_known_digests() {
+        let hex =
+            |digest: [u8; 32]| -> String { digest.iter().map(|b| format!("{b:02x}")).collect() };
+        assert_eq!(
+            hex(sha256(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(sha256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! `itsmine mmap-churn`: threads of one process keep replacing mappings of
+//! random sizes, stressing the kernel's VMA tree and the mmap lock they all
+//! contend on.
+
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+use crate::chaos::{self, Rng};
+use crate::stats::{self, Samples};
+use crate::{CancellationToken, StressError, Stressor, parse, report};
+
+const PAGE: usize = 4096;
+
+#[derive(Clone, Debug, PartialEq, clap::Args)]
+pub struct Args {
+    /// How long to run, e.g. 10s
+    #[arg(long, value_parser = parse::duration, default_value = "10s")]
+    pub duration: Duration,
+    /// Threads mapping and unmapping concurrently
+    #[arg(long, value_name = "N", default_value_t = 4)]
+    pub threads: u32,
+    /// Mappings each thread keeps alive, replacing one at a time
+    #[arg(long, value_name = "N", default_value_t = 256)]
+    pub regions: u32,
+    /// Range the size of each mapping is picked from, e.g. 4K..1M
+    #[arg(long, value_name = "MIN..MAX", value_parser = sizes, default_value = "4K..1M")]
+    pub sizes: (u64, u64),
+}
+
+fn sizes(s: &str) -> Result<(u64, u64), String> {
+    let (min, max) = parse::range(s, parse::bytes)?;
+    match min {
+        0 => Err(format!("sizes '{s}' must start above zero")),
+        _ => Ok((min, max)),
+    }
+}
+
+/// One live mapping.
+struct Region {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+fn map(len: usize) -> std::io::Result<Region> {
+    // SAFETY: a fresh anonymous mapping aliases nothing.
+    let ptr = unsafe {
+        libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        )
+    };
+    if ptr == libc::MAP_FAILED {
+        return Err(std::io::Error::last_os_error());
+    }
+    // Fault the first page in, which takes the mmap lock too.
+    // SAFETY: the mapping is at least a page long and writable.
+    unsafe { ptr.cast::<u8>().write(1) };
+    Ok(Region { ptr, len })
+}
+
+fn unmap(region: Region) {
+    // SAFETY: the region came from `map` and is unmapped once.
+    unsafe { libc::munmap(region.ptr, region.len) };
+}
+
+/// One thread's loop: replaces a random one of `regions` mappings at a time
+/// until `deadline`, timing each unmap and map.
+fn churn(
+    args: &Args,
+    deadline: Instant,
+    cancel: &CancellationToken,
+) -> Result<(Samples, Samples), StressError> {
+    let mut rng = Rng::new(chaos::random_seed());
+    let (min, max) = args.sizes;
+    let size = |rng: &mut Rng| {
+        let bytes = min + rng.up_to(max - min);
+        (bytes as usize).next_multiple_of(PAGE)
+    };
+    let mut live = Vec::with_capacity(args.regions as usize);
+    for _ in 0..args.regions {
+        live.push(map(size(&mut rng)).map_err(|_| StressError::AllocationFailed { bytes: max })?);
+    }
+    let (mut maps, mut unmaps) = (Samples::new(), Samples::new());
+    while Instant::now() < deadline && !cancel.is_cancelled() {
+        let slot = rng.up_to(live.len() as u64 - 1) as usize;
+        let len = size(&mut rng);
+        let started = Instant::now();
+        unmap(std::mem::replace(
+            &mut live[slot],
+            Region {
+                ptr: std::ptr::null_mut(),
+                len: 0,
+            },
+        ));
+        unmaps.record(started.elapsed(), &mut rng);
+        let started = Instant::now();
+        live[slot] = map(len).map_err(|_| StressError::AllocationFailed { bytes: len as u64 })?;
+        maps.record(started.elapsed(), &mut rng);
+    }
+    live.into_iter().for_each(unmap);
+    Ok((maps, unmaps))
+}
+
+/// Counts and latency percentiles in microseconds across all threads.
+fn summarize(maps: &mut [f64], unmaps: &mut [f64], operations: u64, elapsed: Duration) -> Value {
+    maps.sort_by(f64::total_cmp);
+    unmaps.sort_by(f64::total_cmp);
+    let us = |sorted: &[f64], p: f64| stats::percentile(sorted, p) / 1000.0;
+    json!({
+        "operations": operations,
+        "operations_per_sec": operations as f64 / elapsed.as_secs_f64(),
+        "map_p50_us": us(maps, 0.5),
+        "map_p99_us": us(maps, 0.99),
+        "map_max_us": us(maps, 1.0),
+        "unmap_p50_us": us(unmaps, 0.5),
+        "unmap_p99_us": us(unmaps, 0.99),
+        "unmap_max_us": us(unmaps, 1.0),
+    })
+}
+
+impl Stressor for Args {
+    fn name(&self) -> &'static str {
+        "mmap-churn"
+    }
+
+    fn params(&self) -> Value {
+        json!({
+            "duration_secs": self.duration.as_secs_f64(),
+            "threads": self.threads,
+            "regions": self.regions,
+            "min_bytes": self.sizes.0,
+            "max_bytes": self.sizes.1,
+        })
+    }
+
+    fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        if self.threads == 0 || self.regions == 0 {
+            return Err(StressError::InvalidInput(
+                "--threads and --regions must be greater than 0".to_string(),
+            )
+            .into());
+        }
+        log::info!(
+            "Churning {} mappings on each of {} threads for {:?}.",
+            self.regions,
+            self.threads,
+            self.duration
+        );
+        let started = Instant::now();
+        let deadline = started + self.duration;
+        let outcomes = std::thread::scope(|s| {
+            let handles: Vec<_> = (0..self.threads)
+                .map(|_| s.spawn(|| churn(self, deadline, cancel)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join())
+                .collect::<Vec<_>>()
+        });
+        let elapsed = started.elapsed();
+        let (mut maps, mut unmaps, mut operations) = (vec![], vec![], 0);
+        for outcome in outcomes {
+            let (thread_maps, thread_unmaps) = outcome
+                .map_err(|payload| StressError::WorkerPanicked(crate::panic_message(&payload)))??;
+            operations += thread_maps.seen + thread_unmaps.seen;
+            maps.extend(thread_maps.kept);
+            unmaps.extend(thread_unmaps.kept);
+        }
+        let summary = summarize(&mut maps, &mut unmaps, operations, elapsed);
+        log::info!("mmap churn: {summary}.");
+        report::measure(self.name(), summary);
+        match cancel.is_cancelled() {
+            true => Err(StressError::Interrupted.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn churns_and_reports_percentiles() {
+        let args = Args {
+            duration: Duration::from_millis(50),
+            threads: 1,
+            regions: 8,
+            sizes: (4096, 64 << 10),
+        };
+        let deadline = Instant::now() + args.duration;
+        let (mut maps, mut unmaps) = churn(&args, deadline, &CancellationToken::new()).unwrap();
+        assert!(maps.seen > 0);
+        assert_eq!(maps.seen, unmaps.seen);
+        let summary = summarize(
+            &mut maps.kept,
+            &mut unmaps.kept,
+            maps.seen * 2,
+            args.duration,
+        );
+        assert!(summary["map_p50_us"].as_f64().unwrap() <= summary["map_max_us"].as_f64().unwrap());
+        assert!(sizes("0..4K").is_err());
+        assert_eq!(sizes("4K..1M"), Ok((4096, 1 << 20)));
+    }
+}
@@ -0,0 +1,452 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+use crate::report;
+
+const SYSFS_CPU_ROOT: &str = "/sys/devices/system/cpu";
+const PROC_STAT: &str = "/proc/stat";
+const PROC_MEMINFO: &str = "/proc/meminfo";
+
+/// A core is considered throttled when it runs below this fraction of its
+/// maximum frequency.
+const DROP_THRESHOLD: f64 = 0.85;
+
+/// Number of consecutive low samples before a drop counts as sustained.
+const SUSTAINED_SAMPLES: usize = 3;
+
+#[derive(Clone, Debug)]
+struct Core {
+    id: u32,
+    dir: PathBuf,
+    max_khz: Option<u64>,
+}
+
+#[derive(Clone, Debug)]
+struct Sample {
+    at: Duration,
+    khz: Vec<Option<u64>>,
+}
+
+/// A period during which one core stayed below `DROP_THRESHOLD` of its max.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FreqDrop {
+    pub cpu: u32,
+    pub start: Duration,
+    pub duration: Duration,
+    pub min_mhz: u64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CoreSummary {
+    pub cpu: u32,
+    pub min_mhz: u64,
+    pub avg_mhz: u64,
+    pub max_mhz: u64,
+    pub throttle_events: u64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FreqReport {
+    pub cores: Vec<CoreSummary>,
+    pub drops: Vec<FreqDrop>,
+}
+
+impl FreqReport {
+    pub fn throttled(&self) -> bool {
+        !self.drops.is_empty() || self.cores.iter().any(|c| c.throttle_events > 0)
+    }
+
+    pub fn log(&self) {
+        for core in &self.cores {
+            log::debug!(
+                "cpu{}: {}/{}/{} MHz (min/avg/max), {} throttle events",
+                core.cpu,
+                core.min_mhz,
+                core.avg_mhz,
+                core.max_mhz,
+                core.throttle_events
+            );
+        }
+        for drop in &self.drops {
+            log::warn!(
+                "cpu{} dropped to {} MHz for {:.1}s (starting at +{:.1}s)",
+                drop.cpu,
+                drop.min_mhz,
+                drop.duration.as_secs_f64(),
+                drop.start.as_secs_f64()
+            );
+        }
+        if self.throttled() {
+            log::warn!("CPU throttling detected; results may be contaminated.");
+        } else {
+            log::info!("No CPU throttling detected.");
+        }
+    }
+
+    /// The report's `cpu_frequency` measurement.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "throttled": self.throttled(),
+            "cores": self.cores.iter().map(|core| json!({
+                "cpu": core.cpu,
+                "min_mhz": core.min_mhz,
+                "avg_mhz": core.avg_mhz,
+                "max_mhz": core.max_mhz,
+                "throttle_events": core.throttle_events,
+            })).collect::<Vec<_>>(),
+            "drops": self.drops.iter().map(|drop| json!({
+                "cpu": drop.cpu,
+                "start_secs": drop.start.as_secs_f64(),
+                "duration_secs": drop.duration.as_secs_f64(),
+                "min_mhz": drop.min_mhz,
+            })).collect::<Vec<_>>(),
+        })
+    }
+
+    /// Logs the summary and adds it to the run's report, marking the run
+    /// degraded if any core was throttled.
+    pub fn record(&self) {
+        self.log();
+        report::measure("cpu_frequency", self.to_json());
+        if self.throttled() {
+            let events: u64 = self.cores.iter().map(|core| core.throttle_events).sum();
+            report::degrade(format!(
+                "CPU throttled: {} sustained frequency drops, {events} throttle events",
+                self.drops.len()
+            ));
+        }
+    }
+}
+
+/// Samples per-core frequency in the background while a CPU stressor runs.
+pub struct FreqMonitor {
+    cores: Vec<Core>,
+    throttle_start: Vec<Option<u64>>,
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<Vec<Sample>>,
+}
+
+impl FreqMonitor {
+    /// Starts sampling, or returns `None` if the platform exposes no cpufreq
+    /// data.
+    pub fn start(interval: Duration) -> Option<Self> {
+        Self::start_at(Path::new(SYSFS_CPU_ROOT), interval)
+    }
+
+    fn start_at(root: &Path, interval: Duration) -> Option<Self> {
+        let cores = discover_cores(root);
+        if cores.is_empty() {
+            log::debug!(
+                "No cpufreq data under {}, frequency monitor disabled.",
+                root.display()
+            );
+            return None;
+        }
+
+        let throttle_start = cores.iter().map(read_throttle_count).collect();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let cores = cores.clone();
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                let started = Instant::now();
+                let mut samples = vec![];
+                while !stop.load(Ordering::Relaxed) {
+                    samples.push(Sample {
+                        at: started.elapsed(),
+                        khz: cores.iter().map(read_cur_freq).collect(),
+                    });
+                    std::thread::sleep(interval);
+                }
+                samples
+            })
+        };
+
+        Some(FreqMonitor {
+            cores,
+            throttle_start,
+            stop,
+            handle,
+        })
+    }
+
+    pub fn stop(self) -> FreqReport {
+        self.stop.store(true, Ordering::Relaxed);
+        let samples = self.handle.join().unwrap_or_default();
+
+        let throttle_end: Vec<_> = self.cores.iter().map(read_throttle_count).collect();
+        let mut report = summarize(&self.cores, &samples);
+        for (i, core) in report.cores.iter_mut().enumerate() {
+            if let (Some(start), Some(end)) = (self.throttle_start[i], throttle_end[i]) {
+                core.throttle_events = end.saturating_sub(start);
+            }
+        }
+        report
+    }
+}
+
+/// Periodically records host-wide CPU utilisation and available memory as
+/// telemetry gauges.
+pub struct SystemSampler {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl SystemSampler {
+    pub fn start(interval: Duration) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                let mut previous = read_cpu_times();
+                while !stop.load(Ordering::Relaxed) {
+                    std::thread::sleep(interval);
+                    let current = read_cpu_times();
+                    if let (Some(prev), Some(cur)) = (previous, current) {
+                        crate::telemetry::gauge("system.cpu_busy", "%", busy_percent(prev, cur));
+                    }
+                    previous = current;
+                    if let Some(bytes) = read_mem_available() {
+                        crate::telemetry::gauge("system.mem_available_bytes", "By", bytes);
+                    }
+                }
+            })
+        };
+        SystemSampler { stop, handle }
+    }
+
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.handle.join();
+    }
+}
+
+/// Aggregate (busy, total) jiffies from the first line of /proc/stat.
+fn read_cpu_times() -> Option<(u64, u64)> {
+    let stat = std::fs::read_to_string(PROC_STAT).ok()?;
+    parse_cpu_times(stat.lines().next()?)
+}
+
+fn parse_cpu_times(line: &str) -> Option<(u64, u64)> {
+    let fields: Vec<u64> = line
+        .strip_prefix("cpu ")?
+        .split_whitespace()
+        .filter_map(|f| f.parse().ok())
+        .collect();
+    if fields.len() < 5 {
+        return None;
+    }
+    let total: u64 = fields.iter().sum();
+    // idle and iowait
+    let idle = fields[3] + fields[4];
+    Some((total - idle, total))
+}
+
+fn busy_percent(prev: (u64, u64), cur: (u64, u64)) -> u64 {
+    let busy = cur.0.saturating_sub(prev.0);
+    let total = cur.1.saturating_sub(prev.1);
+    (busy * 100).checked_div(total).unwrap_or(0)
+}
+
+fn read_mem_available() -> Option<u64> {
+    let meminfo = std::fs::read_to_string(PROC_MEMINFO).ok()?;
+    parse_meminfo_kb(&meminfo, "MemAvailable").map(|kb| kb * 1024)
+}
+
+pub fn parse_meminfo_kb(meminfo: &str, key: &str) -> Option<u64> {
+    meminfo.lines().find_map(|line| {
+        let value = line.strip_prefix(key)?.strip_prefix(':')?;
+        value.trim().trim_end_matches(" kB").parse().ok()
+    })
+}
+
+fn discover_cores(root: &Path) -> Vec<Core> {
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return vec![];
+    };
+
+    let mut cores: Vec<Core> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let name = e.file_name().into_string().ok()?;
+            let id = name.strip_prefix("cpu")?.parse::<u32>().ok()?;
+            let dir = e.path();
+            if !dir.join("cpufreq/scaling_cur_freq").exists() {
+                return None;
+            }
+            let max_khz = read_u64(&dir.join("cpufreq/cpuinfo_max_freq"));
+            Some(Core { id, dir, max_khz })
+        })
+        .collect();
+    cores.sort_by_key(|c| c.id);
+    cores
+}
+
+fn read_u64(path: &Path) -> Option<u64> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+fn read_cur_freq(core: &Core) -> Option<u64> {
+    read_u64(&core.dir.join("cpufreq/scaling_cur_freq"))
+}
+
+fn read_throttle_count(core: &Core) -> Option<u64> {
+    read_u64(&core.dir.join("thermal_throttle/core_throttle_count"))
+}
+
+fn summarize(cores: &[Core], samples: &[Sample]) -> FreqReport {
+    let mut report = FreqReport::default();
+
+    for (i, core) in cores.iter().enumerate() {
+        let series: Vec<(Duration, u64)> = samples
+            .iter()
+            .filter_map(|s| s.khz[i].map(|khz| (s.at, khz)))
+            .collect();
+        if series.is_empty() {
+            continue;
+        }
+
+        let min = series.iter().map(|&(_, f)| f).min().unwrap_or(0);
+        let max = series.iter().map(|&(_, f)| f).max().unwrap_or(0);
+        let avg = series.iter().map(|&(_, f)| f).sum::<u64>() / series.len() as u64;
+        report.cores.push(CoreSummary {
+            cpu: core.id,
+            min_mhz: min / 1000,
+            avg_mhz: avg / 1000,
+            max_mhz: max / 1000,
+            throttle_events: 0,
+        });
+
+        let reference = core.max_khz.unwrap_or(max);
+        report.drops.extend(find_drops(core.id, reference, &series));
+    }
+
+    report
+}
+
+fn find_drops(cpu: u32, reference_khz: u64, series: &[(Duration, u64)]) -> Vec<FreqDrop> {
+    let limit = (reference_khz as f64 * DROP_THRESHOLD) as u64;
+    let mut drops = vec![];
+    let mut run: Vec<(Duration, u64)> = vec![];
+
+    // A trailing sentinel above the limit flushes the last run.
+    let sentinel = series.last().map(|&(at, _)| (at, u64::MAX));
+    for &(at, khz) in series.iter().chain(sentinel.iter()) {
+        if khz < limit {
+            run.push((at, khz));
+            continue;
+        }
+        if run.len() >= SUSTAINED_SAMPLES {
+            let start = run[0].0;
+            drops.push(FreqDrop {
+                cpu,
+                start,
+                duration: at - start,
+                min_mhz: run.iter().map(|&(_, f)| f).min().unwrap_or(0) / 1000,
+            });
+        }
+        run.clear();
+    }
+
+    drops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(freqs: &[u64]) -> Vec<(Duration, u64)> {
+        freqs
+            .iter()
+            .enumerate()
+            .map(|(i, &f)| (Duration::from_millis(100 * i as u64), f))
+            .collect()
+    }
+
+    #[test]
+    fn find_drops_ignores_short_dips() {
+        let s = series(&[3_000_000, 1_000_000, 1_000_000, 3_000_000]);
+        assert!(find_drops(0, 3_000_000, &s).is_empty());
+    }
+
+    #[test]
+    fn find_drops_reports_sustained_drop() {
+        let s = series(&[3_000_000, 2_000_000, 1_500_000, 2_000_000, 3_000_000]);
+        let drops = find_drops(2, 3_000_000, &s);
+        assert_eq!(
+            drops,
+            vec![FreqDrop {
+                cpu: 2,
+                start: Duration::from_millis(100),
+                duration: Duration::from_millis(300),
+                min_mhz: 1500,
+            }]
+        );
+    }
+
+    #[test]
+    fn find_drops_flushes_trailing_run() {
+        let s = series(&[1_000_000, 1_000_000, 1_000_000]);
+        assert_eq!(find_drops(0, 3_000_000, &s).len(), 1);
+    }
+
+    #[test]
+    fn parse_cpu_times_sums_idle_and_iowait() {
+        let line = "cpu  100 0 50 800 50 0 0 0 0 0";
+        assert_eq!(parse_cpu_times(line), Some((150, 1000)));
+        assert_eq!(parse_cpu_times("cpu0 1 2 3 4 5"), None);
+    }
+
+    #[test]
+    fn busy_percent_uses_deltas() {
+        assert_eq!(busy_percent((100, 1000), (150, 1100)), 50);
+        assert_eq!(busy_percent((100, 1000), (100, 1000)), 0);
+    }
+
+    #[test]
+    fn parse_meminfo_kb_finds_key() {
+        let meminfo = "MemTotal:       16000 kB\nMemAvailable:    8000 kB\n";
+        assert_eq!(parse_meminfo_kb(meminfo, "MemAvailable"), Some(8000));
+        assert_eq!(parse_meminfo_kb(meminfo, "SwapTotal"), None);
+    }
+
+    #[test]
+    fn throttling_is_recorded_in_the_report() {
+        let freq = FreqReport {
+            cores: vec![CoreSummary {
+                cpu: 3,
+                min_mhz: 1500,
+                avg_mhz: 2500,
+                max_mhz: 3000,
+                throttle_events: 2,
+            }],
+            drops: find_drops(3, 3_000_000, &series(&[1_500_000; 4])),
+        };
+        freq.record();
+        let mut run =
+            report::Report::new("thread", json!({}), std::time::SystemTime::now(), &Ok(()));
+        run.collect();
+        let value: Value = serde_json::from_str(&run.to_json()).unwrap();
+        let recorded = &value["measurements"]["cpu_frequency"];
+        assert_eq!(recorded["throttled"], true);
+        assert_eq!(recorded["cores"][0]["throttle_events"], 2);
+        assert_eq!(recorded["drops"][0]["min_mhz"], 1500);
+        assert_eq!(value["status"], "degraded");
+        assert!(value["degradations"].as_array().unwrap().contains(&json!(
+            "CPU throttled: 1 sustained frequency drops, 2 throttle events"
+        )));
+    }
+
+    #[test]
+    fn start_without_cpufreq_is_disabled() {
+        let root = std::env::temp_dir().join("itsmine-no-cpufreq");
+        std::fs::create_dir_all(root.join("cpu0")).unwrap();
+        assert!(FreqMonitor::start_at(&root, Duration::from_millis(10)).is_none());
+    }
+}
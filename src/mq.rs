@@ -0,0 +1,246 @@
+//! `itsmine mq`: pushes messages through POSIX message queues, one producer
+//! and one consumer thread per queue, to load the kernel's mqueue paths and
+//! run into its limits (`/proc/sys/fs/mqueue`).
+
+use std::ffi::CString;
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+use crate::{CancellationToken, StressError, Stressor, parse, report};
+
+/// How long a blocked send or receive waits before checking for the end.
+const POLL: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Debug, PartialEq, clap::Args)]
+pub struct Args {
+    /// Queues, each with its own producer and consumer thread
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    pub queues: u32,
+    /// Messages each queue holds before senders block; more than
+    /// fs.mqueue.msg_max needs CAP_SYS_RESOURCE
+    #[arg(long, value_name = "N", default_value_t = 10)]
+    pub depth: u32,
+    /// Size of each message, e.g. 1K
+    #[arg(long, value_name = "BYTES", value_parser = parse::bytes, default_value = "1K")]
+    pub message_bytes: u64,
+    /// How long to run, e.g. 10s
+    #[arg(long, value_parser = parse::duration, default_value = "10s")]
+    pub duration: Duration,
+}
+
+/// An open queue, already unlinked so nothing is left behind.
+struct Queue(libc::mqd_t);
+
+impl Queue {
+    fn open(index: u32, depth: u32, message_bytes: u64) -> std::io::Result<Self> {
+        let name = CString::new(format!("/itsmine-{}-{index}", std::process::id()))
+            .expect("no NUL in the name");
+        // SAFETY: `mq_attr` is plain data, valid zeroed.
+        let mut attr: libc::mq_attr = unsafe { std::mem::zeroed() };
+        attr.mq_maxmsg = depth as _;
+        attr.mq_msgsize = message_bytes as _;
+        // SAFETY: `name` and `attr` outlive the calls.
+        unsafe {
+            let queue = libc::mq_open(
+                name.as_ptr(),
+                libc::O_CREAT | libc::O_EXCL | libc::O_RDWR | libc::O_CLOEXEC,
+                0o600 as libc::mode_t,
+                &attr as *const libc::mq_attr,
+            );
+            if queue == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            libc::mq_unlink(name.as_ptr());
+            Ok(Queue(queue))
+        }
+    }
+
+    /// Sends `message`, returning false if the queue stayed full for
+    /// `POLL`.
+    fn send(&self, message: &[u8]) -> std::io::Result<bool> {
+        let timeout = realtime_in(POLL);
+        // SAFETY: `message` and `timeout` outlive the call.
+        let sent = unsafe {
+            libc::mq_timedsend(self.0, message.as_ptr().cast(), message.len(), 0, &timeout)
+        };
+        timed_out_or(sent)
+    }
+
+    /// Receives into `buffer`, returning false if the queue stayed empty
+    /// for `POLL`.
+    fn receive(&self, buffer: &mut [u8]) -> std::io::Result<bool> {
+        let timeout = realtime_in(POLL);
+        // SAFETY: as in `send`; `buffer` is exactly the queue's message size.
+        let received = unsafe {
+            libc::mq_timedreceive(
+                self.0,
+                buffer.as_mut_ptr().cast(),
+                buffer.len(),
+                std::ptr::null_mut(),
+                &timeout,
+            )
+        };
+        timed_out_or(received as libc::c_int)
+    }
+}
+
+impl Drop for Queue {
+    fn drop(&mut self) {
+        // SAFETY: the descriptor came from `mq_open` and is closed once.
+        unsafe { libc::mq_close(self.0) };
+    }
+}
+
+fn timed_out_or(result: libc::c_int) -> std::io::Result<bool> {
+    match result {
+        -1 => match std::io::Error::last_os_error() {
+            e if e.raw_os_error() == Some(libc::ETIMEDOUT) => Ok(false),
+            e => Err(e),
+        },
+        _ => Ok(true),
+    }
+}
+
+/// The wall-clock time `after` from now, as the timed queue calls expect.
+fn realtime_in(after: Duration) -> libc::timespec {
+    let mut now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `now` is a valid out-pointer.
+    unsafe { libc::clock_gettime(libc::CLOCK_REALTIME, &mut now) };
+    let nanos = now.tv_nsec as u64 + after.subsec_nanos() as u64;
+    libc::timespec {
+        tv_sec: now.tv_sec
+            + after.as_secs() as libc::time_t
+            + (nanos / 1_000_000_000) as libc::time_t,
+        tv_nsec: (nanos % 1_000_000_000) as libc::c_long,
+    }
+}
+
+/// Runs a producer and a consumer on `queue` until `deadline` and returns
+/// how many messages got through.
+fn pump(
+    queue: &Queue,
+    message_bytes: usize,
+    deadline: Instant,
+    cancel: &CancellationToken,
+) -> Result<u64, StressError> {
+    let running = || Instant::now() < deadline && !cancel.is_cancelled();
+    let failed = |e: std::io::Error| StressError::WorkloadFailed(format!("message queue: {e}"));
+    std::thread::scope(|s| {
+        let producer = s.spawn(|| {
+            let message = vec![0x5a; message_bytes];
+            while running() {
+                queue.send(&message).map_err(failed)?;
+            }
+            Ok::<_, StressError>(())
+        });
+        let mut buffer = vec![0; message_bytes];
+        let mut received = 0;
+        while running() {
+            if queue.receive(&mut buffer).map_err(failed)? {
+                received += 1;
+            }
+        }
+        producer
+            .join()
+            .map_err(|payload| StressError::WorkerPanicked(crate::panic_message(&payload)))??;
+        Ok(received)
+    })
+}
+
+fn mqueue_limit(name: &str) -> Option<u64> {
+    std::fs::read_to_string(format!("/proc/sys/fs/mqueue/{name}"))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+impl Stressor for Args {
+    fn name(&self) -> &'static str {
+        "mq"
+    }
+
+    fn params(&self) -> Value {
+        json!({
+            "queues": self.queues,
+            "depth": self.depth,
+            "message_bytes": self.message_bytes,
+            "duration_secs": self.duration.as_secs_f64(),
+        })
+    }
+
+    fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        if self.queues == 0 || self.depth == 0 || self.message_bytes == 0 {
+            return Err(StressError::InvalidInput(
+                "--queues, --depth and --message-bytes must be greater than 0".to_string(),
+            )
+            .into());
+        }
+        let queues = (0..self.queues)
+            .map(|index| Queue::open(index, self.depth, self.message_bytes))
+            .collect::<std::io::Result<Vec<_>>>()
+            .map_err(|e| {
+                anyhow::anyhow!(
+                    "Failed to create message queues ({e}); see the limits in /proc/sys/fs/mqueue"
+                )
+            })?;
+        log::info!(
+            "Pumping {}-byte messages through {} queues for {:?}.",
+            self.message_bytes,
+            self.queues,
+            self.duration
+        );
+        let started = Instant::now();
+        let deadline = started + self.duration;
+        let messages = std::thread::scope(|s| {
+            let handles: Vec<_> = queues
+                .iter()
+                .map(|queue| {
+                    s.spawn(move || pump(queue, self.message_bytes as usize, deadline, cancel))
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle.join().map_err(|payload| {
+                        StressError::WorkerPanicked(crate::panic_message(&payload))
+                    })?
+                })
+                .sum::<Result<u64, StressError>>()
+        })?;
+        let elapsed = started.elapsed().as_secs_f64();
+        let summary = json!({
+            "messages": messages,
+            "messages_per_sec": messages as f64 / elapsed,
+            "bytes_per_sec": (messages * self.message_bytes) as f64 / elapsed,
+            "msg_max": mqueue_limit("msg_max"),
+            "queues_max": mqueue_limit("queues_max"),
+        });
+        log::info!("Message queues: {summary}.");
+        report::measure(self.name(), summary);
+        match cancel.is_cancelled() {
+            true => Err(StressError::Interrupted.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pumps_messages_through_a_queue() {
+        let queue = Queue::open(u32::MAX, 4, 64).unwrap();
+        let deadline = Instant::now() + Duration::from_millis(50);
+        let messages = pump(&queue, 64, deadline, &CancellationToken::new()).unwrap();
+        assert!(messages > 0);
+        // A second queue under the same name works, since the first was
+        // unlinked on creation.
+        assert!(Queue::open(u32::MAX, 4, 64).is_ok());
+    }
+}
@@ -0,0 +1,222 @@
+//! Fresh namespaces for `--new-ns`, so filesystem, process and network
+//! stressors work on disposable copies of the host's state. The process
+//! forks once, before any thread starts: the parent stays behind in the
+//! host's namespaces only to pass signals on and exit as the child does,
+//! and the child runs everything else inside the new ones. Only the PID
+//! namespace is created before the fork, since it applies to children
+//! alone; the child creates the rest for itself.
+
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use crate::caps::{self, Privilege};
+
+/// The child running the workloads, for the parent's signal handler.
+static CHILD: AtomicI32 = AtomicI32::new(0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Namespace {
+    /// Private mounts, with /proc remounted when PIDs are new too
+    Mount,
+    /// A PID namespace whose init is the workload process
+    Pid,
+    /// A network namespace with only a loopback interface, brought up
+    Net,
+}
+
+impl Namespace {
+    pub fn name(self) -> &'static str {
+        match self {
+            Namespace::Mount => "mount",
+            Namespace::Pid => "pid",
+            Namespace::Net => "net",
+        }
+    }
+
+    fn flag(self) -> libc::c_int {
+        match self {
+            Namespace::Mount => libc::CLONE_NEWNS,
+            Namespace::Pid => libc::CLONE_NEWPID,
+            Namespace::Net => libc::CLONE_NEWNET,
+        }
+    }
+}
+
+extern "C" fn forward(signal: libc::c_int) {
+    let child = CHILD.load(Ordering::SeqCst);
+    // The child handles only SIGINT and SIGTERM, and as a PID namespace's
+    // init it ignores any signal it has no handler for.
+    let signal = match signal {
+        libc::SIGHUP => libc::SIGTERM,
+        signal => signal,
+    };
+    if child > 0 {
+        // SAFETY: kill is async-signal-safe.
+        unsafe { libc::kill(child, signal) };
+    }
+}
+
+/// Moves the rest of this run into fresh `namespaces`, skipping the lot if
+/// `CAP_SYS_ADMIN` is missing. Must be called before any thread starts,
+/// since only the calling thread survives the fork. Returns in the child;
+/// the parent never returns, exiting with the child's status.
+pub fn enter(namespaces: &[Namespace]) -> Result<(), anyhow::Error> {
+    let names: Vec<&str> = namespaces.iter().map(|ns| ns.name()).collect();
+    if namespaces.is_empty()
+        || !caps::require(
+            &format!("--new-ns {}", names.join(",")),
+            Privilege::SysAdmin,
+        )
+    {
+        return Ok(());
+    }
+    // SAFETY: unshare takes no pointers; a new PID namespace only applies
+    // to children forked after it.
+    if namespaces.contains(&Namespace::Pid) && unsafe { libc::unshare(libc::CLONE_NEWPID) } != 0 {
+        return Err(anyhow::anyhow!(
+            "Failed to create a PID namespace: {}",
+            io::Error::last_os_error()
+        ));
+    }
+    // SAFETY: no other thread exists yet, so the child can carry on as
+    // this process would.
+    match unsafe { libc::fork() } {
+        -1 => Err(anyhow::anyhow!(
+            "Failed to fork into new namespaces: {}",
+            io::Error::last_os_error()
+        )),
+        0 => {
+            set_up(namespaces).map_err(|e| anyhow::anyhow!("Failed to set up new namespaces: {e}"))
+        }
+        child => {
+            CHILD.store(child, Ordering::SeqCst);
+            std::process::exit(wait_for(child))
+        }
+    }
+}
+
+/// Moves the child into its other namespaces and prepares them: dies with
+/// the parent, keeps mounts from reaching the host and brings up loopback.
+fn set_up(namespaces: &[Namespace]) -> io::Result<()> {
+    let check = |result: libc::c_int| match result {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    };
+    // SAFETY: prctl with these arguments takes no pointers.
+    check(unsafe { libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL, 0, 0, 0) })?;
+    let flags = namespaces
+        .iter()
+        .filter(|&&ns| ns != Namespace::Pid)
+        .fold(0, |flags, ns| flags | ns.flag());
+    // SAFETY: unshare takes no pointers.
+    if flags != 0 {
+        check(unsafe { libc::unshare(flags) })?;
+    }
+    if namespaces.contains(&Namespace::Mount) {
+        // SAFETY: the strings are NUL-terminated literals; the rest null.
+        check(unsafe {
+            libc::mount(
+                std::ptr::null(),
+                c"/".as_ptr(),
+                std::ptr::null(),
+                libc::MS_REC | libc::MS_PRIVATE,
+                std::ptr::null(),
+            )
+        })?;
+        if namespaces.contains(&Namespace::Pid) {
+            // SAFETY: as above.
+            check(unsafe {
+                libc::mount(
+                    c"proc".as_ptr(),
+                    c"/proc".as_ptr(),
+                    c"proc".as_ptr(),
+                    libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC,
+                    std::ptr::null(),
+                )
+            })?;
+        }
+    }
+    if namespaces.contains(&Namespace::Net) {
+        loopback_up()?;
+    }
+    log::info!(
+        "Running in new {} namespaces.",
+        namespaces
+            .iter()
+            .map(|ns| ns.name())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    Ok(())
+}
+
+/// Brings up `lo`, which a new network namespace starts with down.
+fn loopback_up() -> io::Result<()> {
+    // SAFETY: plain socket call; the descriptor is owned right after.
+    let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: `socket` just returned this descriptor to us alone.
+    let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+    let fd = socket.as_raw_fd();
+    // SAFETY: ifreq is plain data, valid zeroed.
+    let mut request: libc::ifreq = unsafe { std::mem::zeroed() };
+    for (to, from) in request.ifr_name.iter_mut().zip(b"lo") {
+        *to = *from as libc::c_char;
+    }
+    // SAFETY: `request` names an interface and is valid for both calls.
+    unsafe {
+        if libc::ioctl(fd, libc::SIOCGIFFLAGS, &mut request) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        request.ifr_ifru.ifru_flags |= libc::IFF_UP as libc::c_short;
+        if libc::ioctl(fd, libc::SIOCSIFFLAGS, &request) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Passes SIGINT and SIGTERM on to `child` until it ends, and SIGHUP as
+/// SIGTERM, and returns the exit code that reports how it ended, following
+/// the shell's 128 + signal convention.
+fn wait_for(child: libc::pid_t) -> i32 {
+    let handler = forward as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    // SAFETY: the handler only loads an atomic and calls kill, both
+    // async-signal-safe.
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+        libc::signal(libc::SIGHUP, handler);
+    }
+    let mut status = 0;
+    // SAFETY: `status` is valid for writes; `child` is ours to reap.
+    while unsafe { libc::waitpid(child, &mut status, 0) } == -1 {
+        if io::Error::last_os_error().raw_os_error() != Some(libc::EINTR) {
+            return 1;
+        }
+    }
+    match libc::WIFSIGNALED(status) {
+        true => 128 + libc::WTERMSIG(status),
+        false => libc::WEXITSTATUS(status),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combines_namespace_flags() {
+        let flags = [Namespace::Mount, Namespace::Pid, Namespace::Net]
+            .iter()
+            .fold(0, |flags, ns| flags | ns.flag());
+        assert_eq!(
+            flags,
+            libc::CLONE_NEWNS | libc::CLONE_NEWPID | libc::CLONE_NEWNET
+        );
+        assert_eq!(Namespace::Net.name(), "net");
+    }
+}
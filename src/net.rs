@@ -0,0 +1,1162 @@
+//! `itsmine net`: streams TCP, UDP or WebSocket traffic from several
+//! connections at a target, or at a sink it runs on loopback when given none,
+//! to load NICs, conntrack, gateways and whatever sits between. Multicast
+//! mode sends to groups it also joins, counting what each delivers.
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::net::{
+    Ipv4Addr, SocketAddr, SocketAddrV4, TcpListener, TcpStream, ToSocketAddrs, UdpSocket,
+};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+use crate::chaos::{self, Rng};
+use crate::ramp::Ramp;
+use crate::stats::{self, Samples};
+use crate::template::Template;
+use crate::throttle::{Limits, Pacing, Throttle};
+use crate::websocket::{self, BINARY, CLOSE, PING, PONG, TEXT};
+use crate::{CancellationToken, StressError, Stressor, parse, report};
+
+/// How long blocking socket calls wait before checking for the end.
+const POLL: Duration = Duration::from_millis(50);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Largest payload a UDP datagram carries over IPv4.
+const MAX_DATAGRAM: u64 = 65_507;
+/// How often ramps are read again.
+const RAMP_STEP: Duration = Duration::from_millis(100);
+/// What UDP probes start with; the loopback sink echoes datagrams that do.
+const PROBE_PREFIX: &str = "itsmine-probe ";
+/// Longest a probe waits for its answer before it counts as lost.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+/// The group multicast mode uses when given none, in the
+/// organization-local scope.
+const DEFAULT_GROUP: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(239, 255, 0, 1), 5000);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Mode {
+    Tcp,
+    Udp,
+    /// Binary messages over WebSocket, which the loopback sink echoes.
+    Websocket,
+    /// UDP to multicast groups, which it joins to receive from too.
+    Multicast,
+}
+
+impl Mode {
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Tcp => "tcp",
+            Mode::Udp => "udp",
+            Mode::Websocket => "websocket",
+            Mode::Multicast => "multicast",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, clap::Args)]
+pub struct Args {
+    /// Protocol to send with
+    #[arg(long, value_enum, default_value = "tcp")]
+    pub mode: Mode,
+    /// Where to send, as HOST:PORT; without it, to a sink on loopback
+    #[arg(long, value_name = "HOST:PORT")]
+    pub target: Option<String>,
+    /// Connections (or UDP sockets), each sending from its own thread
+    #[arg(long, value_name = "N", default_value_t = 4)]
+    pub connections: u32,
+    /// Open and close connections over time instead, e.g.
+    /// 0s:0,30s:200,1m:200,1m:0 for a storm of 200 held for 30s
+    #[arg(long, value_name = "RAMP", conflicts_with = "connections")]
+    pub ramp_connections: Option<Ramp>,
+    /// Vary sends per second across all connections over time, e.g.
+    /// 0s:100,1m:10000
+    #[arg(long, value_name = "RAMP", conflicts_with = "limit_iops")]
+    pub ramp_rate: Option<Ramp>,
+    /// Multicast group to send to and receive from, as ADDR:PORT; repeat
+    /// for more, with connections spread over them [default: 239.255.0.1:5000]
+    #[arg(long = "group", value_name = "ADDR:PORT")]
+    pub groups: Vec<SocketAddrV4>,
+    /// Address of the interface to join groups and send multicast on
+    /// [default: the one the routing table picks]
+    #[arg(long, value_name = "ADDR")]
+    pub interface: Option<Ipv4Addr>,
+    /// Routers multicast may cross; 1 keeps it on the local network
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    pub ttl: u32,
+    /// Only join the groups and count what arrives, e.g. from another host
+    #[arg(long)]
+    pub receive_only: bool,
+    /// Request path to open WebSocket connections on
+    #[arg(long, value_name = "PATH", default_value = "/")]
+    pub ws_path: String,
+    /// Bytes per send (message, for WebSocket); at most 65507 for UDP
+    #[arg(long, value_name = "BYTES", value_parser = parse::bytes, default_value = "1K")]
+    pub size: u64,
+    /// Send this file instead, with sections like {{hex:8}}, {{digits:4}},
+    /// {{letters:6}} or {{random:16}} filled afresh for every send
+    #[arg(long, value_name = "PATH", conflicts_with = "size")]
+    pub payload: Option<PathBuf>,
+    /// Seed for the template's random sections, to send the same traffic
+    /// again
+    #[arg(long, value_name = "N", requires = "payload")]
+    pub seed: Option<u64>,
+    #[command(flatten)]
+    pub limits: Limits,
+    /// Alongside the load, time a round trip this often, e.g. 100ms, and
+    /// report its latency apart from the throughput: a TCP handshake, or for
+    /// UDP a datagram the target echoes back
+    #[arg(long, value_name = "INTERVAL", value_parser = parse::duration)]
+    pub probe_interval: Option<Duration>,
+    /// How to wait between sends under a limit; spin keeps gaps accurate to
+    /// microseconds, for reproducing microbursts, but keeps a CPU busy per
+    /// connection
+    #[arg(long, value_enum, default_value = "sleep")]
+    pub pacing: Pacing,
+    /// How long to send, e.g. 10s
+    #[arg(long, value_parser = parse::duration, default_value = "10s")]
+    pub duration: Duration,
+}
+
+/// What one connection got out.
+#[derive(Debug, Default, PartialEq)]
+struct Sent {
+    sends: u64,
+    bytes: u64,
+    /// UDP sends the kernel refused, e.g. with nothing listening.
+    errors: u64,
+    /// WebSocket messages that came back.
+    received: u64,
+    connects: u64,
+    /// Multicast sends, by group.
+    per_group: BTreeMap<SocketAddr, u64>,
+}
+
+impl std::ops::AddAssign for Sent {
+    fn add_assign(&mut self, other: Sent) {
+        self.sends += other.sends;
+        self.bytes += other.bytes;
+        self.errors += other.errors;
+        self.received += other.received;
+        self.connects += other.connects;
+        for (group, sends) in other.per_group {
+            *self.per_group.entry(group).or_default() += sends;
+        }
+    }
+}
+
+/// What one connection sends: the same `--size` bytes every time, or a
+/// template with its sections filled afresh for each send.
+struct Payload<'a> {
+    template: Option<&'a Template>,
+    rng: Rng,
+    bytes: Vec<u8>,
+}
+
+impl<'a> Payload<'a> {
+    fn new(size: u64, template: Option<&'a Template>, seed: u64) -> Self {
+        let mut rng = Rng::new(seed);
+        let bytes = match template {
+            Some(template) => template.render(&mut rng),
+            None => vec![0x5a; size as usize],
+        };
+        Payload {
+            template,
+            rng,
+            bytes,
+        }
+    }
+
+    fn next(&mut self) -> &[u8] {
+        if let Some(template) = self.template {
+            template.refill(&mut self.bytes, &mut self.rng);
+        }
+        &self.bytes
+    }
+}
+
+fn timed_out(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+    )
+}
+
+/// Writes all of `bytes`, waiting out a full socket, unless `stopped` first.
+fn write_all_until(
+    stream: &mut TcpStream,
+    mut bytes: &[u8],
+    stopped: &dyn Fn() -> bool,
+) -> std::io::Result<bool> {
+    while !bytes.is_empty() {
+        if stopped() {
+            return Ok(false);
+        }
+        match stream.write(bytes) {
+            Ok(0) => return Err(std::io::ErrorKind::WriteZero.into()),
+            Ok(written) => bytes = &bytes[written..],
+            Err(e) if timed_out(&e) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+/// Counts the messages a WebSocket server sends on `stream` and answers its
+/// pings, until `done` or it closes. `pending` is what the handshake read
+/// past its end.
+fn receive(
+    mut stream: TcpStream,
+    mut pending: Vec<u8>,
+    writer: &Mutex<TcpStream>,
+    done: &AtomicBool,
+) -> u64 {
+    let mut rng = Rng::new(chaos::random_seed());
+    let mut buffer = vec![0; 64 << 10];
+    let mut messages = 0;
+    loop {
+        while let Some(frame) = websocket::decode(&mut pending) {
+            match frame.opcode {
+                TEXT | BINARY => messages += 1,
+                PING => {
+                    let pong = websocket::encode(PONG, &frame.payload, Some(mask(&mut rng)));
+                    let mut writer = writer.lock().unwrap_or_else(|e| e.into_inner());
+                    let _ = write_all_until(&mut writer, &pong, &|| done.load(Ordering::Relaxed));
+                }
+                CLOSE => return messages,
+                _ => {}
+            }
+        }
+        if done.load(Ordering::Relaxed) {
+            return messages;
+        }
+        match stream.read(&mut buffer) {
+            Ok(0) => return messages,
+            Ok(read) => pending.extend_from_slice(&buffer[..read]),
+            Err(e) if timed_out(&e) => {}
+            Err(_) => return messages,
+        }
+    }
+}
+
+fn mask(rng: &mut Rng) -> [u8; 4] {
+    (rng.next_u64() as u32).to_ne_bytes()
+}
+
+/// Round trips timed beside the load.
+struct Probes {
+    samples: Samples,
+    /// Probes unanswered within `PROBE_TIMEOUT`, or refused.
+    lost: u64,
+}
+
+/// Every `interval` until `stop`, times a round trip to `target` on a
+/// socket of its own: a TCP handshake, which any listener answers, or for
+/// UDP a numbered datagram the target must echo.
+fn probe(
+    mode: Mode,
+    target: SocketAddr,
+    interval: Duration,
+    stop: &AtomicBool,
+) -> std::io::Result<Probes> {
+    let udp = match mode {
+        Mode::Udp => {
+            let local: SocketAddr = match target {
+                SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+                SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+            };
+            let socket = UdpSocket::bind(local)?;
+            socket.connect(target)?;
+            Some(socket)
+        }
+        _ => None,
+    };
+    let mut rng = Rng::new(chaos::random_seed());
+    let mut probes = Probes {
+        samples: Samples::new(),
+        lost: 0,
+    };
+    let mut buffer = [0; 64];
+    let mut next = Instant::now();
+    for number in 0u64.. {
+        next += interval;
+        while !stop.load(Ordering::Relaxed) && Instant::now() < next {
+            std::thread::sleep(POLL.min(next.saturating_duration_since(Instant::now())));
+        }
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+        let started = Instant::now();
+        let answered = match &udp {
+            None => TcpStream::connect_timeout(&target, PROBE_TIMEOUT).is_ok(),
+            Some(socket) => {
+                let message = format!("{PROBE_PREFIX}{number}");
+                let deadline = started + PROBE_TIMEOUT;
+                let mut answered = socket.send(message.as_bytes()).is_ok();
+                while answered {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    socket.set_read_timeout(Some(left.max(Duration::from_micros(1))))?;
+                    match socket.recv(&mut buffer) {
+                        Ok(len) if &buffer[..len] == message.as_bytes() => break,
+                        // An answer to an earlier probe, too late.
+                        Ok(_) => {}
+                        Err(_) => answered = false,
+                    }
+                }
+                answered
+            }
+        };
+        match answered {
+            true => probes.samples.record(started.elapsed(), &mut rng),
+            false => probes.lost += 1,
+        }
+        next = next.max(Instant::now());
+    }
+    Ok(probes)
+}
+
+/// Points multicast sent from `socket` out of the interface with `address`.
+fn set_multicast_interface(socket: &UdpSocket, address: Ipv4Addr) -> std::io::Result<()> {
+    let address = libc::in_addr {
+        s_addr: u32::from(address).to_be(),
+    };
+    // SAFETY: `address` outlives the call, which only reads it.
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_IP,
+            libc::IP_MULTICAST_IF,
+            (&raw const address).cast(),
+            size_of::<libc::in_addr>() as libc::socklen_t,
+        )
+    };
+    match result {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+/// A socket receiving `group` on `interface`. It binds the group's own
+/// address, so it gets no other group's traffic, and shares the port with
+/// other listeners on the host.
+fn join(group: SocketAddrV4, interface: Ipv4Addr) -> std::io::Result<UdpSocket> {
+    let address = libc::sockaddr_in {
+        sin_family: libc::AF_INET as libc::sa_family_t,
+        sin_port: group.port().to_be(),
+        sin_addr: libc::in_addr {
+            s_addr: u32::from(*group.ip()).to_be(),
+        },
+        sin_zero: [0; 8],
+    };
+    let on: libc::c_int = 1;
+    // SAFETY: plain syscalls; the descriptor is owned as soon as it's made,
+    // and `on` and `address` outlive the calls reading them.
+    let socket = unsafe {
+        let fd = libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0);
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let fd = OwnedFd::from_raw_fd(fd);
+        if libc::setsockopt(
+            fd.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_REUSEADDR,
+            (&raw const on).cast(),
+            size_of::<libc::c_int>() as libc::socklen_t,
+        ) != 0
+            || libc::bind(
+                fd.as_raw_fd(),
+                (&raw const address).cast(),
+                size_of::<libc::sockaddr_in>() as libc::socklen_t,
+            ) != 0
+        {
+            return Err(std::io::Error::last_os_error());
+        }
+        UdpSocket::from(fd)
+    };
+    socket.join_multicast_v4(group.ip(), &interface)?;
+    socket.set_read_timeout(Some(POLL))?;
+    Ok(socket)
+}
+
+enum Listener {
+    Tcp(TcpListener),
+    Udp(UdpSocket),
+    /// A socket per multicast group.
+    Multicast(Vec<UdpSocket>),
+}
+
+/// A loopback endpoint that takes whatever it is sent and counts it,
+/// echoing WebSocket messages back.
+struct Sink {
+    mode: Mode,
+    listener: Listener,
+    received: AtomicU64,
+    /// Packets each multicast group delivered, in `--group` order.
+    packets: Vec<AtomicU64>,
+    done: AtomicBool,
+}
+
+impl Sink {
+    fn bind(args: &Args) -> std::io::Result<Self> {
+        let mode = args.mode;
+        let groups = args.groups();
+        let listener = match mode {
+            Mode::Tcp | Mode::Websocket => {
+                let listener = TcpListener::bind("127.0.0.1:0")?;
+                listener.set_nonblocking(true)?;
+                Listener::Tcp(listener)
+            }
+            Mode::Udp => {
+                let socket = UdpSocket::bind("127.0.0.1:0")?;
+                socket.set_read_timeout(Some(POLL))?;
+                Listener::Udp(socket)
+            }
+            Mode::Multicast => {
+                let interface = args.interface.unwrap_or(Ipv4Addr::UNSPECIFIED);
+                let sockets = groups.iter().map(|group| join(*group, interface));
+                Listener::Multicast(sockets.collect::<Result<_, _>>()?)
+            }
+        };
+        Ok(Sink {
+            mode,
+            listener,
+            received: AtomicU64::new(0),
+            packets: groups.iter().map(|_| AtomicU64::new(0)).collect(),
+            done: AtomicBool::new(false),
+        })
+    }
+
+    fn addr(&self) -> std::io::Result<SocketAddr> {
+        match &self.listener {
+            Listener::Tcp(listener) => listener.local_addr(),
+            Listener::Udp(socket) => socket.local_addr(),
+            Listener::Multicast(sockets) => sockets[0].local_addr(),
+        }
+    }
+
+    /// Receives until stopped, each TCP connection on a thread of its own.
+    fn serve<'scope>(&'scope self, s: &'scope std::thread::Scope<'scope, '_>) {
+        let mut buffer = vec![0; 64 << 10];
+        while !self.done.load(Ordering::Relaxed) {
+            match &self.listener {
+                Listener::Tcp(listener) => match listener.accept() {
+                    Ok((stream, _)) if self.mode == Mode::Websocket => {
+                        s.spawn(move || self.echo(stream));
+                    }
+                    Ok((stream, _)) => {
+                        s.spawn(move || self.drain(stream));
+                    }
+                    Err(_) => std::thread::sleep(POLL),
+                },
+                Listener::Udp(socket) => {
+                    if let Ok((received, from)) = socket.recv_from(&mut buffer) {
+                        self.received.fetch_add(received as u64, Ordering::Relaxed);
+                        if buffer[..received].starts_with(PROBE_PREFIX.as_bytes()) {
+                            let _ = socket.send_to(&buffer[..received], from);
+                        }
+                    }
+                }
+                Listener::Multicast(sockets) => {
+                    for (index, socket) in sockets.iter().enumerate() {
+                        s.spawn(move || self.gather(index, socket));
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    fn drain(&self, mut stream: TcpStream) {
+        let mut buffer = vec![0; 64 << 10];
+        let _ = stream.set_read_timeout(Some(POLL));
+        while !self.done.load(Ordering::Relaxed) {
+            match stream.read(&mut buffer) {
+                Ok(0) => return,
+                Ok(received) => {
+                    self.received.fetch_add(received as u64, Ordering::Relaxed);
+                }
+                Err(e) if timed_out(&e) => {}
+                Err(_) => return,
+            }
+        }
+    }
+
+    fn gather(&self, index: usize, socket: &UdpSocket) {
+        let mut buffer = vec![0; 64 << 10];
+        while !self.done.load(Ordering::Relaxed) {
+            if let Ok(received) = socket.recv(&mut buffer) {
+                self.received.fetch_add(received as u64, Ordering::Relaxed);
+                self.packets[index].fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn echo(&self, mut stream: TcpStream) {
+        let _ = stream.set_read_timeout(Some(POLL));
+        let _ = stream.set_write_timeout(Some(POLL));
+        let Ok(mut pending) = websocket::accept(&mut stream) else {
+            return;
+        };
+        let stopped = || self.done.load(Ordering::Relaxed);
+        let mut buffer = vec![0; 64 << 10];
+        while !stopped() {
+            while let Some(frame) = websocket::decode(&mut pending) {
+                let reply = match frame.opcode {
+                    CLOSE => {
+                        let _ = stream.write(&websocket::encode(CLOSE, &[], None));
+                        return;
+                    }
+                    PING => websocket::encode(PONG, &frame.payload, None),
+                    PONG => continue,
+                    opcode => {
+                        let received = frame.payload.len() as u64;
+                        self.received.fetch_add(received, Ordering::Relaxed);
+                        websocket::encode(opcode, &frame.payload, None)
+                    }
+                };
+                if !matches!(write_all_until(&mut stream, &reply, &stopped), Ok(true)) {
+                    return;
+                }
+            }
+            match stream.read(&mut buffer) {
+                Ok(0) => return,
+                Ok(read) => pending.extend_from_slice(&buffer[..read]),
+                Err(e) if timed_out(&e) => {}
+                Err(_) => return,
+            }
+        }
+    }
+
+    fn stop(&self) {
+        self.done.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Args {
+    fn target(&self) -> Result<SocketAddr, anyhow::Error> {
+        let target = self.target.as_deref().unwrap_or_default();
+        target
+            .to_socket_addrs()
+            .map_err(|e| anyhow::anyhow!("Failed to resolve --target {target}: {e}"))?
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("--target {target} resolves to no address"))
+    }
+
+    /// The multicast groups, or the default one.
+    fn groups(&self) -> Vec<SocketAddrV4> {
+        match self.groups.is_empty() {
+            true => vec![DEFAULT_GROUP],
+            false => self.groups.clone(),
+        }
+    }
+
+    /// Runs connection `index`: open for the whole run, or under
+    /// `--ramp-connections` only while the ramp is above `index`,
+    /// connecting again each time it climbs back past it.
+    fn connection(
+        &self,
+        index: u32,
+        target: SocketAddr,
+        throttle: &Throttle,
+        started: Instant,
+        mut payload: Payload,
+        cancel: &CancellationToken,
+    ) -> std::io::Result<Sent> {
+        let deadline = started + self.duration;
+        let Some(ramp) = &self.ramp_connections else {
+            return self.send(target, throttle, deadline, &|| true, &mut payload, cancel);
+        };
+        let open = || ramp.at(started.elapsed()).round() > index as f64;
+        let mut total = Sent::default();
+        while Instant::now() < deadline && !cancel.is_cancelled() {
+            match open() {
+                true => {
+                    total += self.send(target, throttle, deadline, &open, &mut payload, cancel)?
+                }
+                false => {
+                    cancel.sleep_until((Instant::now() + RAMP_STEP).min(deadline));
+                }
+            }
+        }
+        Ok(total)
+    }
+
+    /// Sends payloads to `target` over one connection until `deadline` or
+    /// until `open` says to close it, as fast as `throttle` allows.
+    fn send(
+        &self,
+        target: SocketAddr,
+        throttle: &Throttle,
+        deadline: Instant,
+        open: &dyn Fn() -> bool,
+        payload: &mut Payload,
+        cancel: &CancellationToken,
+    ) -> std::io::Result<Sent> {
+        let size = payload.bytes.len() as u64;
+        let mut sent = Sent {
+            connects: 1,
+            ..Sent::default()
+        };
+        let running = || Instant::now() < deadline && open() && throttle.admit(size, cancel);
+        match self.mode {
+            Mode::Tcp => {
+                let mut stream = TcpStream::connect_timeout(&target, CONNECT_TIMEOUT)?;
+                stream.set_write_timeout(Some(POLL))?;
+                while running() {
+                    match stream.write(payload.next()) {
+                        Ok(written) => {
+                            (sent.sends, sent.bytes) = (sent.sends + 1, sent.bytes + written as u64)
+                        }
+                        Err(e) if timed_out(&e) => {}
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+            Mode::Udp | Mode::Multicast => {
+                let local: SocketAddr = match target {
+                    SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+                    SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+                };
+                let socket = UdpSocket::bind(local)?;
+                if self.mode == Mode::Multicast {
+                    socket.set_multicast_ttl_v4(self.ttl)?;
+                    socket.set_multicast_loop_v4(true)?;
+                    if let Some(interface) = self.interface {
+                        set_multicast_interface(&socket, interface)?;
+                    }
+                }
+                socket.connect(target)?;
+                socket.set_write_timeout(Some(POLL))?;
+                while running() {
+                    match socket.send(payload.next()) {
+                        Ok(written) => {
+                            (sent.sends, sent.bytes) = (sent.sends + 1, sent.bytes + written as u64)
+                        }
+                        Err(e) if timed_out(&e) => {}
+                        Err(e)
+                            if matches!(
+                                e.raw_os_error(),
+                                Some(libc::ECONNREFUSED | libc::ENOBUFS)
+                            ) =>
+                        {
+                            sent.errors += 1
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+                if self.mode == Mode::Multicast {
+                    sent.per_group.insert(target, sent.sends);
+                }
+            }
+            Mode::Websocket => {
+                let stream = TcpStream::connect_timeout(&target, CONNECT_TIMEOUT)?;
+                stream.set_read_timeout(Some(POLL))?;
+                stream.set_write_timeout(Some(POLL))?;
+                let mut rng = Rng::new(chaos::random_seed());
+                let mut nonce = [0; 16];
+                nonce[..8].copy_from_slice(&rng.next_u64().to_ne_bytes());
+                nonce[8..].copy_from_slice(&rng.next_u64().to_ne_bytes());
+                let mut reader = stream.try_clone()?;
+                let host = self.target.clone().unwrap_or_else(|| target.to_string());
+                let pending = websocket::connect(&mut reader, &host, &self.ws_path, nonce)?;
+                let writer = Mutex::new(stream);
+                let done = AtomicBool::new(false);
+                let stopped = || Instant::now() >= deadline || cancel.is_cancelled();
+                let outcome = std::thread::scope(|s| {
+                    let receiver = s.spawn(|| receive(reader, pending, &writer, &done));
+                    let mut outcome = Ok(());
+                    while running() {
+                        let frame = websocket::encode(BINARY, payload.next(), Some(mask(&mut rng)));
+                        let mut stream = writer.lock().unwrap_or_else(|e| e.into_inner());
+                        match write_all_until(&mut stream, &frame, &stopped) {
+                            Ok(true) => {
+                                (sent.sends, sent.bytes) = (sent.sends + 1, sent.bytes + size)
+                            }
+                            Ok(false) => break,
+                            Err(e) => {
+                                outcome = Err(e);
+                                break;
+                            }
+                        }
+                    }
+                    let close = websocket::encode(CLOSE, &[], Some(mask(&mut rng)));
+                    let _ = writer
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .write(&close);
+                    done.store(true, Ordering::Relaxed);
+                    sent.received = receiver.join().unwrap_or_default();
+                    outcome
+                });
+                outcome?;
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Sends from every connection at once, returning the total and how
+    /// long it took. Connection `i` seeds its payloads with `seed + i`, and
+    /// in multicast mode sends to group `i` (wrapping) rather than `target`.
+    fn send_all(
+        &self,
+        target: SocketAddr,
+        template: Option<&Template>,
+        seed: u64,
+        cancel: &CancellationToken,
+    ) -> Result<(Sent, Duration), StressError> {
+        let throttle = &Throttle::new(&self.limits).with_pacing(self.pacing);
+        let connections = match &self.ramp_connections {
+            Some(ramp) => ramp.peak().round() as u32,
+            None => self.connections,
+        };
+        let groups = &self.groups();
+        let started = Instant::now();
+        if let Some(ramp) = &self.ramp_rate {
+            throttle.set_limit_iops(ramp.at(Duration::ZERO));
+        }
+        let outcomes = std::thread::scope(|s| {
+            let handles: Vec<_> = (0..connections)
+                .map(|index| {
+                    let payload =
+                        Payload::new(self.size, template, seed.wrapping_add(index as u64));
+                    let target = match self.mode {
+                        Mode::Multicast => groups[index as usize % groups.len()].into(),
+                        _ => target,
+                    };
+                    s.spawn(move || {
+                        self.connection(index, target, throttle, started, payload, cancel)
+                    })
+                })
+                .collect();
+            if let Some(ramp) = &self.ramp_rate {
+                while !handles.iter().all(|handle| handle.is_finished()) {
+                    throttle.set_limit_iops(ramp.at(started.elapsed()));
+                    std::thread::sleep(RAMP_STEP);
+                }
+            }
+            handles
+                .into_iter()
+                .map(|handle| handle.join())
+                .collect::<Vec<_>>()
+        });
+        let elapsed = started.elapsed();
+        let mut total = Sent::default();
+        for outcome in outcomes {
+            let sent = outcome
+                .map_err(|payload| StressError::WorkerPanicked(crate::panic_message(&payload)))?
+                .map_err(|e| StressError::WorkloadFailed(format!("sending to {target}: {e}")))?;
+            total += sent;
+        }
+        Ok((total, elapsed))
+    }
+}
+
+impl Stressor for Args {
+    fn name(&self) -> &'static str {
+        "net"
+    }
+
+    fn params(&self) -> Value {
+        json!({
+            "mode": self.mode.name(),
+            "target": self.target,
+            "ws_path": self.ws_path,
+            "groups": self.groups().iter().map(ToString::to_string).collect::<Vec<_>>(),
+            "interface": self.interface,
+            "ttl": self.ttl,
+            "receive_only": self.receive_only,
+            "connections": self.connections,
+            "ramp_connections": self.ramp_connections.as_ref().map(Ramp::to_string),
+            "ramp_rate": self.ramp_rate.as_ref().map(Ramp::to_string),
+            "size": self.size,
+            "payload": self.payload,
+            "seed": self.seed,
+            "limit_bytes_per_sec": self.limits.limit,
+            "limit_iops": self.limits.limit_iops,
+            "pacing": self.pacing.name(),
+            "probe_interval_secs": self.probe_interval.map(|interval| interval.as_secs_f64()),
+            "duration_secs": self.duration.as_secs_f64(),
+        })
+    }
+
+    fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        if self.connections == 0 || self.size == 0 {
+            return Err(StressError::InvalidInput(
+                "--connections and --size must be greater than 0".to_string(),
+            )
+            .into());
+        }
+        if self
+            .ramp_connections
+            .as_ref()
+            .is_some_and(|ramp| ramp.peak() < 0.5)
+        {
+            return Err(StressError::InvalidInput(
+                "--ramp-connections must reach at least 1".to_string(),
+            )
+            .into());
+        }
+        // A rate of 0 would hold every connection until it rose again.
+        if self
+            .ramp_rate
+            .as_ref()
+            .is_some_and(|ramp| ramp.floor() < 1.0)
+        {
+            return Err(StressError::InvalidInput(
+                "--ramp-rate levels must be at least 1".to_string(),
+            )
+            .into());
+        }
+        let template = self.payload.as_deref().map(Template::load).transpose()?;
+        let size = template.as_ref().map_or(self.size, Template::size);
+        if self.mode == Mode::Udp && size > MAX_DATAGRAM {
+            return Err(StressError::InvalidInput(format!(
+                "payloads must be at most {MAX_DATAGRAM} bytes for udp"
+            ))
+            .into());
+        }
+        let groups = self.groups();
+        if self.mode == Mode::Multicast {
+            if self.target.is_some() {
+                return Err(StressError::InvalidInput(
+                    "multicast sends to --group, not --target".to_string(),
+                )
+                .into());
+            }
+            if let Some(group) = groups.iter().find(|group| !group.ip().is_multicast()) {
+                return Err(StressError::InvalidInput(format!(
+                    "--group {group} is not a multicast address"
+                ))
+                .into());
+            }
+            if self.probe_interval.is_some() {
+                return Err(StressError::InvalidInput(
+                    "--probe-interval needs an endpoint to answer; multicast has none".to_string(),
+                )
+                .into());
+            }
+        } else if self.receive_only {
+            return Err(StressError::InvalidInput(
+                "--receive-only applies to multicast only".to_string(),
+            )
+            .into());
+        }
+        let seed = self.seed.unwrap_or_else(chaos::random_seed);
+        let sink = match self.target {
+            Some(_) => None,
+            None => Some(Sink::bind(self)?),
+        };
+        let target = match &sink {
+            Some(sink) => sink.addr()?,
+            None => self.target()?,
+        };
+        let destination = match self.mode {
+            Mode::Multicast => groups
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(","),
+            _ => target.to_string(),
+        };
+        let connections = match &self.ramp_connections {
+            Some(ramp) => format!("up to {}", ramp.peak().round()),
+            None => self.connections.to_string(),
+        };
+        match self.receive_only {
+            true => log::info!(
+                "Counting multicast to {destination} for {:?}.",
+                self.duration
+            ),
+            false => log::info!(
+                "Sending {size}-byte {} payloads to {destination} from {connections} connections for {:?}.",
+                self.mode.name(),
+                self.duration
+            ),
+        }
+        if let Some(path) = &self.payload {
+            log::info!("Payloads from {} (seed {seed}).", path.display());
+        }
+        let stop = &AtomicBool::new(false);
+        let (sent, probes) = std::thread::scope(|s| {
+            if let Some(sink) = &sink {
+                s.spawn(|| sink.serve(s));
+            }
+            let probes = self
+                .probe_interval
+                .map(|interval| s.spawn(move || probe(self.mode, target, interval, stop)));
+            let sent = match self.receive_only {
+                true => {
+                    let started = Instant::now();
+                    cancel.sleep_until(started + self.duration);
+                    Ok((Sent::default(), started.elapsed()))
+                }
+                false => self.send_all(target, template.as_ref(), seed, cancel),
+            };
+            stop.store(true, Ordering::Relaxed);
+            // The sink answers UDP probes, so it stops last.
+            let probes = probes.map(|probes| probes.join());
+            if let Some(sink) = &sink {
+                sink.stop();
+            }
+            (sent, probes)
+        });
+        let (sent, elapsed) = sent?;
+        let mut summary = json!({
+            "target": destination,
+            "sends": sent.sends,
+            "sends_per_sec": sent.sends as f64 / elapsed.as_secs_f64(),
+            "bytes_per_sec": sent.bytes as f64 / elapsed.as_secs_f64(),
+            "send_errors": sent.errors,
+        });
+        if self.mode == Mode::Websocket {
+            summary["received_messages"] = json!(sent.received);
+        }
+        if self.ramp_connections.is_some() {
+            summary["connects"] = json!(sent.connects);
+        }
+        if let Some(probes) = probes {
+            let mut probes = probes
+                .map_err(|payload| StressError::WorkerPanicked(crate::panic_message(&payload)))?
+                .map_err(|e| anyhow::anyhow!("Probing {target} failed: {e}"))?;
+            probes.samples.kept.sort_by(f64::total_cmp);
+            let us = |p: f64| stats::percentile(&probes.samples.kept, p) / 1000.0;
+            summary["probes"] = json!(probes.samples.seen + probes.lost);
+            summary["probes_lost"] = json!(probes.lost);
+            summary["probe_rtt_p50_us"] = json!(us(0.5));
+            summary["probe_rtt_p99_us"] = json!(us(0.99));
+            summary["probe_rtt_max_us"] = json!(us(1.0));
+        }
+        if let Some(sink) = sink.as_ref().filter(|_| self.mode == Mode::Multicast) {
+            let per_group: Vec<_> = groups
+                .iter()
+                .zip(&sink.packets)
+                .map(|(group, packets)| {
+                    let sent = sent.per_group.get(&(*group).into());
+                    json!({
+                        "group": group.to_string(),
+                        "sent": sent.copied().unwrap_or_default(),
+                        "received": packets.load(Ordering::Relaxed),
+                    })
+                })
+                .collect();
+            summary["groups"] = json!(per_group);
+        }
+        if self.payload.is_some() {
+            summary["seed"] = json!(seed);
+        }
+        if let Some(sink) = &sink {
+            summary["received_bytes"] = json!(sink.received.load(Ordering::Relaxed));
+        }
+        log::info!("Network: {summary}.");
+        report::measure(self.name(), summary);
+        match cancel.is_cancelled() {
+            true => Err(StressError::Interrupted.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sends_to_its_own_sink_under_the_limit() {
+        for mode in [Mode::Tcp, Mode::Udp, Mode::Websocket] {
+            let args = Args {
+                mode,
+                target: None,
+                ramp_connections: None,
+                ramp_rate: None,
+                groups: vec![],
+                interface: None,
+                ttl: 1,
+                receive_only: false,
+                ws_path: "/".to_string(),
+                payload: None,
+                seed: None,
+                connections: 2,
+                size: 1000,
+                limits: Limits {
+                    limit: Some(1e6),
+                    limit_iops: None,
+                },
+                probe_interval: None,
+                pacing: Pacing::Sleep,
+                duration: Duration::from_millis(200),
+            };
+            let sink = Sink::bind(&args).unwrap();
+            let target = sink.addr().unwrap();
+            let (sent, elapsed) = std::thread::scope(|s| {
+                s.spawn(|| sink.serve(s));
+                let sent = args
+                    .send_all(target, None, 0, &CancellationToken::new())
+                    .unwrap();
+                sink.stop();
+                sent
+            });
+            // At 1M/s for 200ms, about 200 sends of 1000 bytes.
+            assert!((100..=300).contains(&sent.sends), "{mode:?}: {sent:?}");
+            assert!(elapsed >= Duration::from_millis(200));
+            assert!(sink.received.load(Ordering::Relaxed) > 0, "{mode:?}");
+            if mode == Mode::Websocket {
+                assert!(sent.received > 0, "{sent:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn counts_multicast_per_group() {
+        let port = 40_000 + (std::process::id() % 20_000) as u16;
+        let args = Args {
+            mode: Mode::Multicast,
+            target: None,
+            connections: 2,
+            ramp_connections: None,
+            ramp_rate: None,
+            groups: vec![
+                SocketAddrV4::new(Ipv4Addr::new(239, 255, 77, 1), port),
+                SocketAddrV4::new(Ipv4Addr::new(239, 255, 77, 2), port),
+            ],
+            interface: Some(Ipv4Addr::LOCALHOST),
+            ttl: 0,
+            receive_only: false,
+            ws_path: "/".to_string(),
+            size: 100,
+            payload: None,
+            seed: None,
+            limits: Limits {
+                limit: None,
+                limit_iops: Some(1000.0),
+            },
+            probe_interval: None,
+            pacing: Pacing::Spin,
+            duration: Duration::from_millis(200),
+        };
+        let sink = Sink::bind(&args).unwrap();
+        let (sent, _) = std::thread::scope(|s| {
+            s.spawn(|| sink.serve(s));
+            let sent = args.send_all(sink.addr().unwrap(), None, 0, &CancellationToken::new());
+            // Let the last packets arrive.
+            std::thread::sleep(Duration::from_millis(50));
+            sink.stop();
+            sent.unwrap()
+        });
+        for (group, packets) in args.groups.iter().zip(&sink.packets) {
+            let sends = sent.per_group[&(*group).into()];
+            // Each group has one sender, at about half the 1000/s.
+            assert!((50..=150).contains(&sends), "{group}: {sent:?}");
+            assert_eq!(packets.load(Ordering::Relaxed), sends, "{group}");
+        }
+    }
+
+    /// The defaults, in `mode`.
+    fn args(mode: Mode) -> Args {
+        #[derive(clap::Parser)]
+        struct Command {
+            #[command(flatten)]
+            args: Args,
+        }
+        let command = <Command as clap::Parser>::parse_from(["net", "--mode", mode.name()]);
+        command.args
+    }
+
+    #[test]
+    fn probes_round_trips_to_the_sink() {
+        for mode in [Mode::Tcp, Mode::Udp] {
+            let sink = Sink::bind(&args(mode)).unwrap();
+            let target = sink.addr().unwrap();
+            let stop = &AtomicBool::new(false);
+            let probes = std::thread::scope(|s| {
+                s.spawn(|| sink.serve(s));
+                let probes =
+                    s.spawn(|| probe(mode, target, Duration::from_millis(10), stop).unwrap());
+                std::thread::sleep(Duration::from_millis(200));
+                stop.store(true, Ordering::Relaxed);
+                let probes = probes.join().unwrap();
+                sink.stop();
+                probes
+            });
+            assert!(probes.samples.seen >= 10, "{mode:?}");
+            assert_eq!(probes.lost, 0, "{mode:?}");
+        }
+        // Nothing answers on the sink's port once it is gone.
+        let target = UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let stop = AtomicBool::new(false);
+        let probes = std::thread::scope(|s| {
+            let probes = s.spawn(|| probe(Mode::Udp, target, Duration::from_millis(10), &stop));
+            std::thread::sleep(Duration::from_millis(50));
+            stop.store(true, Ordering::Relaxed);
+            probes.join().unwrap().unwrap()
+        });
+        assert!(probes.lost > 0 && probes.samples.seen == 0);
+    }
+
+    #[test]
+    fn renders_a_fresh_payload_per_send() {
+        let template = Template::parse(b"id={{digits:6}}").unwrap();
+        let (mut a, mut b) = (
+            Payload::new(0, Some(&template), 3),
+            Payload::new(0, Some(&template), 3),
+        );
+        let first = a.next().to_vec();
+        assert!(first.starts_with(b"id=") && first.len() == 9);
+        assert_eq!(b.next(), first);
+        assert_ne!(a.next(), first);
+        assert_eq!(Payload::new(4, None, 3).next(), [0x5a; 4]);
+    }
+
+    #[test]
+    fn follows_connection_and_rate_ramps() {
+        let args = Args {
+            mode: Mode::Tcp,
+            target: None,
+            connections: 4,
+            // Two connections open within 100ms, closing at 200ms, then a
+            // third alone from 300ms.
+            ramp_connections: Some(
+                "0s:0,100ms:2,200ms:2,200ms:0,300ms:0,300ms:1"
+                    .parse()
+                    .unwrap(),
+            ),
+            // 1000/s, dropping to 100/s halfway.
+            ramp_rate: Some("0s:1000,200ms:1000,200ms:100".parse().unwrap()),
+            groups: vec![],
+            interface: None,
+            ttl: 1,
+            receive_only: false,
+            ws_path: "/".to_string(),
+            size: 100,
+            payload: None,
+            seed: None,
+            limits: Limits::default(),
+            probe_interval: None,
+            pacing: Pacing::Sleep,
+            duration: Duration::from_millis(400),
+        };
+        let sink = Sink::bind(&args).unwrap();
+        let target = sink.addr().unwrap();
+        let (sent, _) = std::thread::scope(|s| {
+            s.spawn(|| sink.serve(s));
+            let sent = args
+                .send_all(target, None, 0, &CancellationToken::new())
+                .unwrap();
+            sink.stop();
+            sent
+        });
+        assert_eq!(sent.connects, 3);
+        // Up to 200 sends in the first half, with connections open for
+        // most of it, and about 10 in the second.
+        assert!((100..=230).contains(&sent.sends), "{sent:?}");
+    }
+}
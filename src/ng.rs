@@ -0,0 +1,171 @@
+//! `itsmine ng`: a front-end accepting the most common stress-ng flags, so
+//! existing runbooks can switch tools with minimal edits.
+
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::scenario::{Phase, Scenario, Schedule};
+use crate::{CancellationToken, Resource, Stressor, parse};
+
+/// stress-ng's default `--vm-bytes` per worker.
+const DEFAULT_VM_BYTES: u64 = 256 << 20;
+
+/// stress-ng's default run time when `--timeout` is omitted.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(86_400);
+
+#[derive(Clone, Debug, PartialEq, clap::Args)]
+pub struct Args {
+    /// Number of CPU workers (0 = one per online CPU)
+    #[arg(long, value_name = "N")]
+    pub cpu: Option<u32>,
+    /// Number of virtual memory workers
+    #[arg(long, value_name = "N")]
+    pub vm: Option<u32>,
+    /// Memory per vm worker, e.g. 256m or 1G
+    #[arg(long, value_name = "BYTES", value_parser = bytes)]
+    pub vm_bytes: Option<u64>,
+    /// Run time, e.g. 60, 60s, 5m or 1h (default 24h)
+    #[arg(short, long, value_name = "T", value_parser = timeout)]
+    pub timeout: Option<Duration>,
+}
+
+impl Args {
+    /// Translates the flags into a single-phase native scenario.
+    pub fn scenario(&self) -> Result<Scenario, anyhow::Error> {
+        let mut stressors = vec![];
+        if let Some(cpu) = self.cpu {
+            let num = match cpu {
+                0 => std::thread::available_parallelism().map_or(1, |n| n.get() as u32),
+                n => n,
+            };
+            stressors.push(Resource::Thread { num });
+        }
+        let vm_bytes = self.vm_bytes.unwrap_or(DEFAULT_VM_BYTES);
+        for _ in 0..self.vm.unwrap_or(0) {
+            stressors.push(Resource::Memory {
+                arg: format!("{vm_bytes}B"),
+                bench: None,
+            });
+        }
+        if stressors.is_empty() {
+            return Err(anyhow::anyhow!("no stressors given (use --cpu or --vm)"));
+        }
+        Ok(Scenario {
+            phases: vec![Phase {
+                name: "ng".to_string(),
+                duration: self.timeout.unwrap_or(DEFAULT_TIMEOUT),
+                stressors,
+                timeouts: Default::default(),
+            }],
+            schedule: Schedule::default(),
+        })
+    }
+}
+
+impl Stressor for Args {
+    fn name(&self) -> &'static str {
+        "ng"
+    }
+
+    fn params(&self) -> serde_json::Value {
+        json!({
+            "cpu": self.cpu,
+            "vm": self.vm,
+            "vm_bytes": self.vm_bytes,
+            "timeout_secs": self.timeout.map(|t| t.as_secs_f64()),
+        })
+    }
+
+    fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        self.scenario()?.run(cancel)
+    }
+}
+
+/// stress-ng sizes accept lowercase suffixes (`512m`, `1g`).
+fn bytes(s: &str) -> Result<u64, String> {
+    if s.ends_with('%') {
+        return Err(format!("percentage sizes like '{s}' are not supported"));
+    }
+    parse::bytes(&s.to_ascii_uppercase())
+}
+
+/// stress-ng timeouts are seconds unless a unit is given.
+fn timeout(s: &str) -> Result<Duration, String> {
+    match s.parse::<u64>() {
+        Ok(secs) => Ok(Duration::from_secs(secs)),
+        Err(_) => parse::duration(s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: Args,
+    }
+
+    fn translate(argv: &[&str]) -> Result<Scenario, anyhow::Error> {
+        let cli = Cli::try_parse_from(std::iter::once("ng").chain(argv.iter().copied()))?;
+        cli.args.scenario()
+    }
+
+    #[test]
+    fn translates_common_flags() {
+        let scenario = translate(&[
+            "--vm",
+            "2",
+            "--vm-bytes",
+            "1g",
+            "--cpu",
+            "4",
+            "--timeout",
+            "60s",
+        ])
+        .unwrap();
+        let phase = &scenario.phases[0];
+        assert_eq!(phase.duration, Duration::from_secs(60));
+        assert_eq!(
+            phase.stressors,
+            vec![
+                Resource::Thread { num: 4 },
+                Resource::Memory {
+                    arg: "1073741824B".to_string(),
+                    bench: None,
+                },
+                Resource::Memory {
+                    arg: "1073741824B".to_string(),
+                    bench: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn applies_stress_ng_defaults() {
+        let scenario = translate(&["--vm", "1", "-t", "90"]).unwrap();
+        let phase = &scenario.phases[0];
+        assert_eq!(phase.duration, Duration::from_secs(90));
+        assert_eq!(
+            phase.stressors,
+            vec![Resource::Memory {
+                arg: "268435456B".to_string(),
+                bench: None,
+            }]
+        );
+        assert_eq!(
+            translate(&["--cpu", "1"]).unwrap().phases[0].duration,
+            DEFAULT_TIMEOUT
+        );
+    }
+
+    #[test]
+    fn rejects_empty_and_unsupported() {
+        assert!(translate(&["--timeout", "1m"]).is_err());
+        assert!(translate(&["--vm", "1", "--vm-bytes", "50%"]).is_err());
+    }
+}
@@ -0,0 +1,42 @@
+use std::path::Path;
+
+const SCORE_ADJ: &str = "/proc/self/oom_score_adj";
+
+/// The `oom_score_adj` that exempts a process from the OOM killer.
+pub const PROTECT: i32 = -1000;
+
+pub fn score_adj() -> Option<i32> {
+    read_at(Path::new(SCORE_ADJ))
+}
+
+/// Sets this process's `oom_score_adj`: 1000 makes it the OOM killer's first
+/// choice, -1000 exempts it. Lowering the value needs `CAP_SYS_RESOURCE`.
+pub fn set_score_adj(value: i32) -> Result<(), anyhow::Error> {
+    write_at(Path::new(SCORE_ADJ), value)?;
+    log::info!("Set oom_score_adj to {value}.");
+    Ok(())
+}
+
+fn read_at(path: &Path) -> Option<i32> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+fn write_at(path: &Path, value: i32) -> Result<(), anyhow::Error> {
+    std::fs::write(path, value.to_string())
+        .map_err(|e| anyhow::anyhow!("Failed to set oom_score_adj to {value}: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_score_adj() {
+        let path = std::env::temp_dir().join("itsmine-oom-score-adj");
+        write_at(&path, 1000).unwrap();
+        assert_eq!(read_at(&path), Some(1000));
+        write_at(&path, PROTECT).unwrap();
+        assert_eq!(read_at(&path), Some(PROTECT));
+        assert!(score_adj().is_some());
+    }
+}
@@ -0,0 +1,438 @@
+//! Value syntaxes shared by every subcommand, flag and file format.
+
+use std::time::Duration;
+
+/// Parses a duration made of one or more `<integer><unit>` terms, e.g.
+/// `500ms`, `90s`, `5m` or `1h30m`. Units are `ms`, `s`, `m`, `h` and `d`.
+pub fn duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err("empty duration".to_string());
+    }
+    let mut total = Duration::ZERO;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(|| format!("duration '{s}' is missing a unit (ms, s, m, h)"))?;
+        let (value, after) = rest.split_at(digits);
+        let value: u64 = value
+            .parse()
+            .map_err(|e| format!("invalid duration '{s}': {e}"))?;
+        let unit_len = after
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(after.len());
+        let (unit, next) = after.split_at(unit_len);
+        let term = match unit {
+            "ms" => Some(Duration::from_millis(value)),
+            "s" => Some(Duration::from_secs(value)),
+            "m" => value.checked_mul(60).map(Duration::from_secs),
+            "h" => value.checked_mul(3600).map(Duration::from_secs),
+            "d" => value.checked_mul(86_400).map(Duration::from_secs),
+            _ => return Err(format!("invalid duration unit '{unit}' in '{s}'")),
+        };
+        total = term
+            .and_then(|term| total.checked_add(term))
+            .ok_or_else(|| format!("duration '{s}' is too large"))?;
+        rest = next;
+    }
+    Ok(total)
+}
+
+/// Multiplier of a binary size suffix (`B`, `K`, `M`, `G`, `T`).
+pub fn size_multiplier(suffix: char) -> Option<u64> {
+    match suffix {
+        'B' => Some(1),
+        'K' => Some(1 << 10),
+        'M' => Some(1 << 20),
+        'G' => Some(1 << 30),
+        'T' => Some(1 << 40),
+        _ => None,
+    }
+}
+
+/// Parses a byte count: plain bytes, or a number (possibly fractional) with
+/// a binary suffix, e.g. `4096`, `512M` or `1.5G`.
+pub fn bytes(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (number, multiplier) = match s.chars().last().and_then(size_multiplier) {
+        Some(multiplier) => (&s[..s.len() - 1], multiplier),
+        None => (s, 1),
+    };
+    if let Ok(whole) = number.parse::<u64>() {
+        return whole
+            .checked_mul(multiplier)
+            .ok_or_else(|| format!("size '{s}' is too large"));
+    }
+    let value: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid size '{s}' (use e.g. 4096, 512M or 1.5G)"))?;
+    let bytes = value * multiplier as f64;
+    if !(0.0..u64::MAX as f64).contains(&bytes) {
+        return Err(format!("size '{s}' is out of range"));
+    }
+    Ok(bytes as u64)
+}
+
+/// Slowest rate a limit or target may ask for, once a day: anything slower
+/// waits past any run, and past what a `Duration` holds.
+const MIN_RATE: f64 = 1.0 / 86_400.0;
+
+/// A throughput limit or target.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rate {
+    BytesPerSec(f64),
+    OpsPerSec(f64),
+}
+
+/// Parses `100M/s` (bytes, binary suffixes) or `5k ops/s` (operations,
+/// decimal `k`/`M`/`G` suffixes). The period may be `s`, `m` (or `min`)
+/// or `h`.
+pub fn rate(s: &str) -> Result<Rate, String> {
+    let (amount, period) = s
+        .trim()
+        .rsplit_once('/')
+        .ok_or_else(|| format!("rate '{s}' is missing a period (e.g. /s)"))?;
+    let per_secs = match period.trim() {
+        "s" => 1.0,
+        "m" | "min" => 60.0,
+        "h" => 3600.0,
+        other => return Err(format!("invalid rate period '{other}' in '{s}'")),
+    };
+    let amount = amount.trim();
+    match amount.strip_suffix("ops") {
+        Some(ops) => {
+            let ops = ops.trim();
+            let (number, multiplier) = match ops.chars().last() {
+                Some('k') => (&ops[..ops.len() - 1], 1e3),
+                Some('M') => (&ops[..ops.len() - 1], 1e6),
+                Some('G') => (&ops[..ops.len() - 1], 1e9),
+                _ => (ops, 1.0),
+            };
+            let value: f64 = number
+                .parse()
+                .map_err(|_| format!("invalid operation count in rate '{s}'"))?;
+            if !value.is_finite() || value < 0.0 {
+                return Err(format!("rate '{s}' must be finite and not negative"));
+            }
+            Ok(Rate::OpsPerSec(value * multiplier / per_secs))
+        }
+        None => Ok(Rate::BytesPerSec(bytes(amount)? as f64 / per_secs)),
+    }
+}
+
+/// Parses an event rate such as `500/s` or `2k/s` into events per second,
+/// reading the amount as operations whether or not `ops` is spelled out.
+pub fn per_second(s: &str) -> Result<f64, String> {
+    let spelled = match s.split_once('/') {
+        Some((amount, period)) if !amount.trim().ends_with("ops") => {
+            format!("{amount} ops/{period}")
+        }
+        _ => s.to_string(),
+    };
+    match rate(&spelled)? {
+        Rate::OpsPerSec(rate) if rate >= MIN_RATE => Ok(rate),
+        _ => Err(format!("rate '{s}' must be at least 1/day")),
+    }
+}
+
+/// Parses a throughput such as `200M/s` into bytes per second.
+pub fn bytes_per_second(s: &str) -> Result<f64, String> {
+    match rate(s)? {
+        Rate::BytesPerSec(rate) if rate >= MIN_RATE => Ok(rate),
+        Rate::BytesPerSec(_) => Err(format!("rate '{s}' must be at least 1B/day")),
+        Rate::OpsPerSec(_) => Err(format!("rate '{s}' must be in bytes, e.g. 200M/s")),
+    }
+}
+
+/// A busy/idle pattern: busy for `busy` of every `period`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Duty {
+    pub busy: Duration,
+    pub period: Duration,
+}
+
+/// Parses `30%:100ms`: busy for 30% of every 100 ms period.
+pub fn duty(s: &str) -> Result<Duty, String> {
+    let (percent, period) = s
+        .split_once(':')
+        .ok_or_else(|| format!("duty cycle '{s}' must look like <percent>%:<period>"))?;
+    let percent: f64 = percent
+        .trim()
+        .strip_suffix('%')
+        .unwrap_or(percent)
+        .parse()
+        .map_err(|_| format!("invalid percentage in duty cycle '{s}'"))?;
+    if !(0.0..=100.0).contains(&percent) || percent == 0.0 {
+        return Err(format!(
+            "duty cycle '{s}' must be above 0% and at most 100%"
+        ));
+    }
+    let period = duration(period)?;
+    if period.is_zero() {
+        return Err(format!("duty cycle '{s}' needs a non-zero period"));
+    }
+    Ok(Duty {
+        busy: period.mul_f64(percent / 100.0),
+        period,
+    })
+}
+
+/// Parses a percentage above 0 and at most 100, `90%` or `90`.
+pub fn percent(s: &str) -> Result<f64, String> {
+    let value: f64 = s
+        .trim()
+        .trim_end_matches('%')
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid percentage '{s}' (e.g. 90%)"))?;
+    match value > 0.0 && value <= 100.0 {
+        true => Ok(value),
+        false => Err(format!(
+            "percentage '{s}' must be above 0% and at most 100%"
+        )),
+    }
+}
+
+/// Parses a temperature in degrees Celsius: `90C`, `90°C` or `90`.
+pub fn celsius(s: &str) -> Result<f64, String> {
+    let number = s.trim().trim_end_matches(['C', 'c']).trim_end_matches('°');
+    let value: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid temperature '{s}' (e.g. 90C)"))?;
+    match value.is_finite() {
+        true => Ok(value),
+        false => Err(format!("invalid temperature '{s}'")),
+    }
+}
+
+/// Parses a CPU frequency into kHz, the unit cpufreq uses, e.g. `2.4GHz`,
+/// `800MHz` or `1200000kHz`.
+pub fn frequency(s: &str) -> Result<u64, String> {
+    let lower = s.trim().to_ascii_lowercase();
+    let (number, khz) = if let Some(n) = lower.strip_suffix("ghz") {
+        (n, 1_000_000.0)
+    } else if let Some(n) = lower.strip_suffix("mhz") {
+        (n, 1_000.0)
+    } else if let Some(n) = lower.strip_suffix("khz") {
+        (n, 1.0)
+    } else {
+        return Err(format!(
+            "frequency '{s}' needs a unit (e.g. 2.4GHz, 800MHz)"
+        ));
+    };
+    let value: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid frequency '{s}'"))?;
+    match value.is_finite() && value > 0.0 {
+        true => Ok((value * khz).round() as u64),
+        false => Err(format!("invalid frequency '{s}'")),
+    }
+}
+
+/// Parses a kernel CPU list such as `0-3,8,10-11`, as found in sysfs
+/// `cpulist` and `shared_cpu_list` files.
+pub fn cpu_list(s: &str) -> Result<Vec<usize>, String> {
+    let mut cpus = vec![];
+    for part in s.trim().split(',').filter(|part| !part.is_empty()) {
+        let invalid = || format!("invalid CPU list '{s}' (e.g. 0-3,8)");
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (first, last),
+            None => (part, part),
+        };
+        let first: usize = first.trim().parse().map_err(|_| invalid())?;
+        let last: usize = last.trim().parse().map_err(|_| invalid())?;
+        if first > last {
+            return Err(invalid());
+        }
+        cpus.extend(first..=last);
+    }
+    Ok(cpus)
+}
+
+/// Parses a `key=value` tag, e.g. `host-class=m6i`.
+pub fn tag(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("tag '{s}' must look like <key>=<value>"))?;
+    let key = key.trim();
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return Err(format!("tag '{s}' needs a key without spaces"));
+    }
+    Ok((key.to_string(), value.trim().to_string()))
+}
+
+/// Parses `<min>..<max>` with `parse` applied to both ends, e.g. `1G..4G`.
+pub fn range<T: PartialOrd>(
+    s: &str,
+    parse: impl Fn(&str) -> Result<T, String>,
+) -> Result<(T, T), String> {
+    let (min, max) = s
+        .split_once("..")
+        .ok_or_else(|| format!("range '{s}' must look like <min>..<max>"))?;
+    let (min, max) = (parse(min)?, parse(max)?);
+    if min > max {
+        return Err(format!("range '{s}' has its minimum above its maximum"));
+    }
+    Ok((min, max))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn duration_units() {
+        assert_eq!(duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(duration("1h"), Ok(Duration::from_secs(3600)));
+        assert_eq!(duration("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(duration("1m500ms"), Ok(Duration::from_millis(60_500)));
+        assert!(duration("10").is_err());
+        assert!(duration("").is_err());
+        assert!(duration("5x").is_err());
+    }
+
+    #[test]
+    fn bytes_suffixes() {
+        assert_eq!(bytes("4096"), Ok(4096));
+        assert_eq!(bytes("512M"), Ok(512 << 20));
+        assert_eq!(bytes("1.5G"), Ok(3 << 29));
+        assert!(bytes("4X").is_err());
+        assert!(bytes("-1K").is_err());
+    }
+
+    #[test]
+    fn rates() {
+        assert_eq!(rate("100M/s"), Ok(Rate::BytesPerSec((100 << 20) as f64)));
+        assert_eq!(rate("5k ops/s"), Ok(Rate::OpsPerSec(5000.0)));
+        assert_eq!(rate("120ops/m"), Ok(Rate::OpsPerSec(2.0)));
+        assert!(rate("100M").is_err());
+        assert!(rate("100M/week").is_err());
+    }
+
+    #[test]
+    fn event_rates() {
+        assert_eq!(per_second("500/s"), Ok(500.0));
+        assert_eq!(per_second("2k/s"), Ok(2000.0));
+        assert_eq!(per_second("60 ops/m"), Ok(1.0));
+        assert_eq!(per_second("120/min"), Ok(2.0));
+        assert_eq!(per_second("1/h"), Ok(1.0 / 3600.0));
+        assert!(per_second("1e-30/s").is_err());
+        assert!(per_second("NaN/s").is_err());
+        assert!(per_second("inf/s").is_err());
+        assert!(per_second("0/s").is_err());
+        assert!(per_second("500").is_err());
+    }
+
+    #[test]
+    fn byte_rates() {
+        assert_eq!(bytes_per_second("200M/s"), Ok((200 << 20) as f64));
+        assert!(bytes_per_second("0M/s").is_err());
+        assert_eq!(bytes_per_second("1/h"), Ok(1.0 / 3600.0));
+        assert!(bytes_per_second("5k ops/s").is_err());
+    }
+
+    #[test]
+    fn percentages() {
+        assert_eq!(percent("90%"), Ok(90.0));
+        assert_eq!(percent("12.5"), Ok(12.5));
+        assert!(percent("0%").is_err());
+        assert!(percent("101%").is_err());
+        assert!(percent("NaN%").is_err());
+    }
+
+    #[test]
+    fn duty_cycles() {
+        assert_eq!(
+            duty("30%:100ms"),
+            Ok(Duty {
+                busy: Duration::from_millis(30),
+                period: Duration::from_millis(100)
+            })
+        );
+        assert_eq!(duty("100%:1s").unwrap().busy, Duration::from_secs(1));
+        assert!(duty("0%:100ms").is_err());
+        assert!(duty("120%:100ms").is_err());
+        assert!(duty("30%").is_err());
+        assert!(duty("30%:0ms").is_err());
+    }
+
+    #[test]
+    fn temperatures() {
+        assert_eq!(celsius("90C"), Ok(90.0));
+        assert_eq!(celsius("85.5°C"), Ok(85.5));
+        assert_eq!(celsius("70"), Ok(70.0));
+        assert!(celsius("hot").is_err());
+        assert!(celsius("90F").is_err());
+    }
+
+    #[test]
+    fn frequencies() {
+        assert_eq!(frequency("2.4GHz"), Ok(2_400_000));
+        assert_eq!(frequency("800MHz"), Ok(800_000));
+        assert_eq!(frequency("1200000khz"), Ok(1_200_000));
+        assert!(frequency("2400").is_err());
+        assert!(frequency("0GHz").is_err());
+    }
+
+    #[test]
+    fn cpu_lists() {
+        assert_eq!(cpu_list("0-3,8,10-11\n"), Ok(vec![0, 1, 2, 3, 8, 10, 11]));
+        assert_eq!(cpu_list(""), Ok(vec![]));
+        assert!(cpu_list("3-1").is_err());
+        assert!(cpu_list("a").is_err());
+    }
+
+    #[test]
+    fn tags() {
+        assert_eq!(
+            tag("host-class=m6i"),
+            Ok(("host-class".to_string(), "m6i".to_string()))
+        );
+        assert_eq!(tag("note=a=b"), Ok(("note".to_string(), "a=b".to_string())));
+        assert!(tag("build").is_err());
+        assert!(tag("=1234").is_err());
+        assert!(tag("my build=1").is_err());
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(range("1G..4G", bytes), Ok((1 << 30, 4 << 30)));
+        assert!(range("4G..1G", bytes).is_err());
+        assert!(range("1G-4G", bytes).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn duration_terms_add_up(h in 0u64..1000, m in 0u64..60, s in 0u64..60, ms in 0u64..1000) {
+            let parsed = duration(&format!("{h}h{m}m{s}s{ms}ms")).unwrap();
+            prop_assert_eq!(parsed, Duration::from_millis(((h * 60 + m) * 60 + s) * 1000 + ms));
+        }
+
+        #[test]
+        fn bytes_round_trip(n in 0u64..(1 << 20), suffix in "[BKMGT]") {
+            let multiplier = size_multiplier(suffix.chars().next().unwrap()).unwrap();
+            prop_assert_eq!(bytes(&format!("{n}{suffix}")), Ok(n * multiplier));
+        }
+
+        #[test]
+        fn ranges_are_ordered(a in 0u64..u32::MAX as u64, b in 0u64..u32::MAX as u64) {
+            match range(&format!("{a}..{b}"), bytes) {
+                Ok((min, max)) => prop_assert!(a <= b && min == a && max == b),
+                Err(_) => prop_assert!(a > b),
+            }
+        }
+
+        #[test]
+        fn parsers_never_panic(s in "\\PC*") {
+            let _ = duration(&s);
+            let _ = bytes(&s);
+            let _ = rate(&s);
+            let _ = range(&s, duration);
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! Minimal `perf_event_open` counters for the calling thread, for stressors
+//! that can report hardware events when the kernel lets them.
+
+/// `PERF_TYPE_HW_CACHE` and the cache event ids from
+/// `include/uapi/linux/perf_event.h`.
+const PERF_TYPE_HW_CACHE: u32 = 3;
+const CACHE_DTLB: u64 = 3;
+const CACHE_OP_READ: u64 = 0;
+const CACHE_RESULT_ACCESS: u64 = 0;
+const CACHE_RESULT_MISS: u64 = 1;
+
+const PERF_FLAG_FD_CLOEXEC: libc::c_ulong = 1 << 3;
+const IOC_ENABLE: libc::c_ulong = 0x2400;
+const IOC_DISABLE: libc::c_ulong = 0x2401;
+
+/// `disabled`, `exclude_kernel` and `exclude_hv` in the attribute flags, so
+/// the counter starts stopped and works at `perf_event_paranoid` 2.
+const FLAGS: u64 = 1 | 1 << 5 | 1 << 6;
+
+/// The first version of `struct perf_event_attr`, which every kernel
+/// accepts.
+#[repr(C)]
+#[derive(Default)]
+struct Attr {
+    kind: u32,
+    size: u32,
+    config: u64,
+    sample_period: u64,
+    sample_type: u64,
+    read_format: u64,
+    flags: u64,
+    wakeup_events: u32,
+    bp_type: u32,
+    config1: u64,
+}
+
+/// A hardware event counted on the calling thread.
+pub struct Counter(std::os::fd::OwnedFd);
+
+impl Counter {
+    fn open(kind: u32, config: u64) -> std::io::Result<Self> {
+        let attr = Attr {
+            kind,
+            size: size_of::<Attr>() as u32,
+            config,
+            flags: FLAGS,
+            ..Default::default()
+        };
+        // SAFETY: `attr` outlives the call and its size field matches.
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_perf_event_open,
+                &attr as *const Attr,
+                0,
+                -1,
+                -1,
+                PERF_FLAG_FD_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        // SAFETY: the kernel just handed us this descriptor.
+        Ok(Counter(unsafe {
+            std::os::fd::FromRawFd::from_raw_fd(fd as i32)
+        }))
+    }
+
+    /// Data TLB load misses.
+    pub fn dtlb_load_misses() -> std::io::Result<Self> {
+        Counter::open(
+            PERF_TYPE_HW_CACHE,
+            CACHE_DTLB | CACHE_OP_READ << 8 | CACHE_RESULT_MISS << 16,
+        )
+    }
+
+    /// Data TLB loads.
+    pub fn dtlb_loads() -> std::io::Result<Self> {
+        Counter::open(
+            PERF_TYPE_HW_CACHE,
+            CACHE_DTLB | CACHE_OP_READ << 8 | CACHE_RESULT_ACCESS << 16,
+        )
+    }
+
+    pub fn enable(&self) {
+        self.ioctl(IOC_ENABLE);
+    }
+
+    pub fn disable(&self) {
+        self.ioctl(IOC_DISABLE);
+    }
+
+    fn ioctl(&self, request: libc::c_ulong) {
+        use std::os::fd::AsRawFd;
+        // SAFETY: the request takes no argument and the descriptor is ours.
+        unsafe { libc::ioctl(self.0.as_raw_fd(), request as _, 0) };
+    }
+
+    /// The count so far.
+    pub fn read(&self) -> std::io::Result<u64> {
+        use std::io::Read;
+        let mut value = [0u8; 8];
+        std::fs::File::from(self.0.try_clone()?).read_exact(&mut value)?;
+        Ok(u64::from_ne_bytes(value))
+    }
+}
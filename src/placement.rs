@@ -0,0 +1,269 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use clap::ValueEnum;
+use serde_json::json;
+
+use crate::{parse, report, sysinfo};
+
+const CPU_ROOT: &str = "/sys/devices/system/cpu";
+
+/// How thread stressor workers are spread over the CPU topology.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Placement {
+    /// One worker per physical core, leaving SMT siblings idle.
+    PhysicalCoresOnly,
+    /// Fill both SMT siblings of a core before moving to the next.
+    SmtPairs,
+    /// One worker per NUMA node.
+    OnePerNuma,
+}
+
+impl Placement {
+    fn name(self) -> String {
+        self.to_possible_value()
+            .map(|value| value.get_name().to_string())
+            .unwrap_or_default()
+    }
+}
+
+/// CPUs workers are pinned to, worker `i` on `PLAN[i % len]`.
+static PLAN: OnceLock<Vec<usize>> = OnceLock::new();
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Cpu {
+    id: usize,
+    package: u32,
+    core: u32,
+    node: u32,
+    /// Lowest CPU sharing this one's last-level cache, naming the cache.
+    llc: Option<usize>,
+}
+
+/// Makes every thread stressor worker pin itself to a CPU chosen by
+/// `placement` from the CPUs this process may run on.
+pub fn configure(placement: Placement) {
+    let cpus = detected();
+    let plan = plan(placement, &cpus);
+    if plan.is_empty() {
+        log::warn!("No CPU topology found; --placement has no effect.");
+        return;
+    }
+    if placement == Placement::SmtPairs && plan.len() == cores(&cpus).len() {
+        log::warn!("No SMT siblings found; smt-pairs places one worker per core.");
+    }
+    let list = plan
+        .iter()
+        .map(usize::to_string)
+        .collect::<Vec<_>>()
+        .join(",");
+    log::info!("Placing workers {} on CPUs {list}.", placement.name());
+    report::measure(
+        "placement",
+        json!({ "policy": placement.name(), "cpus": plan }),
+    );
+    let _ = PLAN.set(plan);
+}
+
+/// Two SMT siblings of one physical core this process may run on.
+pub fn smt_siblings() -> Option<(usize, usize)> {
+    smt_pair(&detected())
+}
+
+/// Two CPUs on different physical cores that share a last-level cache.
+pub fn llc_neighbours() -> Option<(usize, usize)> {
+    llc_pair(&detected())
+}
+
+/// CPUs this process may run on, lowest first.
+pub fn allowed() -> Vec<usize> {
+    allowed_cpus().unwrap_or_default()
+}
+
+/// How many distinct CPUs the configured placement uses, if any.
+pub fn slots() -> Option<usize> {
+    PLAN.get().map(Vec::len)
+}
+
+/// Pins the calling thread, worker `i`, to its CPU under the configured
+/// placement. Workers beyond the plan wrap around onto it again.
+pub fn pin(i: u32) {
+    let Some(plan) = PLAN.get() else {
+        return;
+    };
+    let cpu = plan[i as usize % plan.len()];
+    if let Err(e) = pin_current(cpu) {
+        log::warn!("Failed to pin thread {i} to CPU {cpu}: {e}");
+    }
+}
+
+/// Restricts the calling thread to `cpu`.
+pub fn pin_current(cpu: usize) -> std::io::Result<()> {
+    // SAFETY: `set` is a plain bitmask passed by pointer for the call only.
+    let result = unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set)
+    };
+    match result {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+/// CPUs in the process's affinity mask, or `None` if it can't be read.
+fn allowed_cpus() -> Option<Vec<usize>> {
+    // SAFETY: `set` is a plain bitmask filled in by the call.
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        if libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+            return None;
+        }
+        Some(
+            (0..libc::CPU_SETSIZE as usize)
+                .filter(|&cpu| libc::CPU_ISSET(cpu, &set))
+                .collect(),
+        )
+    }
+}
+
+/// The topology of the CPUs this process may run on.
+fn detected() -> Vec<Cpu> {
+    let allowed = allowed_cpus();
+    cpus_at(Path::new(CPU_ROOT))
+        .into_iter()
+        .filter(|cpu| {
+            allowed
+                .as_ref()
+                .is_none_or(|allowed| allowed.contains(&cpu.id))
+        })
+        .collect()
+}
+
+fn cpus_at(root: &Path) -> Vec<Cpu> {
+    let read =
+        |path: &Path| -> Option<u32> { std::fs::read_to_string(path).ok()?.trim().parse().ok() };
+    let mut cpus: Vec<Cpu> = sysinfo::cpu_dirs(root)
+        .into_iter()
+        .filter_map(|dir| {
+            let id = dir
+                .file_name()?
+                .to_str()?
+                .strip_prefix("cpu")?
+                .parse()
+                .ok()?;
+            // Each CPU directory links to its node as `node<N>`.
+            let node = std::fs::read_dir(&dir)
+                .into_iter()
+                .flatten()
+                .flatten()
+                .find_map(|entry| {
+                    entry
+                        .file_name()
+                        .to_str()?
+                        .strip_prefix("node")?
+                        .parse()
+                        .ok()
+                })
+                .unwrap_or(0);
+            let llc = std::fs::read_dir(dir.join("cache"))
+                .into_iter()
+                .flatten()
+                .flatten()
+                .filter(|entry| entry.file_name().to_string_lossy().starts_with("index"))
+                .filter_map(|entry| {
+                    let level = read(&entry.path().join("level"))?;
+                    let shared =
+                        std::fs::read_to_string(entry.path().join("shared_cpu_list")).ok()?;
+                    Some((level, parse::cpu_list(&shared).ok()?.into_iter().min()?))
+                })
+                .max_by_key(|(level, _)| *level)
+                .map(|(_, first)| first);
+            Some(Cpu {
+                id,
+                package: read(&dir.join("topology/physical_package_id"))?,
+                core: read(&dir.join("topology/core_id"))?,
+                node,
+                llc,
+            })
+        })
+        .collect();
+    cpus.sort_by_key(|cpu| cpu.id);
+    cpus
+}
+
+/// SMT siblings of each physical core, cores in order of their first CPU.
+fn cores(cpus: &[Cpu]) -> Vec<Vec<usize>> {
+    let mut siblings = BTreeMap::<(u32, u32), Vec<usize>>::new();
+    for cpu in cpus {
+        siblings
+            .entry((cpu.package, cpu.core))
+            .or_default()
+            .push(cpu.id);
+    }
+    let mut cores: Vec<Vec<usize>> = siblings.into_values().collect();
+    cores.sort();
+    cores
+}
+
+fn smt_pair(cpus: &[Cpu]) -> Option<(usize, usize)> {
+    cores(cpus)
+        .into_iter()
+        .find(|siblings| siblings.len() >= 2)
+        .map(|siblings| (siblings[0], siblings[1]))
+}
+
+fn llc_pair(cpus: &[Cpu]) -> Option<(usize, usize)> {
+    cpus.iter().find_map(|a| {
+        let b = cpus.iter().find(|b| {
+            a.llc.is_some() && b.llc == a.llc && (b.package, b.core) != (a.package, a.core)
+        })?;
+        Some((a.id, b.id))
+    })
+}
+
+fn plan(placement: Placement, cpus: &[Cpu]) -> Vec<usize> {
+    match placement {
+        Placement::PhysicalCoresOnly => cores(cpus).into_iter().map(|core| core[0]).collect(),
+        Placement::SmtPairs => cores(cpus).into_iter().flatten().collect(),
+        Placement::OnePerNuma => {
+            let mut first = BTreeMap::<u32, usize>::new();
+            for cpu in cpus {
+                first.entry(cpu.node).or_insert(cpu.id);
+            }
+            first.into_values().collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plans_follow_the_topology() {
+        let root = std::env::temp_dir().join(format!("itsmine-placement-{}", std::process::id()));
+        // Two nodes of one core each, both cores with two SMT siblings.
+        for (cpu, core, node) in [(0, 0, 0), (1, 1, 1), (2, 0, 0), (3, 1, 1)] {
+            let dir = root.join(format!("cpu{cpu}"));
+            std::fs::create_dir_all(dir.join("topology")).unwrap();
+            std::fs::create_dir_all(dir.join(format!("node{node}"))).unwrap();
+            std::fs::write(dir.join("topology/physical_package_id"), "0\n").unwrap();
+            std::fs::write(dir.join("topology/core_id"), format!("{core}\n")).unwrap();
+            std::fs::create_dir_all(dir.join("cache/index3")).unwrap();
+            std::fs::write(dir.join("cache/index3/level"), "3\n").unwrap();
+            std::fs::write(dir.join("cache/index3/shared_cpu_list"), "0-3\n").unwrap();
+        }
+        let cpus = cpus_at(&root);
+        assert_eq!(cpus.len(), 4);
+        assert_eq!(plan(Placement::PhysicalCoresOnly, &cpus), [0, 1]);
+        assert_eq!(plan(Placement::SmtPairs, &cpus), [0, 2, 1, 3]);
+        assert_eq!(plan(Placement::OnePerNuma, &cpus), [0, 1]);
+        assert!(plan(Placement::SmtPairs, &[]).is_empty());
+        assert_eq!(smt_pair(&cpus), Some((0, 2)));
+        assert_eq!(llc_pair(&cpus), Some((0, 1)));
+        assert_eq!(smt_pair(&cpus[..2]), None);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,137 @@
+use std::path::Path;
+use std::sync::OnceLock;
+
+use wasmi::{Engine, Instance, Linker, Module, Store, TypedFunc};
+
+use crate::StressError;
+
+static PLUGIN: OnceLock<Plugin> = OnceLock::new();
+
+/// A thread workload shipped as a WASM module. It must export
+/// `iterate(id: i32) -> i32` and may export `setup(id: i32)` and
+/// `teardown(id: i32)`; each stressor thread gets its own instance. No host
+/// functions are linked, so a plugin can burn CPU and its own linear memory
+/// but cannot reach the host.
+pub struct Plugin {
+    engine: Engine,
+    module: Module,
+}
+
+impl Plugin {
+    pub fn compile(wasm: &[u8]) -> Result<Self, anyhow::Error> {
+        let engine = Engine::default();
+        let module = Module::new(&engine, wasm)?;
+        if module.imports().len() > 0 {
+            return Err(anyhow::anyhow!("plugins must not import host functions"));
+        }
+        if !module.exports().any(|e| e.name() == "iterate") {
+            return Err(anyhow::anyhow!("plugin must export iterate(i32) -> i32"));
+        }
+        Ok(Plugin { engine, module })
+    }
+
+    /// Instantiates the module for `thread` and calls its `setup`.
+    pub fn runner(&self, thread: u32) -> Result<Runner, anyhow::Error> {
+        let mut store = Store::new(&self.engine, ());
+        let instance =
+            Linker::<()>::new(&self.engine).instantiate_and_start(&mut store, &self.module)?;
+        let iterate = instance.get_typed_func::<i32, i32>(&store, "iterate")?;
+        let id = thread as i32;
+        if let Some(setup) = optional_func(&instance, &store, "setup")? {
+            setup.call(&mut store, id)?;
+        }
+        Ok(Runner {
+            store,
+            instance,
+            iterate,
+            id,
+        })
+    }
+}
+
+fn optional_func(
+    instance: &Instance,
+    store: &Store<()>,
+    name: &str,
+) -> Result<Option<TypedFunc<i32, ()>>, anyhow::Error> {
+    match instance.get_func(store, name) {
+        Some(func) => Ok(Some(func.typed(store)?)),
+        None => Ok(None),
+    }
+}
+
+/// One thread's plugin instance; calls `teardown` when dropped.
+pub struct Runner {
+    store: Store<()>,
+    instance: Instance,
+    iterate: TypedFunc<i32, i32>,
+    id: i32,
+}
+
+impl Runner {
+    pub fn iterate(&mut self) -> Result<u32, StressError> {
+        self.iterate
+            .call(&mut self.store, self.id)
+            .map(|n| n as u32)
+            .map_err(|e| StressError::WorkloadFailed(format!("plugin iterate: {e}")))
+    }
+}
+
+impl Drop for Runner {
+    fn drop(&mut self) {
+        match optional_func(&self.instance, &self.store, "teardown") {
+            Ok(Some(teardown)) => {
+                if let Err(e) = teardown.call(&mut self.store, self.id) {
+                    log::warn!("Plugin teardown failed: {e}");
+                }
+            }
+            Ok(None) => {}
+            Err(e) => log::warn!("Plugin teardown has the wrong signature: {e}"),
+        }
+    }
+}
+
+/// Loads the `--plugin` used by thread stressors in place of the built-in
+/// Fibonacci kernel.
+pub fn load(path: &Path) -> Result<(), anyhow::Error> {
+    let wasm = std::fs::read(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read plugin {}: {e}", path.display()))?;
+    let plugin = Plugin::compile(&wasm)
+        .map_err(|e| anyhow::anyhow!("Invalid plugin {}: {e}", path.display()))?;
+    log::info!("Loaded plugin {}.", path.display());
+    let _ = PLUGIN.set(plugin);
+    Ok(())
+}
+
+pub fn loaded() -> Option<&'static Plugin> {
+    PLUGIN.get()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COUNTER: &str = r#"
+        (module
+            (global $n (mut i32) (i32.const 0))
+            (func (export "setup") (param i32) (global.set $n (local.get 0)))
+            (func (export "iterate") (param i32) (result i32)
+                (global.set $n (i32.add (global.get $n) (i32.const 1)))
+                (global.get $n)))
+    "#;
+
+    #[test]
+    fn runs_setup_and_iterate() {
+        let plugin = Plugin::compile(COUNTER.as_bytes()).unwrap();
+        let mut runner = plugin.runner(10).unwrap();
+        assert_eq!(runner.iterate().unwrap(), 11);
+        assert_eq!(runner.iterate().unwrap(), 12);
+    }
+
+    #[test]
+    fn rejects_imports_and_missing_iterate() {
+        let imports = r#"(module (import "env" "exit" (func (param i32))) (func (export "iterate") (param i32) (result i32) (i32.const 0)))"#;
+        assert!(Plugin::compile(imports.as_bytes()).is_err());
+        assert!(Plugin::compile(b"(module)").is_err());
+    }
+}
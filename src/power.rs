@@ -0,0 +1,206 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+
+use crate::{duty, events, report};
+
+const POWER_SUPPLY_ROOT: &str = "/sys/class/power_supply";
+
+/// How often the power source is checked.
+const INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Source {
+    Mains,
+    Battery,
+}
+
+impl Source {
+    fn name(self) -> &'static str {
+        match self {
+            Source::Mains => "mains",
+            Source::Battery => "battery",
+        }
+    }
+}
+
+/// What the machine runs on, or `None` if it has no battery to speak of.
+pub fn source() -> Option<Source> {
+    source_from(Path::new(POWER_SUPPLY_ROOT))
+}
+
+fn source_from(root: &Path) -> Option<Source> {
+    let read = |dir: &Path, file: &str| {
+        std::fs::read_to_string(dir.join(file))
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default()
+    };
+    let (mut battery, mut discharging, mut mains_online) = (false, false, false);
+    for supply in std::fs::read_dir(root).into_iter().flatten().flatten() {
+        let dir = supply.path();
+        match read(&dir, "type").as_str() {
+            // Peripherals (mice, headsets) report batteries with scope Device.
+            "Battery" if read(&dir, "scope") != "Device" => {
+                battery = true;
+                discharging |= read(&dir, "status") == "Discharging";
+            }
+            "Mains" | "USB" | "USB_PD" => mains_online |= read(&dir, "online") == "1",
+            _ => {}
+        }
+    }
+    match (battery, mains_online || !discharging) {
+        (false, _) => None,
+        (true, true) => Some(Source::Mains),
+        (true, false) => Some(Source::Battery),
+    }
+}
+
+/// Reports every switch between mains and battery for the rest of the
+/// process and, with `pause_on_battery`, pauses thread stressor workers
+/// while on battery. Does nothing on machines without a battery.
+pub fn start(pause_on_battery: bool) {
+    let Some(initial) = source() else {
+        if pause_on_battery {
+            log::info!("No battery found; --pause-on-battery has no effect.");
+        }
+        return;
+    };
+    if pause_on_battery {
+        duty::enable_throttle();
+    } else if initial == Source::Battery {
+        log::warn!("Running on battery; power-saving frequency scaling may skew results.");
+    }
+    let started = Instant::now();
+    let mut watcher = Watcher::new(initial, pause_on_battery);
+    watcher.apply();
+    std::thread::spawn(move || {
+        loop {
+            std::thread::sleep(INTERVAL);
+            if let Some(source) = source()
+                && watcher.observe(source, started.elapsed())
+            {
+                watcher.apply();
+            }
+        }
+    });
+}
+
+/// Tracks power-source transitions and what they mean for the load.
+struct Watcher {
+    source: Source,
+    pause_on_battery: bool,
+    transitions: Vec<serde_json::Value>,
+}
+
+impl Watcher {
+    fn new(source: Source, pause_on_battery: bool) -> Self {
+        Watcher {
+            source,
+            pause_on_battery,
+            transitions: vec![],
+        }
+    }
+
+    /// Records a reading taken `at` into the process; returns whether the
+    /// source changed.
+    fn observe(&mut self, source: Source, at: Duration) -> bool {
+        if source == self.source {
+            return false;
+        }
+        log::warn!(
+            "Power source changed from {} to {} at +{:.0}s.",
+            self.source.name(),
+            source.name(),
+            at.as_secs_f64()
+        );
+        events::emit(
+            "power_source_changed",
+            json!({ "from": self.source.name(), "to": source.name() }),
+        );
+        self.transitions.push(json!({
+            "at_secs": at.as_secs_f64(),
+            "source": source.name(),
+        }));
+        self.source = source;
+        true
+    }
+
+    fn paused(&self) -> bool {
+        self.pause_on_battery && self.source == Source::Battery
+    }
+
+    fn apply(&self) {
+        if self.pause_on_battery {
+            match self.paused() {
+                true => log::warn!("On battery; pausing CPU load until mains power returns."),
+                false if !self.transitions.is_empty() => log::info!("On mains power; resuming."),
+                false => {}
+            }
+            duty::pause(self.paused());
+        }
+        if !self.transitions.is_empty() {
+            report::measure(
+                "power",
+                json!({ "source": self.source.name(), "transitions": self.transitions }),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn supply(root: &Path, name: &str, files: &[(&str, &str)]) {
+        let dir = root.join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, value) in files {
+            std::fs::write(dir.join(file), format!("{value}\n")).unwrap();
+        }
+    }
+
+    #[test]
+    fn detects_the_power_source() {
+        let root = std::env::temp_dir().join(format!("itsmine-power-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        assert_eq!(source_from(&root), None);
+
+        supply(
+            &root,
+            "hidpp_battery_0",
+            &[
+                ("type", "Battery"),
+                ("scope", "Device"),
+                ("status", "Discharging"),
+            ],
+        );
+        assert_eq!(source_from(&root), None);
+
+        supply(
+            &root,
+            "BAT0",
+            &[("type", "Battery"), ("status", "Discharging")],
+        );
+        supply(&root, "AC", &[("type", "Mains"), ("online", "0")]);
+        assert_eq!(source_from(&root), Some(Source::Battery));
+
+        supply(&root, "AC", &[("type", "Mains"), ("online", "1")]);
+        assert_eq!(source_from(&root), Some(Source::Mains));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn records_transitions() {
+        let mut watcher = Watcher::new(Source::Mains, true);
+        assert!(!watcher.paused());
+        assert!(!watcher.observe(Source::Mains, Duration::from_secs(5)));
+        assert!(watcher.observe(Source::Battery, Duration::from_secs(10)));
+        assert!(watcher.paused());
+        assert!(watcher.observe(Source::Mains, Duration::from_secs(20)));
+        assert_eq!(watcher.transitions.len(), 2);
+        assert_eq!(watcher.transitions[0]["source"], "battery");
+        assert!(!Watcher::new(Source::Battery, false).paused());
+    }
+}
@@ -0,0 +1,195 @@
+//! Dropping privileges once setup that needs them is done, for `--user`
+//! and `--drop-caps`. The realtime priority and locked memory the setup
+//! asked for survive through the rlimits that stand in for
+//! `CAP_SYS_NICE` and `CAP_IPC_LOCK`, raised before the drop.
+
+use std::ffi::CString;
+use std::io;
+
+use crate::caps::{self, Privilege};
+
+/// A user to switch to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct User {
+    pub name: String,
+    pub uid: libc::uid_t,
+    pub gid: libc::gid_t,
+}
+
+impl User {
+    /// Looks `user` up in the password database, by name or by UID.
+    pub fn lookup(user: &str) -> Result<Self, anyhow::Error> {
+        let name = CString::new(user).map_err(|_| anyhow::anyhow!("Invalid user {user:?}"))?;
+        // SAFETY: passwd is plain data, valid zeroed.
+        let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut found = std::ptr::null_mut();
+        let mut buffer = vec![0 as libc::c_char; 16 << 10];
+        // SAFETY: every pointer is valid for the call, and `buffer` for its
+        // stated length; `entry` points into `buffer` afterwards.
+        let error = match user.parse::<libc::uid_t>() {
+            Ok(uid) => unsafe {
+                libc::getpwuid_r(
+                    uid,
+                    &mut entry,
+                    buffer.as_mut_ptr(),
+                    buffer.len(),
+                    &mut found,
+                )
+            },
+            Err(_) => unsafe {
+                libc::getpwnam_r(
+                    name.as_ptr(),
+                    &mut entry,
+                    buffer.as_mut_ptr(),
+                    buffer.len(),
+                    &mut found,
+                )
+            },
+        };
+        if error != 0 {
+            return Err(anyhow::anyhow!(
+                "Failed to look up user {user}: {}",
+                io::Error::from_raw_os_error(error)
+            ));
+        }
+        if found.is_null() {
+            return Err(anyhow::anyhow!("No such user: {user}"));
+        }
+        // SAFETY: a found entry's name is a C string in `buffer`.
+        let name = unsafe { std::ffi::CStr::from_ptr(entry.pw_name) };
+        Ok(User {
+            name: name.to_string_lossy().into_owned(),
+            uid: entry.pw_uid,
+            gid: entry.pw_gid,
+        })
+    }
+}
+
+/// Raises the soft and hard value of `resource` to `value`, which takes
+/// `CAP_SYS_RESOURCE` unless it is no higher than the hard limit already.
+fn raise(resource: libc::__rlimit_resource_t, value: libc::rlim_t) -> io::Result<()> {
+    let limit = libc::rlimit {
+        rlim_cur: value,
+        rlim_max: value,
+    };
+    // SAFETY: `limit` is valid for the duration of the call.
+    match unsafe { libc::setrlimit(resource, &limit) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Raises `RLIMIT_RTPRIO` to `priority` and, with `memlock`, lifts
+/// `RLIMIT_MEMLOCK`, so both still work without the capabilities they
+/// need now. Failures are logged, not fatal: the features degrade later,
+/// as they would without the drop.
+pub fn keep_limits(priority: Option<i32>, memlock: bool) {
+    if let Some(priority) = priority
+        && let Err(e) = raise(libc::RLIMIT_RTPRIO, priority as libc::rlim_t)
+    {
+        log::warn!("Failed to raise RLIMIT_RTPRIO to {priority}: {e}");
+    }
+    if memlock && let Err(e) = raise(libc::RLIMIT_MEMLOCK, libc::RLIM_INFINITY) {
+        log::warn!("Failed to lift RLIMIT_MEMLOCK: {e}");
+    }
+}
+
+/// Switches every thread to `uid` and `gid` with no supplementary groups,
+/// which clears every capability too unless `uid` is root, and checks the
+/// switch cannot be undone.
+pub fn switch_user(uid: libc::uid_t, gid: libc::gid_t) -> io::Result<()> {
+    // SAFETY: these calls take no pointers except the empty group list;
+    // glibc applies each to every thread.
+    unsafe {
+        if libc::setgroups(0, std::ptr::null()) != 0
+            || libc::setgid(gid) != 0
+            || libc::setuid(uid) != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        if uid != 0 && libc::setuid(0) == 0 {
+            return Err(io::Error::other("regained root after setuid"));
+        }
+    }
+    Ok(())
+}
+
+/// Drops every capability from the bounding set and then the effective,
+/// permitted and inheritable sets, staying the same user. The bounding set
+/// goes first, since dropping from it takes `CAP_SETPCAP`, and keeps
+/// programs run later, such as isolated workers, from gaining any back.
+fn drop_capabilities() -> io::Result<()> {
+    // Reading fails past the last capability this kernel has.
+    // SAFETY: prctl with these arguments takes no pointers.
+    for cap in (0..).take_while(|&cap| unsafe { libc::prctl(libc::PR_CAPBSET_READ, cap) } >= 0) {
+        // SAFETY: as above.
+        if unsafe { libc::prctl(libc::PR_CAPBSET_DROP, cap, 0, 0, 0) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    // `_LINUX_CAPABILITY_VERSION_3` with this process's PID, then
+    // effective, permitted and inheritable for capabilities 0-31 and 32-63.
+    let header: [u32; 2] = [0x2008_0522, 0];
+    let data = [0u32; 6];
+    // SAFETY: version 3 takes two data structs, both valid for the call.
+    match unsafe { libc::syscall(libc::SYS_capset, header.as_ptr(), data.as_ptr()) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Drops to `user` if given, and every capability with `drop_caps`, for the
+/// rest of the process's life. Any failure is an error: the run must not
+/// go ahead with privileges it was asked to shed.
+pub fn drop_privileges(user: Option<&User>, drop_caps: bool) -> Result<(), anyhow::Error> {
+    // Nothing regains privileges through a set-user-ID program either.
+    // SAFETY: prctl with these arguments only sets a flag on this process.
+    if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+        return Err(anyhow::anyhow!(
+            "Failed to set no_new_privs: {}",
+            io::Error::last_os_error()
+        ));
+    }
+    if drop_caps {
+        drop_capabilities().map_err(|e| anyhow::anyhow!("Failed to drop capabilities: {e}"))?;
+    }
+    if let Some(user) = user {
+        switch_user(user.uid, user.gid)
+            .map_err(|e| anyhow::anyhow!("Failed to switch to user {}: {e}", user.name))?;
+    }
+    let kept = [
+        Privilege::IpcLock,
+        Privilege::SysAdmin,
+        Privilege::SysNice,
+        Privilege::SysResource,
+    ]
+    .into_iter()
+    .filter(|&privilege| caps::held(privilege))
+    .map(Privilege::name)
+    .collect::<Vec<_>>();
+    if !kept.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Still holding {} after dropping privileges",
+            kept.join(", ")
+        ));
+    }
+    log::info!(
+        "Dropped privileges; running workloads as UID {} with no capabilities.",
+        // SAFETY: geteuid has no preconditions.
+        unsafe { libc::geteuid() }
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looks_users_up_by_name_and_uid() {
+        let root = User::lookup("root").unwrap();
+        assert_eq!((root.uid, root.gid), (0, 0));
+        assert_eq!(User::lookup("0").unwrap(), root);
+        assert!(User::lookup("itsmine-no-such-user").is_err());
+    }
+}
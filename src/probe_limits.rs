@@ -0,0 +1,236 @@
+//! `itsmine probe-limits`: finds how many open files, threads, bytes of
+//! address space and bytes of file this process can really have, by
+//! pushing each until the kernel refuses, and sets that next to what the
+//! rlimits claim. Other limits often bite first: `fs.file-max`,
+//! `kernel.threads-max`, `pids.max`, overcommit or the filesystem's own
+//! maximum file size.
+
+use std::fs::File;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::CancellationToken;
+use crate::address_space::{self, Reservations};
+
+/// Stack of each probing thread, kept small so memory runs out last.
+const THREAD_STACK: usize = 64 << 10;
+/// First address space reservation tried; halved on each refusal.
+const CHUNK: u64 = 1 << 46;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Probe {
+    /// What was counted, e.g. `open files`.
+    pub limit: &'static str,
+    /// The rlimit that claims to govern it.
+    pub rlimit: &'static str,
+    /// The rlimit's soft and hard values; `None` is unlimited.
+    pub soft: Option<u64>,
+    pub hard: Option<u64>,
+    /// How far the probe got, counting what the process already held.
+    pub found: u64,
+    /// Whether probing stopped at the cap rather than at a refusal.
+    pub capped: bool,
+    /// The refusal that ended probing.
+    pub stopped_by: Option<String>,
+}
+
+/// The soft and hard values of `resource`.
+fn rlimit(resource: libc::__rlimit_resource_t) -> (Option<u64>, Option<u64>) {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: `limit` is a valid out-pointer for the duration of the call.
+    if unsafe { libc::getrlimit(resource, &mut limit) } != 0 {
+        return (None, None);
+    }
+    let finite = |value| (value != libc::RLIM_INFINITY).then_some(value);
+    (finite(limit.rlim_cur), finite(limit.rlim_max))
+}
+
+/// Runs every probe, counting open files and threads up to `cap`, and
+/// probing file size with a scratch file in `dir`.
+pub fn run(dir: &Path, cap: u64) -> Result<Vec<Probe>, anyhow::Error> {
+    Ok(vec![
+        files(cap),
+        threads(cap)?,
+        address_space(),
+        file_size(dir)?,
+    ])
+}
+
+/// Opens /dev/null until refused.
+fn files(cap: u64) -> Probe {
+    // Less the descriptor listing the directory.
+    let open = std::fs::read_dir("/proc/self/fd").map_or(0, |fds| fds.count() as u64 - 1);
+    let mut held = vec![];
+    let stopped_by = loop {
+        if open + held.len() as u64 >= cap {
+            break None;
+        }
+        match File::open("/dev/null") {
+            Ok(file) => held.push(file),
+            Err(e) => break Some(e.to_string()),
+        }
+    };
+    let (soft, hard) = rlimit(libc::RLIMIT_NOFILE);
+    Probe {
+        limit: "open files",
+        rlimit: "RLIMIT_NOFILE",
+        soft,
+        hard,
+        found: open + held.len() as u64,
+        capped: stopped_by.is_none(),
+        stopped_by,
+    }
+}
+
+/// A probing thread's body: blocks until the pipe's write end closes.
+extern "C" fn wait_for_release(fd: *mut libc::c_void) -> *mut libc::c_void {
+    let mut byte = 0u8;
+    // SAFETY: `byte` is valid for a one-byte write; the read end outlives
+    // every probing thread.
+    unsafe { libc::read(fd as libc::c_int, (&mut byte as *mut u8).cast(), 1) };
+    std::ptr::null_mut()
+}
+
+/// Starts blocked threads until refused. They are raw pthreads because
+/// std's threads abort the process when a signal stack cannot be mapped,
+/// which is one of the ways this ends.
+fn threads(cap: u64) -> Result<Probe, anyhow::Error> {
+    let running = std::fs::read_dir("/proc/self/task").map_or(1, |tasks| tasks.count() as u64);
+    let mut fds = [0; 2];
+    // SAFETY: `fds` has room for both ends; both are owned right after.
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(anyhow::anyhow!(
+            "Failed to create a pipe: {}",
+            std::io::Error::last_os_error()
+        ));
+    }
+    // SAFETY: `pipe2` just returned these descriptors to us alone.
+    let (release, write) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+    // SAFETY: `pthread_attr_t` is plain data, valid zeroed.
+    let mut attr: libc::pthread_attr_t = unsafe { std::mem::zeroed() };
+    // SAFETY: `attr` is initialized before use and destroyed after.
+    unsafe {
+        libc::pthread_attr_init(&mut attr);
+        libc::pthread_attr_setstacksize(&mut attr, THREAD_STACK);
+    }
+    let mut handles = vec![];
+    let stopped_by = loop {
+        if running + handles.len() as u64 >= cap {
+            break None;
+        }
+        let mut handle: libc::pthread_t = 0;
+        // SAFETY: the thread only reads from `release`, which outlives it.
+        let error = unsafe {
+            libc::pthread_create(
+                &mut handle,
+                &attr,
+                wait_for_release,
+                release.as_raw_fd() as usize as *mut libc::c_void,
+            )
+        };
+        match error {
+            0 => handles.push(handle),
+            error => break Some(std::io::Error::from_raw_os_error(error).to_string()),
+        }
+    };
+    drop(write);
+    for handle in &handles {
+        // SAFETY: each handle is a joinable thread started above, joined
+        // once.
+        unsafe { libc::pthread_join(*handle, std::ptr::null_mut()) };
+    }
+    // SAFETY: no thread is being created with `attr` any more.
+    unsafe { libc::pthread_attr_destroy(&mut attr) };
+    let started = handles.len() as u64;
+    // RLIMIT_NPROC counts the user's threads, which the kernel ignores
+    // for root.
+    let (soft, hard) = rlimit(libc::RLIMIT_NPROC);
+    Ok(Probe {
+        limit: "threads",
+        rlimit: "RLIMIT_NPROC",
+        soft,
+        hard,
+        found: running + started,
+        capped: stopped_by.is_none(),
+        stopped_by,
+    })
+}
+
+/// Reserves address space until refused, from this process's current size.
+fn address_space() -> Probe {
+    let before = address_space::sizes().0.unwrap_or(0);
+    let reservations = Reservations::reserve(u64::MAX, CHUNK, &CancellationToken::new());
+    let (soft, hard) = rlimit(libc::RLIMIT_AS);
+    Probe {
+        limit: "address space",
+        rlimit: "RLIMIT_AS",
+        soft,
+        hard,
+        found: before + reservations.bytes(),
+        capped: false,
+        stopped_by: Some("mmap refused even a page".to_string()),
+    }
+}
+
+/// Finds the longest a scratch file in `dir` can be truncated to, by
+/// bisection; the file stays sparse throughout.
+fn file_size(dir: &Path) -> Result<Probe, anyhow::Error> {
+    let path = dir.join(format!("itsmine-probe-limits-{}", std::process::id()));
+    let file = File::create(&path)
+        .map_err(|e| anyhow::anyhow!("Failed to create {}: {e}", path.display()))?;
+    let _ = std::fs::remove_file(&path);
+    // SAFETY: ignoring a signal installs no handler; passing RLIMIT_FSIZE
+    // then fails with EFBIG instead of killing the process.
+    let previous = unsafe { libc::signal(libc::SIGXFSZ, libc::SIG_IGN) };
+    let (mut fits, mut too_long) = (0u64, i64::MAX as u64 + 1);
+    let mut stopped_by = None;
+    while too_long - fits > 1 {
+        let len = fits + (too_long - fits) / 2;
+        // SAFETY: plain call on a descriptor this function owns.
+        match unsafe { libc::ftruncate(file.as_raw_fd(), len as libc::off_t) } {
+            0 => fits = len,
+            _ => {
+                too_long = len;
+                stopped_by = Some(std::io::Error::last_os_error().to_string());
+            }
+        }
+    }
+    // SAFETY: restores the disposition replaced above.
+    unsafe { libc::signal(libc::SIGXFSZ, previous) };
+    let (soft, hard) = rlimit(libc::RLIMIT_FSIZE);
+    Ok(Probe {
+        limit: "file size",
+        rlimit: "RLIMIT_FSIZE",
+        soft,
+        hard,
+        found: fits,
+        capped: stopped_by.is_none(),
+        stopped_by,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probes_stop_at_the_cap() {
+        let probe = files(256);
+        assert_eq!((probe.found, probe.capped), (256, true));
+        assert_eq!(probe.rlimit, "RLIMIT_NOFILE");
+        let probe = threads(256).unwrap();
+        assert_eq!((probe.found, probe.capped), (256, true));
+    }
+
+    #[test]
+    fn finds_the_filesystems_largest_file() {
+        let probe = file_size(&std::env::temp_dir()).unwrap();
+        // Every filesystem takes a terabyte, sparse.
+        assert!(probe.found >= 1 << 40, "{probe:?}");
+    }
+}
@@ -0,0 +1,39 @@
+use std::path::{Path, PathBuf};
+
+/// Samples the stressor's own call stacks for `--profile-self`.
+pub struct SelfProfiler {
+    guard: pprof::ProfilerGuard<'static>,
+    output: PathBuf,
+}
+
+impl SelfProfiler {
+    pub fn start(output: &Path) -> Result<Self, anyhow::Error> {
+        let guard = pprof::ProfilerGuardBuilder::default()
+            .frequency(1000)
+            .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+            .build()
+            .map_err(|e| anyhow::anyhow!("Failed to start profiler: {e}"))?;
+        Ok(SelfProfiler {
+            guard,
+            output: output.to_path_buf(),
+        })
+    }
+
+    /// Writes the collected samples as a flamegraph SVG.
+    pub fn finish(self) -> Result<(), anyhow::Error> {
+        let report = self
+            .guard
+            .report()
+            .build()
+            .map_err(|e| anyhow::anyhow!("Failed to build profile: {e}"))?;
+        let file = std::fs::File::create(&self.output)?;
+        report
+            .flamegraph(file)
+            .map_err(|e| anyhow::anyhow!("Failed to write flamegraph: {e}"))?;
+        log::info!(
+            "Wrote self-profile flamegraph to {}.",
+            self.output.display()
+        );
+        Ok(())
+    }
+}
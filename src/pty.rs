@@ -0,0 +1,160 @@
+//! `itsmine pty`: opens and holds pseudo-terminal pairs, up to a count or
+//! until the kernel refuses one, timing each open.
+
+use std::fs::{File, OpenOptions};
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+use crate::{CancellationToken, StressError, Stressor, parse, report, stats, sysinfo};
+
+#[derive(Clone, Debug, PartialEq, clap::Args)]
+pub struct Args {
+    /// Pseudo-terminals to hold
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 256,
+        conflicts_with = "exhaust"
+    )]
+    pub count: u32,
+    /// Open until the kernel refuses (see kernel.pty.max), ignoring --count
+    #[arg(long, default_value_t = false)]
+    pub exhaust: bool,
+    /// How long to hold them, e.g. 10s
+    #[arg(long, value_parser = parse::duration, default_value = "10s")]
+    pub duration: Duration,
+}
+
+/// A master and its slave end.
+struct Pty {
+    _master: OwnedFd,
+    _slave: File,
+}
+
+fn open_pty() -> std::io::Result<Pty> {
+    let last_error = std::io::Error::last_os_error;
+    // SAFETY: plain libc calls; the master descriptor is owned right away
+    // and `name` outlives `ptsname_r`, which NUL-terminates it.
+    unsafe {
+        let fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC);
+        if fd < 0 {
+            return Err(last_error());
+        }
+        let master = OwnedFd::from_raw_fd(fd);
+        let mut name = [0 as libc::c_char; 64];
+        if libc::grantpt(fd) != 0
+            || libc::unlockpt(fd) != 0
+            || libc::ptsname_r(fd, name.as_mut_ptr(), name.len()) != 0
+        {
+            return Err(last_error());
+        }
+        let path = std::ffi::CStr::from_ptr(name.as_ptr())
+            .to_string_lossy()
+            .into_owned();
+        let slave = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY)
+            .open(path)?;
+        Ok(Pty {
+            _master: master,
+            _slave: slave,
+        })
+    }
+}
+
+/// Opens up to `count` pairs, stopping at the first failure, and returns
+/// them with each open's latency in nanoseconds and the failure, if any.
+fn open_many(
+    count: Option<u32>,
+    cancel: &CancellationToken,
+) -> (Vec<Pty>, Vec<f64>, Option<std::io::Error>) {
+    let (mut ptys, mut latencies) = (vec![], vec![]);
+    while count.is_none_or(|count| ptys.len() < count as usize) && !cancel.is_cancelled() {
+        let started = Instant::now();
+        match open_pty() {
+            Ok(pty) => {
+                latencies.push(started.elapsed().as_nanos() as f64);
+                ptys.push(pty);
+            }
+            Err(e) => return (ptys, latencies, Some(e)),
+        }
+    }
+    (ptys, latencies, None)
+}
+
+/// Pseudo-terminals open system-wide.
+fn system_ptys() -> Option<u64> {
+    std::fs::read_to_string("/proc/sys/kernel/pty/nr")
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+impl Stressor for Args {
+    fn name(&self) -> &'static str {
+        "pty"
+    }
+
+    fn params(&self) -> Value {
+        json!({
+            "count": (!self.exhaust).then_some(self.count),
+            "duration_secs": self.duration.as_secs_f64(),
+        })
+    }
+
+    fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        // Each pair takes two descriptors.
+        if let Err(e) = sysinfo::raise_fd_limit() {
+            log::warn!("Could not raise the open-file limit: {e}.");
+        }
+        let count = (!self.exhaust).then_some(self.count);
+        let (ptys, mut latencies, error) = open_many(count, cancel);
+        if let Some(e) = &error {
+            log::warn!("Opening pseudo-terminal {} failed: {e}.", ptys.len() + 1);
+        }
+        log::info!(
+            "Holding {} pseudo-terminals for {:?}.",
+            ptys.len(),
+            self.duration
+        );
+        latencies.sort_by(f64::total_cmp);
+        let us = |p| stats::percentile(&latencies, p) / 1000.0;
+        let summary = json!({
+            "opened": ptys.len(),
+            "error": error.map(|e| e.to_string()),
+            "system_ptys": system_ptys(),
+            "open_p50_us": us(0.5),
+            "open_p99_us": us(0.99),
+        });
+        cancel.sleep_until(Instant::now() + self.duration);
+        drop(ptys);
+        log::info!("PTYs: {summary}.");
+        report::measure(self.name(), summary);
+        match cancel.is_cancelled() {
+            true => Err(StressError::Interrupted.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_and_holds_pairs() {
+        let (ptys, latencies, error) = open_many(Some(4), &CancellationToken::new());
+        if let Some(e) = error {
+            // No devpts in this environment; nothing to hold.
+            assert!(ptys.is_empty(), "{e}");
+            return;
+        }
+        assert_eq!(ptys.len(), 4);
+        assert_eq!(latencies.len(), 4);
+    }
+}
@@ -0,0 +1,113 @@
+//! Load profiles: a level that changes over a run, e.g. how many connections
+//! are open, ramping linearly between points and held after the last.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::parse;
+
+/// Levels at offsets into the run, in order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ramp {
+    points: Vec<(Duration, f64)>,
+}
+
+impl Ramp {
+    /// The level `elapsed` into the run.
+    pub fn at(&self, elapsed: Duration) -> f64 {
+        let after = self.points.partition_point(|(at, _)| *at <= elapsed);
+        match (
+            after.checked_sub(1).map(|i| self.points[i]),
+            self.points.get(after),
+        ) {
+            (Some((from, low)), Some(&(to, high))) => {
+                let progress = (elapsed - from).as_secs_f64() / (to - from).as_secs_f64();
+                low + (high - low) * progress
+            }
+            (Some((_, level)), None) => level,
+            (None, _) => self.points[0].1,
+        }
+    }
+
+    /// The highest level it reaches.
+    pub fn peak(&self) -> f64 {
+        self.points
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(0.0, f64::max)
+    }
+
+    /// The lowest level it reaches.
+    pub fn floor(&self) -> f64 {
+        self.points
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(f64::INFINITY, f64::min)
+    }
+}
+
+/// Parses `0s:0,30s:64,1m:64,90s:0`: comma-separated `<offset>:<level>`
+/// points with offsets in order. Equal offsets make a step.
+impl FromStr for Ramp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut points: Vec<(Duration, f64)> = vec![];
+        for point in s.split(',') {
+            let (at, level) = point
+                .split_once(':')
+                .ok_or_else(|| format!("ramp point '{point}' must look like <offset>:<level>"))?;
+            let at = parse::duration(at)?;
+            let level: f64 = level
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid level in ramp point '{point}'"))?;
+            if !level.is_finite() || level < 0.0 {
+                return Err(format!("ramp level '{level}' must be 0 or more"));
+            }
+            if points.last().is_some_and(|(last, _)| at < *last) {
+                return Err(format!(
+                    "ramp offsets must be in order, but '{point}' goes back"
+                ));
+            }
+            points.push((at, level));
+        }
+        Ok(Ramp { points })
+    }
+}
+
+impl fmt::Display for Ramp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let points: Vec<_> = self
+            .points
+            .iter()
+            .map(|(at, level)| format!("{}ms:{level}", at.as_millis()))
+            .collect();
+        write!(f, "{}", points.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolates_between_points() {
+        let ramp: Ramp = "10s:0,30s:100,1m:100,1m:20".parse().unwrap();
+        let at = |secs: u64| ramp.at(Duration::from_secs(secs));
+        assert_eq!(at(0), 0.0);
+        assert_eq!(at(20), 50.0);
+        assert_eq!(at(45), 100.0);
+        // The step at a minute, and the last level held after it.
+        assert_eq!(at(60), 20.0);
+        assert_eq!(at(600), 20.0);
+        assert_eq!((ramp.peak(), ramp.floor()), (100.0, 0.0));
+        assert_eq!(ramp.to_string().parse::<Ramp>(), Ok(ramp));
+
+        assert!("30s:1,10s:2".parse::<Ramp>().is_err());
+        assert!("10s:-1".parse::<Ramp>().is_err());
+        assert!("10s".parse::<Ramp>().is_err());
+        assert!("".parse::<Ramp>().is_err());
+    }
+}
@@ -0,0 +1,531 @@
+//! The stressors the command line can run, keyed by subcommand name, and
+//! the catalogue `itsmine list` prints from them. Adding a stressor means
+//! implementing [`Stressor`] on a `clap::Args` type and registering it in
+//! [`registry`], which also makes it usable in scenario phases.
+
+use clap::{ArgMatches, FromArgMatches, Subcommand};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::scenario::{Scenario, Schedule};
+use crate::{
+    CancellationToken, Resource, address_space, balloon, bank, chaos, clocks, disk, dns, entropy,
+    exec, forks, http_load, interference, inversion, links, locks, mmap_churn, mq, net, ng, pty,
+    sem, tlb, udp_flood, watches, zombies,
+};
+
+/// A runnable stressor built from its subcommand's arguments.
+pub trait Stressor: Send + Sync {
+    /// Name recorded in events, metrics and reports.
+    fn name(&self) -> &'static str;
+
+    /// The stressor's parameters as recorded in events and reports.
+    fn params(&self) -> Value;
+
+    /// How often the run repeats when `--every`/`--times` are not given.
+    fn schedule(&self) -> Schedule {
+        Schedule::default()
+    }
+
+    fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error>;
+}
+
+impl Stressor for Resource {
+    fn name(&self) -> &'static str {
+        Resource::name(self)
+    }
+
+    fn params(&self) -> Value {
+        Resource::params(self)
+    }
+
+    fn schedule(&self) -> Schedule {
+        match self {
+            Resource::Run { scenario } => Scenario::load(scenario)
+                .map(|s| s.schedule)
+                .unwrap_or_default(),
+            _ => Schedule::default(),
+        }
+    }
+
+    fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        crate::run_stressor(self.clone(), cancel)
+    }
+}
+
+type Build = fn(&ArgMatches) -> Result<Box<dyn Stressor>, clap::Error>;
+
+/// A subcommand and the factory turning its arguments into a stressor.
+pub struct Registration {
+    pub name: &'static str,
+    pub command: clap::Command,
+    pub platforms: &'static str,
+    pub privileges: &'static str,
+    build: Build,
+}
+
+impl Registration {
+    /// Registers `T`, whose fields are the subcommand's arguments.
+    pub fn new<T: clap::Args + Stressor + 'static>(
+        name: &'static str,
+        about: &'static str,
+    ) -> Self {
+        Registration {
+            name,
+            command: T::augment_args(clap::Command::new(name).about(about)),
+            platforms: "linux",
+            privileges: "none",
+            build: build::<T>,
+        }
+    }
+
+    /// Registers one of the stressors defined as a `Resource` variant.
+    fn builtin(name: &'static str) -> Self {
+        let command = Resource::augment_subcommands(clap::Command::new("itsmine"))
+            .find_subcommand(name)
+            .cloned()
+            .expect("builtin stressors are Resource variants");
+        Registration {
+            name,
+            command,
+            platforms: "linux",
+            privileges: "none",
+            build: build_resource,
+        }
+    }
+
+    pub fn privileges(mut self, privileges: &'static str) -> Self {
+        self.privileges = privileges;
+        self
+    }
+
+    /// Builds the stressor from the top-level matches of a command line
+    /// that selected this subcommand.
+    pub fn build(&self, matches: &ArgMatches) -> Result<Box<dyn Stressor>, clap::Error> {
+        (self.build)(matches)
+    }
+}
+
+fn build<T: clap::Args + Stressor + 'static>(
+    matches: &ArgMatches,
+) -> Result<Box<dyn Stressor>, clap::Error> {
+    let (_, matches) = matches
+        .subcommand()
+        .ok_or_else(|| clap::Error::new(clap::error::ErrorKind::MissingSubcommand))?;
+    Ok(Box::new(T::from_arg_matches(matches)?))
+}
+
+fn build_resource(matches: &ArgMatches) -> Result<Box<dyn Stressor>, clap::Error> {
+    let mut resource = Resource::from_arg_matches(matches)?;
+    // Pick the seed up front so events and reports record it.
+    if let Resource::Chaos { seed, .. } = &mut resource {
+        seed.get_or_insert_with(chaos::random_seed);
+    }
+    Ok(Box::new(resource))
+}
+
+/// Every stressor the command line offers, in `--help` order.
+pub fn registry() -> Vec<Registration> {
+    let mut registry: Vec<_> = ["memory", "thread", "run", "chaos", "replay-trace"]
+        .into_iter()
+        .map(|name| match name {
+            "run" => Registration::builtin(name).privileges("those of its phases' stressors"),
+            name => Registration::builtin(name),
+        })
+        .collect();
+    registry.push(Registration::new::<ng::Args>(
+        "ng",
+        "Run a stress-ng style command line, e.g. --vm 2 --vm-bytes 1G --cpu 4",
+    ));
+    registry.push(
+        Registration::new::<inversion::Args>(
+            "priority-inversion",
+            "Measure how long a high-priority thread waits on a lock held by a low-priority one while medium-priority threads spin",
+        )
+        .privileges("CAP_SYS_NICE for realtime priorities"),
+    );
+    registry.push(Registration::new::<interference::Args>(
+        "interference",
+        "Measure how much a cache-thrashing aggressor on an SMT sibling or shared-LLC core slows a latency-bound victim",
+    ));
+    registry.push(
+        Registration::new::<tlb::Args>(
+            "tlb",
+            "Walk 4K and 2M pages scattered over a sparse mapping in random order to maximize TLB misses",
+        )
+        .privileges("perf_event_paranoid <= 2 for dTLB miss counts"),
+    );
+    registry.push(Registration::new::<bank::Args>(
+        "bank-conflict",
+        "Read addresses a DRAM-bank stride apart to maximize row-buffer misses",
+    ));
+    registry.push(Registration::new::<mmap_churn::Args>(
+        "mmap-churn",
+        "Map and unmap regions from several threads to contend on the VMA tree and mmap lock",
+    ));
+    registry.push(Registration::new::<address_space::Args>(
+        "address-space",
+        "Reserve huge amounts of PROT_NONE address space to push VSZ to its limits without using memory",
+    ));
+    registry.push(
+        Registration::new::<balloon::Args>(
+            "balloon",
+            "Hold memory that an HTTP controller grows and shrinks on demand",
+        )
+        .privileges("CAP_NET_BIND_SERVICE for --listen ports below 1024"),
+    );
+    registry.push(Registration::new::<zombies::Args>(
+        "zombies",
+        "Hold unreaped exited children to fill the process table",
+    ));
+    registry.push(
+        Registration::new::<forks::Args>(
+            "fork-rate",
+            "Fork short-lived children at a bounded rate to exercise process limits like pids.max",
+        )
+        .privileges("CAP_SYS_ADMIN for --pid-namespace"),
+    );
+    registry.push(Registration::new::<exec::Args>(
+        "exec-churn",
+        "Launch a trivial helper binary at a fixed rate, timing each exec",
+    ));
+    registry.push(Registration::new::<pty::Args>(
+        "pty",
+        "Open and hold pseudo-terminals, up to a count or until the kernel refuses",
+    ));
+    registry.push(Registration::new::<watches::Args>(
+        "watches",
+        "Register inotify watches or epoll entries up to a count or until the kernel refuses",
+    ));
+    registry.push(
+        Registration::new::<mq::Args>(
+            "mq",
+            "Pump messages through POSIX message queues between producer and consumer threads",
+        )
+        .privileges("CAP_SYS_RESOURCE for --depth or --message-bytes above fs.mqueue limits"),
+    );
+    registry.push(Registration::new::<sem::Args>(
+        "sem",
+        "Hammer a POSIX or System V semaphore from several threads",
+    ));
+    registry.push(Registration::new::<locks::Args>(
+        "locks",
+        "Have processes contend for flock or fcntl locks on shared files, timing each acquisition",
+    ));
+    registry.push(Registration::new::<links::Args>(
+        "links",
+        "Build a deep tree of looping symlinks and hardlinks and walk it repeatedly",
+    ));
+    registry.push(
+        Registration::new::<disk::Args>(
+            "disk",
+            "Load a filesystem through a scratch file: stream writes, or punch and fill holes in a sparse one",
+        )
+        .privileges("CAP_SYS_ADMIN for --io-class rt"),
+    );
+    registry.push(Registration::new::<net::Args>(
+        "net",
+        "Stream TCP, UDP, WebSocket or multicast traffic at a target, or at a loopback sink",
+    ));
+    registry.push(Registration::new::<dns::Args>(
+        "dns",
+        "Send lookups at a resolver at a fixed rate and report answers and latency",
+    ));
+    registry.push(Registration::new::<http_load::Args>(
+        "http",
+        "Send HTTP requests at a URL at a fixed rate over TCP or QUIC and report handshake and request latency",
+    ));
+    registry.push(Registration::new::<entropy::Args>(
+        "entropy",
+        "Read the kernel's random number generator from several threads as fast as it gives",
+    ));
+    registry.push(Registration::new::<clocks::Args>(
+        "clocks",
+        "Hammer clock_gettime and gettimeofday while measuring clock drift and timer jitter",
+    ));
+    registry.push(Registration::new::<udp_flood::Args>(
+        "udp-flood",
+        "Flood loopback UDP between sender and receiver threads to drive softirq load",
+    ));
+    registry
+}
+
+/// Builds the stressor registered as `name` from its arguments, the way a
+/// scenario phase lists them.
+pub fn stressor(name: &str, args: &[String]) -> Result<Box<dyn Stressor>, anyhow::Error> {
+    let registration = registry()
+        .into_iter()
+        .find(|registration| registration.name == name)
+        .ok_or_else(|| anyhow::anyhow!("unknown stressor '{name}'"))?;
+    clap::Command::new("itsmine")
+        .subcommand(registration.command.clone())
+        .try_get_matches_from(
+            ["itsmine", name]
+                .into_iter()
+                .chain(args.iter().map(String::as_str)),
+        )
+        .and_then(|matches| registration.build(&matches))
+        .map_err(|e| {
+            // clap's first line names the problem; the rest is usage help.
+            let rendered = e.to_string();
+            let problem = rendered.lines().next().unwrap_or_default();
+            anyhow::anyhow!("{name}: {}", problem.trim_start_matches("error: "))
+        })
+}
+
+/// Runs `stressor`, turning a panic into an error so the run can still be
+/// reported.
+pub fn run(stressor: &dyn Stressor, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| stressor.run(cancel))).map_err(
+        |payload| anyhow::anyhow!("Stressor panicked: {}", crate::panic_message(&payload)),
+    )?
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StressorInfo {
+    pub name: String,
+    pub about: String,
+    pub params: Vec<Param>,
+    pub platforms: &'static str,
+    pub privileges: &'static str,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Param {
+    pub name: String,
+    pub help: String,
+    pub required: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct WorkloadInfo {
+    pub name: &'static str,
+    pub about: &'static str,
+    /// Whether this build includes the workload.
+    pub available: bool,
+}
+
+/// Every registered stressor with its parameters and requirements.
+pub fn stressors() -> Vec<StressorInfo> {
+    registry()
+        .into_iter()
+        .map(|registration| {
+            let command = &registration.command;
+            StressorInfo {
+                name: registration.name.to_string(),
+                about: command
+                    .get_about()
+                    .map(|s| s.to_string())
+                    .unwrap_or_default(),
+                params: command
+                    .get_arguments()
+                    .filter(|arg| !matches!(arg.get_id().as_str(), "help" | "version"))
+                    .map(|arg| Param {
+                        name: match arg.get_long() {
+                            Some(long) => format!("--{long}"),
+                            None => format!("<{}>", arg.get_id()),
+                        },
+                        help: arg.get_help().map(|s| s.to_string()).unwrap_or_default(),
+                        required: arg.is_required_set(),
+                    })
+                    .collect(),
+                platforms: registration.platforms,
+                privileges: registration.privileges,
+            }
+        })
+        .collect()
+}
+
+/// The thread stressor's per-iteration workloads, in order of precedence.
+pub fn workloads() -> Vec<WorkloadInfo> {
+    vec![
+        WorkloadInfo {
+            name: "plugin",
+            about: "WASM module given with --plugin",
+            available: cfg!(feature = "plugins"),
+        },
+        WorkloadInfo {
+            name: "script",
+            about: "Rhai script given with --workload-script",
+            available: cfg!(feature = "scripting"),
+        },
+        WorkloadInfo {
+            name: "fibonacci",
+            about: "Built-in recursive Fibonacci(30)",
+            available: true,
+        },
+        WorkloadInfo {
+            name: "sha256",
+            about: "Built-in SHA-256 hashing, selected with --mix",
+            available: true,
+        },
+        WorkloadInfo {
+            name: "memcpy",
+            about: "Built-in buffer copies, selected with --mix",
+            available: true,
+        },
+    ]
+}
+
+/// Renders the catalogue as the text printed by `itsmine list`.
+pub fn render() -> String {
+    let mut out = String::from("Stressors:\n");
+    for stressor in stressors() {
+        out.push_str(&format!(
+            "  {:<13} {} [{}; privileges: {}]\n",
+            stressor.name, stressor.about, stressor.platforms, stressor.privileges
+        ));
+        for param in &stressor.params {
+            let required = if param.required { " (required)" } else { "" };
+            out.push_str(&format!(
+                "      {:<12} {}{required}\n",
+                param.name, param.help
+            ));
+        }
+    }
+    out.push_str("Workloads:\n");
+    for workload in workloads() {
+        let available = if workload.available {
+            ""
+        } else {
+            " (not built in)"
+        };
+        out.push_str(&format!(
+            "  {:<13} {}{available}\n",
+            workload.name, workload.about
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_every_subcommand_with_params() {
+        let stressors = stressors();
+        let names: Vec<_> = stressors.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "memory",
+                "thread",
+                "run",
+                "chaos",
+                "replay-trace",
+                "ng",
+                "priority-inversion",
+                "interference",
+                "tlb",
+                "bank-conflict",
+                "mmap-churn",
+                "address-space",
+                "balloon",
+                "zombies",
+                "fork-rate",
+                "exec-churn",
+                "pty",
+                "watches",
+                "mq",
+                "sem",
+                "locks",
+                "links",
+                "disk",
+                "net",
+                "dns",
+                "http",
+                "entropy",
+                "clocks",
+                "udp-flood"
+            ]
+        );
+        let chaos = &stressors[3];
+        assert!(
+            chaos
+                .params
+                .iter()
+                .any(|p| p.name == "--budget" && p.required)
+        );
+        assert_eq!(stressors[0].params[0].name, "<arg>");
+    }
+
+    #[test]
+    fn declares_privileges_for_flags_it_has() {
+        let stressors = stressors();
+        let privileges = |name: &str| {
+            let stressor = stressors.iter().find(|s| s.name == name).unwrap();
+            (stressor.privileges, &stressor.params)
+        };
+        assert_eq!(privileges("disk").0, "CAP_SYS_ADMIN for --io-class rt");
+        assert_eq!(privileges("sem").0, "none");
+        for stressor in &stressors {
+            for flag in stressor.privileges.split_whitespace() {
+                let flag = flag.trim_end_matches(',');
+                if flag.starts_with("--") {
+                    assert!(
+                        privileges(&stressor.name).1.iter().any(|p| p.name == flag),
+                        "{} declares privileges for missing {flag}",
+                        stressor.name
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn builds_registered_stressors() {
+        let registry = registry();
+        let command = registry
+            .iter()
+            .fold(clap::Command::new("itsmine"), |cmd, r| {
+                cmd.subcommand(r.command.clone())
+            });
+        let matches = command
+            .try_get_matches_from([
+                "itsmine",
+                "chaos",
+                "--budget",
+                "cpu=50%",
+                "--duration",
+                "1m",
+            ])
+            .unwrap();
+        let stressor = registry
+            .iter()
+            .find(|r| Some(r.name) == matches.subcommand_name())
+            .unwrap()
+            .build(&matches)
+            .unwrap();
+        assert_eq!(stressor.name(), "chaos");
+        assert!(stressor.params()["seed"].is_u64());
+    }
+
+    #[test]
+    fn run_reports_panics() {
+        struct Panics;
+        impl Stressor for Panics {
+            fn name(&self) -> &'static str {
+                "panics"
+            }
+            fn params(&self) -> Value {
+                Value::Null
+            }
+            fn run(&self, _: &CancellationToken) -> Result<(), anyhow::Error> {
+                panic!("boom")
+            }
+        }
+        let err = run(&Panics, &CancellationToken::new()).unwrap_err();
+        assert_eq!(err.to_string(), "Stressor panicked: boom");
+    }
+
+    #[test]
+    fn fibonacci_is_always_available() {
+        assert!(
+            workloads()
+                .iter()
+                .any(|w| w.name == "fibonacci" && w.available)
+        );
+        assert!(render().contains("fibonacci"));
+    }
+}
@@ -0,0 +1,267 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+
+use crate::{CancellationToken, StressError, events, parse, telemetry};
+
+/// Length of one busy/idle cycle of the CPU workers.
+const DUTY_PERIOD: Duration = Duration::from_millis(100);
+
+/// Memory is held in chunks of this size so it can grow and shrink cheaply.
+const CHUNK: usize = 1024 * 1024;
+
+/// One row of a utilization trace.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TracePoint {
+    /// Offset from the first row.
+    pub at: Duration,
+    /// Host-wide CPU utilization, 0-100.
+    pub cpu_percent: f64,
+    pub mem_bytes: u64,
+}
+
+/// Reproduces a recorded CPU%/memory shape. Each row's utilization holds
+/// until the next row's timestamp; the last row ends the replay.
+pub struct Replay {
+    pub points: Vec<TracePoint>,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read trace {}: {e}", path.display()))?;
+        let points =
+            parse(&text).map_err(|e| anyhow::anyhow!("Invalid trace {}: {e}", path.display()))?;
+        Ok(Replay { points })
+    }
+
+    pub fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+        log::info!(
+            "Replaying {} trace points over {:.0}s on {cores} cores.",
+            self.points.len(),
+            self.points.last().map_or(0.0, |p| p.at.as_secs_f64())
+        );
+
+        // Target duty cycle in permille, shared by all CPU workers.
+        let duty = Arc::new(AtomicU32::new(0));
+        let stop = Arc::new(AtomicBool::new(false));
+        let workers: Vec<_> = (0..cores)
+            .map(|_| {
+                let duty = duty.clone();
+                let stop = stop.clone();
+                std::thread::spawn(move || cpu_worker(&duty, &stop))
+            })
+            .collect();
+
+        let mut held: Vec<Vec<u8>> = vec![];
+        let started = Instant::now();
+        for (index, point) in self.points.iter().enumerate() {
+            if !cancel.sleep_until(started + point.at) {
+                break;
+            }
+
+            duty.store((point.cpu_percent * 10.0).round() as u32, Ordering::Relaxed);
+            resize(&mut held, point.mem_bytes);
+
+            log::debug!(
+                "Trace point {index} at +{:.1}s: {:.1}% CPU, {} bytes.",
+                point.at.as_secs_f64(),
+                point.cpu_percent,
+                point.mem_bytes
+            );
+            telemetry::gauge("replay.cpu_percent", "%", point.cpu_percent.round() as u64);
+            telemetry::gauge("replay.mem_bytes", "By", (held.len() * CHUNK) as u64);
+            events::emit(
+                "trace_point",
+                json!({
+                    "index": index,
+                    "cpu_percent": point.cpu_percent,
+                    "mem_bytes": point.mem_bytes,
+                }),
+            );
+        }
+
+        stop.store(true, Ordering::Relaxed);
+        for worker in workers {
+            worker
+                .join()
+                .map_err(|payload| StressError::WorkerPanicked(crate::panic_message(&payload)))?;
+        }
+        log::info!("Trace replay complete.");
+        Ok(())
+    }
+}
+
+fn cpu_worker(duty: &AtomicU32, stop: &AtomicBool) {
+    while !stop.load(Ordering::Relaxed) {
+        let period_start = Instant::now();
+        let busy = DUTY_PERIOD * duty.load(Ordering::Relaxed).min(1000) / 1000;
+        while period_start.elapsed() < busy {
+            std::hint::spin_loop();
+        }
+        std::thread::sleep(DUTY_PERIOD.saturating_sub(period_start.elapsed()));
+    }
+}
+
+/// Grows or shrinks the held memory to the nearest chunk of `target` bytes.
+fn resize(held: &mut Vec<Vec<u8>>, target: u64) {
+    let chunks = (target as usize).div_ceil(CHUNK);
+    held.truncate(chunks);
+    while held.len() < chunks {
+        // A non-zero fill forces every page to be committed.
+        held.push(vec![0xa5; CHUNK]);
+    }
+}
+
+/// Parses `timestamp,cpu_percent,mem` rows. Timestamps are seconds (relative
+/// or Unix epoch) or `HH:MM:SS` as printed by sar; memory is bytes or a size
+/// with a K/M/G suffix. A leading header row is skipped.
+pub fn parse(text: &str) -> Result<Vec<TracePoint>, anyhow::Error> {
+    let mut rows: Vec<(f64, f64, u64)> = vec![];
+    let mut first = true;
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let may_be_header = std::mem::replace(&mut first, false);
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if fields.len() < 3 {
+            return Err(anyhow::anyhow!(
+                "line {}: expected timestamp,cpu_percent,mem",
+                number + 1
+            ));
+        }
+        let Some(at) = parse_timestamp(fields[0]) else {
+            if may_be_header {
+                continue;
+            }
+            return Err(anyhow::anyhow!(
+                "line {}: invalid timestamp '{}'",
+                number + 1,
+                fields[0]
+            ));
+        };
+        let cpu: f64 = fields[1].trim_end_matches('%').parse().map_err(|e| {
+            anyhow::anyhow!("line {}: invalid CPU% '{}': {e}", number + 1, fields[1])
+        })?;
+        if !(0.0..=100.0).contains(&cpu) {
+            return Err(anyhow::anyhow!(
+                "line {}: CPU% {cpu} out of range",
+                number + 1
+            ));
+        }
+        let mem = parse::bytes(fields[2])
+            .map_err(|e| anyhow::anyhow!("line {}: invalid memory: {e}", number + 1))?;
+        if let Some(&(prev, _, _)) = rows.last()
+            && at < prev
+        {
+            return Err(anyhow::anyhow!(
+                "line {}: timestamps go backwards",
+                number + 1
+            ));
+        }
+        rows.push((at, cpu, mem));
+    }
+
+    let Some(&(origin, _, _)) = rows.first() else {
+        return Err(anyhow::anyhow!("trace has no data rows"));
+    };
+    Ok(rows
+        .into_iter()
+        .map(|(at, cpu_percent, mem_bytes)| TracePoint {
+            at: Duration::from_secs_f64(at - origin),
+            cpu_percent,
+            mem_bytes,
+        })
+        .collect())
+}
+
+/// Seconds, or `HH:MM:SS`; `NaN`, infinities and negative times are not
+/// timestamps.
+fn parse_timestamp(s: &str) -> Option<f64> {
+    let secs = match s.parse::<f64>() {
+        Ok(secs) => secs,
+        Err(_) => {
+            let parts: Vec<f64> = s
+                .split(':')
+                .map(|p| p.parse().ok())
+                .collect::<Option<_>>()?;
+            match parts[..] {
+                [h, m, s] => h * 3600.0 + m * 60.0 + s,
+                _ => return None,
+            }
+        }
+    };
+    (secs.is_finite() && secs >= 0.0).then_some(secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_relative_trace_with_header() {
+        let points = parse("time,cpu,mem\n100,10,1M\n160,55.5%,2048\n").unwrap();
+        assert_eq!(
+            points,
+            vec![
+                TracePoint {
+                    at: Duration::ZERO,
+                    cpu_percent: 10.0,
+                    mem_bytes: 1024 * 1024,
+                },
+                TracePoint {
+                    at: Duration::from_secs(60),
+                    cpu_percent: 55.5,
+                    mem_bytes: 2048,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_sar_style_timestamps() {
+        let points = parse("10:00:00,5,0\n10:01:30,5,0\n").unwrap();
+        assert_eq!(points[1].at, Duration::from_secs(90));
+    }
+
+    #[test]
+    fn parse_rejects_bad_rows() {
+        assert!(parse("").is_err());
+        assert!(parse("0,10\n").is_err());
+        assert!(parse("0,120,0\n").is_err());
+        assert!(parse("10,1,0\n5,1,0\n").is_err());
+        assert!(parse("0,1,0\nlater,1,0\n").is_err());
+        assert!(parse("NaN,50,1M\n").is_err());
+        assert!(parse("0,1,0\nNaN,50,1M\n").is_err());
+        assert!(parse("0,1,0\ninf,50,1M\n").is_err());
+        assert!(parse("0,1,0\n00:00:-5,1,0\n").is_err());
+    }
+
+    #[test]
+    fn resize_rounds_up_to_chunks() {
+        let mut held = vec![];
+        resize(&mut held, 1);
+        assert_eq!(held.len(), 1);
+        resize(&mut held, 3 * CHUNK as u64);
+        assert_eq!(held.len(), 3);
+        resize(&mut held, 0);
+        assert!(held.is_empty());
+    }
+
+    #[test]
+    fn run_follows_trace_timing() {
+        let replay = Replay {
+            points: parse("0,50,1M\n0.2,0,0\n").unwrap(),
+        };
+        let started = Instant::now();
+        replay.run(&CancellationToken::new()).unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(200));
+    }
+}
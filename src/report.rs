@@ -0,0 +1,311 @@
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::kmsg::KernelEvent;
+use crate::sysinfo::SystemInfo;
+
+static MEASUREMENTS: Mutex<Vec<(String, Value)>> = Mutex::new(vec![]);
+static DEGRADATIONS: Mutex<Vec<String>> = Mutex::new(vec![]);
+static PENDING: Mutex<Option<Pending>> = Mutex::new(None);
+
+/// Records a stressor's measured result under `key` for the run's report.
+pub fn measure(key: &str, value: Value) {
+    MEASUREMENTS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push((key.to_string(), value));
+}
+
+/// Takes the measurements recorded since the last call.
+pub fn take_measurements() -> Map<String, Value> {
+    std::mem::take(&mut *MEASUREMENTS.lock().unwrap_or_else(|e| e.into_inner()))
+        .into_iter()
+        .collect()
+}
+
+/// Marks the run degraded: it finished, but with less load than asked for.
+pub fn degrade(reason: String) {
+    DEGRADATIONS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(reason);
+}
+
+/// Takes the degradation reasons recorded since the last call.
+pub fn take_degradations() -> Vec<String> {
+    std::mem::take(&mut *DEGRADATIONS.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+/// The run in progress and where its report goes, kept so a partial report
+/// can still be written if the process dies before the run finishes.
+struct Pending {
+    run: String,
+    parameters: Value,
+    started: SystemTime,
+    path: Option<PathBuf>,
+    stdout: bool,
+}
+
+impl Pending {
+    fn partial(&self, reason: &str) -> Report {
+        let outcome = Err(anyhow::anyhow!("{reason}"));
+        let mut report = Report::new(&self.run, self.parameters.clone(), self.started, &outcome);
+        report.partial = true;
+        report
+    }
+}
+
+/// Starts tracking a run whose report goes to `path` and, with `stdout`, to
+/// standard output. A partial report is written to `path` straight away, so
+/// even a death nothing can catch, like SIGKILL from the OOM killer, leaves
+/// one behind.
+pub fn begin(
+    run: &str,
+    parameters: Value,
+    started: SystemTime,
+    path: Option<PathBuf>,
+    stdout: bool,
+) {
+    let pending = Pending {
+        run: run.to_string(),
+        parameters,
+        started,
+        path,
+        stdout,
+    };
+    if let Some(path) = &pending.path
+        && let Err(e) = std::fs::write(path, pending.partial("run did not finish").to_json())
+    {
+        log::warn!("Failed to write partial report to {}: {e}", path.display());
+    }
+    *PENDING.lock().unwrap_or_else(|e| e.into_inner()) = Some(pending);
+}
+
+/// Stops tracking the run once its full report has been written.
+pub fn finish() {
+    PENDING.lock().unwrap_or_else(|e| e.into_inner()).take();
+}
+
+/// Writes a partial report for the run in progress, if any, with whatever
+/// it gathered so far.
+pub fn flush_partial(reason: &str) {
+    // Never block a dying process on a lock its own thread may hold.
+    let Ok(mut pending) = PENDING.try_lock() else {
+        return;
+    };
+    let Some(pending) = pending.take() else {
+        return;
+    };
+    let mut report = pending.partial(reason);
+    report.collect();
+    if let Some(path) = &pending.path {
+        match std::fs::write(path, report.to_json()) {
+            Ok(()) => log::warn!("Wrote partial report to {}.", path.display()),
+            Err(e) => log::error!("Failed to write partial report to {}: {e}", path.display()),
+        }
+    }
+    if pending.stdout {
+        println!("{}", report.to_json());
+    }
+}
+
+extern "C" fn flush_at_exit() {
+    flush_partial("process exited before the run finished");
+}
+
+/// Flushes a partial report if the main thread panics or the process exits
+/// while a run is in progress.
+pub fn install_flush() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        previous(info);
+        // Worker panics are caught and handled; one on the main thread ends
+        // the process.
+        if std::thread::current().name() == Some("main") {
+            flush_partial(&format!("panicked: {info}"));
+        }
+    }));
+    // SAFETY: the handler is a plain function that lives for the whole
+    // process.
+    unsafe { libc::atexit(flush_at_exit) };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    /// Completed, but part of the load was lost along the way.
+    Degraded,
+    Failed,
+}
+
+/// Final summary of a run, serialized as JSON for external consumers.
+#[derive(Clone, Debug, Serialize)]
+pub struct Report {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// `--tag` key/value pairs, for filtering and grouping runs.
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub tags: Map<String, Value>,
+    pub stressor: String,
+    pub parameters: Value,
+    pub command: String,
+    pub started_at: f64,
+    pub duration_secs: f64,
+    pub status: Status,
+    /// Set when the process died mid-run and this is what it had gathered.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub partial: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Process exit code this outcome maps to (0 on success).
+    pub exit_code: i32,
+    /// Transient allocation or spawn failures retried during the run.
+    pub retries: u64,
+    /// How each stressor of a scenario phase stopped.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stops: Vec<crate::scenario::Stop>,
+    /// Why a completed run delivered less load than asked for.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub degradations: Vec<String>,
+    /// Features left out for lack of a privilege, e.g. `--sched fifo:50:
+    /// skipped: missing CAP_SYS_NICE`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub skipped: Vec<String>,
+    /// Why the run ended early without failing, e.g. an `--until` condition.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub kernel_events: Vec<KernelEvent>,
+    /// Whether workload results matched what they should compute.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verification: Option<crate::verify::Verification>,
+    /// Results stressors measured, such as latencies, keyed by stressor.
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub measurements: Map<String, Value>,
+    /// The machine the run happened on.
+    pub system: SystemInfo,
+}
+
+impl Report {
+    pub fn new(
+        stressor: &str,
+        parameters: Value,
+        started: SystemTime,
+        outcome: &Result<(), anyhow::Error>,
+    ) -> Self {
+        Report {
+            label: crate::telemetry::label().map(str::to_string),
+            tags: crate::telemetry::tags()
+                .iter()
+                .map(|(key, value)| (key.clone(), Value::from(value.as_str())))
+                .collect(),
+            stressor: stressor.to_string(),
+            parameters,
+            command: std::env::args().collect::<Vec<_>>().join(" "),
+            started_at: started
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64(),
+            duration_secs: started.elapsed().unwrap_or_default().as_secs_f64(),
+            status: match outcome {
+                Ok(()) => Status::Ok,
+                Err(_) => Status::Failed,
+            },
+            partial: false,
+            error: outcome.as_ref().err().map(|e| format!("{e:#}")),
+            exit_code: outcome.as_ref().err().map_or(0, crate::error::exit_code),
+            retries: crate::retry::take(),
+            stops: vec![],
+            degradations: vec![],
+            skipped: crate::caps::skipped(),
+            stop_reason: None,
+            kernel_events: vec![],
+            verification: None,
+            measurements: Map::new(),
+            system: SystemInfo::collect(),
+        }
+    }
+
+    /// Fills in what stressors recorded during the run: scenario stops,
+    /// degradations, verification results and measurements.
+    pub fn collect(&mut self) {
+        self.stops = crate::scenario::take_stops();
+        self.degradations = take_degradations();
+        if self.status == Status::Ok && !self.degradations.is_empty() {
+            self.status = Status::Degraded;
+        }
+        self.verification = crate::verify::take();
+        self.measurements = take_measurements();
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("report is always serializable")
+    }
+
+    /// POSTs the report as JSON to a webhook.
+    pub fn notify(&self, url: &str) -> Result<(), anyhow::Error> {
+        crate::http::post(url, "application/json", self.to_json())
+            .map_err(|e| anyhow::anyhow!("Failed to notify {url}: {e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn report_records_success() {
+        let report = Report::new(
+            "thread",
+            json!({ "threads": 2 }),
+            SystemTime::now(),
+            &Ok(()),
+        );
+        let value: Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(value["status"], "ok");
+        assert_eq!(value["parameters"]["threads"], 2);
+        assert!(value.get("error").is_none());
+        assert!(value.get("kernel_events").is_none());
+        assert!(value["system"]["cpus"]["logical"].is_u64());
+    }
+
+    #[test]
+    fn report_records_failure() {
+        let outcome = Err(anyhow::anyhow!("Memory allocation failed"));
+        let report = Report::new("memory", json!({}), SystemTime::now(), &outcome);
+        let value: Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(value["status"], "failed");
+        assert_eq!(value["error"], "Memory allocation failed");
+    }
+
+    #[test]
+    fn partial_report_survives_an_unfinished_run() {
+        let path =
+            std::env::temp_dir().join(format!("itsmine-partial-{}.json", std::process::id()));
+        let read =
+            || -> Value { serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap() };
+        begin(
+            "thread",
+            json!({ "threads": 1 }),
+            SystemTime::now(),
+            Some(path.clone()),
+            false,
+        );
+        assert_eq!(read()["partial"], true);
+        assert_eq!(read()["status"], "failed");
+
+        flush_partial("panicked: boom");
+        assert_eq!(read()["error"], "panicked: boom");
+        // Nothing is pending any more, so a second flush leaves it alone.
+        std::fs::write(&path, "{}").unwrap();
+        flush_partial("exited");
+        assert_eq!(read(), json!({}));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,113 @@
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::{CancellationToken, StressError};
+
+static POLICY: OnceLock<Policy> = OnceLock::new();
+static RETRIES: AtomicU64 = AtomicU64::new(0);
+
+/// Delay before the first retry; later delays grow per `Backoff`.
+const BASE_DELAY: Duration = Duration::from_millis(100);
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub enum Backoff {
+    Fixed,
+    Linear,
+    #[default]
+    Exp,
+}
+
+/// How often transient allocation and spawn failures are retried. On busy
+/// shared hosts the first attempt frequently loses a race.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Policy {
+    pub retries: u32,
+    pub backoff: Backoff,
+}
+
+impl Policy {
+    /// Wait before retry number `attempt` (starting at 0).
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let delay = match self.backoff {
+            Backoff::Fixed => Some(BASE_DELAY),
+            Backoff::Linear => BASE_DELAY.checked_mul(attempt + 1),
+            Backoff::Exp => 2u32
+                .checked_pow(attempt)
+                .and_then(|factor| BASE_DELAY.checked_mul(factor)),
+        };
+        delay.unwrap_or(MAX_DELAY).min(MAX_DELAY)
+    }
+}
+
+pub fn configure(policy: Policy) {
+    let _ = POLICY.set(policy);
+}
+
+/// Retries taken since the last call, for the run's report.
+pub fn take() -> u64 {
+    RETRIES.swap(0, Ordering::Relaxed)
+}
+
+/// Counts retries taken elsewhere, e.g. in an isolated child, toward the
+/// run's report.
+pub fn add(retries: u64) {
+    RETRIES.fetch_add(retries, Ordering::Relaxed);
+}
+
+/// Runs `f`, retrying transient failures per the configured policy.
+pub fn run<T>(
+    what: &str,
+    cancel: &CancellationToken,
+    mut f: impl FnMut() -> Result<T, StressError>,
+) -> Result<T, StressError> {
+    let policy = POLICY.get().copied().unwrap_or_default();
+    let mut attempt = 0;
+    loop {
+        match f() {
+            Err(e) if e.is_transient() && attempt < policy.retries => {
+                let delay = policy.delay(attempt);
+                attempt += 1;
+                RETRIES.fetch_add(1, Ordering::Relaxed);
+                log::warn!(
+                    "Failed to {what} ({e}); retry {attempt}/{} in {:.1}s.",
+                    policy.retries,
+                    delay.as_secs_f64()
+                );
+                if !cancel.sleep_until(Instant::now() + delay) {
+                    return Err(StressError::Interrupted);
+                }
+            }
+            outcome => return outcome,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_delays() {
+        let policy = |backoff| Policy {
+            retries: 5,
+            backoff,
+        };
+        assert_eq!(policy(Backoff::Fixed).delay(3), Duration::from_millis(100));
+        assert_eq!(policy(Backoff::Linear).delay(3), Duration::from_millis(400));
+        assert_eq!(policy(Backoff::Exp).delay(3), Duration::from_millis(800));
+        assert_eq!(policy(Backoff::Exp).delay(40), MAX_DELAY);
+    }
+
+    #[test]
+    fn permanent_failures_are_not_retried() {
+        let mut calls = 0;
+        let outcome: Result<(), _> = run("test", &CancellationToken::new(), || {
+            calls += 1;
+            Err(StressError::WorkloadMismatch)
+        });
+        assert!(outcome.is_err());
+        assert_eq!(calls, 1);
+    }
+}
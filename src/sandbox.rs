@@ -0,0 +1,255 @@
+use std::io;
+
+/// `nobody`/`nogroup`, which root workers switch to.
+const NOBODY: libc::uid_t = 65534;
+
+/// The architecture seccomp reports for this build's syscalls; the filter
+/// is only written for these.
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: Option<u32> = Some(0xC000_003E);
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: Option<u32> = Some(0xC000_00B7);
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const AUDIT_ARCH: Option<u32> = None;
+
+/// Set in the numbers of x32 syscalls, which share x86_64's architecture
+/// but not its numbering.
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+/// Syscalls a workload never needs: launching programs, debugging other
+/// processes, reconfiguring the kernel or mounts, opening sockets or files
+/// by handle, and io_uring, whose operations never pass the filter.
+const DENIED: &[libc::c_long] = &[
+    libc::SYS_execve,
+    libc::SYS_execveat,
+    libc::SYS_ptrace,
+    libc::SYS_process_vm_readv,
+    libc::SYS_process_vm_writev,
+    libc::SYS_mount,
+    libc::SYS_umount2,
+    libc::SYS_pivot_root,
+    libc::SYS_chroot,
+    libc::SYS_setns,
+    libc::SYS_unshare,
+    libc::SYS_reboot,
+    libc::SYS_kexec_load,
+    libc::SYS_init_module,
+    libc::SYS_finit_module,
+    libc::SYS_delete_module,
+    libc::SYS_swapon,
+    libc::SYS_swapoff,
+    libc::SYS_bpf,
+    libc::SYS_perf_event_open,
+    libc::SYS_keyctl,
+    libc::SYS_add_key,
+    libc::SYS_socket,
+    libc::SYS_io_uring_setup,
+    libc::SYS_open_by_handle_at,
+];
+
+/// Syscalls failed with `ENOSYS` rather than `EPERM`, so the C library falls
+/// back to an older call the filter can see into: `clone3` passes its flags,
+/// namespaces included, in memory seccomp cannot read, and glibc retries
+/// with `clone` only on `ENOSYS`.
+const UNIMPLEMENTED: &[libc::c_long] = &[libc::SYS_clone3];
+
+/// `clone` flags creating namespaces, which would get around the `unshare`
+/// and `setns` denial.
+const CLONE_NAMESPACES: libc::c_int = libc::CLONE_NEWNS
+    | libc::CLONE_NEWCGROUP
+    | libc::CLONE_NEWUTS
+    | libc::CLONE_NEWIPC
+    | libc::CLONE_NEWUSER
+    | libc::CLONE_NEWPID
+    | libc::CLONE_NEWNET;
+
+/// Confines the calling process for the rest of its life: no privilege
+/// regain, root dropped to `nobody`, and a seccomp filter failing `DENIED`
+/// syscalls and `clone` into new namespaces with `EPERM` on every thread.
+pub fn apply() -> Result<(), anyhow::Error> {
+    let Some(arch) = AUDIT_ARCH else {
+        return Err(anyhow::anyhow!(
+            "--sandbox is not supported on {}",
+            std::env::consts::ARCH
+        ));
+    };
+    // SAFETY: prctl with these arguments only sets a flag on this process.
+    if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+        return Err(anyhow::anyhow!(
+            "Failed to set no_new_privs: {}",
+            io::Error::last_os_error()
+        ));
+    }
+    drop_privileges().map_err(|e| anyhow::anyhow!("Failed to drop privileges: {e}"))?;
+    install(&filter(arch)).map_err(|e| anyhow::anyhow!("Failed to install seccomp filter: {e}"))?;
+    log::info!("Sandbox applied.");
+    Ok(())
+}
+
+fn drop_privileges() -> io::Result<()> {
+    // SAFETY: geteuid has no preconditions.
+    if unsafe { libc::geteuid() } != 0 {
+        return Ok(());
+    }
+    crate::privileges::switch_user(NOBODY, NOBODY)
+}
+
+fn statement(code: u32, k: u32) -> libc::sock_filter {
+    jump(code, k, 0, 0)
+}
+
+fn jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    }
+}
+
+/// Appends a check failing `syscall` with `errno`.
+fn deny(program: &mut Vec<libc::sock_filter>, syscall: libc::c_long, errno: i32) {
+    program.push(jump(
+        libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+        syscall as u32,
+        0,
+        1,
+    ));
+    program.push(statement(
+        libc::BPF_RET | libc::BPF_K,
+        libc::SECCOMP_RET_ERRNO | errno as u32,
+    ));
+}
+
+/// The BPF program for syscalls of `audit_arch`: kill on a foreign
+/// architecture or an x32 syscall, `EPERM` for denied syscalls and for
+/// `clone` into new namespaces, `ENOSYS` for unimplemented ones, allow the
+/// rest.
+fn filter(audit_arch: u32) -> Vec<libc::sock_filter> {
+    let arch = std::mem::offset_of!(libc::seccomp_data, arch) as u32;
+    let nr = std::mem::offset_of!(libc::seccomp_data, nr) as u32;
+    // The low half of the first argument on these little-endian targets,
+    // which holds every namespace flag.
+    let flags = std::mem::offset_of!(libc::seccomp_data, args) as u32;
+    let mut program = vec![
+        statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, arch),
+        jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            audit_arch,
+            1,
+            0,
+        ),
+        statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+        statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, nr),
+        // x32 numbers would slip past every check below.
+        jump(
+            libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K,
+            X32_SYSCALL_BIT,
+            0,
+            1,
+        ),
+        statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+    ];
+    for &syscall in DENIED {
+        deny(&mut program, syscall, libc::EPERM);
+    }
+    for &syscall in UNIMPLEMENTED {
+        deny(&mut program, syscall, libc::ENOSYS);
+    }
+    program.extend([
+        jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            libc::SYS_clone as u32,
+            0,
+            3,
+        ),
+        statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, flags),
+        jump(
+            libc::BPF_JMP | libc::BPF_JSET | libc::BPF_K,
+            CLONE_NAMESPACES as u32,
+            0,
+            1,
+        ),
+        statement(
+            libc::BPF_RET | libc::BPF_K,
+            libc::SECCOMP_RET_ERRNO | libc::EPERM as u32,
+        ),
+    ]);
+    program.push(statement(
+        libc::BPF_RET | libc::BPF_K,
+        libc::SECCOMP_RET_ALLOW,
+    ));
+    program
+}
+
+fn install(program: &[libc::sock_filter]) -> io::Result<()> {
+    let prog = libc::sock_fprog {
+        len: program.len() as u16,
+        filter: program.as_ptr() as *mut libc::sock_filter,
+    };
+    // SAFETY: `prog` points at `program`, which outlives the call; the kernel
+    // copies the filter.
+    let installed = unsafe {
+        libc::syscall(
+            libc::SYS_seccomp,
+            libc::SECCOMP_SET_MODE_FILTER,
+            libc::SECCOMP_FILTER_FLAG_TSYNC,
+            &prog as *const libc::sock_fprog,
+        )
+    };
+    match installed {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(all(test, any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_denies_listed_syscalls() {
+        let program = filter(AUDIT_ARCH.unwrap());
+        assert_eq!(
+            program.len(),
+            6 + 2 * (DENIED.len() + UNIMPLEMENTED.len()) + 4 + 1
+        );
+        assert_eq!(program.last().unwrap().k, libc::SECCOMP_RET_ALLOW);
+    }
+
+    #[test]
+    fn installed_filter_fails_denied_syscalls() {
+        let program = filter(AUDIT_ARCH.unwrap());
+        // SAFETY: the child only makes raw syscalls (no allocation) before
+        // `_exit`, which is safe after fork in a threaded process.
+        unsafe {
+            let pid = libc::fork();
+            if pid == 0 {
+                libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0);
+                let denied = install(&program).is_ok()
+                    && libc::unshare(0) == -1
+                    && *libc::__errno_location() == libc::EPERM
+                    && libc::syscall(libc::SYS_clone3, std::ptr::null::<u8>(), 0) == -1
+                    && *libc::__errno_location() == libc::ENOSYS;
+                let flags = (libc::CLONE_NEWUSER | libc::SIGCHLD) as libc::c_ulong;
+                match libc::syscall(libc::SYS_clone, flags, 0, 0, 0, 0) {
+                    0 => libc::_exit(1),
+                    -1 if *libc::__errno_location() == libc::EPERM => {}
+                    _ => libc::_exit(1),
+                }
+                // A plain fork still goes through.
+                let forked = libc::fork();
+                if forked == 0 {
+                    libc::_exit(0);
+                }
+                let mut status = 1;
+                libc::waitpid(forked, &mut status, 0);
+                libc::_exit(if denied && status == 0 { 0 } else { 1 });
+            }
+            let mut status = 0;
+            libc::waitpid(pid, &mut status, 0);
+            assert!(libc::WIFEXITED(status));
+            assert_eq!(libc::WEXITSTATUS(status), 0);
+        }
+    }
+}
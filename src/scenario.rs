@@ -0,0 +1,707 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{CancellationToken, Resource, StressError, events, parse, registry, telemetry};
+
+/// How long a stressor past its limit gets before it is cancelled, and again
+/// before it is abandoned as hung.
+const GRACE: Duration = Duration::from_secs(5);
+
+/// How often a phase checks on its stressors.
+const POLL: Duration = Duration::from_millis(50);
+
+/// Registered stressors that run other stressors rather than load anything
+/// themselves, so they have no place in a phase.
+const ORCHESTRATORS: &[&str] = &["run", "chaos", "replay-trace"];
+
+/// How often `--on-error restart` restarts one failing stressor per phase.
+const MAX_RESTARTS: u32 = 5;
+
+static STOPS: Mutex<Vec<Stop>> = Mutex::new(vec![]);
+static ON_ERROR: OnceLock<OnError> = OnceLock::new();
+
+/// What a phase does when one of its stressors fails.
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub enum OnError {
+    /// Stop the other stressors and the remaining phases.
+    #[default]
+    AbortAll,
+    /// Keep the other stressors and later phases running; fail at the end.
+    Continue,
+    /// Restart the failed stressor for the rest of its time.
+    Restart,
+}
+
+pub fn set_on_error(policy: OnError) {
+    let _ = ON_ERROR.set(policy);
+}
+
+fn on_error() -> OnError {
+    ON_ERROR.get().copied().unwrap_or_default()
+}
+
+/// A scenario file: an ordered list of phases, each running its own set of
+/// stressors for a fixed duration. Any registered stressor can be listed
+/// with its subcommand's arguments; it is stopped when the phase ends.
+///
+/// ```text
+/// # comments and blank lines are ignored
+/// phase "ramp" 2m
+///   memory 512M
+///   thread 2
+/// phase "peak" 10m
+///   memory 2G timeout 5m
+///   thread 8
+///   net --limit 100M/s
+/// # optional: repeat the whole scenario
+/// schedule every 6h times 4
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scenario {
+    pub phases: Vec<Phase>,
+    pub schedule: Schedule,
+}
+
+/// How often a run is repeated.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Schedule {
+    pub every: Option<Duration>,
+    pub times: Option<u32>,
+}
+
+impl Schedule {
+    /// Total number of runs, or `None` to repeat until interrupted.
+    pub fn runs(&self) -> Option<u32> {
+        match (self.every, self.times) {
+            (_, Some(times)) => Some(times),
+            (Some(_), None) => None,
+            (None, None) => Some(1),
+        }
+    }
+
+    pub fn is_recurring(&self) -> bool {
+        self.runs() != Some(1)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Phase {
+    pub name: String,
+    pub duration: Duration,
+    pub stressors: Vec<Resource>,
+    /// Per-stressor time limits shorter than the phase, keyed by index into
+    /// `stressors`.
+    pub timeouts: BTreeMap<usize, Duration>,
+}
+
+/// Why a stressor in a phase stopped.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    /// Finished on its own before any limit.
+    Completed,
+    PhaseEnd,
+    Timeout,
+    Cancelled,
+    /// Ignored cancellation and was abandoned.
+    Hung,
+}
+
+/// How one stressor of a phase ended, for the run report.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Stop {
+    pub phase: String,
+    pub stressor: &'static str,
+    pub index: usize,
+    pub reason: StopReason,
+    pub restarts: u32,
+    pub elapsed_secs: f64,
+}
+
+/// Stops recorded since the last call, for the run's report.
+pub fn take_stops() -> Vec<Stop> {
+    std::mem::take(&mut *STOPS.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+impl Scenario {
+    pub fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read scenario {}: {e}", path.display()))?;
+        Self::parse(&text).map_err(|e| anyhow::anyhow!("Invalid scenario {}: {e}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, anyhow::Error> {
+        let mut phases: Vec<Phase> = vec![];
+        let mut schedule = Schedule::default();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |msg: String| anyhow::anyhow!("line {}: {msg}", number + 1);
+
+            let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let rest = rest.trim();
+            match keyword {
+                "phase" => {
+                    let (name, duration) = parse_phase_header(rest).map_err(error)?;
+                    phases.push(Phase {
+                        name,
+                        duration,
+                        stressors: vec![],
+                        timeouts: BTreeMap::new(),
+                    });
+                }
+                "schedule" => schedule = parse_schedule(rest).map_err(error)?,
+                keyword => {
+                    let (rest, timeout) = split_timeout(rest).map_err(error)?;
+                    let stressor = parse_stressor(keyword, rest).map_err(error)?;
+                    let phase = phases
+                        .last_mut()
+                        .ok_or_else(|| error(format!("'{keyword}' outside of a phase")))?;
+                    if let Some(timeout) = timeout {
+                        phase.timeouts.insert(phase.stressors.len(), timeout);
+                    }
+                    phase.stressors.push(stressor);
+                }
+            }
+        }
+
+        if phases.is_empty() {
+            return Err(anyhow::anyhow!("no phases defined"));
+        }
+        Ok(Scenario { phases, schedule })
+    }
+
+    /// Runs every phase in order. A failing phase stops the scenario unless
+    /// `--on-error continue` is in effect.
+    pub fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        let mut result = Ok(());
+        for (index, phase) in self.phases.iter().enumerate() {
+            if cancel.is_cancelled() {
+                log::info!("Cancelled; skipping remaining phases.");
+                break;
+            }
+            log::info!(
+                "Phase '{}' started ({:.0}s, {} stressors).",
+                phase.name,
+                phase.duration.as_secs_f64(),
+                phase.stressors.len()
+            );
+            events::emit(
+                "phase_started",
+                json!({
+                    "phase": phase.name,
+                    "index": index,
+                    "duration_secs": phase.duration.as_secs_f64(),
+                }),
+            );
+            telemetry::gauge("scenario.phase", "{phase}", index as u64 + 1);
+
+            let span = telemetry::span(format!("phase.{}", phase.name));
+            let outcome = phase.run(cancel);
+            drop(span);
+            if let Err(e) = outcome {
+                if on_error() != OnError::Continue {
+                    return Err(e);
+                }
+                log::error!("{e:#}; continuing with the next phase.");
+                result = Err(e);
+                continue;
+            }
+
+            log::info!("Phase '{}' finished.", phase.name);
+            events::emit(
+                "phase_finished",
+                json!({ "phase": phase.name, "index": index }),
+            );
+        }
+        result
+    }
+}
+
+struct Running {
+    index: usize,
+    resource: Resource,
+    handle: std::thread::JoinHandle<Result<(), anyhow::Error>>,
+    cancel: CancellationToken,
+    deadline: Instant,
+    /// When the stressor was first seen past its limit or cancelled.
+    overdue_since: Option<Instant>,
+    restarts: u32,
+}
+
+impl Running {
+    fn spawn(
+        index: usize,
+        resource: Resource,
+        deadline: Instant,
+        phase: &CancellationToken,
+    ) -> Self {
+        let cancel = phase.child_token();
+        let token = cancel.clone();
+        let stressor = resource.clone();
+        let handle = std::thread::spawn(move || crate::execute(stressor, Some(deadline), &token));
+        Running {
+            index,
+            resource,
+            handle,
+            cancel,
+            deadline,
+            overdue_since: None,
+            restarts: 0,
+        }
+    }
+}
+
+impl Phase {
+    pub fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        self.run_with(cancel, on_error())
+    }
+
+    fn run_with(&self, cancel: &CancellationToken, on_error: OnError) -> Result<(), anyhow::Error> {
+        let started = Instant::now();
+        let deadline = started + self.duration;
+        // Cancelled on its own to abort the whole phase under --on-error
+        // abort-all.
+        let phase = cancel.child_token();
+        let mut running: Vec<Running> = self
+            .stressors
+            .iter()
+            .cloned()
+            .enumerate()
+            .map(|(index, resource)| {
+                let limit = self
+                    .timeouts
+                    .get(&index)
+                    .map_or(deadline, |timeout| deadline.min(started + *timeout));
+                Running::spawn(index, resource, limit, &phase)
+            })
+            .collect();
+
+        let mut result = Ok(());
+        while !running.is_empty() {
+            let now = Instant::now();
+            let mut still_running = vec![];
+            for mut stressor in running {
+                let name = stressor.resource.name();
+                if stressor.handle.is_finished() {
+                    let outcome = stressor.handle.join().unwrap_or_else(|payload| {
+                        Err(anyhow::anyhow!(
+                            "Stressor panicked: {}",
+                            crate::panic_message(&payload)
+                        ))
+                    });
+                    if let Err(e) = &outcome
+                        && on_error == OnError::Restart
+                        && stressor.restarts < MAX_RESTARTS
+                        && now < stressor.deadline
+                        && !phase.is_cancelled()
+                    {
+                        let restarts = stressor.restarts + 1;
+                        log::warn!(
+                            "Stressor {} ({name}) in phase '{}' failed ({e:#}); restart {restarts}/{MAX_RESTARTS}.",
+                            stressor.index,
+                            self.name
+                        );
+                        events::emit(
+                            "stressor_restarted",
+                            json!({ "phase": self.name, "index": stressor.index, "stressor": name, "restarts": restarts }),
+                        );
+                        let mut restarted = Running::spawn(
+                            stressor.index,
+                            stressor.resource,
+                            stressor.deadline,
+                            &phase,
+                        );
+                        restarted.restarts = restarts;
+                        still_running.push(restarted);
+                        continue;
+                    }
+                    let reason = if phase.is_cancelled() {
+                        StopReason::Cancelled
+                    } else if now < stressor.deadline {
+                        StopReason::Completed
+                    } else if stressor.deadline < deadline {
+                        StopReason::Timeout
+                    } else {
+                        StopReason::PhaseEnd
+                    };
+                    self.record(stressor.index, name, stressor.restarts, reason, started);
+                    if let Err(e) = outcome {
+                        if on_error == OnError::AbortAll && !phase.is_cancelled() {
+                            log::error!(
+                                "Stressor {} ({name}) in phase '{}' failed; aborting the others.",
+                                stressor.index,
+                                self.name
+                            );
+                            phase.cancel();
+                        }
+                        result = Err(e.context(format!("Phase '{}' failed", self.name)));
+                    }
+                    continue;
+                }
+
+                if stressor.overdue_since.is_none()
+                    && (now >= stressor.deadline || phase.is_cancelled())
+                {
+                    stressor.overdue_since = Some(now);
+                }
+                match stressor.overdue_since.map(|since| now - since) {
+                    Some(overdue) if overdue >= GRACE * 2 => {
+                        log::error!(
+                            "Stressor {} ({name}) in phase '{}' ignored cancellation; abandoning it.",
+                            stressor.index,
+                            self.name
+                        );
+                        self.record(
+                            stressor.index,
+                            name,
+                            stressor.restarts,
+                            StopReason::Hung,
+                            started,
+                        );
+                        result = Err(anyhow::Error::new(StressError::Hung {
+                            stressor: name,
+                            grace_secs: (GRACE * 2).as_secs(),
+                        })
+                        .context(format!("Phase '{}' failed", self.name)));
+                    }
+                    Some(overdue) => {
+                        if overdue >= GRACE {
+                            stressor.cancel.cancel();
+                        }
+                        still_running.push(stressor);
+                    }
+                    None => still_running.push(stressor),
+                }
+            }
+            running = still_running;
+            if !running.is_empty() {
+                std::thread::sleep(POLL);
+            }
+        }
+
+        // Phases without stressors (or whose stressors finished early) still
+        // last for their full duration.
+        phase.sleep_until(deadline);
+        result
+    }
+
+    fn record(
+        &self,
+        index: usize,
+        name: &'static str,
+        restarts: u32,
+        reason: StopReason,
+        started: Instant,
+    ) {
+        log::info!(
+            "Stressor {index} ({name}) in phase '{}' stopped: {reason:?}.",
+            self.name
+        );
+        events::emit(
+            "stressor_stopped",
+            json!({ "phase": self.name, "index": index, "stressor": name, "reason": reason }),
+        );
+        let stop = Stop {
+            phase: self.name.clone(),
+            stressor: name,
+            index,
+            reason,
+            restarts,
+            elapsed_secs: started.elapsed().as_secs_f64(),
+        };
+        STOPS.lock().unwrap_or_else(|e| e.into_inner()).push(stop);
+    }
+}
+
+/// Splits a trailing `timeout <duration>` off a stressor's arguments.
+fn split_timeout(rest: &str) -> Result<(&str, Option<Duration>), String> {
+    match rest.rsplit_once(" timeout ") {
+        Some((args, timeout)) => Ok((args.trim(), Some(parse::duration(timeout)?))),
+        None => Ok((rest, None)),
+    }
+}
+
+/// A phase's stressor from its line. Memory and thread stressors hold their
+/// load until the phase ends; any other registered stressor is checked
+/// against its arguments now and built when the phase starts.
+fn parse_stressor(keyword: &str, rest: &str) -> Result<Resource, String> {
+    match keyword {
+        "memory" => Ok(Resource::Memory {
+            arg: rest.to_string(),
+            bench: None,
+        }),
+        "thread" => Ok(Resource::Thread {
+            num: rest
+                .parse()
+                .map_err(|e| format!("invalid thread count '{rest}': {e}"))?,
+        }),
+        keyword if ORCHESTRATORS.contains(&keyword) => {
+            Err(format!("'{keyword}' cannot run inside a scenario"))
+        }
+        keyword => {
+            let name = registry::registry()
+                .into_iter()
+                .map(|registration| registration.name)
+                .find(|name| *name == keyword)
+                .ok_or_else(|| format!("unknown directive '{keyword}'"))?;
+            let args: Vec<String> = rest.split_whitespace().map(str::to_string).collect();
+            registry::stressor(name, &args).map_err(|e| e.to_string())?;
+            Ok(Resource::Registered { name, args })
+        }
+    }
+}
+
+fn parse_phase_header(rest: &str) -> Result<(String, Duration), String> {
+    let (name, duration) = if let Some(quoted) = rest.strip_prefix('"') {
+        let (name, after) = quoted
+            .split_once('"')
+            .ok_or_else(|| "unterminated phase name".to_string())?;
+        (name.to_string(), after.trim())
+    } else {
+        let (name, after) = rest
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| "expected: phase \"<name>\" <duration>".to_string())?;
+        (name.to_string(), after.trim())
+    };
+    if name.is_empty() {
+        return Err("phase name must not be empty".to_string());
+    }
+    Ok((name, parse::duration(duration)?))
+}
+
+fn parse_schedule(rest: &str) -> Result<Schedule, String> {
+    let mut schedule = Schedule::default();
+    let mut words = rest.split_whitespace();
+    while let Some(word) = words.next() {
+        let value = words
+            .next()
+            .ok_or_else(|| format!("missing value for '{word}'"))?;
+        match word {
+            "every" => schedule.every = Some(parse::duration(value)?),
+            "times" => {
+                schedule.times = Some(
+                    value
+                        .parse()
+                        .map_err(|e| format!("invalid run count '{value}': {e}"))?,
+                )
+            }
+            other => return Err(format!("unknown schedule option '{other}'")),
+        }
+    }
+    Ok(schedule)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_phases_with_stressors() {
+        let scenario = Scenario::parse(
+            "# capacity test\n\
+             phase \"ramp up\" 2m\n\
+             \x20 memory 512M timeout 30s\n\
+             \x20 thread 2\n\
+             \n\
+             phase peak 10m\n\
+             \x20 thread 8\n",
+        )
+        .unwrap();
+        assert_eq!(
+            scenario.phases,
+            vec![
+                Phase {
+                    name: "ramp up".to_string(),
+                    duration: Duration::from_secs(120),
+                    stressors: vec![
+                        Resource::Memory {
+                            arg: "512M".to_string(),
+                            bench: None,
+                        },
+                        Resource::Thread { num: 2 },
+                    ],
+                    timeouts: BTreeMap::from([(0, Duration::from_secs(30))]),
+                },
+                Phase {
+                    name: "peak".to_string(),
+                    duration: Duration::from_secs(600),
+                    stressors: vec![Resource::Thread { num: 8 }],
+                    timeouts: BTreeMap::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_rejects_stressor_outside_phase() {
+        let err = Scenario::parse("thread 2\n").unwrap_err().to_string();
+        assert_eq!(err, "line 1: 'thread' outside of a phase");
+    }
+
+    #[test]
+    fn parse_rejects_unknown_directive_and_bad_duration() {
+        assert!(Scenario::parse("phase a 1m\nfork 3\n").is_err());
+        assert!(Scenario::parse("phase a 1x\n").is_err());
+        assert!(Scenario::parse("phase \"a 1m\n").is_err());
+        assert!(Scenario::parse("# empty\n").is_err());
+    }
+
+    #[test]
+    fn parse_builds_registered_stressors_from_their_arguments() {
+        let scenario = Scenario::parse(
+            "phase a 1m
+  entropy --threads 1 timeout 30s
+",
+        )
+        .unwrap();
+        assert_eq!(
+            scenario.phases[0].stressors,
+            vec![Resource::Registered {
+                name: "entropy",
+                args: vec!["--threads".to_string(), "1".to_string()],
+            }]
+        );
+        assert_eq!(scenario.phases[0].timeouts[&0], Duration::from_secs(30));
+        let err = Scenario::parse(
+            "phase a 1m
+  entropy --threads x
+",
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(
+            err.starts_with("line 2: entropy: invalid value 'x'"),
+            "{err}"
+        );
+        let err = Scenario::parse(
+            "phase a 1m
+  run s.txt
+",
+        )
+        .unwrap_err()
+        .to_string();
+        assert_eq!(err, "line 2: 'run' cannot run inside a scenario");
+    }
+
+    #[test]
+    fn parse_schedule_directive() {
+        let scenario = Scenario::parse("schedule every 6h times 4\nphase a 1m\n").unwrap();
+        assert_eq!(
+            scenario.schedule,
+            Schedule {
+                every: Some(Duration::from_secs(6 * 3600)),
+                times: Some(4),
+            }
+        );
+        assert!(Scenario::parse("schedule every\nphase a 1m\n").is_err());
+        assert!(Scenario::parse("schedule daily 1\nphase a 1m\n").is_err());
+    }
+
+    #[test]
+    fn schedule_run_counts() {
+        assert_eq!(Schedule::default().runs(), Some(1));
+        let every = Some(Duration::from_secs(60));
+        assert_eq!(Schedule { every, times: None }.runs(), None);
+        assert_eq!(
+            Schedule {
+                every,
+                times: Some(3)
+            }
+            .runs(),
+            Some(3)
+        );
+        assert!(!Schedule::default().is_recurring());
+    }
+
+    #[test]
+    fn run_executes_phases_in_order() {
+        let scenario =
+            Scenario::parse("phase one 50ms\n  memory 1K\nphase two 50ms\n  thread 1\n").unwrap();
+        let started = Instant::now();
+        scenario.run(&CancellationToken::new()).unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn phase_reports_which_limit_stopped_each_stressor() {
+        let scenario =
+            Scenario::parse("phase limits 300ms\n  memory 1K timeout 50ms\n  memory 1K\n").unwrap();
+        scenario.run(&CancellationToken::new()).unwrap();
+        let reasons: Vec<_> = take_stops()
+            .into_iter()
+            .filter(|stop| stop.phase == "limits")
+            .map(|stop| (stop.index, stop.reason))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![(0, StopReason::Timeout), (1, StopReason::PhaseEnd)]
+        );
+    }
+
+    #[test]
+    fn registered_stressors_stop_with_their_phase() {
+        let scenario = Scenario::parse(
+            "phase mixed 300ms
+               entropy --threads 1 --duration 1h
+               clocks --threads 1 --duration 50ms
+",
+        )
+        .unwrap();
+        let started = Instant::now();
+        scenario.run(&CancellationToken::new()).unwrap();
+        assert!(started.elapsed() < GRACE);
+        let reasons: Vec<_> = take_stops()
+            .into_iter()
+            .filter(|stop| stop.phase == "mixed")
+            .map(|stop| (stop.index, stop.stressor, stop.reason))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                (1, "clocks", StopReason::Completed),
+                (0, "entropy", StopReason::PhaseEnd)
+            ]
+        );
+    }
+
+    #[test]
+    fn failing_stressor_aborts_or_restarts_per_policy() {
+        let scenario = Scenario::parse("phase abort 10s\n  memory 0B\n  memory 1K\n").unwrap();
+        let started = Instant::now();
+        assert!(
+            scenario.phases[0]
+                .run_with(&CancellationToken::new(), OnError::AbortAll)
+                .is_err()
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let scenario = Scenario::parse("phase restart 1s\n  memory 0B\n").unwrap();
+        assert!(
+            scenario.phases[0]
+                .run_with(&CancellationToken::new(), OnError::Restart)
+                .is_err()
+        );
+
+        let stops: Vec<_> = take_stops()
+            .into_iter()
+            .filter(|stop| stop.phase == "abort" || stop.phase == "restart")
+            .map(|stop| (stop.phase, stop.index, stop.reason, stop.restarts))
+            .collect();
+        assert!(stops.contains(&("abort".to_string(), 1, StopReason::Cancelled, 0)));
+        assert!(stops.contains(&(
+            "restart".to_string(),
+            0,
+            StopReason::Completed,
+            MAX_RESTARTS
+        )));
+    }
+}
@@ -0,0 +1,275 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::caps::{self, Privilege};
+
+/// A scheduling class for the stressor process, as given to `--sched`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Policy {
+    /// `SCHED_FIFO` at a realtime priority (1-99).
+    Fifo(i32),
+    /// `SCHED_RR` at a realtime priority (1-99).
+    Rr(i32),
+    Batch,
+    Idle,
+}
+
+impl Policy {
+    fn raw(self) -> (libc::c_int, libc::c_int) {
+        match self {
+            Policy::Fifo(priority) => (libc::SCHED_FIFO, priority),
+            Policy::Rr(priority) => (libc::SCHED_RR, priority),
+            Policy::Batch => (libc::SCHED_BATCH, 0),
+            Policy::Idle => (libc::SCHED_IDLE, 0),
+        }
+    }
+
+    fn is_realtime(self) -> bool {
+        matches!(self, Policy::Fifo(_) | Policy::Rr(_))
+    }
+
+    /// The realtime priority, for the realtime policies.
+    pub fn realtime_priority(self) -> Option<i32> {
+        self.is_realtime().then_some(self.raw().1)
+    }
+
+    /// Whether this process may switch to the policy: a realtime priority
+    /// needs `CAP_SYS_NICE` unless `RLIMIT_RTPRIO` reaches it. When it may
+    /// not, `feature` is recorded as skipped.
+    pub fn permitted(self, feature: &str) -> bool {
+        let (_, priority) = self.raw();
+        !self.is_realtime()
+            || rtprio_limit() >= priority as u64
+            || caps::require(feature, Privilege::SysNice)
+    }
+}
+
+/// The soft `RLIMIT_RTPRIO`: the highest realtime priority allowed without
+/// `CAP_SYS_NICE`.
+fn rtprio_limit() -> u64 {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: `limit` is a valid out-pointer for the duration of the call.
+    match unsafe { libc::getrlimit(libc::RLIMIT_RTPRIO, &mut limit) } {
+        0 => limit.rlim_cur,
+        _ => 0,
+    }
+}
+
+impl FromStr for Policy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, priority) = match s.split_once(':') {
+            Some((name, priority)) => (name, Some(priority)),
+            None => (s, None),
+        };
+        let realtime = |priority: Option<&str>| {
+            let priority: i32 = priority
+                .ok_or_else(|| format!("{name} needs a priority, e.g. {name}:50"))?
+                .parse()
+                .map_err(|_| format!("invalid priority in '{s}'"))?;
+            match priority {
+                1..=99 => Ok(priority),
+                _ => Err(format!("realtime priority must be 1-99, got {priority}")),
+            }
+        };
+        match (name, priority) {
+            ("fifo", p) => Ok(Policy::Fifo(realtime(p)?)),
+            ("rr", p) => Ok(Policy::Rr(realtime(p)?)),
+            ("batch", None) => Ok(Policy::Batch),
+            ("idle", None) => Ok(Policy::Idle),
+            ("batch" | "idle", Some(_)) => Err(format!("{name} takes no priority")),
+            _ => Err(format!(
+                "unknown scheduling policy '{name}' (use fifo:N, rr:N, batch or idle)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Policy::Fifo(priority) => write!(f, "fifo:{priority}"),
+            Policy::Rr(priority) => write!(f, "rr:{priority}"),
+            Policy::Batch => write!(f, "batch"),
+            Policy::Idle => write!(f, "idle"),
+        }
+    }
+}
+
+/// Switches the calling thread, and so every thread it spawns afterwards, to
+/// `policy`. Without `CAP_SYS_NICE` or a high enough `RLIMIT_RTPRIO`, a
+/// realtime policy fails with a warning and the run keeps the default class.
+pub fn apply(policy: Policy) -> Result<(), anyhow::Error> {
+    let Err(e) = set_current(policy) else {
+        log::info!("Scheduling policy set to {policy}.");
+        return Ok(());
+    };
+    let (_, priority) = policy.raw();
+    if e.raw_os_error() == Some(libc::EPERM) && policy.is_realtime() {
+        log::warn!(
+            "Cannot use {policy} without CAP_SYS_NICE or RLIMIT_RTPRIO >= {priority}; \
+             running with the default scheduling policy."
+        );
+        return Ok(());
+    }
+    Err(anyhow::anyhow!(
+        "Failed to set scheduling policy {policy}: {e}"
+    ))
+}
+
+/// Switches only the calling thread to `policy`, without fallback.
+pub fn set_current(policy: Policy) -> std::io::Result<()> {
+    let (raw, priority) = policy.raw();
+    let param = libc::sched_param {
+        sched_priority: priority,
+    };
+    // SAFETY: `param` is a valid sched_param for the duration of the call.
+    match unsafe { libc::sched_setscheduler(0, raw, &param) } {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+/// An I/O scheduling class, as given to `--io-class`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IoClass {
+    /// `IOPRIO_CLASS_RT` at a level (0-7, 0 first).
+    Realtime(u8),
+    /// `IOPRIO_CLASS_BE` at a level (0-7, 0 first).
+    BestEffort(u8),
+    Idle,
+}
+
+const IOPRIO_CLASS_SHIFT: u32 = 13;
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+
+impl IoClass {
+    fn raw(self) -> libc::c_int {
+        let (class, level) = match self {
+            IoClass::Realtime(level) => (1, level),
+            IoClass::BestEffort(level) => (2, level),
+            IoClass::Idle => (3, 0),
+        };
+        class << IOPRIO_CLASS_SHIFT | level as libc::c_int
+    }
+}
+
+impl FromStr for IoClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, level) = match s.split_once(':') {
+            Some((name, level)) => (name, Some(level)),
+            None => (s, None),
+        };
+        let parse_level = |level: Option<&str>| {
+            let Some(level) = level else {
+                return Ok(4);
+            };
+            match level.parse() {
+                Ok(level @ 0..=7) => Ok(level),
+                _ => Err(format!("I/O priority level must be 0-7, got '{level}'")),
+            }
+        };
+        match (name, level) {
+            ("rt", l) => Ok(IoClass::Realtime(parse_level(l)?)),
+            ("best-effort", l) => Ok(IoClass::BestEffort(parse_level(l)?)),
+            ("idle", None) => Ok(IoClass::Idle),
+            ("idle", Some(_)) => Err("idle takes no level".to_string()),
+            _ => Err(format!(
+                "unknown I/O class '{name}' (use idle, best-effort[:N] or rt[:N])"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for IoClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IoClass::Realtime(level) => write!(f, "rt:{level}"),
+            IoClass::BestEffort(level) => write!(f, "best-effort:{level}"),
+            IoClass::Idle => write!(f, "idle"),
+        }
+    }
+}
+
+/// Switches the calling thread, and so every thread it spawns afterwards, to
+/// I/O class `class`. The realtime class needs `CAP_SYS_ADMIN`.
+pub fn set_io_class(class: IoClass) -> std::io::Result<()> {
+    // SAFETY: plain syscall on the calling thread.
+    match unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, class.raw()) } {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+/// The calling thread's effective I/O class. Threads never given one get
+/// best-effort at a level derived from their nice value.
+pub fn io_class() -> std::io::Result<IoClass> {
+    // SAFETY: plain syscalls on the calling thread.
+    let raw = unsafe { libc::syscall(libc::SYS_ioprio_get, IOPRIO_WHO_PROCESS, 0) };
+    if raw == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let level = (raw & 7) as u8;
+    Ok(match raw >> IOPRIO_CLASS_SHIFT {
+        1 => IoClass::Realtime(level),
+        3 => IoClass::Idle,
+        2 => IoClass::BestEffort(level),
+        _ => {
+            // SAFETY: as above; errno is not checked, -1 being a valid nice.
+            let nice = unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) };
+            IoClass::BestEffort(((nice + 20) / 5).clamp(0, 7) as u8)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_policies() {
+        assert_eq!("fifo:50".parse(), Ok(Policy::Fifo(50)));
+        assert_eq!("rr:10".parse(), Ok(Policy::Rr(10)));
+        assert_eq!("batch".parse(), Ok(Policy::Batch));
+        assert_eq!("idle".parse(), Ok(Policy::Idle));
+        for invalid in ["fifo", "rr:0", "fifo:100", "batch:1", "deadline", "rr:x"] {
+            assert!(invalid.parse::<Policy>().is_err(), "{invalid}");
+        }
+        assert_eq!(Policy::Fifo(50).to_string(), "fifo:50");
+    }
+
+    #[test]
+    fn parses_and_applies_io_classes() {
+        assert_eq!("idle".parse(), Ok(IoClass::Idle));
+        assert_eq!("best-effort".parse(), Ok(IoClass::BestEffort(4)));
+        assert_eq!("best-effort:0".parse(), Ok(IoClass::BestEffort(0)));
+        assert_eq!("rt:7".parse(), Ok(IoClass::Realtime(7)));
+        for invalid in ["idle:1", "rt:8", "best-effort:x", "cfq"] {
+            assert!(invalid.parse::<IoClass>().is_err(), "{invalid}");
+        }
+        assert_eq!(IoClass::BestEffort(2).to_string(), "best-effort:2");
+        std::thread::spawn(|| {
+            set_io_class(IoClass::Idle).unwrap();
+            assert_eq!(io_class().unwrap(), IoClass::Idle);
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn batch_applies_to_a_thread() {
+        std::thread::spawn(|| {
+            apply(Policy::Batch).unwrap();
+            // SAFETY: querying the calling thread's policy has no preconditions.
+            assert_eq!(unsafe { libc::sched_getscheduler(0) }, libc::SCHED_BATCH);
+        })
+        .join()
+        .unwrap();
+    }
+}
@@ -0,0 +1,92 @@
+use std::path::Path;
+use std::sync::OnceLock;
+
+use rhai::{AST, Dynamic, Engine, Scope};
+
+use crate::StressError;
+
+static WORKLOAD: OnceLock<Workload> = OnceLock::new();
+
+/// A user-defined thread workload: a Rhai script defining `fn workload(id)`,
+/// which the harness calls in a loop on every stressor thread with the thread
+/// index. An integer return value is checked for consistency across threads
+/// like the built-in kernel's.
+pub struct Workload {
+    ast: AST,
+}
+
+impl Workload {
+    pub fn compile(source: &str) -> Result<Self, anyhow::Error> {
+        let ast = Engine::new().compile(source)?;
+        if !ast
+            .iter_functions()
+            .any(|f| f.name == "workload" && f.params.len() == 1)
+        {
+            return Err(anyhow::anyhow!("script must define fn workload(id)"));
+        }
+        Ok(Workload { ast })
+    }
+
+    pub fn runner(&self) -> Runner<'_> {
+        Runner {
+            engine: Engine::new(),
+            scope: Scope::new(),
+            ast: &self.ast,
+        }
+    }
+}
+
+/// Per-thread evaluation state for a `Workload`.
+pub struct Runner<'a> {
+    engine: Engine,
+    scope: Scope<'static>,
+    ast: &'a AST,
+}
+
+impl Runner<'_> {
+    /// Runs one iteration of the workload on `thread`.
+    pub fn iterate(&mut self, thread: u32) -> Result<u32, StressError> {
+        let result: Dynamic = self
+            .engine
+            .call_fn(&mut self.scope, self.ast, "workload", (thread as i64,))
+            .map_err(|e| StressError::WorkloadFailed(format!("script: {e}")))?;
+        Ok(result.as_int().map_or(0, |n| n as u32))
+    }
+}
+
+/// Loads the `--workload-script` used by thread stressors in place of the
+/// built-in Fibonacci kernel.
+pub fn load(path: &Path) -> Result<(), anyhow::Error> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read script {}: {e}", path.display()))?;
+    let workload = Workload::compile(&source)
+        .map_err(|e| anyhow::anyhow!("Invalid script {}: {e}", path.display()))?;
+    log::info!("Loaded workload script {}.", path.display());
+    let _ = WORKLOAD.set(workload);
+    Ok(())
+}
+
+pub fn loaded() -> Option<&'static Workload> {
+    WORKLOAD.get()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_workload_function() {
+        let workload =
+            Workload::compile("fn workload(id) { let s = 0; for i in 0..10 { s += i; } s + id }")
+                .unwrap();
+        let mut runner = workload.runner();
+        assert_eq!(runner.iterate(0).unwrap(), 45);
+        assert_eq!(runner.iterate(5).unwrap(), 50);
+    }
+
+    #[test]
+    fn rejects_script_without_workload() {
+        assert!(Workload::compile("fn setup() { 1 }").is_err());
+        assert!(Workload::compile("fn workload( {").is_err());
+    }
+}
@@ -0,0 +1,286 @@
+//! `itsmine selftest`: runs every registered stressor briefly with tiny
+//! parameters and checks the side effects the rest of the tool relies on,
+//! for validating a new platform port.
+
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+use serde_json::json;
+
+use crate::registry::{self, Registration};
+use crate::{CancellationToken, buffer, report};
+
+const SCENARIO: &str = "phase selftest 200ms\n  memory 1M\n  thread 1\n";
+const TRACE: &str = "seconds,cpu,mem\n0,10,1M\n1,10,1M\n";
+
+pub enum Verdict {
+    Pass,
+    Fail(anyhow::Error),
+    /// The stressor has no self-test parameters.
+    Skip,
+}
+
+pub struct Check {
+    pub name: String,
+    pub verdict: Verdict,
+    pub elapsed: Duration,
+}
+
+/// Runs the self-test, returning one check per stressor plus the buffer and
+/// report-file checks.
+pub fn run(cancel: &CancellationToken) -> Result<Vec<Check>, anyhow::Error> {
+    let dir = std::env::temp_dir().join(format!("itsmine-selftest-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| anyhow::anyhow!("Failed to create {}: {e}", dir.display()))?;
+    std::fs::write(dir.join("scenario.txt"), SCENARIO)?;
+    std::fs::write(dir.join("trace.csv"), TRACE)?;
+
+    let mut checks = vec![
+        check("buffer", || verify_buffer(1024 * 1024)),
+        check("report-file", || verify_report_file(&dir)),
+    ];
+    for registration in registry::registry() {
+        let checked = match tiny_args(registration.name, &dir) {
+            Some(args) => check(registration.name, || {
+                run_stressor(&registration, &args, cancel)
+            }),
+            None => Check {
+                name: registration.name.to_string(),
+                verdict: Verdict::Skip,
+                elapsed: Duration::ZERO,
+            },
+        };
+        checks.push(checked);
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+    Ok(checks)
+}
+
+fn check(name: &str, f: impl FnOnce() -> Result<(), anyhow::Error>) -> Check {
+    let started = Instant::now();
+    let verdict = match f() {
+        Ok(()) => Verdict::Pass,
+        Err(e) => Verdict::Fail(e),
+    };
+    Check {
+        name: name.to_string(),
+        verdict,
+        elapsed: started.elapsed(),
+    }
+}
+
+/// Arguments small enough to finish within a second or so.
+fn tiny_args(name: &str, dir: &Path) -> Option<Vec<String>> {
+    let path = |file: &str| dir.join(file).display().to_string();
+    let args: Vec<String> = match name {
+        "memory" => vec!["1M".into()],
+        "thread" => vec!["2".into()],
+        "run" => vec![path("scenario.txt")],
+        "chaos" => ["--budget", "cpu=10%,mem=1M", "--duration", "1s"]
+            .map(String::from)
+            .to_vec(),
+        "replay-trace" => vec![path("trace.csv")],
+        "ng" => ["--cpu", "1", "--vm", "1", "--vm-bytes", "1m", "-t", "200ms"]
+            .map(String::from)
+            .to_vec(),
+        "priority-inversion" => ["--duration", "200ms", "--hold", "1ms"]
+            .map(String::from)
+            .to_vec(),
+        "interference" => ["--duration", "200ms", "--aggressor-bytes", "1M"]
+            .map(String::from)
+            .to_vec(),
+        "bank-conflict" => ["--duration", "200ms", "--bytes", "4M"]
+            .map(String::from)
+            .to_vec(),
+        "watches" => vec![
+            "--count".to_string(),
+            "100".to_string(),
+            "--duration".to_string(),
+            "200ms".to_string(),
+            format!("--dir={}", dir.display()),
+        ],
+        "mq" => ["--duration", "200ms", "--message-bytes", "64"]
+            .map(String::from)
+            .to_vec(),
+        "sem" => ["--duration", "200ms", "--threads", "2"]
+            .map(String::from)
+            .to_vec(),
+        "entropy" => ["--duration", "200ms", "--threads", "2"]
+            .map(String::from)
+            .to_vec(),
+        "clocks" => ["--duration", "200ms", "--threads", "2"]
+            .map(String::from)
+            .to_vec(),
+        "udp-flood" => ["--duration", "200ms", "--pairs", "1"]
+            .map(String::from)
+            .to_vec(),
+        "net" => ["--duration", "200ms", "--limit", "10M/s"]
+            .map(String::from)
+            .to_vec(),
+        "dns" => [
+            "--resolver",
+            "127.0.0.1:9",
+            "--rate",
+            "50/s",
+            "--timeout",
+            "100ms",
+            "--duration",
+            "200ms",
+        ]
+        .map(String::from)
+        .to_vec(),
+        "http" => [
+            "http://127.0.0.1:9/",
+            "--rate",
+            "50/s",
+            "--timeout",
+            "100ms",
+            "--duration",
+            "200ms",
+        ]
+        .map(String::from)
+        .to_vec(),
+        "disk" => vec![
+            "--allow-tmpfs".to_string(),
+            "--mode".to_string(),
+            "sparse".to_string(),
+            "--size".to_string(),
+            "1G".to_string(),
+            "--block".to_string(),
+            "64K".to_string(),
+            "--duration".to_string(),
+            "200ms".to_string(),
+            format!("--path={}", dir.display()),
+        ],
+        "links" => vec![
+            "--depth".to_string(),
+            "4".to_string(),
+            "--hardlinks".to_string(),
+            "10".to_string(),
+            "--duration".to_string(),
+            "200ms".to_string(),
+            format!("--dir={}", dir.display()),
+        ],
+        "locks" => vec![
+            "--processes".to_string(),
+            "2".to_string(),
+            "--duration".to_string(),
+            "200ms".to_string(),
+            format!("--dir={}", dir.display()),
+        ],
+        "pty" => ["--count", "4", "--duration", "200ms"]
+            .map(String::from)
+            .to_vec(),
+        "exec-churn" => ["--rate", "50/s", "--duration", "200ms"]
+            .map(String::from)
+            .to_vec(),
+        "fork-rate" => ["--rate", "200/s", "--max-live", "8", "--duration", "200ms"]
+            .map(String::from)
+            .to_vec(),
+        "zombies" => ["--count", "4", "--duration", "200ms"]
+            .map(String::from)
+            .to_vec(),
+        "balloon" => [
+            "--listen",
+            "127.0.0.1:0",
+            "--initial",
+            "1M",
+            "--duration",
+            "200ms",
+        ]
+        .map(String::from)
+        .to_vec(),
+        "address-space" => ["--duration", "200ms", "--bytes", "1T"]
+            .map(String::from)
+            .to_vec(),
+        "mmap-churn" => ["--duration", "200ms", "--threads", "2", "--regions", "16"]
+            .map(String::from)
+            .to_vec(),
+        "tlb" => [
+            "--duration",
+            "200ms",
+            "--span",
+            "1G",
+            "--pages",
+            "64",
+            "--huge-pages",
+            "2",
+        ]
+        .map(String::from)
+        .to_vec(),
+        _ => return None,
+    };
+    Some(args)
+}
+
+fn run_stressor(
+    registration: &Registration,
+    args: &[String],
+    cancel: &CancellationToken,
+) -> Result<(), anyhow::Error> {
+    let matches = clap::Command::new("itsmine")
+        .subcommand(registration.command.clone())
+        .try_get_matches_from(
+            ["itsmine", registration.name]
+                .into_iter()
+                .map(String::from)
+                .chain(args.iter().cloned()),
+        )?;
+    let stressor = registration.build(&matches)?;
+    registry::run(stressor.as_ref(), cancel)
+}
+
+/// The memory stressor's buffer must really hold and touch every byte.
+fn verify_buffer(size: usize) -> Result<(), anyhow::Error> {
+    let mut buffer = buffer::Buffer::allocate(size)?;
+    buffer.touch_to(size);
+    match (buffer.size(), buffer.touched()) {
+        (s, t) if s == size && t == size => Ok(()),
+        (s, t) => Err(anyhow::anyhow!(
+            "buffer of {size} bytes reports size {s}, touched {t}"
+        )),
+    }
+}
+
+/// Reports must round-trip through the filesystem intact.
+fn verify_report_file(dir: &Path) -> Result<(), anyhow::Error> {
+    let written = report::Report::new(
+        "selftest",
+        json!({ "check": "report-file" }),
+        SystemTime::now(),
+        &Ok(()),
+    );
+    let path = dir.join("report.json");
+    std::fs::write(&path, written.to_json())?;
+    let read: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+    if read["stressor"] != "selftest" || read["parameters"]["check"] != "report-file" {
+        return Err(anyhow::anyhow!("{} read back differently", path.display()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_builtin_has_tiny_args() {
+        let dir = Path::new("/tmp");
+        for registration in registry::registry() {
+            assert!(
+                tiny_args(registration.name, dir).is_some(),
+                "{} has no self-test",
+                registration.name
+            );
+        }
+    }
+
+    #[test]
+    fn side_effect_checks_pass() {
+        verify_buffer(4096).unwrap();
+        let dir = std::env::temp_dir().join("itsmine-selftest-report");
+        std::fs::create_dir_all(&dir).unwrap();
+        verify_report_file(&dir).unwrap();
+    }
+}
@@ -0,0 +1,212 @@
+//! `itsmine sem`: threads hammer one POSIX or System V semaphore, waiting
+//! and posting as fast as they can, to load the kernel's semaphore paths.
+
+use std::cell::UnsafeCell;
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+use crate::{CancellationToken, StressError, Stressor, parse, report};
+
+/// Wait/post pairs between deadline checks.
+const BATCH: u64 = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Api {
+    Posix,
+    Sysv,
+}
+
+impl Api {
+    pub fn name(self) -> &'static str {
+        match self {
+            Api::Posix => "posix",
+            Api::Sysv => "sysv",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, clap::Args)]
+pub struct Args {
+    /// Threads contending for the semaphore
+    #[arg(long, value_name = "N", default_value_t = 4)]
+    pub threads: u32,
+    /// Semaphore API to use
+    #[arg(long, value_enum, default_value = "posix")]
+    pub api: Api,
+    /// Initial count, i.e. how many threads may hold it at once
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    pub value: u32,
+    /// How long to run, e.g. 10s
+    #[arg(long, value_parser = parse::duration, default_value = "10s")]
+    pub duration: Duration,
+}
+
+enum Semaphore {
+    /// Boxed, since a `sem_t` must not move once initialized.
+    Posix(Box<UnsafeCell<libc::sem_t>>),
+    Sysv(libc::c_int),
+}
+
+// SAFETY: semaphores exist to be shared between threads; every access goes
+// through the kernel or glibc's atomic operations.
+unsafe impl Sync for Semaphore {}
+
+fn check(result: libc::c_int) -> std::io::Result<()> {
+    match result {
+        -1 => Err(std::io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+impl Semaphore {
+    fn new(api: Api, value: u32) -> std::io::Result<Self> {
+        // SAFETY: plain calls on a semaphore this function creates; the
+        // `sem_t` is initialized before use and never moves.
+        unsafe {
+            match api {
+                Api::Posix => {
+                    let sem = Box::new(UnsafeCell::new(std::mem::zeroed()));
+                    check(libc::sem_init(sem.get(), 0, value))?;
+                    Ok(Semaphore::Posix(sem))
+                }
+                Api::Sysv => {
+                    let id = libc::semget(libc::IPC_PRIVATE, 1, libc::IPC_CREAT | 0o600);
+                    check(id)?;
+                    let sem = Semaphore::Sysv(id);
+                    check(libc::semctl(id, 0, libc::SETVAL, value as libc::c_int))?;
+                    Ok(sem)
+                }
+            }
+        }
+    }
+
+    fn adjust(&self, by: libc::c_short) -> std::io::Result<()> {
+        // SAFETY: the semaphore is initialized and alive for `&self`.
+        unsafe {
+            match self {
+                Semaphore::Posix(sem) if by < 0 => check(libc::sem_wait(sem.get())),
+                Semaphore::Posix(sem) => check(libc::sem_post(sem.get())),
+                Semaphore::Sysv(id) => {
+                    let mut op = libc::sembuf {
+                        sem_num: 0,
+                        sem_op: by,
+                        sem_flg: 0,
+                    };
+                    check(libc::semop(*id, &mut op, 1))
+                }
+            }
+        }
+    }
+
+    fn wait(&self) -> std::io::Result<()> {
+        self.adjust(-1)
+    }
+
+    fn post(&self) -> std::io::Result<()> {
+        self.adjust(1)
+    }
+}
+
+impl Drop for Semaphore {
+    fn drop(&mut self) {
+        // SAFETY: no thread uses the semaphore any more.
+        unsafe {
+            match self {
+                Semaphore::Posix(sem) => libc::sem_destroy(sem.get()),
+                Semaphore::Sysv(id) => libc::semctl(*id, 0, libc::IPC_RMID),
+            };
+        }
+    }
+}
+
+/// Waits on and posts `sem` until `deadline`, returning the pairs done.
+fn hammer(sem: &Semaphore, deadline: Instant, cancel: &CancellationToken) -> std::io::Result<u64> {
+    let mut pairs = 0;
+    while Instant::now() < deadline && !cancel.is_cancelled() {
+        for _ in 0..BATCH {
+            sem.wait()?;
+            sem.post()?;
+        }
+        pairs += BATCH;
+    }
+    Ok(pairs)
+}
+
+impl Stressor for Args {
+    fn name(&self) -> &'static str {
+        "sem"
+    }
+
+    fn params(&self) -> Value {
+        json!({
+            "threads": self.threads,
+            "api": self.api.name(),
+            "value": self.value,
+            "duration_secs": self.duration.as_secs_f64(),
+        })
+    }
+
+    fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        if self.threads == 0 || self.value == 0 {
+            return Err(StressError::InvalidInput(
+                "--threads and --value must be greater than 0".to_string(),
+            )
+            .into());
+        }
+        let sem = Semaphore::new(self.api, self.value).map_err(|e| {
+            anyhow::anyhow!("Failed to create a {} semaphore: {e}", self.api.name())
+        })?;
+        log::info!(
+            "Hammering a {} semaphore from {} threads for {:?}.",
+            self.api.name(),
+            self.threads,
+            self.duration
+        );
+        let started = Instant::now();
+        let deadline = started + self.duration;
+        let pairs = std::thread::scope(|s| {
+            let handles: Vec<_> = (0..self.threads)
+                .map(|_| s.spawn(|| hammer(&sem, deadline, cancel)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    let pairs = handle.join().map_err(|payload| {
+                        StressError::WorkerPanicked(crate::panic_message(&payload))
+                    })?;
+                    pairs.map_err(|e| StressError::WorkloadFailed(format!("semaphore: {e}")))
+                })
+                .sum::<Result<u64, StressError>>()
+        })?;
+        let summary = json!({
+            "operations": pairs * 2,
+            "operations_per_sec": (pairs * 2) as f64 / started.elapsed().as_secs_f64(),
+        });
+        log::info!("Semaphores: {summary}.");
+        report::measure(self.name(), summary);
+        match cancel.is_cancelled() {
+            true => Err(StressError::Interrupted.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hammers_both_apis() {
+        for api in [Api::Posix, Api::Sysv] {
+            let sem = Semaphore::new(api, 1).unwrap();
+            let deadline = Instant::now() + Duration::from_millis(20);
+            let pairs = std::thread::scope(|s| {
+                let other = s.spawn(|| hammer(&sem, deadline, &CancellationToken::new()));
+                hammer(&sem, deadline, &CancellationToken::new()).unwrap()
+                    + other.join().unwrap().unwrap()
+            });
+            assert!(pairs >= 2 * BATCH, "{api:?}");
+        }
+    }
+}
@@ -0,0 +1,366 @@
+//! Small statistics helpers for comparing runs: least-squares fits and the
+//! distributions needed to turn test statistics into p-values.
+
+use std::time::Duration;
+
+use crate::chaos::Rng;
+
+pub fn mean(xs: &[f64]) -> f64 {
+    xs.iter().sum::<f64>() / xs.len() as f64
+}
+
+/// Sample variance (n - 1 denominator).
+pub fn variance(xs: &[f64]) -> f64 {
+    let m = mean(xs);
+    xs.iter().map(|x| (x - m).powi(2)).sum::<f64>() / (xs.len() as f64 - 1.0)
+}
+
+/// Latency samples kept per thread and operation; beyond it, a uniform
+/// sample of all of them.
+pub(crate) const MAX_SAMPLES: usize = 100_000;
+
+/// Operation latencies in nanoseconds, reservoir-sampled once full.
+pub(crate) struct Samples {
+    pub(crate) kept: Vec<f64>,
+    pub(crate) seen: u64,
+}
+
+impl Samples {
+    pub(crate) fn new() -> Self {
+        Samples {
+            kept: vec![],
+            seen: 0,
+        }
+    }
+
+    pub(crate) fn record(&mut self, elapsed: Duration, rng: &mut Rng) {
+        let ns = elapsed.as_nanos() as f64;
+        self.seen += 1;
+        if self.kept.len() < MAX_SAMPLES {
+            self.kept.push(ns);
+            return;
+        }
+        let slot = rng.up_to(self.seen - 1) as usize;
+        if slot < MAX_SAMPLES {
+            self.kept[slot] = ns;
+        }
+    }
+}
+
+/// The `p` quantile (0-1) of ascending `sorted` by nearest rank; NaN when
+/// empty.
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    match sorted.len() {
+        0 => f64::NAN,
+        n => sorted[((n as f64 * p) as usize).min(n - 1)],
+    }
+}
+
+/// Half-width of the two-sided `confidence` interval of the mean of `xs`,
+/// from Student's t distribution. Needs at least two samples.
+pub fn mean_ci(xs: &[f64], confidence: f64) -> Option<f64> {
+    let n = xs.len();
+    if n < 2 {
+        return None;
+    }
+    let t = student_t_quantile(1.0 - confidence, n as f64 - 1.0);
+    Some(t * (variance(xs) / n as f64).sqrt())
+}
+
+/// Welch's t-test: two-sided p-value of the means of `a` and `b` differing,
+/// without assuming equal variances.
+pub fn welch_t_test(a: &[f64], b: &[f64]) -> Option<f64> {
+    if a.len() < 2 || b.len() < 2 {
+        return None;
+    }
+    let (va, vb) = (variance(a) / a.len() as f64, variance(b) / b.len() as f64);
+    let difference = mean(b) - mean(a);
+    if va + vb == 0.0 {
+        return Some(if difference == 0.0 { 1.0 } else { 0.0 });
+    }
+    let t = difference / (va + vb).sqrt();
+    let df = (va + vb).powi(2)
+        / (va.powi(2) / (a.len() as f64 - 1.0) + vb.powi(2) / (b.len() as f64 - 1.0));
+    Some(student_t_p(t, df))
+}
+
+/// Mann-Whitney U test: two-sided p-value of `a` and `b` coming from
+/// different distributions, by the normal approximation with tie and
+/// continuity corrections. Makes no assumption about the shape of the
+/// distributions, so one outlier run cannot swing it.
+pub fn mann_whitney_p(a: &[f64], b: &[f64]) -> Option<f64> {
+    if a.is_empty() || b.is_empty() {
+        return None;
+    }
+    let mut all: Vec<(f64, bool)> = a
+        .iter()
+        .map(|&x| (x, true))
+        .chain(b.iter().map(|&x| (x, false)))
+        .collect();
+    all.sort_by(|x, y| x.0.total_cmp(&y.0));
+    let (n1, n2) = (a.len() as f64, b.len() as f64);
+    let n = n1 + n2;
+    let (mut rank_sum_a, mut ties) = (0.0, 0.0);
+    let mut i = 0;
+    while i < all.len() {
+        let mut j = i;
+        while j + 1 < all.len() && all[j + 1].0 == all[i].0 {
+            j += 1;
+        }
+        // Tied values share the average of their ranks.
+        let rank = (i + j) as f64 / 2.0 + 1.0;
+        let tied = (j - i + 1) as f64;
+        ties += tied.powi(3) - tied;
+        rank_sum_a += rank * all[i..=j].iter().filter(|(_, in_a)| *in_a).count() as f64;
+        i = j + 1;
+    }
+    let u = rank_sum_a - n1 * (n1 + 1.0) / 2.0;
+    let mean_u = n1 * n2 / 2.0;
+    let variance_u = n1 * n2 / 12.0 * ((n + 1.0) - ties / (n * (n - 1.0)));
+    if variance_u <= 0.0 {
+        return Some(1.0);
+    }
+    let z = ((u - mean_u).abs() - 0.5).max(0.0) / variance_u.sqrt();
+    Some(normal_p(z))
+}
+
+/// A least-squares line `y = intercept + slope * x`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fit {
+    pub slope: f64,
+    pub intercept: f64,
+    pub r_squared: f64,
+    /// Two-sided p-value of the slope differing from zero.
+    pub p_value: f64,
+}
+
+/// Fits a line through the points. Needs at least three points and some
+/// spread in `xs`.
+pub fn linear_fit(xs: &[f64], ys: &[f64]) -> Option<Fit> {
+    let n = xs.len().min(ys.len());
+    if n < 3 {
+        return None;
+    }
+    let (xs, ys) = (&xs[..n], &ys[..n]);
+    let (mx, my) = (mean(xs), mean(ys));
+    let sxx: f64 = xs.iter().map(|x| (x - mx).powi(2)).sum();
+    let syy: f64 = ys.iter().map(|y| (y - my).powi(2)).sum();
+    let sxy: f64 = xs.iter().zip(ys).map(|(x, y)| (x - mx) * (y - my)).sum();
+    if sxx == 0.0 {
+        return None;
+    }
+    let slope = sxy / sxx;
+    let intercept = my - slope * mx;
+    let residual: f64 = xs
+        .iter()
+        .zip(ys)
+        .map(|(x, y)| (y - intercept - slope * x).powi(2))
+        .sum();
+    let df = n as f64 - 2.0;
+    let p_value = match residual {
+        0.0 if slope == 0.0 => 1.0,
+        0.0 => 0.0,
+        _ => student_t_p(slope / (residual / df / sxx).sqrt(), df),
+    };
+    Some(Fit {
+        slope,
+        intercept,
+        r_squared: if syy == 0.0 {
+            0.0
+        } else {
+            1.0 - residual / syy
+        },
+        p_value,
+    })
+}
+
+/// Two-sided p-value of Student's t statistic `t` with `df` degrees of
+/// freedom.
+pub fn student_t_p(t: f64, df: f64) -> f64 {
+    if !t.is_finite() {
+        return 0.0;
+    }
+    incomplete_beta(df / 2.0, 0.5, df / (df + t * t)).clamp(0.0, 1.0)
+}
+
+/// The `t` whose two-sided p-value at `df` degrees of freedom is `p`.
+fn student_t_quantile(p: f64, df: f64) -> f64 {
+    let (mut low, mut high) = (0.0, 1e6);
+    for _ in 0..200 {
+        let mid = (low + high) / 2.0;
+        match student_t_p(mid, df) > p {
+            true => low = mid,
+            false => high = mid,
+        }
+    }
+    (low + high) / 2.0
+}
+
+/// Two-sided p-value of a standard normal statistic `z`.
+pub fn normal_p(z: f64) -> f64 {
+    erfc(z.abs() / std::f64::consts::SQRT_2).clamp(0.0, 1.0)
+}
+
+/// Complementary error function (Numerical Recipes' Chebyshev fit,
+/// accurate to about 1e-7).
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let r = t
+        * (-z * z - 1.265_512_23
+            + t * (1.000_023_68
+                + t * (0.374_091_96
+                    + t * (0.096_784_18
+                        + t * (-0.186_288_06
+                            + t * (0.278_868_07
+                                + t * (-1.135_203_98
+                                    + t * (1.488_515_87
+                                        + t * (-0.822_152_23 + t * 0.170_872_77)))))))))
+            .exp();
+    if x >= 0.0 { r } else { 2.0 - r }
+}
+
+/// ln Γ(x) by the Lanczos approximation.
+fn ln_gamma(x: f64) -> f64 {
+    const G: [f64; 6] = [
+        76.180_091_729_471_46,
+        -86.505_320_329_416_77,
+        24.014_098_240_830_91,
+        -1.231_739_572_450_155,
+        0.001_208_650_973_866_179,
+        -0.000_005_395_239_384_953,
+    ];
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let series = G
+        .iter()
+        .enumerate()
+        .fold(1.000_000_000_190_015, |sum, (i, g)| {
+            sum + g / (x + 1.0 + i as f64)
+        });
+    -tmp + (2.506_628_274_631_000_5 * series / x).ln()
+}
+
+/// Regularized incomplete beta function I_x(a, b).
+fn incomplete_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    // The continued fraction converges quickly only on this side.
+    match x < (a + 1.0) / (a + b + 2.0) {
+        true => front * beta_fraction(a, b, x) / a,
+        false => 1.0 - front * beta_fraction(b, a, 1.0 - x) / b,
+    }
+}
+
+/// Lentz's method for the incomplete beta continued fraction.
+fn beta_fraction(a: f64, b: f64, x: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut h = d;
+    for m in 1..300 {
+        let m = m as f64;
+        let m2 = 2.0 * m;
+        for numerator in [
+            m * (b - m) * x / ((a + m2 - 1.0) * (a + m2)),
+            -(a + m) * (a + b + m) * x / ((a + m2) * (a + m2 + 1.0)),
+        ] {
+            d = 1.0 + numerator * d;
+            if d.abs() < TINY {
+                d = TINY;
+            }
+            c = 1.0 + numerator / c;
+            if c.abs() < TINY {
+                c = TINY;
+            }
+            d = 1.0 / d;
+            h *= d * c;
+        }
+        if (d * c - 1.0).abs() < 1e-12 {
+            break;
+        }
+    }
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_stay_bounded() {
+        let mut rng = Rng::new(3);
+        let mut samples = Samples::new();
+        for i in 0..MAX_SAMPLES as u64 * 2 {
+            samples.record(Duration::from_nanos(i), &mut rng);
+        }
+        assert_eq!(samples.kept.len(), MAX_SAMPLES);
+        assert_eq!(samples.seen, MAX_SAMPLES as u64 * 2);
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn p_values_match_tables() {
+        // t = 2.228 is the two-sided 5% critical value at 10 df.
+        assert!(close(student_t_p(2.228, 10.0), 0.05));
+        assert!(close(student_t_p(0.0, 5.0), 1.0));
+        assert!(close(normal_p(1.959_964), 0.05));
+        assert!(close(normal_p(0.0), 1.0));
+    }
+
+    #[test]
+    fn percentiles_by_nearest_rank() {
+        let xs: Vec<f64> = (1..=100).map(f64::from).collect();
+        assert_eq!(percentile(&xs, 0.5), 51.0);
+        assert_eq!(percentile(&xs, 0.99), 100.0);
+        assert_eq!(percentile(&xs, 1.0), 100.0);
+        assert!(percentile(&[], 0.5).is_nan());
+    }
+
+    #[test]
+    fn confidence_intervals_use_t_quantiles() {
+        // t(0.975, 4) = 2.776.
+        assert!((student_t_quantile(0.05, 4.0) - 2.776).abs() < 1e-3);
+        let half = mean_ci(&[9.0, 10.0, 11.0, 10.0, 10.0], 0.95).unwrap();
+        assert!((half - 2.776 * (0.5f64 / 5.0).sqrt()).abs() < 1e-3);
+        assert!(mean_ci(&[1.0], 0.95).is_none());
+    }
+
+    #[test]
+    fn tests_separate_shifts_from_noise() {
+        let a = [10.0, 10.4, 9.8, 10.1, 9.9, 10.2];
+        let shifted = [11.0, 11.3, 10.9, 11.2, 11.1, 10.8];
+        let noise = [10.1, 9.9, 10.3, 9.7, 10.0, 10.2];
+        assert!(welch_t_test(&a, &shifted).unwrap() < 0.001);
+        assert!(welch_t_test(&a, &noise).unwrap() > 0.5);
+        // Fully separated samples of six: exact p is 0.0022.
+        assert!(mann_whitney_p(&a, &shifted).unwrap() < 0.01);
+        assert!(mann_whitney_p(&a, &noise).unwrap() > 0.5);
+        assert_eq!(mann_whitney_p(&[1.0, 1.0], &[1.0, 1.0]), Some(1.0));
+        assert!(welch_t_test(&[1.0], &shifted).is_none());
+    }
+
+    #[test]
+    fn fits_lines() {
+        let xs = [0.0, 1.0, 2.0, 3.0, 4.0];
+        let fit = linear_fit(&xs, &[1.0, 3.1, 4.9, 7.0, 9.1]).unwrap();
+        assert!((fit.slope - 2.0).abs() < 0.1);
+        assert!(fit.p_value < 0.001);
+        let flat = linear_fit(&xs, &[5.0, 5.2, 4.8, 5.1, 4.9]).unwrap();
+        assert!(flat.p_value > 0.5);
+        assert!(linear_fit(&[1.0, 2.0], &[1.0, 2.0]).is_none());
+        assert!(linear_fit(&[1.0; 4], &[1.0, 2.0, 3.0, 4.0]).is_none());
+    }
+}
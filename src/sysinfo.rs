@@ -0,0 +1,386 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::monitor::parse_meminfo_kb;
+use crate::{cgroup, parse};
+
+const SYSFS_ROOT: &str = "/sys/devices/system";
+const PROC_MEMINFO: &str = "/proc/meminfo";
+const THP_ENABLED: &str = "/sys/kernel/mm/transparent_hugepage/enabled";
+
+/// What the machine offers a stress run, for sizing scenarios and for
+/// context in reports.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct SystemInfo {
+    pub cpus: Topology,
+    pub caches: Vec<Cache>,
+    pub memory: Memory,
+    pub numa_nodes: Vec<NumaNode>,
+    pub cgroup: cgroup::Limits,
+    pub huge_pages: HugePages,
+    /// Soft limits by name; `None` means unlimited.
+    pub rlimits: BTreeMap<&'static str, Option<u64>>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct Topology {
+    pub logical: usize,
+    pub cores: usize,
+    pub packages: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Cache {
+    pub level: u32,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub size_bytes: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct Memory {
+    pub total_bytes: Option<u64>,
+    pub available_bytes: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct NumaNode {
+    pub id: u32,
+    pub cpus: String,
+    pub memory_bytes: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct HugePages {
+    pub total: Option<u64>,
+    pub free: Option<u64>,
+    pub page_size_bytes: Option<u64>,
+    /// Transparent huge page mode (`always`, `madvise` or `never`).
+    pub transparent: Option<String>,
+}
+
+impl SystemInfo {
+    pub fn collect() -> Self {
+        let sys = Path::new(SYSFS_ROOT);
+        let meminfo = std::fs::read_to_string(PROC_MEMINFO).unwrap_or_default();
+        let thp = std::fs::read_to_string(THP_ENABLED).unwrap_or_default();
+        SystemInfo {
+            cpus: topology_at(&sys.join("cpu")),
+            caches: caches_at(&sys.join("cpu/cpu0/cache")),
+            memory: Memory {
+                total_bytes: parse_meminfo_kb(&meminfo, "MemTotal").map(|kb| kb * 1024),
+                available_bytes: parse_meminfo_kb(&meminfo, "MemAvailable").map(|kb| kb * 1024),
+            },
+            numa_nodes: numa_at(&sys.join("node")),
+            cgroup: cgroup::Limits::detect(),
+            huge_pages: huge_pages(&meminfo, &thp),
+            rlimits: rlimits(),
+        }
+    }
+}
+
+/// The machine's NUMA nodes, lowest id first.
+pub fn numa_nodes() -> Vec<NumaNode> {
+    numa_at(&Path::new(SYSFS_ROOT).join("node"))
+}
+
+pub(crate) fn cpu_dirs(root: &Path) -> Vec<std::path::PathBuf> {
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return vec![];
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            e.file_name()
+                .to_str()
+                .and_then(|name| name.strip_prefix("cpu"))
+                .is_some_and(|id| id.parse::<u32>().is_ok())
+        })
+        .map(|e| e.path())
+        .collect()
+}
+
+fn topology_at(root: &Path) -> Topology {
+    let mut cores = BTreeSet::new();
+    let mut packages = BTreeSet::new();
+    let dirs = cpu_dirs(root);
+    for dir in &dirs {
+        let package = read_string(&dir.join("topology/physical_package_id"));
+        let core = read_string(&dir.join("topology/core_id"));
+        if let (Some(package), Some(core)) = (package, core) {
+            cores.insert((package.clone(), core));
+            packages.insert(package);
+        }
+    }
+    Topology {
+        logical: dirs.len(),
+        cores: cores.len(),
+        packages: packages.len(),
+    }
+}
+
+fn caches_at(root: &Path) -> Vec<Cache> {
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return vec![];
+    };
+    let mut caches: Vec<Cache> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name().to_string_lossy().starts_with("index"))
+        .filter_map(|e| {
+            let dir = e.path();
+            Some(Cache {
+                level: read_string(&dir.join("level"))?.parse().ok()?,
+                kind: read_string(&dir.join("type"))?,
+                size_bytes: parse::bytes(&read_string(&dir.join("size"))?).ok()?,
+            })
+        })
+        .collect();
+    caches.sort_by(|a, b| (a.level, &a.kind).cmp(&(b.level, &b.kind)));
+    caches
+}
+
+fn numa_at(root: &Path) -> Vec<NumaNode> {
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return vec![];
+    };
+    let mut nodes: Vec<NumaNode> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let name = e.file_name().into_string().ok()?;
+            let id = name.strip_prefix("node")?.parse().ok()?;
+            let dir = e.path();
+            let meminfo = std::fs::read_to_string(dir.join("meminfo")).unwrap_or_default();
+            Some(NumaNode {
+                id,
+                cpus: read_string(&dir.join("cpulist")).unwrap_or_default(),
+                memory_bytes: parse_node_meminfo_kb(&meminfo, "MemTotal").map(|kb| kb * 1024),
+            })
+        })
+        .collect();
+    nodes.sort_by_key(|n| n.id);
+    nodes
+}
+
+/// Per-node meminfo lines carry a `Node <id> ` prefix.
+fn parse_node_meminfo_kb(meminfo: &str, key: &str) -> Option<u64> {
+    meminfo.lines().find_map(|line| {
+        let (_, rest) = line.strip_prefix("Node ")?.split_once(' ')?;
+        parse_meminfo_kb(rest, key)
+    })
+}
+
+fn huge_pages(meminfo: &str, thp_enabled: &str) -> HugePages {
+    HugePages {
+        total: parse_meminfo_kb(meminfo, "HugePages_Total"),
+        free: parse_meminfo_kb(meminfo, "HugePages_Free"),
+        page_size_bytes: parse_meminfo_kb(meminfo, "Hugepagesize").map(|kb| kb * 1024),
+        // The active mode is bracketed: `always [madvise] never`.
+        transparent: thp_enabled
+            .split_once('[')
+            .and_then(|(_, rest)| rest.split_once(']'))
+            .map(|(mode, _)| mode.to_string()),
+    }
+}
+
+fn rlimits() -> BTreeMap<&'static str, Option<u64>> {
+    [
+        ("as", libc::RLIMIT_AS),
+        ("memlock", libc::RLIMIT_MEMLOCK),
+        ("nofile", libc::RLIMIT_NOFILE),
+        ("nproc", libc::RLIMIT_NPROC),
+        ("stack", libc::RLIMIT_STACK),
+    ]
+    .into_iter()
+    .filter_map(|(name, resource)| {
+        let mut limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        // SAFETY: `limit` is a valid out-pointer for the duration of the call.
+        if unsafe { libc::getrlimit(resource, &mut limit) } != 0 {
+            return None;
+        }
+        let soft = (limit.rlim_cur != libc::RLIM_INFINITY).then_some(limit.rlim_cur);
+        Some((name, soft))
+    })
+    .collect()
+}
+
+/// Raises the soft open-file limit to the hard one, for stressors that hold
+/// many descriptors, and returns the new soft limit.
+pub(crate) fn raise_fd_limit() -> std::io::Result<u64> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: `limit` is a valid pointer for the duration of both calls.
+    unsafe {
+        if libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        limit.rlim_cur = limit.rlim_max;
+        if libc::setrlimit(libc::RLIMIT_NOFILE, &limit) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(limit.rlim_cur)
+}
+
+fn read_string(path: &Path) -> Option<String> {
+    std::fs::read_to_string(path)
+        .ok()
+        .map(|s| s.trim().to_string())
+}
+
+fn or_unknown(value: Option<impl fmt::Display>) -> String {
+    value.map_or_else(|| "unknown".to_string(), |v| v.to_string())
+}
+
+fn or_unlimited(value: Option<impl fmt::Display>) -> String {
+    value.map_or_else(|| "unlimited".to_string(), |v| v.to_string())
+}
+
+impl fmt::Display for SystemInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "CPUs:        {} logical, {} cores, {} packages",
+            self.cpus.logical, self.cpus.cores, self.cpus.packages
+        )?;
+        for cache in &self.caches {
+            writeln!(
+                f,
+                "Cache:       L{} {} {} KiB",
+                cache.level,
+                cache.kind,
+                cache.size_bytes / 1024
+            )?;
+        }
+        writeln!(
+            f,
+            "Memory:      {} bytes total, {} available",
+            or_unknown(self.memory.total_bytes),
+            or_unknown(self.memory.available_bytes)
+        )?;
+        for node in &self.numa_nodes {
+            writeln!(
+                f,
+                "NUMA node {}: cpus {}, {} bytes",
+                node.id,
+                node.cpus,
+                or_unknown(node.memory_bytes)
+            )?;
+        }
+        writeln!(
+            f,
+            "cgroup:      memory {}, cpu {}",
+            or_unlimited(self.cgroup.memory_max),
+            or_unlimited(self.cgroup.cpu_max)
+        )?;
+        writeln!(
+            f,
+            "Huge pages:  {} free of {} ({} bytes each), THP {}",
+            or_unknown(self.huge_pages.free),
+            or_unknown(self.huge_pages.total),
+            or_unknown(self.huge_pages.page_size_bytes),
+            or_unknown(self.huge_pages.transparent.as_deref())
+        )?;
+        for (name, limit) in &self.rlimits {
+            writeln!(f, "rlimit {name}: {}", or_unlimited(*limit))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake_root(name: &str) -> std::path::PathBuf {
+        let root = std::env::temp_dir().join(format!("itsmine-sysinfo-{name}"));
+        let _ = std::fs::remove_dir_all(&root);
+        root
+    }
+
+    fn write(path: &Path, content: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn topology_counts_cores_and_packages() {
+        let root = fake_root("cpu");
+        for (cpu, core) in [(0, 0), (1, 1), (2, 0), (3, 1)] {
+            let dir = root.join(format!("cpu{cpu}/topology"));
+            write(&dir.join("physical_package_id"), "0\n");
+            write(&dir.join("core_id"), &format!("{core}\n"));
+        }
+        write(&root.join("cpufreq/policy0"), "");
+        assert_eq!(
+            topology_at(&root),
+            Topology {
+                logical: 4,
+                cores: 2,
+                packages: 1
+            }
+        );
+    }
+
+    #[test]
+    fn caches_and_numa_nodes() {
+        let root = fake_root("caches");
+        for (index, level, kind, size) in [(0, 1, "Data", "48K"), (2, 2, "Unified", "2048K")] {
+            let dir = root.join(format!("cache/index{index}"));
+            write(&dir.join("level"), &format!("{level}\n"));
+            write(&dir.join("type"), &format!("{kind}\n"));
+            write(&dir.join("size"), &format!("{size}\n"));
+        }
+        assert_eq!(
+            caches_at(&root.join("cache")),
+            vec![
+                Cache {
+                    level: 1,
+                    kind: "Data".to_string(),
+                    size_bytes: 48 * 1024
+                },
+                Cache {
+                    level: 2,
+                    kind: "Unified".to_string(),
+                    size_bytes: 2 * 1024 * 1024
+                },
+            ]
+        );
+
+        write(&root.join("node/node0/cpulist"), "0-3\n");
+        write(
+            &root.join("node/node0/meminfo"),
+            "Node 0 MemTotal:       16384 kB\nNode 0 MemFree:  1024 kB\n",
+        );
+        assert_eq!(
+            numa_at(&root.join("node")),
+            vec![NumaNode {
+                id: 0,
+                cpus: "0-3".to_string(),
+                memory_bytes: Some(16 * 1024 * 1024)
+            }]
+        );
+    }
+
+    #[test]
+    fn huge_pages_from_meminfo() {
+        let meminfo =
+            "HugePages_Total:      16\nHugePages_Free:        8\nHugepagesize:       2048 kB\n";
+        assert_eq!(
+            huge_pages(meminfo, "always [madvise] never\n"),
+            HugePages {
+                total: Some(16),
+                free: Some(8),
+                page_size_bytes: Some(2 * 1024 * 1024),
+                transparent: Some("madvise".to_string()),
+            }
+        );
+    }
+}
@@ -0,0 +1,615 @@
+use std::collections::BTreeMap;
+use std::hash::{BuildHasher, RandomState};
+use std::net::UdpSocket;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde_json::{Value, json};
+
+const SERVICE_NAME: &str = "itsmine";
+/// How often queued InfluxDB points are written.
+const INFLUX_FLUSH: Duration = Duration::from_secs(1);
+/// How often finished spans and the latest gauges go to the OTLP collector.
+const OTLP_FLUSH: Duration = Duration::from_secs(10);
+/// Points kept per metric for charting; longer series are thinned evenly.
+const MAX_HISTORY_POINTS: usize = 2000;
+
+static EXPORTER: OnceLock<Exporter> = OnceLock::new();
+static STATSD: OnceLock<UdpSocket> = OnceLock::new();
+static INFLUX: OnceLock<Influx> = OnceLock::new();
+static HISTORY: OnceLock<History> = OnceLock::new();
+static LABEL: OnceLock<String> = OnceLock::new();
+static TAGS: OnceLock<Vec<(String, String)>> = OnceLock::new();
+
+/// Gauge points recorded during the run, kept for the final report.
+struct History {
+    started: Instant,
+    series: Mutex<BTreeMap<String, Thinned>>,
+}
+
+/// One metric's points, halved whenever they reach `MAX_HISTORY_POINTS`
+/// so a run of any length keeps an evenly spaced, bounded series.
+#[derive(Default)]
+struct Thinned {
+    points: Series,
+    /// Points offered so far, of which every `every`-th is kept.
+    offered: u64,
+    every: u64,
+}
+
+impl Thinned {
+    fn push(&mut self, at: Duration, value: u64) {
+        let every = self.every.max(1);
+        if self.offered.is_multiple_of(every) {
+            self.points.push((at, value));
+        }
+        self.offered += 1;
+        if self.points.len() >= MAX_HISTORY_POINTS {
+            let mut index = 0;
+            self.points.retain(|_| {
+                index += 1;
+                index % 2 == 1
+            });
+            self.every = every * 2;
+        }
+    }
+}
+
+/// Line protocol points waiting to be written to InfluxDB, so recording a
+/// gauge never waits on the network.
+struct Influx {
+    url: String,
+    lines: Mutex<Vec<String>>,
+}
+
+impl Influx {
+    /// Writes every queued point in one request.
+    fn flush(&self) {
+        let lines = std::mem::take(&mut *self.lines.lock().unwrap_or_else(|e| e.into_inner()));
+        if lines.is_empty() {
+            return;
+        }
+        if let Err(e) = crate::http::post(&self.url, "text/plain", lines.join("\n")) {
+            log::warn!("Failed to push {} points to InfluxDB: {e}", lines.len());
+        }
+    }
+}
+
+/// A metric's data points as offsets from the start of the run.
+pub type Series = Vec<(Duration, u64)>;
+
+/// Collects spans and metrics for a single run and ships them to an OTLP/HTTP
+/// collector every `OTLP_FLUSH` and when the run ends.
+struct Exporter {
+    endpoint: String,
+    trace_id: String,
+    root_span_id: String,
+    started: SystemTime,
+    spans: Mutex<Vec<Value>>,
+    /// The latest point of each gauge since the last export.
+    gauges: Mutex<BTreeMap<String, Gauge>>,
+}
+
+struct Gauge {
+    unit: String,
+    value: u64,
+    at: SystemTime,
+}
+
+/// Enables telemetry export to `endpoint` (e.g. `http://localhost:4318`).
+pub fn init(endpoint: &str) {
+    let exporter = Exporter {
+        endpoint: endpoint.trim_end_matches('/').to_string(),
+        trace_id: random_hex(16),
+        root_span_id: random_hex(8),
+        started: SystemTime::now(),
+        spans: Mutex::new(vec![]),
+        gauges: Mutex::new(BTreeMap::new()),
+    };
+    if EXPORTER.set(exporter).is_err() {
+        log::warn!("Telemetry already initialized, ignoring {endpoint}.");
+        return;
+    }
+    std::thread::spawn(|| {
+        let exporter = EXPORTER.get().expect("set above");
+        loop {
+            std::thread::sleep(OTLP_FLUSH);
+            if let Err(e) = exporter.export(None) {
+                log::warn!("{e}");
+            }
+        }
+    });
+}
+
+/// Pushes every gauge to a statsd daemon at `addr` (e.g. `localhost:8125`) as
+/// soon as it is recorded.
+pub fn init_statsd(addr: &str) -> Result<(), anyhow::Error> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket
+        .connect(addr)
+        .map_err(|e| anyhow::anyhow!("Invalid statsd address '{addr}': {e}"))?;
+    if STATSD.set(socket).is_err() {
+        log::warn!("statsd sink already initialized, ignoring {addr}.");
+    }
+    Ok(())
+}
+
+/// Pushes every gauge to an InfluxDB write endpoint `url` in line protocol,
+/// in batches written from a background thread every `INFLUX_FLUSH` and at
+/// shutdown. The URL must include the database or bucket parameters, e.g.
+/// `http://localhost:8086/write?db=lab`.
+pub fn init_influx(url: &str) {
+    let influx = Influx {
+        url: url.to_string(),
+        lines: Mutex::new(vec![]),
+    };
+    if INFLUX.set(influx).is_err() {
+        log::warn!("InfluxDB sink already initialized, ignoring {url}.");
+        return;
+    }
+    std::thread::spawn(|| {
+        let influx = INFLUX.get().expect("set above");
+        loop {
+            std::thread::sleep(INFLUX_FLUSH);
+            influx.flush();
+        }
+    });
+}
+
+/// Tags every metric, span and event of this run with a job label.
+pub fn set_label(label: &str) {
+    let _ = LABEL.set(label.to_string());
+}
+
+pub fn label() -> Option<&'static str> {
+    LABEL.get().map(String::as_str)
+}
+
+/// Attaches `key=value` tags to every metric, span, event and report of
+/// this run, so results can be filtered and grouped later.
+pub fn set_tags(tags: Vec<(String, String)>) {
+    let _ = TAGS.set(tags);
+}
+
+pub fn tags() -> &'static [(String, String)] {
+    TAGS.get().map_or(&[], Vec::as_slice)
+}
+
+/// Keeps gauge points in memory, up to `MAX_HISTORY_POINTS` per metric, so
+/// they can be charted after the run.
+pub fn record_history() {
+    let _ = HISTORY.set(History {
+        started: Instant::now(),
+        series: Mutex::new(BTreeMap::new()),
+    });
+}
+
+/// Drops the gauge points recorded so far, e.g. between runs of an
+/// embedding program.
+pub fn clear_history() {
+    if let Some(history) = HISTORY.get() {
+        history
+            .series
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+}
+
+/// Returns the recorded gauge points grouped by metric name.
+pub fn history() -> BTreeMap<String, Series> {
+    let Some(history) = HISTORY.get() else {
+        return BTreeMap::new();
+    };
+    history
+        .series
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .map(|(name, thinned)| (name.clone(), thinned.points.clone()))
+        .collect()
+}
+
+/// A stressor phase. The span is recorded when dropped.
+pub struct Span {
+    name: String,
+    start: SystemTime,
+}
+
+pub fn span(name: impl Into<String>) -> Span {
+    Span {
+        name: name.into(),
+        start: SystemTime::now(),
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let Some(exporter) = EXPORTER.get() else {
+            return;
+        };
+        let span = exporter.span_json(
+            &self.name,
+            &random_hex(8),
+            &exporter.root_span_id,
+            self.start,
+            SystemTime::now(),
+        );
+        exporter
+            .spans
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(span);
+    }
+}
+
+/// Records a gauge data point.
+pub fn gauge(name: &str, unit: &str, value: u64) {
+    if let Some(history) = HISTORY.get() {
+        let at = history.started.elapsed();
+        history
+            .series
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(name.to_string())
+            .or_default()
+            .push(at, value);
+    }
+
+    if let Some(socket) = STATSD.get()
+        && let Err(e) = socket.send(statsd_line(label(), tags(), name, value).as_bytes())
+    {
+        log::warn!("Failed to push {name} to statsd: {e}");
+    }
+
+    if let Some(influx) = INFLUX.get() {
+        let line = influx_line(label(), tags(), name, value, SystemTime::now());
+        influx
+            .lines
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(line);
+    }
+
+    if let Some(exporter) = EXPORTER.get() {
+        exporter.gauge(name, unit, value);
+    }
+}
+
+/// Closes the root span named after `run` and exports everything recorded.
+pub fn shutdown(run: &str) -> Result<(), anyhow::Error> {
+    if let Some(influx) = INFLUX.get() {
+        influx.flush();
+    }
+    let Some(exporter) = EXPORTER.get() else {
+        return Ok(());
+    };
+    let root = exporter.span_json(
+        &format!("{SERVICE_NAME} {run}"),
+        &exporter.root_span_id,
+        "",
+        exporter.started,
+        SystemTime::now(),
+    );
+    exporter.export(Some(root))?;
+    log::debug!("Exported telemetry to {}.", exporter.endpoint);
+    Ok(())
+}
+
+impl Exporter {
+    /// Keeps `value` as the gauge's latest point until the next export.
+    fn gauge(&self, name: &str, unit: &str, value: u64) {
+        self.gauges
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(
+                name.to_string(),
+                Gauge {
+                    unit: unit.to_string(),
+                    value,
+                    at: SystemTime::now(),
+                },
+            );
+    }
+
+    /// Sends the spans finished and the gauges recorded since the last
+    /// export, with `root` among the spans once the run is over.
+    fn export(&self, root: Option<Value>) -> Result<(), anyhow::Error> {
+        let mut spans = std::mem::take(&mut *self.spans.lock().unwrap_or_else(|e| e.into_inner()));
+        spans.extend(root);
+        let gauges = std::mem::take(&mut *self.gauges.lock().unwrap_or_else(|e| e.into_inner()));
+        let metrics: Vec<_> = gauges
+            .into_iter()
+            .map(|(name, gauge)| {
+                json!({
+                    "name": format!("{SERVICE_NAME}.{name}"),
+                    "unit": gauge.unit,
+                    "gauge": {
+                        "dataPoints": [{
+                            "asInt": gauge.value.to_string(),
+                            "timeUnixNano": unix_nanos(gauge.at),
+                        }]
+                    }
+                })
+            })
+            .collect();
+        if !spans.is_empty() {
+            self.post(
+                "v1/traces",
+                json!({
+                    "resourceSpans": [{
+                        "resource": resource(),
+                        "scopeSpans": [{ "scope": { "name": SERVICE_NAME }, "spans": spans }]
+                    }]
+                }),
+            )?;
+        }
+        if !metrics.is_empty() {
+            self.post(
+                "v1/metrics",
+                json!({
+                    "resourceMetrics": [{
+                        "resource": resource(),
+                        "scopeMetrics": [{ "scope": { "name": SERVICE_NAME }, "metrics": metrics }]
+                    }]
+                }),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn span_json(
+        &self,
+        name: &str,
+        span_id: &str,
+        parent_span_id: &str,
+        start: SystemTime,
+        end: SystemTime,
+    ) -> Value {
+        json!({
+            "traceId": self.trace_id,
+            "spanId": span_id,
+            "parentSpanId": parent_span_id,
+            "name": name,
+            "kind": 1,
+            "startTimeUnixNano": unix_nanos(start),
+            "endTimeUnixNano": unix_nanos(end),
+        })
+    }
+
+    fn post(&self, path: &str, body: Value) -> Result<(), anyhow::Error> {
+        let url = format!("{}/{path}", self.endpoint);
+        crate::http::post(&url, "application/json", body.to_string())
+            .map_err(|e| anyhow::anyhow!("Failed to export telemetry to {url}: {e}"))
+    }
+}
+
+/// A statsd gauge, with tags in the DogStatsD `|#key:value` extension.
+fn statsd_line(label: Option<&str>, tags: &[(String, String)], name: &str, value: u64) -> String {
+    let line = match label {
+        Some(label) => format!("{SERVICE_NAME}.{label}.{name}:{value}|g"),
+        None => format!("{SERVICE_NAME}.{name}:{value}|g"),
+    };
+    match tags.is_empty() {
+        true => line,
+        false => {
+            let tags: Vec<_> = tags.iter().map(|(k, v)| format!("{k}:{v}")).collect();
+            format!("{line}|#{}", tags.join(","))
+        }
+    }
+}
+
+fn influx_line(
+    label: Option<&str>,
+    tags: &[(String, String)],
+    name: &str,
+    value: u64,
+    at: SystemTime,
+) -> String {
+    let mut line_tags = match label {
+        Some(label) => format!(",label={}", escape_influx_tag(label)),
+        None => String::new(),
+    };
+    for (key, value) in tags {
+        line_tags.push_str(&format!(
+            ",{}={}",
+            escape_influx_tag(key),
+            escape_influx_tag(value)
+        ));
+    }
+    format!(
+        "{SERVICE_NAME}.{name}{line_tags} value={value}i {}",
+        unix_nanos(at)
+    )
+}
+
+fn escape_influx_tag(value: &str) -> String {
+    value
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+fn resource() -> Value {
+    let mut attributes = vec![json!({
+        "key": "service.name",
+        "value": { "stringValue": SERVICE_NAME }
+    })];
+    if let Some(label) = label() {
+        attributes.push(json!({
+            "key": "itsmine.label",
+            "value": { "stringValue": label }
+        }));
+    }
+    for (key, value) in tags() {
+        attributes.push(json!({
+            "key": format!("itsmine.tag.{key}"),
+            "value": { "stringValue": value }
+        }));
+    }
+    json!({ "attributes": attributes })
+}
+
+fn unix_nanos(t: SystemTime) -> String {
+    t.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+fn random_hex(bytes: usize) -> String {
+    let mut hex = String::new();
+    while hex.len() < bytes * 2 {
+        hex.push_str(&format!(
+            "{:016x}",
+            RandomState::new().hash_one(SystemTime::now())
+        ));
+    }
+    hex.truncate(bytes * 2);
+    hex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_hex_has_requested_length() {
+        assert_eq!(random_hex(8).len(), 16);
+        assert_eq!(random_hex(16).len(), 32);
+        assert!(random_hex(16).chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    #[test]
+    fn statsd_line_is_gauge() {
+        assert_eq!(
+            statsd_line(None, &[], "memory.allocated_bytes", 1024),
+            "itsmine.memory.allocated_bytes:1024|g"
+        );
+    }
+
+    #[test]
+    fn statsd_line_is_keyed_by_label() {
+        assert_eq!(
+            statsd_line(Some("frontend-sim"), &[], "thread.count", 4),
+            "itsmine.frontend-sim.thread.count:4|g"
+        );
+    }
+
+    #[test]
+    fn influx_line_uses_integer_field_and_ns_timestamp() {
+        let at = UNIX_EPOCH + Duration::from_secs(2);
+        assert_eq!(
+            influx_line(None, &[], "thread.count", 4, at),
+            "itsmine.thread.count value=4i 2000000000"
+        );
+    }
+
+    #[test]
+    fn influx_line_tags_and_escapes_label() {
+        let at = UNIX_EPOCH + Duration::from_secs(2);
+        assert_eq!(
+            influx_line(Some("front end"), &[], "thread.count", 4, at),
+            "itsmine.thread.count,label=front\\ end value=4i 2000000000"
+        );
+    }
+
+    #[test]
+    fn lines_carry_tags() {
+        let tags = [
+            ("build".to_string(), "1234".to_string()),
+            ("host-class".to_string(), "m6i large".to_string()),
+        ];
+        assert_eq!(
+            statsd_line(None, &tags, "thread.count", 4),
+            "itsmine.thread.count:4|g|#build:1234,host-class:m6i large"
+        );
+        assert_eq!(
+            influx_line(None, &tags, "thread.count", 4, UNIX_EPOCH),
+            "itsmine.thread.count,build=1234,host-class=m6i\\ large value=4i 0"
+        );
+    }
+
+    #[test]
+    fn influx_points_are_written_in_one_batch() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let influx = Influx {
+            url: format!("http://{}/write?db=lab", listener.local_addr().unwrap()),
+            lines: Mutex::new(vec!["a value=1i 0".to_string(), "b value=2i 0".to_string()]),
+        };
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut buffer = [0; 1024];
+            while !String::from_utf8_lossy(&request).ends_with("b value=2i 0") {
+                let read = stream.read(&mut buffer).unwrap();
+                assert!(read > 0, "request ended early");
+                request.extend_from_slice(&buffer[..read]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        influx.flush();
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /write?db=lab "));
+        assert!(request.ends_with("\r\n\r\na value=1i 0\nb value=2i 0"));
+        assert!(
+            influx
+                .lines
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .is_empty()
+        );
+        // Nothing queued, nothing sent.
+        influx.flush();
+    }
+
+    #[test]
+    fn history_stays_bounded_and_evenly_spaced() {
+        let mut thinned = Thinned::default();
+        for i in 0..10 * MAX_HISTORY_POINTS as u64 {
+            thinned.push(Duration::from_millis(i), i);
+        }
+        assert!(thinned.points.len() < MAX_HISTORY_POINTS);
+        assert!(thinned.points.len() >= MAX_HISTORY_POINTS / 4);
+        assert_eq!(thinned.points[0].1, 0);
+        let step = thinned.points[1].1;
+        assert!(thinned.points.windows(2).all(|w| w[1].1 - w[0].1 == step));
+    }
+
+    #[test]
+    fn exporter_keeps_one_point_per_gauge() {
+        let exporter = Exporter {
+            endpoint: "http://127.0.0.1:9".to_string(),
+            trace_id: random_hex(16),
+            root_span_id: random_hex(8),
+            started: SystemTime::now(),
+            spans: Mutex::new(vec![]),
+            gauges: Mutex::new(BTreeMap::new()),
+        };
+        for value in 0..1000 {
+            exporter.gauge("thread.completed", "{thread}", value);
+        }
+        exporter.gauge("memory.allocated_bytes", "By", 1024);
+        let gauges = exporter.gauges.lock().unwrap_or_else(|e| e.into_inner());
+        assert_eq!(gauges.len(), 2);
+        assert_eq!(gauges["thread.completed"].value, 999);
+        drop(gauges);
+        // Nothing recorded, nothing sent.
+        exporter
+            .gauges
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+        assert!(exporter.export(None).is_ok());
+    }
+
+    #[test]
+    fn random_hex_is_not_constant() {
+        assert_ne!(random_hex(16), random_hex(16));
+    }
+}
@@ -0,0 +1,158 @@
+//! Payload templates for `itsmine net --payload`: the file's bytes as they
+//! are, except for `{{kind:N}}` sections refilled with N random bytes of
+//! that kind on every send, so traffic looks like traffic to DPI and
+//! conntrack rather than like zeros.
+
+use std::ops::Range;
+use std::path::Path;
+
+use crate::chaos::Rng;
+
+/// Largest payload a template may render to.
+const MAX_SIZE: usize = 64 << 20;
+
+/// What a random section is filled with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Fill {
+    /// Any byte.
+    Bytes,
+    /// Lowercase hex digits.
+    Hex,
+    /// Decimal digits.
+    Digits,
+    /// Lowercase letters.
+    Letters,
+}
+
+impl Fill {
+    fn byte(self, random: u8) -> u8 {
+        let pick = |alphabet: &[u8]| alphabet[random as usize % alphabet.len()];
+        match self {
+            Fill::Bytes => random,
+            Fill::Hex => pick(b"0123456789abcdef"),
+            Fill::Digits => pick(b"0123456789"),
+            Fill::Letters => pick(b"abcdefghijklmnopqrstuvwxyz"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    /// The rendered payload's fixed bytes, with zeros where sections go.
+    fixed: Vec<u8>,
+    sections: Vec<(Range<usize>, Fill)>,
+}
+
+impl Template {
+    pub fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        let bytes = std::fs::read(path).map_err(|e| {
+            anyhow::anyhow!("Failed to read payload template {}: {e}", path.display())
+        })?;
+        Self::parse(&bytes)
+            .map_err(|e| anyhow::anyhow!("Invalid payload template {}: {e}", path.display()))
+    }
+
+    /// Parses `bytes`, where `{{random:N}}`, `{{hex:N}}`, `{{digits:N}}`
+    /// and `{{letters:N}}` mark random sections.
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        let mut fixed = vec![];
+        let mut sections = vec![];
+        let mut rest = bytes;
+        while let Some(start) = find(rest, b"{{") {
+            fixed.extend_from_slice(&rest[..start]);
+            let after = &rest[start + 2..];
+            let end = find(after, b"}}").ok_or("a '{{' section is never closed")?;
+            let section = String::from_utf8_lossy(&after[..end]);
+            let (kind, len) = section
+                .split_once(':')
+                .ok_or_else(|| format!("section '{section}' must look like <kind>:<bytes>"))?;
+            let fill = match kind.trim() {
+                "random" => Fill::Bytes,
+                "hex" => Fill::Hex,
+                "digits" => Fill::Digits,
+                "letters" => Fill::Letters,
+                other => {
+                    return Err(format!(
+                        "unknown section kind '{other}' (use random, hex, digits or letters)"
+                    ));
+                }
+            };
+            let len: usize = len
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid length in section '{section}'"))?;
+            if fixed.len() + len > MAX_SIZE {
+                return Err(format!("payloads must be at most {MAX_SIZE} bytes"));
+            }
+            sections.push((fixed.len()..fixed.len() + len, fill));
+            fixed.resize(fixed.len() + len, 0);
+            rest = &after[end + 2..];
+        }
+        fixed.extend_from_slice(rest);
+        match fixed.is_empty() {
+            true => Err("the template is empty".to_string()),
+            false => Ok(Template { fixed, sections }),
+        }
+    }
+
+    /// Length of every payload rendered from it.
+    pub fn size(&self) -> u64 {
+        self.fixed.len() as u64
+    }
+
+    /// A payload with its sections filled from `rng`.
+    pub fn render(&self, rng: &mut Rng) -> Vec<u8> {
+        let mut payload = self.fixed.clone();
+        self.refill(&mut payload, rng);
+        payload
+    }
+
+    /// Refills the sections of a payload `render` returned.
+    pub fn refill(&self, payload: &mut [u8], rng: &mut Rng) {
+        for (range, fill) in &self.sections {
+            for chunk in payload[range.clone()].chunks_mut(8) {
+                let random = rng.next_u64().to_ne_bytes();
+                for (byte, random) in chunk.iter_mut().zip(random) {
+                    *byte = fill.byte(random);
+                }
+            }
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_fixed_bytes_and_seeded_sections() {
+        let template =
+            Template::parse(b"GET /{{hex:8}} HTTP/1.1\r\nX-Id: {{digits:4}}{{random:3}}\r\n\r\n")
+                .unwrap();
+        assert_eq!(template.size(), 41);
+        let payload = template.render(&mut Rng::new(7));
+        assert_eq!(&payload[..5], b"GET /");
+        assert!(payload[5..13].iter().all(u8::is_ascii_hexdigit));
+        assert_eq!(&payload[13..30], b" HTTP/1.1\r\nX-Id: ");
+        assert!(payload[30..34].iter().all(u8::is_ascii_digit));
+        assert_eq!(&payload[37..], b"\r\n\r\n");
+        // The same seed gives the same payloads; refilling changes them.
+        assert_eq!(template.render(&mut Rng::new(7)), payload);
+        let mut next = payload.clone();
+        template.refill(&mut next, &mut Rng::new(8));
+        assert_ne!(next, payload);
+
+        assert_eq!(
+            Template::parse(b"plain").unwrap().render(&mut Rng::new(1)),
+            b"plain"
+        );
+        assert!(Template::parse(b"{{hex:4").is_err());
+        assert!(Template::parse(b"{{bits:4}}").is_err());
+        assert!(Template::parse(b"{{hex}}").is_err());
+        assert!(Template::parse(b"").is_err());
+    }
+}
@@ -0,0 +1,179 @@
+use std::path::Path;
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::{duty, events, report};
+
+const THERMAL_ROOT: &str = "/sys/class/thermal";
+const HWMON_ROOT: &str = "/sys/class/hwmon";
+
+/// How often the temperature is read and the throttle adjusted.
+const INTERVAL: Duration = Duration::from_secs(2);
+/// Busy share workers are never throttled below, so the load never stops.
+const MIN_SHARE: f64 = 0.1;
+
+/// The hottest reading, in degrees Celsius, across thermal zones and hwmon
+/// sensors.
+pub fn read_max() -> Option<f64> {
+    read_max_from(Path::new(THERMAL_ROOT), Path::new(HWMON_ROOT))
+}
+
+fn read_max_from(thermal: &Path, hwmon: &Path) -> Option<f64> {
+    let millidegrees = |path: &Path| -> Option<f64> {
+        let value: f64 = std::fs::read_to_string(path).ok()?.trim().parse().ok()?;
+        // Disconnected sensors report zero or absurd values.
+        let celsius = value / 1000.0;
+        (celsius > 0.0 && celsius < 200.0).then_some(celsius)
+    };
+    let mut readings = vec![];
+    for zone in std::fs::read_dir(thermal).into_iter().flatten().flatten() {
+        if zone
+            .file_name()
+            .to_string_lossy()
+            .starts_with("thermal_zone")
+        {
+            readings.extend(millidegrees(&zone.path().join("temp")));
+        }
+    }
+    for chip in std::fs::read_dir(hwmon).into_iter().flatten().flatten() {
+        for input in std::fs::read_dir(chip.path())
+            .into_iter()
+            .flatten()
+            .flatten()
+        {
+            let name = input.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with("temp") && name.ends_with("_input") {
+                readings.extend(millidegrees(&input.path()));
+            }
+        }
+    }
+    readings.into_iter().reduce(f64::max)
+}
+
+/// Halves the workers' busy share each time the temperature is at or above
+/// `limit`, and doubles it back once it has fallen below `limit - hysteresis`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Governor {
+    pub limit: f64,
+    pub hysteresis: f64,
+    pub share: f64,
+    pub backoffs: u32,
+    pub max_celsius: f64,
+}
+
+impl Governor {
+    pub fn new(limit: f64, hysteresis: f64) -> Self {
+        Governor {
+            limit,
+            hysteresis,
+            share: 1.0,
+            backoffs: 0,
+            max_celsius: f64::MIN,
+        }
+    }
+
+    /// Takes a reading and returns the new busy share if it changed.
+    pub fn step(&mut self, celsius: f64) -> Option<f64> {
+        self.max_celsius = self.max_celsius.max(celsius);
+        let share = if celsius >= self.limit && self.share > MIN_SHARE {
+            self.backoffs += 1;
+            (self.share / 2.0).max(MIN_SHARE)
+        } else if celsius < self.limit - self.hysteresis && self.share < 1.0 {
+            (self.share * 2.0).min(1.0)
+        } else {
+            return None;
+        };
+        self.share = share;
+        Some(share)
+    }
+}
+
+/// Watches the temperature for the rest of the process and throttles thread
+/// stressor workers through their duty cycle to keep it below `limit`.
+/// Returns `false` if no temperature sensor could be read.
+pub fn start(limit: f64, hysteresis: f64) -> bool {
+    if read_max().is_none() {
+        log::warn!("No readable temperature sensor; --thermal-limit has no effect.");
+        return false;
+    }
+    duty::enable_throttle();
+    log::info!(
+        "Backing off CPU load above {limit:.1}°C (resuming below {:.1}°C).",
+        limit - hysteresis
+    );
+    std::thread::spawn(move || {
+        let mut governor = Governor::new(limit, hysteresis);
+        loop {
+            if let Some(celsius) = read_max()
+                && let Some(share) = governor.step(celsius)
+            {
+                duty::set_throttle(share);
+                match share < 1.0 {
+                    true => log::warn!(
+                        "Temperature {celsius:.1}°C; CPU load throttled to {:.0}% of its duty cycle.",
+                        share * 100.0
+                    ),
+                    false => log::info!("Temperature {celsius:.1}°C; CPU load restored."),
+                }
+                events::emit(
+                    "thermal_backoff",
+                    json!({ "celsius": celsius, "share": share, "limit": limit }),
+                );
+                report::measure(
+                    "thermal",
+                    json!({
+                        "backoffs": governor.backoffs,
+                        "max_celsius": governor.max_celsius,
+                        "share": share,
+                    }),
+                );
+            }
+            std::thread::sleep(INTERVAL);
+        }
+    });
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backs_off_and_resumes_with_hysteresis() {
+        let mut governor = Governor::new(90.0, 5.0);
+        assert_eq!(governor.step(80.0), None);
+        assert_eq!(governor.step(91.0), Some(0.5));
+        assert_eq!(governor.step(92.0), Some(0.25));
+        // Between the resume threshold and the limit nothing changes.
+        assert_eq!(governor.step(87.0), None);
+        assert_eq!(governor.step(84.0), Some(0.5));
+        assert_eq!(governor.step(84.0), Some(1.0));
+        assert_eq!(governor.step(84.0), None);
+        for _ in 0..10 {
+            governor.step(99.0);
+        }
+        assert_eq!(governor.share, MIN_SHARE);
+        assert_eq!(governor.backoffs, 6);
+        assert_eq!(governor.max_celsius, 99.0);
+    }
+
+    #[test]
+    fn reads_the_hottest_sensor() {
+        let root = std::env::temp_dir().join(format!("itsmine-thermal-{}", std::process::id()));
+        let (thermal, hwmon) = (root.join("thermal"), root.join("hwmon"));
+        for (dir, file, value) in [
+            (thermal.join("thermal_zone0"), "temp", "45000\n"),
+            (thermal.join("cooling_device0"), "temp", "99000\n"),
+            (hwmon.join("hwmon0"), "temp1_input", "61500\n"),
+            (hwmon.join("hwmon0"), "temp2_input", "0\n"),
+        ] {
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join(file), value).unwrap();
+        }
+        assert_eq!(read_max_from(&thermal, &hwmon), Some(61.5));
+        assert_eq!(read_max_from(&root.join("none"), &root.join("none")), None);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
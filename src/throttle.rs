@@ -0,0 +1,209 @@
+//! Token-bucket throttling for stressors that move data, so load can be held
+//! at a set level below saturation with `--limit` and `--limit-iops`.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::{CancellationToken, parse};
+
+/// Longest the buckets may save up for, i.e. the burst they allow after a
+/// pause.
+const BURST: Duration = Duration::from_millis(50);
+
+/// Longest a reservation makes a worker wait, far from overflowing an
+/// `Instant` and longer than any run.
+const LONGEST_WAIT: Duration = Duration::from_secs(365 * 86_400);
+
+/// How a throttled worker waits for its turn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Pacing {
+    /// Sleep, to within the OS timer's resolution.
+    #[default]
+    Sleep,
+    /// Busy-wait the last stretch, to within microseconds.
+    Spin,
+}
+
+impl Pacing {
+    pub fn name(self) -> &'static str {
+        match self {
+            Pacing::Sleep => "sleep",
+            Pacing::Spin => "spin",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, clap::Args)]
+pub struct Limits {
+    /// Cap throughput across all workers, e.g. 200M/s
+    #[arg(long, value_name = "RATE", value_parser = parse::bytes_per_second)]
+    pub limit: Option<f64>,
+    /// Cap operations per second across all workers, e.g. 5000
+    #[arg(long, value_name = "N", value_parser = iops)]
+    pub limit_iops: Option<f64>,
+}
+
+fn iops(s: &str) -> Result<f64, String> {
+    match s.contains('/') {
+        true => parse::per_second(s),
+        false => parse::per_second(&format!("{s}/s")),
+    }
+}
+
+/// Tokens accruing at `rate` per second, up to `BURST` worth. Reservations
+/// may overdraw it; the overdraft is how long the caller must wait.
+#[derive(Debug)]
+struct Bucket {
+    rate: f64,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Bucket {
+    fn new(rate: f64, now: Instant) -> Self {
+        Bucket {
+            rate,
+            tokens: 0.0,
+            refilled: now,
+        }
+    }
+
+    /// Takes `amount` tokens and returns how long until they're covered.
+    fn reserve(&mut self, amount: f64, now: Instant) -> Duration {
+        let saved = self.rate * BURST.as_secs_f64();
+        let accrued = now.saturating_duration_since(self.refilled).as_secs_f64() * self.rate;
+        self.tokens = (self.tokens + accrued).min(saved.max(amount)) - amount;
+        self.refilled = now;
+        match self.tokens < 0.0 {
+            true => Duration::try_from_secs_f64(-self.tokens / self.rate)
+                .map_or(LONGEST_WAIT, |wait| wait.min(LONGEST_WAIT)),
+            false => Duration::ZERO,
+        }
+    }
+}
+
+/// Both limits, shared by every worker of a stressor.
+#[derive(Debug)]
+pub struct Throttle {
+    /// Bytes, then operations.
+    buckets: Mutex<(Option<Bucket>, Option<Bucket>)>,
+    pacing: Pacing,
+}
+
+impl Throttle {
+    pub fn new(limits: &Limits) -> Self {
+        let now = Instant::now();
+        Throttle {
+            buckets: Mutex::new((
+                limits.limit.map(|rate| Bucket::new(rate, now)),
+                limits.limit_iops.map(|rate| Bucket::new(rate, now)),
+            )),
+            pacing: Pacing::Sleep,
+        }
+    }
+
+    pub fn with_pacing(self, pacing: Pacing) -> Self {
+        Throttle { pacing, ..self }
+    }
+
+    /// Changes the operations limit, e.g. as a ramp moves; what accrued so
+    /// far counts at the old rate.
+    pub fn set_limit_iops(&self, rate: f64) {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        match &mut buckets.1 {
+            Some(bucket) => {
+                bucket.reserve(0.0, now);
+                bucket.rate = rate;
+            }
+            None => buckets.1 = Some(Bucket::new(rate, now)),
+        }
+    }
+
+    /// Reserves one operation moving `bytes` and returns when it fits
+    /// under both limits, for callers that wait their own way.
+    pub fn reserve(&self, bytes: u64) -> Instant {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let (by_bytes, by_ops) = &mut *buckets;
+        let bytes = by_bytes.as_mut().map(|b| b.reserve(bytes as f64, now));
+        let ops = by_ops.as_mut().map(|b| b.reserve(1.0, now));
+        now + bytes.max(ops).unwrap_or_default()
+    }
+
+    /// Waits until one operation moving `bytes` fits under both limits.
+    /// Returns false if cancelled first, even with no limit to wait on, so
+    /// unthrottled loops still stop.
+    pub fn admit(&self, bytes: u64, cancel: &CancellationToken) -> bool {
+        let at = self.reserve(bytes);
+        match (at <= Instant::now(), self.pacing) {
+            (true, _) => !cancel.is_cancelled(),
+            (false, Pacing::Sleep) => cancel.sleep_until(at),
+            (false, Pacing::Spin) => cancel.spin_until(at),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_limits() {
+        assert_eq!(iops("5000"), Ok(5000.0));
+        assert_eq!(iops("5k/s"), Ok(5000.0));
+        assert!(iops("0").is_err());
+        assert!(iops("1e-30").is_err());
+    }
+
+    #[test]
+    fn saturates_waits_too_long_to_hold() {
+        let now = Instant::now();
+        let mut bucket = Bucket::new(1e-30, now);
+        assert_eq!(bucket.reserve(1.0, now), LONGEST_WAIT);
+        assert!(now.checked_add(LONGEST_WAIT).is_some());
+    }
+
+    #[test]
+    fn holds_operations_to_the_limit() {
+        let throttle = Throttle::new(&Limits {
+            limit: Some(1e6),
+            limit_iops: Some(1000.0),
+        });
+        let cancel = CancellationToken::new();
+        let started = Instant::now();
+        // 100 operations of 1K at 1000/s, well under 1M/s: about 100ms.
+        for _ in 0..100 {
+            assert!(throttle.admit(1024, &cancel));
+        }
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(90), "{elapsed:?}");
+        // Bytes bind now: 100K at 1M/s also takes about 100ms.
+        let started = Instant::now();
+        for _ in 0..10 {
+            assert!(throttle.admit(10_000, &cancel));
+        }
+        assert!(started.elapsed() >= Duration::from_millis(90));
+        assert!(Throttle::new(&Limits::default()).admit(u64::MAX, &cancel));
+        cancel.cancel();
+        assert!(!Throttle::new(&Limits::default()).admit(0, &cancel));
+    }
+
+    #[test]
+    fn spins_to_gaps_finer_than_the_timer() {
+        let throttle = Throttle::new(&Limits {
+            limit: None,
+            limit_iops: Some(20_000.0),
+        })
+        .with_pacing(Pacing::Spin);
+        let cancel = CancellationToken::new();
+        // 200 operations 50us apart.
+        let started = Instant::now();
+        for _ in 0..200 {
+            assert!(throttle.admit(0, &cancel));
+        }
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_micros(9_900), "{elapsed:?}");
+        assert!(elapsed < Duration::from_millis(50), "{elapsed:?}");
+    }
+}
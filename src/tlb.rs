@@ -0,0 +1,272 @@
+//! `itsmine tlb`: touches pages scattered over a large sparse mapping, some
+//! backed by 2M transparent huge pages and the rest by 4K pages, and walks
+//! them in random order so nearly every load misses the TLB.
+
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+use crate::chaos::{self, Rng};
+use crate::monitor::parse_meminfo_kb;
+use crate::perf::Counter;
+use crate::{CancellationToken, StressError, Stressor, parse, report};
+
+const SMALL_PAGE: usize = 4096;
+pub(crate) const HUGE_PAGE: usize = 2 << 20;
+const LINE: usize = 64;
+/// Loads between deadline and cancellation checks.
+const STEPS: usize = 10_000;
+
+#[derive(Clone, Debug, PartialEq, clap::Args)]
+pub struct Args {
+    /// How long to walk, e.g. 10s
+    #[arg(long, value_parser = parse::duration, default_value = "10s")]
+    pub duration: Duration,
+    /// Address space to scatter pages over, e.g. 64G; only touched pages use
+    /// memory
+    #[arg(long, value_name = "BYTES", value_parser = parse::bytes, default_value = "64G")]
+    pub span: u64,
+    /// 4K pages to touch, each in a 2M region of its own
+    #[arg(long, value_name = "N", default_value_t = 8192)]
+    pub pages: u32,
+    /// 2M huge pages to touch alongside them
+    #[arg(long, value_name = "N", default_value_t = 64)]
+    pub huge_pages: u32,
+}
+
+/// Address space reserved `PROT_NONE` and 2M-aligned; regions are made
+/// writable as they're used, so only those count against the commit limit.
+pub(crate) struct Reservation {
+    mapping: *mut libc::c_void,
+    len: usize,
+    base: usize,
+    pub(crate) regions: usize,
+}
+
+impl Reservation {
+    pub(crate) fn new(bytes: u64) -> std::io::Result<Self> {
+        let regions = bytes as usize / HUGE_PAGE;
+        let len = (regions + 1) * HUGE_PAGE;
+        // SAFETY: a fresh anonymous mapping aliases nothing.
+        let mapping = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+                -1,
+                0,
+            )
+        };
+        if mapping == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Reservation {
+            mapping,
+            len,
+            base: (mapping as usize).next_multiple_of(HUGE_PAGE),
+            regions,
+        })
+    }
+
+    /// Makes 2M region `index` writable, asking for a huge page if `huge`.
+    pub(crate) fn open(&self, index: usize, huge: bool) -> std::io::Result<usize> {
+        let region = self.base + index * HUGE_PAGE;
+        let advice = match huge {
+            true => libc::MADV_HUGEPAGE,
+            false => libc::MADV_NOHUGEPAGE,
+        };
+        // SAFETY: the region lies within the reservation, which nothing else
+        // uses.
+        let failed = unsafe {
+            libc::mprotect(
+                region as *mut libc::c_void,
+                HUGE_PAGE,
+                libc::PROT_READ | libc::PROT_WRITE,
+            ) != 0
+                || libc::madvise(region as *mut libc::c_void, HUGE_PAGE, advice) != 0
+        };
+        match failed {
+            true => Err(std::io::Error::last_os_error()),
+            false => Ok(region),
+        }
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        // SAFETY: the mapping came from mmap in `new` and is unmapped once.
+        unsafe { libc::munmap(self.mapping, self.len) };
+    }
+}
+
+/// Opens `pages` 4K and `huge_pages` 2M regions at random and returns one
+/// cache line's address in each, huge ones first.
+fn scatter(
+    reservation: &Reservation,
+    pages: usize,
+    huge_pages: usize,
+    rng: &mut Rng,
+) -> Result<Vec<usize>, anyhow::Error> {
+    let wanted = pages + huge_pages;
+    // Leave room to spare so picking distinct regions stays quick.
+    if wanted * 2 > reservation.regions {
+        return Err(StressError::InvalidInput(format!(
+            "--span is too small for {wanted} pages; it needs at least {}M",
+            (wanted * 2 * HUGE_PAGE) >> 20
+        ))
+        .into());
+    }
+    let mut chosen = HashSet::new();
+    let mut addresses = Vec::with_capacity(wanted);
+    while addresses.len() < wanted {
+        let index = rng.up_to(reservation.regions as u64 - 1) as usize;
+        if !chosen.insert(index) {
+            continue;
+        }
+        let huge = addresses.len() < huge_pages;
+        let region = reservation.open(index, huge)?;
+        let page = match huge {
+            true => region,
+            false => region + rng.up_to((HUGE_PAGE / SMALL_PAGE - 1) as u64) as usize * SMALL_PAGE,
+        };
+        let within = if huge { HUGE_PAGE } else { SMALL_PAGE };
+        addresses.push(page + rng.up_to((within / LINE - 1) as u64) as usize * LINE);
+    }
+    Ok(addresses)
+}
+
+/// Stores in each address the next one of a random cycle through all of
+/// them, faulting every page in.
+fn link(addresses: &[usize], rng: &mut Rng) {
+    let mut next: Vec<usize> = (0..addresses.len()).collect();
+    // Sattolo's shuffle leaves a single cycle through every address.
+    for i in (1..next.len()).rev() {
+        next.swap(i, rng.up_to(i as u64 - 1) as usize);
+    }
+    for (i, &address) in addresses.iter().enumerate() {
+        // SAFETY: every address points into a writable region of the
+        // reservation, aligned to a cache line.
+        unsafe { (address as *mut usize).write(addresses[next[i]]) };
+    }
+}
+
+/// Follows `steps` links from `at` and returns where it ended.
+fn walk(mut at: usize, steps: usize) -> usize {
+    for _ in 0..steps {
+        // SAFETY: `link` made every address in the cycle hold the next one.
+        at = unsafe { std::ptr::read_volatile(at as *const usize) };
+    }
+    at
+}
+
+/// This process's anonymous memory backed by transparent huge pages.
+pub(crate) fn anon_huge_bytes() -> Option<u64> {
+    let rollup = std::fs::read_to_string("/proc/self/smaps_rollup").ok()?;
+    parse_meminfo_kb(&rollup, "AnonHugePages").map(|kb| kb * 1024)
+}
+
+impl Stressor for Args {
+    fn name(&self) -> &'static str {
+        "tlb"
+    }
+
+    fn params(&self) -> Value {
+        json!({
+            "duration_secs": self.duration.as_secs_f64(),
+            "span_bytes": self.span,
+            "pages": self.pages,
+            "huge_pages": self.huge_pages,
+        })
+    }
+
+    fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        let mut rng = Rng::new(chaos::random_seed());
+        let reservation = Reservation::new(self.span)
+            .map_err(|e| anyhow::anyhow!("Failed to reserve {} bytes: {e}", self.span))?;
+        let addresses = scatter(
+            &reservation,
+            self.pages as usize,
+            self.huge_pages as usize,
+            &mut rng,
+        )?;
+        link(&addresses, &mut rng);
+        let huge_bytes = anon_huge_bytes();
+        if self.huge_pages > 0 && huge_bytes == Some(0) {
+            log::warn!(
+                "No transparent huge pages were allocated (is THP disabled?); every page is 4K."
+            );
+        }
+        let counters = Counter::dtlb_load_misses()
+            .and_then(|misses| Ok((misses, Counter::dtlb_loads()?)))
+            .inspect_err(|e| log::info!("dTLB counters unavailable ({e}); reporting latency only."))
+            .ok();
+        log::info!(
+            "Walking {} 4K and {} 2M pages over {} bytes for {:?}.",
+            self.pages,
+            self.huge_pages,
+            self.span,
+            self.duration
+        );
+
+        if let Some((misses, loads)) = &counters {
+            misses.enable();
+            loads.enable();
+        }
+        let started = Instant::now();
+        let deadline = started + self.duration;
+        let mut at = addresses[0];
+        let mut accesses = 0u64;
+        while Instant::now() < deadline && !cancel.is_cancelled() {
+            at = walk(at, STEPS);
+            accesses += STEPS as u64;
+        }
+        let elapsed = started.elapsed();
+        let mut summary = json!({
+            "accesses": accesses,
+            "ns_per_access": elapsed.as_nanos() as f64 / accesses.max(1) as f64,
+            "anon_huge_bytes": huge_bytes,
+        });
+        if let Some((misses, loads)) = &counters {
+            misses.disable();
+            loads.disable();
+            let (misses, loads) = (misses.read()?, loads.read()?);
+            summary["dtlb_load_misses"] = json!(misses);
+            summary["dtlb_loads"] = json!(loads);
+            summary["dtlb_miss_rate"] = json!((loads > 0).then(|| misses as f64 / loads as f64));
+        }
+        log::info!("TLB walk: {summary}.");
+        report::measure(self.name(), summary);
+        match cancel.is_cancelled() {
+            true => Err(StressError::Interrupted.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn walks_every_scattered_page() {
+        let mut rng = Rng::new(11);
+        let reservation = Reservation::new(64 << 20).unwrap();
+        let addresses = scatter(&reservation, 8, 2, &mut rng).unwrap();
+        assert_eq!(addresses.len(), 10);
+        let regions: HashSet<usize> = addresses.iter().map(|a| a / HUGE_PAGE).collect();
+        assert_eq!(regions.len(), 10);
+        assert!(addresses.iter().all(|a| a % LINE == 0));
+
+        link(&addresses, &mut rng);
+        let mut seen = HashSet::new();
+        let mut at = addresses[0];
+        for _ in 0..addresses.len() {
+            assert!(seen.insert(at));
+            at = walk(at, 1);
+        }
+        assert_eq!(at, addresses[0]);
+        assert!(scatter(&reservation, 100, 0, &mut rng).is_err());
+    }
+}
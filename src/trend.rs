@@ -0,0 +1,195 @@
+//! `itsmine trend`: fits a line through a metric's recent history and flags
+//! a statistically significant drift in the wrong direction.
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::stats;
+
+const SECS_PER_DAY: f64 = 86_400.0;
+
+/// Which direction of a metric is an improvement.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Better {
+    Higher,
+    Lower,
+}
+
+impl Better {
+    /// Throughputs and counts of work done are better higher; times,
+    /// latencies, retries and errors better lower.
+    pub fn guess(metric: &str) -> Better {
+        let name = metric.rsplit('.').next().unwrap_or(metric);
+        let higher = [
+            "_bw",
+            "bandwidth",
+            "per_sec",
+            "throughput",
+            "iterations",
+            "checked",
+        ];
+        match higher.iter().any(|marker| name.contains(marker)) {
+            true => Better::Higher,
+            false => Better::Lower,
+        }
+    }
+}
+
+/// One recorded value of the metric.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Point {
+    pub run: i64,
+    pub started_at: f64,
+    pub value: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    Degrading,
+    Improving,
+    Stable,
+    /// Too few runs, or all at the same instant, to fit a trend.
+    Insufficient,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Trend {
+    pub metric: String,
+    pub better: Better,
+    pub runs: usize,
+    /// Fitted change per day.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slope_per_day: Option<f64>,
+    /// Fitted change from the first to the last run, relative to the first.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change_percent: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p_value: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r_squared: Option<f64>,
+    pub alpha: f64,
+    pub verdict: Verdict,
+}
+
+/// Fits `points` (oldest first) against time and calls the drift
+/// significant when the slope's p-value is below `alpha`.
+pub fn analyze(metric: &str, points: &[Point], better: Better, alpha: f64) -> Trend {
+    let days: Vec<f64> = points
+        .iter()
+        .map(|point| point.started_at / SECS_PER_DAY)
+        .collect();
+    let values: Vec<f64> = points.iter().map(|point| point.value).collect();
+    let fit = stats::linear_fit(&days, &values);
+    let change_percent = fit.and_then(|fit| {
+        let (first, last) = (days.first()?, days.last()?);
+        let start = fit.intercept + fit.slope * first;
+        let end = fit.intercept + fit.slope * last;
+        (start != 0.0).then(|| (end - start) / start.abs() * 100.0)
+    });
+    let verdict = match fit {
+        None => Verdict::Insufficient,
+        Some(fit) if fit.p_value >= alpha || fit.slope == 0.0 => Verdict::Stable,
+        Some(fit) => match (fit.slope > 0.0, better) {
+            (true, Better::Higher) | (false, Better::Lower) => Verdict::Improving,
+            _ => Verdict::Degrading,
+        },
+    };
+    Trend {
+        metric: metric.to_string(),
+        better,
+        runs: points.len(),
+        slope_per_day: fit.map(|fit| fit.slope),
+        change_percent,
+        p_value: fit.map(|fit| fit.p_value),
+        r_squared: fit.map(|fit| fit.r_squared),
+        alpha,
+        verdict,
+    }
+}
+
+impl fmt::Display for Trend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Metric:  {} ({} is better) over {} runs",
+            self.metric,
+            match self.better {
+                Better::Higher => "higher",
+                Better::Lower => "lower",
+            },
+            self.runs
+        )?;
+        if let (Some(slope), Some(p), Some(r_squared)) =
+            (self.slope_per_day, self.p_value, self.r_squared)
+        {
+            writeln!(f, "Slope:   {slope:+.4}/day")?;
+            if let Some(change) = self.change_percent {
+                writeln!(f, "Change:  {change:+.1}% across the window")?;
+            }
+            writeln!(f, "Fit:     p = {p:.4}, R² = {r_squared:.3}")?;
+        }
+        let verdict = match self.verdict {
+            Verdict::Degrading => format!("DEGRADING (significant at {})", self.alpha),
+            Verdict::Improving => format!("improving (significant at {})", self.alpha),
+            Verdict::Stable => format!("stable (no significant trend at {})", self.alpha),
+            Verdict::Insufficient => "not enough runs to fit a trend".to_string(),
+        };
+        writeln!(f, "Verdict: {verdict}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(values: &[f64]) -> Vec<Point> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, &value)| Point {
+                run: i as i64 + 1,
+                started_at: i as f64 * SECS_PER_DAY,
+                value,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn flags_drift_in_the_wrong_direction() {
+        let falling = points(&[100.0, 98.5, 97.2, 95.9, 94.1, 93.0, 91.4]);
+        let trend = analyze("mem.fill_bw", &falling, Better::guess("mem.fill_bw"), 0.05);
+        assert_eq!(trend.verdict, Verdict::Degrading);
+        assert!(trend.change_percent.unwrap() < -7.0);
+        assert_eq!(
+            analyze("wait_us", &falling, Better::Lower, 0.05).verdict,
+            Verdict::Improving
+        );
+    }
+
+    #[test]
+    fn noise_is_stable() {
+        let noisy = points(&[100.0, 103.0, 98.0, 101.0, 99.0, 102.0, 100.0]);
+        assert_eq!(
+            analyze("mem.fill_bw", &noisy, Better::Higher, 0.05).verdict,
+            Verdict::Stable
+        );
+        assert_eq!(
+            analyze("mem.fill_bw", &noisy[..2], Better::Higher, 0.05).verdict,
+            Verdict::Insufficient
+        );
+    }
+
+    #[test]
+    fn guesses_direction_from_the_name() {
+        assert_eq!(Better::guess("mem.fill_bw"), Better::Higher);
+        assert_eq!(Better::guess("mix.fib.iterations_per_sec"), Better::Higher);
+        assert_eq!(
+            Better::guess("priority-inversion.wait_p99_us"),
+            Better::Lower
+        );
+        assert_eq!(Better::guess("duration_secs"), Better::Lower);
+    }
+}
@@ -0,0 +1,256 @@
+//! `itsmine udp-flood`: sender threads blast small datagrams over loopback
+//! at receiver threads as fast as both can go, to drive NET_RX softirq
+//! load. It needs no target, and it reports which CPUs handled the
+//! softirqs, which is what IRQ-affinity and RPS setups change.
+
+use std::net::{Ipv4Addr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+use crate::{CancellationToken, StressError, Stressor, parse, report};
+
+/// How long a receiver waits for a datagram before checking for the end.
+const POLL: Duration = Duration::from_millis(50);
+/// Sends between deadline checks.
+const BATCH: u64 = 256;
+
+#[derive(Clone, Debug, PartialEq, clap::Args)]
+pub struct Args {
+    /// Sender and receiver thread pairs, each with its own sockets
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    pub pairs: u32,
+    /// Size of each datagram, e.g. 64
+    #[arg(long, value_name = "BYTES", value_parser = parse::bytes, default_value = "64")]
+    pub size: u64,
+    /// Receive buffer for each receiver (SO_RCVBUF); smaller drops sooner
+    #[arg(long, value_name = "BYTES", value_parser = parse::bytes)]
+    pub rcvbuf: Option<u64>,
+    /// How long to run, e.g. 10s
+    #[arg(long, value_parser = parse::duration, default_value = "10s")]
+    pub duration: Duration,
+}
+
+/// What one pair moved.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Flow {
+    sent: u64,
+    send_errors: u64,
+    received: u64,
+    /// Datagrams the kernel dropped at the receiving socket.
+    dropped: u64,
+}
+
+/// A receiver socket bound to a loopback port, and a sender connected to
+/// it.
+struct Pair {
+    receiver: UdpSocket,
+    sender: UdpSocket,
+}
+
+impl Pair {
+    fn open(rcvbuf: Option<u64>) -> std::io::Result<Self> {
+        let receiver = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+        receiver.set_read_timeout(Some(POLL))?;
+        if let Some(bytes) = rcvbuf {
+            let bytes = libc::c_int::try_from(bytes).unwrap_or(libc::c_int::MAX);
+            // SAFETY: `bytes` outlives the call and is the size given.
+            let set = unsafe {
+                libc::setsockopt(
+                    std::os::fd::AsRawFd::as_raw_fd(&receiver),
+                    libc::SOL_SOCKET,
+                    libc::SO_RCVBUF,
+                    (&bytes as *const libc::c_int).cast(),
+                    size_of::<libc::c_int>() as libc::socklen_t,
+                )
+            };
+            if set == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        let sender = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+        sender.connect(receiver.local_addr()?)?;
+        Ok(Pair { receiver, sender })
+    }
+
+    /// Floods the receiver until `deadline`, while the receiver counts what
+    /// arrives.
+    fn flood(&self, size: usize, deadline: Instant, cancel: &CancellationToken) -> Flow {
+        let stopped = AtomicBool::new(false);
+        let (sent, send_errors, received) = std::thread::scope(|s| {
+            let receiver = s.spawn(|| {
+                let mut buffer = vec![0; size.max(1)];
+                let mut received = 0;
+                loop {
+                    match self.receiver.recv(&mut buffer) {
+                        Ok(_) => received += 1,
+                        // Timed out: the flood may be over, and drained.
+                        Err(_) if stopped.load(Ordering::Relaxed) => return received,
+                        Err(_) => {}
+                    }
+                }
+            });
+            let datagram = vec![0xa5; size];
+            let (mut sent, mut send_errors) = (0, 0);
+            while Instant::now() < deadline && !cancel.is_cancelled() {
+                for _ in 0..BATCH {
+                    match self.sender.send(&datagram) {
+                        Ok(_) => sent += 1,
+                        Err(_) => send_errors += 1,
+                    }
+                }
+            }
+            stopped.store(true, Ordering::Relaxed);
+            (sent, send_errors, receiver.join().unwrap_or(0))
+        });
+        let port = self.receiver.local_addr().map(|a| a.port()).unwrap_or(0);
+        Flow {
+            sent,
+            send_errors,
+            received,
+            dropped: std::fs::read_to_string("/proc/net/udp")
+                .ok()
+                .and_then(|table| socket_drops(&table, port))
+                .unwrap_or(sent.saturating_sub(received)),
+        }
+    }
+}
+
+/// The drops column of the loopback socket on `port` in a /proc/net/udp
+/// table.
+fn socket_drops(table: &str, port: u16) -> Option<u64> {
+    let local = format!("0100007F:{port:04X}");
+    table.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.get(1) {
+            Some(address) if *address == local => fields.last()?.parse().ok(),
+            _ => None,
+        }
+    })
+}
+
+/// NET_RX softirqs handled so far on each CPU, from a /proc/softirqs table.
+fn net_rx(table: &str) -> Option<Vec<u64>> {
+    let line = table
+        .lines()
+        .find(|line| line.trim_start().starts_with("NET_RX:"))?;
+    line.split_whitespace()
+        .skip(1)
+        .map(|count| count.parse().ok())
+        .collect()
+}
+
+fn read_net_rx() -> Option<Vec<u64>> {
+    net_rx(&std::fs::read_to_string("/proc/softirqs").ok()?)
+}
+
+impl Stressor for Args {
+    fn name(&self) -> &'static str {
+        "udp-flood"
+    }
+
+    fn params(&self) -> Value {
+        json!({
+            "pairs": self.pairs,
+            "size": self.size,
+            "rcvbuf": self.rcvbuf,
+            "duration_secs": self.duration.as_secs_f64(),
+        })
+    }
+
+    fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        if self.pairs == 0 {
+            return Err(
+                StressError::InvalidInput("--pairs must be greater than 0".to_string()).into(),
+            );
+        }
+        // The largest UDP payload over IPv4.
+        if self.size > 65_507 {
+            return Err(
+                StressError::InvalidInput("--size must be at most 65507".to_string()).into(),
+            );
+        }
+        let pairs = (0..self.pairs)
+            .map(|_| Pair::open(self.rcvbuf))
+            .collect::<std::io::Result<Vec<_>>>()
+            .map_err(|e| anyhow::anyhow!("Failed to open loopback sockets: {e}"))?;
+        log::info!(
+            "Flooding loopback with {}-byte datagrams from {} pairs for {:?}.",
+            self.size,
+            self.pairs,
+            self.duration
+        );
+        let softirqs_before = read_net_rx();
+        let started = Instant::now();
+        let deadline = started + self.duration;
+        let flow = std::thread::scope(|s| {
+            let handles: Vec<_> = pairs
+                .iter()
+                .map(|pair| s.spawn(|| pair.flood(self.size as usize, deadline, cancel)))
+                .collect();
+            let mut total = Flow::default();
+            for handle in handles {
+                let flow = handle.join().map_err(|payload| {
+                    StressError::WorkerPanicked(crate::panic_message(&payload))
+                })?;
+                total.sent += flow.sent;
+                total.send_errors += flow.send_errors;
+                total.received += flow.received;
+                total.dropped += flow.dropped;
+            }
+            Ok::<_, StressError>(total)
+        })?;
+        let elapsed = started.elapsed().as_secs_f64();
+        let softirqs: Option<Vec<u64>> =
+            softirqs_before.zip(read_net_rx()).map(|(before, after)| {
+                after
+                    .iter()
+                    .zip(before)
+                    .map(|(after, before)| after.saturating_sub(before))
+                    .collect()
+            });
+        let summary = json!({
+            "sent": flow.sent,
+            "sent_per_sec": flow.sent as f64 / elapsed,
+            "send_errors": flow.send_errors,
+            "received": flow.received,
+            "received_per_sec": flow.received as f64 / elapsed,
+            "receiver_drops": flow.dropped,
+            "drop_rate": flow.dropped as f64 / flow.sent.max(1) as f64,
+            "net_rx_softirqs": softirqs.as_ref().map(|per_cpu| per_cpu.iter().sum::<u64>()),
+            "net_rx_softirqs_per_cpu": softirqs,
+        });
+        log::info!("UDP flood: {summary}.");
+        report::measure(self.name(), summary);
+        match cancel.is_cancelled() {
+            true => Err(StressError::Interrupted.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn floods_a_pair_and_counts_what_arrives() {
+        let pair = Pair::open(Some(4096)).unwrap();
+        let deadline = Instant::now() + Duration::from_millis(50);
+        let flow = pair.flood(64, deadline, &CancellationToken::new());
+        assert!(flow.sent >= BATCH);
+        assert!(flow.received > 0);
+        assert!(flow.received <= flow.sent);
+    }
+
+    #[test]
+    fn parses_proc_tables() {
+        let udp = "   sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode ref pointer drops\n\
+                   \x20 12: 0100007F:A1B2 00000000:0000 07 00000000:00000000 00:00000000 00000000     0        0 123 2 0000000000000000 42\n";
+        assert_eq!(socket_drops(udp, 0xa1b2), Some(42));
+        assert_eq!(socket_drops(udp, 53), None);
+        let softirqs = "                    CPU0       CPU1\n          HI:          1          0\n      NET_RX:        300         25\n";
+        assert_eq!(net_rx(softirqs), Some(vec![300, 25]));
+    }
+}
@@ -0,0 +1,249 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::monitor::parse_meminfo_kb;
+use crate::{CancellationToken, parse};
+
+/// How often `--until` conditions are evaluated.
+const POLL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Above,
+    Below,
+}
+
+/// A system state that ends the run once reached, e.g. `swap-used>1G`,
+/// `mem-available<512M`, `load>16` or `file-exists=/tmp/stop`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Condition {
+    SwapUsed(Comparison, u64),
+    MemAvailable(Comparison, u64),
+    /// One-minute load average.
+    Load(Comparison, f64),
+    FileExists(PathBuf),
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(path) = s.strip_prefix("file-exists") {
+            let path = path.trim_start_matches(['=', ' ']).trim();
+            if path.is_empty() {
+                return Err("file-exists needs a path, e.g. file-exists=/tmp/stop".to_string());
+            }
+            return Ok(Condition::FileExists(PathBuf::from(path)));
+        }
+        let (metric, comparison, value) = match (s.split_once('>'), s.split_once('<')) {
+            (Some((metric, value)), None) => (metric, Comparison::Above, value),
+            (None, Some((metric, value))) => (metric, Comparison::Below, value),
+            _ => {
+                return Err(format!(
+                    "condition '{s}' must look like <metric>><value> or <metric><<value>"
+                ));
+            }
+        };
+        match metric.trim() {
+            "swap-used" => Ok(Condition::SwapUsed(comparison, parse::bytes(value)?)),
+            "mem-available" => Ok(Condition::MemAvailable(comparison, parse::bytes(value)?)),
+            "load" => Ok(Condition::Load(
+                comparison,
+                value
+                    .trim()
+                    .parse()
+                    .map_err(|e| format!("invalid load '{value}': {e}"))?,
+            )),
+            other => Err(format!(
+                "unknown metric '{other}' (use swap-used, mem-available, load or file-exists)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = |c: &Comparison| match c {
+            Comparison::Above => '>',
+            Comparison::Below => '<',
+        };
+        match self {
+            Condition::SwapUsed(c, v) => write!(f, "swap-used{}{v}", op(c)),
+            Condition::MemAvailable(c, v) => write!(f, "mem-available{}{v}", op(c)),
+            Condition::Load(c, v) => write!(f, "load{}{v}", op(c)),
+            Condition::FileExists(path) => write!(f, "file-exists={}", path.display()),
+        }
+    }
+}
+
+/// Current readings of the metrics conditions compare against.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Sample {
+    pub swap_used: Option<u64>,
+    pub mem_available: Option<u64>,
+    pub load: Option<f64>,
+}
+
+impl Sample {
+    pub fn read() -> Self {
+        let meminfo = std::fs::read_to_string("/proc/meminfo").unwrap_or_default();
+        let kb = |key| parse_meminfo_kb(&meminfo, key).map(|kb| kb * 1024);
+        Sample {
+            swap_used: kb("SwapTotal")
+                .zip(kb("SwapFree"))
+                .map(|(total, free)| total.saturating_sub(free)),
+            mem_available: kb("MemAvailable"),
+            load: std::fs::read_to_string("/proc/loadavg")
+                .ok()
+                .and_then(|s| s.split_whitespace().next()?.parse().ok()),
+        }
+    }
+}
+
+impl Condition {
+    pub fn is_met(&self, sample: &Sample) -> bool {
+        fn compare<T: PartialOrd>(comparison: Comparison, value: Option<T>, limit: T) -> bool {
+            value.is_some_and(|value| match comparison {
+                Comparison::Above => value > limit,
+                Comparison::Below => value < limit,
+            })
+        }
+        match self {
+            Condition::SwapUsed(c, limit) => compare(*c, sample.swap_used, *limit),
+            Condition::MemAvailable(c, limit) => compare(*c, sample.mem_available, *limit),
+            Condition::Load(c, limit) => compare(*c, sample.load, *limit),
+            Condition::FileExists(path) => path.exists(),
+        }
+    }
+}
+
+/// What ended a run before it finished on its own.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Trigger {
+    Condition(Condition),
+    /// The invocation's `--deadline` passed.
+    Deadline,
+}
+
+impl fmt::Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Trigger::Condition(condition) => write!(f, "until {condition}"),
+            Trigger::Deadline => write!(f, "deadline"),
+        }
+    }
+}
+
+/// Polls conditions in the background and cancels the run when one is met
+/// or `deadline` passes.
+pub struct Watcher {
+    handle: std::thread::JoinHandle<Option<Trigger>>,
+    stop: CancellationToken,
+}
+
+impl Watcher {
+    pub fn start(
+        conditions: Vec<Condition>,
+        deadline: Option<Instant>,
+        run: CancellationToken,
+    ) -> Self {
+        let stop = CancellationToken::new();
+        let token = stop.clone();
+        let handle = std::thread::spawn(move || {
+            loop {
+                let sample = Sample::read();
+                let trigger = match conditions.iter().find(|c| c.is_met(&sample)) {
+                    Some(met) => Some(Trigger::Condition(met.clone())),
+                    None if deadline.is_some_and(|d| Instant::now() >= d) => {
+                        Some(Trigger::Deadline)
+                    }
+                    None => None,
+                };
+                if let Some(trigger) = trigger {
+                    log::info!("Stopping the run: {trigger}.");
+                    run.cancel();
+                    return Some(trigger);
+                }
+                let next = Instant::now() + POLL;
+                if !token.sleep_until(deadline.map_or(next, |d| d.min(next))) {
+                    return None;
+                }
+            }
+        });
+        Watcher { handle, stop }
+    }
+
+    /// Stops polling and returns what ended the run, if anything.
+    pub fn stop(self) -> Option<Trigger> {
+        self.stop.cancel();
+        self.handle.join().ok().flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_conditions() {
+        assert_eq!(
+            "swap-used>1G".parse(),
+            Ok(Condition::SwapUsed(Comparison::Above, 1 << 30))
+        );
+        assert_eq!(
+            "mem-available<512M".parse(),
+            Ok(Condition::MemAvailable(Comparison::Below, 512 << 20))
+        );
+        assert_eq!(
+            "load>16".parse(),
+            Ok(Condition::Load(Comparison::Above, 16.0))
+        );
+        assert_eq!(
+            "file-exists /tmp/stop".parse(),
+            Ok(Condition::FileExists(PathBuf::from("/tmp/stop")))
+        );
+        assert!("load=3".parse::<Condition>().is_err());
+        assert!("cpu>50".parse::<Condition>().is_err());
+        assert!("file-exists".parse::<Condition>().is_err());
+    }
+
+    #[test]
+    fn conditions_compare_samples() {
+        let sample = Sample {
+            swap_used: Some(2 << 30),
+            mem_available: None,
+            load: Some(3.5),
+        };
+        assert!(Condition::SwapUsed(Comparison::Above, 1 << 30).is_met(&sample));
+        assert!(!Condition::Load(Comparison::Above, 16.0).is_met(&sample));
+        assert!(Condition::Load(Comparison::Below, 16.0).is_met(&sample));
+        // Unknown readings never trigger.
+        assert!(!Condition::MemAvailable(Comparison::Below, u64::MAX).is_met(&sample));
+    }
+
+    #[test]
+    fn watcher_cancels_run_when_file_appears() {
+        let path = std::env::temp_dir().join("itsmine-until-stop");
+        std::fs::write(&path, "").unwrap();
+        let run = CancellationToken::new();
+        let watcher = Watcher::start(vec![Condition::FileExists(path.clone())], None, run.clone());
+        assert!(!run.sleep_until(Instant::now() + Duration::from_secs(5)));
+        assert_eq!(
+            watcher.stop(),
+            Some(Trigger::Condition(Condition::FileExists(path)))
+        );
+    }
+
+    #[test]
+    fn watcher_cancels_run_at_deadline() {
+        let run = CancellationToken::new();
+        let deadline = Instant::now() + Duration::from_millis(100);
+        let watcher = Watcher::start(vec![], Some(deadline), run.clone());
+        assert!(!run.sleep_until(Instant::now() + Duration::from_secs(5)));
+        assert!(Instant::now() < deadline + POLL);
+        assert_eq!(watcher.stop(), Some(Trigger::Deadline));
+    }
+}
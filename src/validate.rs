@@ -0,0 +1,507 @@
+//! `itsmine validate-cgroup`: runs stressors sized from a cgroup v2 group's
+//! own limits inside that group, and checks from its counters that each
+//! configured controller held: CPU throttled at `cpu.max`, `memory.high`
+//! reclaiming, an OOM kill confined to the group at `memory.max`, forks
+//! refused at `pids.max`, and writes slowed to `io.max`.
+
+use std::fs::File;
+use std::os::fd::AsRawFd;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::CancellationToken;
+
+/// How often a running check samples the group.
+const POLL: Duration = Duration::from_millis(50);
+/// How long a child may run past its duration before it is killed.
+const GRACE: Duration = Duration::from_secs(5);
+/// How far over a rate limit a measurement may land and still pass, for
+/// accounting granularity.
+const TOLERANCE: f64 = 1.1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    Pass,
+    Fail,
+    /// The limit is not configured, or its controller not enabled.
+    Skip,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Check {
+    /// The limit checked, e.g. `memory.high`.
+    pub limit: &'static str,
+    pub verdict: Verdict,
+    /// What was observed, or why the check was skipped.
+    pub detail: String,
+    #[serde(rename = "elapsed_secs", serialize_with = "secs")]
+    pub elapsed: Duration,
+}
+
+fn secs<S: serde::Serializer>(elapsed: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(elapsed.as_secs_f64())
+}
+
+/// A cgroup v2 group to validate.
+pub struct Group {
+    dir: PathBuf,
+}
+
+/// How a stressor run inside the group ended.
+struct Run {
+    status: ExitStatus,
+    elapsed: Duration,
+    /// The highest value the sampled file showed.
+    peak: Option<u64>,
+}
+
+impl Group {
+    pub fn open(dir: &Path) -> Result<Self, anyhow::Error> {
+        if !dir.join("cgroup.controllers").exists() {
+            return Err(anyhow::anyhow!(
+                "{} is not a cgroup v2 group (no cgroup.controllers)",
+                dir.display()
+            ));
+        }
+        Ok(Group {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    fn read(&self, file: &str) -> Option<String> {
+        std::fs::read_to_string(self.dir.join(file)).ok()
+    }
+
+    /// A limit file's value; `None` for `max` or when the file is missing.
+    fn limit(&self, file: &str) -> Option<u64> {
+        self.read(file)?.trim().parse().ok()
+    }
+
+    /// `key`'s value in a flat keyed file such as `cpu.stat`.
+    fn keyed(&self, file: &str, key: &str) -> u64 {
+        self.read(file)
+            .and_then(|content| keyed(&content, key))
+            .unwrap_or(0)
+    }
+
+    /// Runs `itsmine <args>` inside the group until it exits, sampling the
+    /// `sample` file meanwhile; the child is killed `GRACE` after
+    /// `duration`.
+    fn stress(
+        &self,
+        args: &[String],
+        duration: Duration,
+        sample: Option<&str>,
+        cancel: &CancellationToken,
+    ) -> Result<Run, anyhow::Error> {
+        let procs = File::options()
+            .write(true)
+            .open(self.dir.join("cgroup.procs"))
+            .map_err(|e| {
+                anyhow::anyhow!("Failed to open {}/cgroup.procs: {e}", self.dir.display())
+            })?;
+        let fd = procs.as_raw_fd();
+        let mut command = Command::new(std::env::current_exe()?);
+        command
+            .arg("--quiet")
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        // SAFETY: the hook only calls `write`, which is async-signal-safe,
+        // on a descriptor that stays open until `spawn` returns.
+        unsafe {
+            command.pre_exec(move || match libc::write(fd, b"0".as_ptr().cast(), 1) {
+                -1 => Err(std::io::Error::last_os_error()),
+                _ => Ok(()),
+            });
+        }
+        let started = Instant::now();
+        let mut child = command
+            .spawn()
+            .map_err(|e| anyhow::anyhow!("Failed to start itsmine {}: {e}", args[0]))?;
+        let mut peak = None;
+        let status = loop {
+            peak = peak.max(sample.and_then(|file| self.limit(file)));
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if cancel.is_cancelled() || started.elapsed() > duration + GRACE {
+                break kill(&mut child)?;
+            }
+            std::thread::sleep(POLL);
+        };
+        Ok(Run {
+            status,
+            elapsed: started.elapsed(),
+            peak,
+        })
+    }
+}
+
+fn kill(child: &mut Child) -> std::io::Result<ExitStatus> {
+    child.kill()?;
+    child.wait()
+}
+
+/// `key`'s value in a flat keyed file's `content`.
+fn keyed(content: &str, key: &str) -> Option<u64> {
+    content.lines().find_map(|line| {
+        let (name, value) = line.split_once(' ')?;
+        (name == key).then(|| value.trim().parse().ok())?
+    })
+}
+
+/// The `<quota> <period>` of a `cpu.max` in cores, unless it is `max`.
+fn cores(cpu_max: &str) -> Option<f64> {
+    let (quota, period) = cpu_max.trim().split_once(' ')?;
+    let (quota, period): (f64, f64) = (quota.parse().ok()?, period.parse().ok()?);
+    (period > 0.0).then_some(quota / period)
+}
+
+/// `key`'s value on an `io.max` or `io.stat` line, which look like
+/// `8:16 rbps=max wbps=1048576 ...`; `None` for `max`.
+fn io_field(line: &str, key: &str) -> Option<u64> {
+    line.split_whitespace().skip(1).find_map(|field| {
+        let (name, value) = field.split_once('=')?;
+        (name == key).then(|| value.parse().ok())?
+    })
+}
+
+/// `key` for `device` in an `io.stat`.
+fn io_stat(content: &str, device: &str, key: &str) -> u64 {
+    content
+        .lines()
+        .find(|line| line.split_whitespace().next() == Some(device))
+        .and_then(|line| io_field(line, key))
+        .unwrap_or(0)
+}
+
+fn skip(limit: &'static str, detail: impl Into<String>) -> Check {
+    Check {
+        limit,
+        verdict: Verdict::Skip,
+        detail: detail.into(),
+        elapsed: Duration::ZERO,
+    }
+}
+
+fn judged(limit: &'static str, pass: bool, detail: String, elapsed: Duration) -> Check {
+    Check {
+        limit,
+        verdict: match pass {
+            true => Verdict::Pass,
+            false => Verdict::Fail,
+        },
+        detail,
+        elapsed,
+    }
+}
+
+fn strings<const N: usize>(args: [&str; N]) -> Vec<String> {
+    args.map(String::from).to_vec()
+}
+
+/// Runs every check against the group at `dir`, each stressor for
+/// `duration`, writing `io.max` load under `io_dir`.
+pub fn run(
+    dir: &Path,
+    duration: Duration,
+    io_dir: &Path,
+    cancel: &CancellationToken,
+) -> Result<Vec<Check>, anyhow::Error> {
+    let group = Group::open(dir)?;
+    Ok(vec![
+        cpu(&group, duration, cancel)?,
+        memory_high(&group, duration, cancel)?,
+        memory_max(&group, duration, cancel)?,
+        pids(&group, duration, cancel)?,
+        io(&group, duration, io_dir, cancel)?,
+    ])
+}
+
+/// Spins more threads than `cpu.max` allows and expects throttling, with
+/// usage at most the quota.
+fn cpu(
+    group: &Group,
+    duration: Duration,
+    cancel: &CancellationToken,
+) -> Result<Check, anyhow::Error> {
+    let Some(content) = group.read("cpu.max") else {
+        return Ok(skip("cpu.max", "the cpu controller is not enabled"));
+    };
+    let Some(limit) = cores(&content) else {
+        return Ok(skip("cpu.max", "no quota is set"));
+    };
+    let (usage, throttled) = (
+        group.keyed("cpu.stat", "usage_usec"),
+        group.keyed("cpu.stat", "nr_throttled"),
+    );
+    let threads = limit.ceil() as u64 + 1;
+    let timeout = format!("{}ms", duration.as_millis());
+    let run = group.stress(
+        &strings(["ng", "--cpu", &threads.to_string(), "--timeout", &timeout]),
+        duration,
+        None,
+        cancel,
+    )?;
+    let used =
+        (group.keyed("cpu.stat", "usage_usec") - usage) as f64 / 1e6 / run.elapsed.as_secs_f64();
+    let throttled = group.keyed("cpu.stat", "nr_throttled") - throttled;
+    Ok(judged(
+        "cpu.max",
+        throttled > 0 && used <= limit * TOLERANCE,
+        format!(
+            "{threads} spinning threads used {used:.2} of {limit:.2} cores, throttled in {throttled} periods"
+        ),
+        run.elapsed,
+    ))
+}
+
+/// Touches half again `memory.high` and expects the group to be held near
+/// it by reclaim, counted as `high` events.
+fn memory_high(
+    group: &Group,
+    duration: Duration,
+    cancel: &CancellationToken,
+) -> Result<Check, anyhow::Error> {
+    if group.read("memory.high").is_none() {
+        return Ok(skip("memory.high", "the memory controller is not enabled"));
+    }
+    let Some(high) = group.limit("memory.high") else {
+        return Ok(skip("memory.high", "no memory.high is set"));
+    };
+    if group
+        .limit("memory.max")
+        .is_some_and(|max| max <= high * 3 / 2)
+    {
+        return Ok(skip(
+            "memory.high",
+            "memory.max is within half again memory.high, which would OOM first",
+        ));
+    }
+    let events = group.keyed("memory.events", "high");
+    let timeout = format!("{}ms", duration.as_millis());
+    let run = group.stress(
+        &strings([
+            "ng",
+            "--vm",
+            "1",
+            "--vm-bytes",
+            &(high * 3 / 2).to_string(),
+            "--timeout",
+            &timeout,
+        ]),
+        duration,
+        Some("memory.current"),
+        cancel,
+    )?;
+    let events = group.keyed("memory.events", "high") - events;
+    let peak = run.peak.unwrap_or(0);
+    Ok(judged(
+        "memory.high",
+        events > 0,
+        format!(
+            "touching {} bytes raised {events} high events, peaking at {peak} of {high} bytes",
+            high * 3 / 2
+        ),
+        run.elapsed,
+    ))
+}
+
+/// Touches twice `memory.max` and expects an OOM kill inside the group,
+/// of the stressor, while this process survives outside it.
+fn memory_max(
+    group: &Group,
+    duration: Duration,
+    cancel: &CancellationToken,
+) -> Result<Check, anyhow::Error> {
+    use std::os::unix::process::ExitStatusExt;
+
+    if group.read("memory.max").is_none() {
+        return Ok(skip("memory.max", "the memory controller is not enabled"));
+    }
+    let Some(max) = group.limit("memory.max") else {
+        return Ok(skip("memory.max", "no memory.max is set"));
+    };
+    let kills = group.keyed("memory.events", "oom_kill");
+    let timeout = format!("{}ms", duration.as_millis());
+    let run = group.stress(
+        &strings([
+            "ng",
+            "--vm",
+            "1",
+            "--vm-bytes",
+            &(max * 2).to_string(),
+            "--timeout",
+            &timeout,
+        ]),
+        duration,
+        Some("memory.current"),
+        cancel,
+    )?;
+    let kills = group.keyed("memory.events", "oom_kill") - kills;
+    let killed = run.status.signal() == Some(libc::SIGKILL);
+    Ok(judged(
+        "memory.max",
+        kills > 0 && killed,
+        format!(
+            "touching {} bytes led to {kills} OOM kills in the group; the stressor {}",
+            max * 2,
+            match killed {
+                true => "was killed".to_string(),
+                false => format!("ended with {}", run.status),
+            }
+        ),
+        run.elapsed,
+    ))
+}
+
+/// Forks past `pids.max` and expects forks refused, counted as `max`
+/// events, with the group never above the limit.
+fn pids(
+    group: &Group,
+    duration: Duration,
+    cancel: &CancellationToken,
+) -> Result<Check, anyhow::Error> {
+    if group.read("pids.max").is_none() {
+        return Ok(skip("pids.max", "the pids controller is not enabled"));
+    }
+    let Some(max) = group.limit("pids.max") else {
+        return Ok(skip("pids.max", "no pids.max is set"));
+    };
+    let events = group.keyed("pids.events", "max");
+    let run = group.stress(
+        &strings([
+            "zombies",
+            "--count",
+            &(max + 16).to_string(),
+            "--duration",
+            &format!("{}ms", duration.as_millis()),
+        ]),
+        duration,
+        Some("pids.current"),
+        cancel,
+    )?;
+    let events = group.keyed("pids.events", "max") - events;
+    let peak = run.peak.unwrap_or(0);
+    Ok(judged(
+        "pids.max",
+        events > 0 && peak <= max,
+        format!(
+            "forking {} zombies hit the limit {events} times, peaking at {peak} of {max} tasks",
+            max + 16
+        ),
+        run.elapsed,
+    ))
+}
+
+/// Streams synced writes under `io_dir` and expects the first device with
+/// a `wbps` limit to be written no faster than it.
+fn io(
+    group: &Group,
+    duration: Duration,
+    io_dir: &Path,
+    cancel: &CancellationToken,
+) -> Result<Check, anyhow::Error> {
+    let Some(content) = group.read("io.max") else {
+        return Ok(skip("io.max", "the io controller is not enabled"));
+    };
+    let Some((device, wbps)) = content.lines().find_map(|line| {
+        Some((
+            line.split_whitespace().next()?.to_string(),
+            io_field(line, "wbps")?,
+        ))
+    }) else {
+        return Ok(skip("io.max", "no device has a wbps limit"));
+    };
+    let written = |group: &Group| {
+        io_stat(
+            &group.read("io.stat").unwrap_or_default(),
+            &device,
+            "wbytes",
+        )
+    };
+    let before = written(group);
+    let run = group.stress(
+        &[
+            "disk".to_string(),
+            format!("--path={}", io_dir.display()),
+            "--size=256M".to_string(),
+            format!("--duration={}ms", duration.as_millis()),
+        ],
+        duration,
+        None,
+        cancel,
+    )?;
+    let bytes = written(group) - before;
+    let rate = bytes as f64 / run.elapsed.as_secs_f64();
+    if bytes == 0 {
+        return Ok(judged(
+            "io.max",
+            false,
+            format!("no writes reached {device}; is {} on it?", io_dir.display()),
+            run.elapsed,
+        ));
+    }
+    Ok(judged(
+        "io.max",
+        rate <= wbps as f64 * TOLERANCE,
+        format!("wrote {rate:.0} bytes/s to {device}, limited to {wbps}"),
+        run.elapsed,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_group_files() {
+        let dir = std::env::temp_dir().join("itsmine-validate-group");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        assert!(Group::open(&dir).is_err());
+        std::fs::write(dir.join("cgroup.controllers"), "cpu memory pids io\n").unwrap();
+        std::fs::write(dir.join("memory.high"), "max\n").unwrap();
+        std::fs::write(dir.join("pids.max"), "64\n").unwrap();
+        std::fs::write(
+            dir.join("cpu.stat"),
+            "usage_usec 500\nnr_periods 10\nnr_throttled 3\n",
+        )
+        .unwrap();
+        let group = Group::open(&dir).unwrap();
+        assert_eq!(group.limit("memory.high"), None);
+        assert_eq!(group.limit("pids.max"), Some(64));
+        assert_eq!(group.keyed("cpu.stat", "nr_throttled"), 3);
+        assert_eq!(group.keyed("cpu.stat", "nr_bursts"), 0);
+
+        // Unset or missing limits are skipped without running anything.
+        let cancel = CancellationToken::new();
+        let check = memory_high(&group, Duration::ZERO, &cancel).unwrap();
+        assert_eq!(
+            (check.verdict, check.detail.as_str()),
+            (Verdict::Skip, "no memory.high is set")
+        );
+        let check = cpu(&group, Duration::ZERO, &cancel).unwrap();
+        assert_eq!(check.verdict, Verdict::Skip);
+    }
+
+    #[test]
+    fn parses_cpu_and_io_limits() {
+        assert_eq!(cores("150000 100000\n"), Some(1.5));
+        assert_eq!(cores("max 100000\n"), None);
+        let line = "8:16 rbps=max wbps=1048576 riops=max wiops=120";
+        assert_eq!(io_field(line, "wbps"), Some(1_048_576));
+        assert_eq!(io_field(line, "rbps"), None);
+        assert_eq!(io_field(line, "wbytes"), None);
+        let stat = "8:0 rbytes=1 wbytes=2\n8:16 rbytes=3 wbytes=4096 rios=1 wios=2\n";
+        assert_eq!(io_stat(stat, "8:16", "wbytes"), 4096);
+        assert_eq!(io_stat(stat, "9:0", "wbytes"), 0);
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+/// Mismatches kept in the report; later ones are only counted.
+const MAX_MISMATCHES: usize = 100;
+
+static VERIFICATION: Mutex<Option<Verification>> = Mutex::new(None);
+
+/// What a workload's results must satisfy.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Expectation {
+    /// Every iteration must produce this value.
+    Value(u32),
+    /// Iterations must agree with each other; the first result seen becomes
+    /// the reference. Used for user workloads with no known answer.
+    Agreement,
+}
+
+/// One iteration's result as reported by a worker.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Observation {
+    pub thread: u32,
+    pub iteration: u64,
+    /// CPU the iteration finished on, if known.
+    pub cpu: Option<u32>,
+    pub result: u32,
+}
+
+impl Observation {
+    /// An observation stamped with the CPU the calling thread is on.
+    pub fn here(thread: u32, iteration: u64, result: u32) -> Self {
+        // SAFETY: sched_getcpu has no preconditions.
+        let cpu = unsafe { libc::sched_getcpu() };
+        Observation {
+            thread,
+            iteration,
+            cpu: u32::try_from(cpu).ok(),
+            result,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Mismatch {
+    pub workload: String,
+    pub thread: u32,
+    pub cpu: Option<u32>,
+    pub iteration: u64,
+    pub expected: u32,
+    pub got: u32,
+}
+
+/// The report's verification section: how many results were checked and
+/// which ones were wrong.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Verification {
+    pub checked: u64,
+    pub mismatch_count: u64,
+    /// The first mismatches, up to a cap.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mismatches: Vec<Mismatch>,
+    pub passed: bool,
+}
+
+impl Verification {
+    fn merge(&mut self, other: Verification) {
+        self.checked += other.checked;
+        self.mismatch_count += other.mismatch_count;
+        let room = MAX_MISMATCHES.saturating_sub(self.mismatches.len());
+        self.mismatches
+            .extend(other.mismatches.into_iter().take(room));
+        self.passed = self.mismatch_count == 0;
+    }
+}
+
+/// Checks observations against each workload's expectation as they arrive.
+#[derive(Default)]
+pub struct Verifier {
+    references: BTreeMap<&'static str, u32>,
+    verification: Verification,
+}
+
+impl Verifier {
+    pub fn new() -> Self {
+        Verifier::default()
+    }
+
+    /// Checks one result of `workload`, recording it if it's wrong.
+    pub fn check(&mut self, workload: &'static str, expectation: Expectation, seen: Observation) {
+        let expected = match expectation {
+            Expectation::Value(value) => value,
+            Expectation::Agreement => *self.references.entry(workload).or_insert(seen.result),
+        };
+        self.verification.checked += 1;
+        if seen.result == expected {
+            return;
+        }
+        log::error!(
+            "Thread {} ({workload}) on CPU {:?} computed {} at iteration {}, expected {expected}.",
+            seen.thread,
+            seen.cpu,
+            seen.result,
+            seen.iteration
+        );
+        self.verification.mismatch_count += 1;
+        if self.verification.mismatches.len() < MAX_MISMATCHES {
+            self.verification.mismatches.push(Mismatch {
+                workload: workload.to_string(),
+                thread: seen.thread,
+                cpu: seen.cpu,
+                iteration: seen.iteration,
+                expected,
+                got: seen.result,
+            });
+        }
+    }
+
+    /// Adds this verifier's findings to the run's verification section and
+    /// returns whether everything checked out.
+    pub fn finish(mut self) -> bool {
+        self.verification.passed = self.verification.mismatch_count == 0;
+        let passed = self.verification.passed;
+        record(self.verification);
+        passed
+    }
+}
+
+/// Adds `verification` to the run's verification section, e.g. one an
+/// isolated child sent back.
+pub fn record(verification: Verification) {
+    VERIFICATION
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get_or_insert_with(|| Verification {
+            passed: true,
+            ..Default::default()
+        })
+        .merge(verification);
+}
+
+/// Takes the verification section recorded since the last call, if any
+/// results were checked.
+pub fn take() -> Option<Verification> {
+    VERIFICATION
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seen(thread: u32, iteration: u64, result: u32) -> Observation {
+        Observation {
+            thread,
+            iteration,
+            cpu: Some(thread),
+            result,
+        }
+    }
+
+    #[test]
+    fn collects_mismatches_against_expectations() {
+        let mut verifier = Verifier::new();
+        verifier.check("fib", Expectation::Value(832040), seen(0, 1, 832040));
+        verifier.check("fib", Expectation::Value(832040), seen(1, 3, 7));
+        verifier.check("script", Expectation::Agreement, seen(0, 1, 5));
+        verifier.check("script", Expectation::Agreement, seen(1, 1, 5));
+        verifier.check("script", Expectation::Agreement, seen(2, 2, 6));
+        let verification = verifier.verification.clone();
+        assert_eq!(verification.checked, 5);
+        assert_eq!(verification.mismatch_count, 2);
+        assert_eq!(
+            verification.mismatches[0],
+            Mismatch {
+                workload: "fib".to_string(),
+                thread: 1,
+                cpu: Some(1),
+                iteration: 3,
+                expected: 832040,
+                got: 7
+            }
+        );
+        assert_eq!(verification.mismatches[1].expected, 5);
+        assert!(!verifier.finish());
+    }
+
+    #[test]
+    fn merging_caps_kept_mismatches() {
+        let mut total = Verification {
+            passed: true,
+            ..Default::default()
+        };
+        let mismatch = Mismatch {
+            workload: "fib".to_string(),
+            thread: 0,
+            cpu: None,
+            iteration: 1,
+            expected: 1,
+            got: 2,
+        };
+        total.merge(Verification {
+            checked: 200,
+            mismatch_count: 150,
+            mismatches: vec![mismatch; MAX_MISMATCHES],
+            passed: false,
+        });
+        assert_eq!(total.mismatches.len(), MAX_MISMATCHES);
+        assert_eq!(total.mismatch_count, 150);
+        assert!(!total.passed);
+    }
+}
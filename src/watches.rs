@@ -0,0 +1,241 @@
+//! `itsmine watches`: registers inotify watches or epoll entries up to a
+//! count or until the kernel refuses one, to check limits such as
+//! `fs.inotify.max_user_watches` and how file-watching daemons cope once
+//! they are reached.
+
+use std::ffi::CString;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+use crate::{CancellationToken, StressError, Stressor, parse, report, sysinfo};
+
+/// Distinct targets per instance: files to watch or eventfds to poll. Each
+/// instance watches all of them, so watches add up without as many files.
+const TARGETS: usize = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Kind {
+    Inotify,
+    Epoll,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Inotify => "inotify",
+            Kind::Epoll => "epoll",
+        }
+    }
+
+    /// The per-user limit on this kind of registration.
+    fn limit_path(self) -> &'static str {
+        match self {
+            Kind::Inotify => "/proc/sys/fs/inotify/max_user_watches",
+            Kind::Epoll => "/proc/sys/fs/epoll/max_user_watches",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, clap::Args)]
+pub struct Args {
+    /// What to register
+    #[arg(long, value_enum, default_value = "inotify")]
+    pub kind: Kind,
+    /// Watches to register; by default, until the kernel refuses one
+    #[arg(long, value_name = "N")]
+    pub count: Option<u64>,
+    /// Directory to create the watched files in
+    #[arg(long, value_name = "PATH", default_value_os_t = std::env::temp_dir())]
+    pub dir: PathBuf,
+    /// How long to hold them, e.g. 10s
+    #[arg(long, value_parser = parse::duration, default_value = "10s")]
+    pub duration: Duration,
+}
+
+/// Registered watches, released on drop along with their files.
+#[derive(Default)]
+struct Watches {
+    instances: Vec<OwnedFd>,
+    /// Eventfds polled by every epoll instance.
+    targets: Vec<OwnedFd>,
+    /// Files watched by every inotify instance, and their directory.
+    paths: Vec<CString>,
+    files: Option<PathBuf>,
+    registered: u64,
+}
+
+impl Drop for Watches {
+    fn drop(&mut self) {
+        if let Some(dir) = &self.files {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+    }
+}
+
+fn check(result: libc::c_int) -> std::io::Result<libc::c_int> {
+    match result {
+        -1 => Err(std::io::Error::last_os_error()),
+        result => Ok(result),
+    }
+}
+
+impl Watches {
+    /// Registers one more watch, opening a new instance once the current
+    /// one watches every target.
+    fn add(&mut self, kind: Kind, dir: &Path) -> std::io::Result<()> {
+        let target = (self.registered % TARGETS as u64) as usize;
+        // SAFETY: plain syscalls; every descriptor is owned as soon as it's
+        // made, and the path and event outlive the calls using them.
+        unsafe {
+            if target == 0 {
+                let instance = match kind {
+                    Kind::Inotify => check(libc::inotify_init1(libc::IN_CLOEXEC))?,
+                    Kind::Epoll => check(libc::epoll_create1(libc::EPOLL_CLOEXEC))?,
+                };
+                self.instances.push(OwnedFd::from_raw_fd(instance));
+            }
+            let instance = self.instances.last().expect("opened above").as_raw_fd();
+            match kind {
+                Kind::Inotify => {
+                    if target == self.paths.len() {
+                        self.create_file(dir)?;
+                    }
+                    let mask = libc::IN_MODIFY | libc::IN_ATTRIB;
+                    check(libc::inotify_add_watch(
+                        instance,
+                        self.paths[target].as_ptr(),
+                        mask,
+                    ))?;
+                }
+                Kind::Epoll => {
+                    if target == self.targets.len() {
+                        let fd = check(libc::eventfd(0, libc::EFD_CLOEXEC))?;
+                        self.targets.push(OwnedFd::from_raw_fd(fd));
+                    }
+                    let mut event = libc::epoll_event {
+                        events: libc::EPOLLIN as u32,
+                        u64: target as u64,
+                    };
+                    check(libc::epoll_ctl(
+                        instance,
+                        libc::EPOLL_CTL_ADD,
+                        self.targets[target].as_raw_fd(),
+                        &mut event,
+                    ))?;
+                }
+            }
+        }
+        self.registered += 1;
+        Ok(())
+    }
+
+    fn create_file(&mut self, dir: &Path) -> std::io::Result<()> {
+        let files = match &self.files {
+            Some(files) => files,
+            None => {
+                let files = dir.join(format!("itsmine-watches-{}", std::process::id()));
+                std::fs::create_dir_all(&files)?;
+                self.files.insert(files)
+            }
+        };
+        let path = files.join(self.paths.len().to_string());
+        std::fs::File::create(&path)?;
+        self.paths.push(CString::new(path.as_os_str().as_bytes())?);
+        Ok(())
+    }
+}
+
+/// Registers up to `count` watches of `kind`, stopping at the first
+/// failure, which is returned alongside what was registered.
+fn register(
+    kind: Kind,
+    count: Option<u64>,
+    dir: &Path,
+    cancel: &CancellationToken,
+) -> (Watches, Option<std::io::Error>) {
+    let mut watches = Watches::default();
+    while count.is_none_or(|count| watches.registered < count) && !cancel.is_cancelled() {
+        if let Err(e) = watches.add(kind, dir) {
+            return (watches, Some(e));
+        }
+    }
+    (watches, None)
+}
+
+impl Stressor for Args {
+    fn name(&self) -> &'static str {
+        "watches"
+    }
+
+    fn params(&self) -> Value {
+        json!({
+            "kind": self.kind.name(),
+            "count": self.count,
+            "duration_secs": self.duration.as_secs_f64(),
+        })
+    }
+
+    fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        if let Err(e) = sysinfo::raise_fd_limit() {
+            log::warn!("Could not raise the open-file limit: {e}.");
+        }
+        let limit: Option<u64> = std::fs::read_to_string(self.kind.limit_path())
+            .ok()
+            .and_then(|limit| limit.trim().parse().ok());
+        let started = Instant::now();
+        let (watches, error) = register(self.kind, self.count, &self.dir, cancel);
+        let elapsed = started.elapsed();
+        if let Some(e) = &error {
+            log::warn!(
+                "Registering {} watch {} failed: {e}.",
+                self.kind.name(),
+                watches.registered + 1
+            );
+        }
+        log::info!(
+            "Holding {} {} watches for {:?}.",
+            watches.registered,
+            self.kind.name(),
+            self.duration
+        );
+        let summary = json!({
+            "registered": watches.registered,
+            "limit": limit,
+            "error": error.map(|e| e.to_string()),
+            "registrations_per_sec": watches.registered as f64 / elapsed.as_secs_f64(),
+        });
+        cancel.sleep_until(Instant::now() + self.duration);
+        drop(watches);
+        log::info!("Watches: {summary}.");
+        report::measure(self.name(), summary);
+        match cancel.is_cancelled() {
+            true => Err(StressError::Interrupted.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registers_across_instances() {
+        let dir = std::env::temp_dir().join(format!("itsmine-watches-test-{}", std::process::id()));
+        for kind in [Kind::Inotify, Kind::Epoll] {
+            let count = TARGETS as u64 + 10;
+            let (watches, error) = register(kind, Some(count), &dir, &CancellationToken::new());
+            assert!(error.is_none(), "{kind:?}: {error:?}");
+            assert_eq!(watches.registered, count);
+            assert_eq!(watches.instances.len(), 2);
+            let files = watches.files.clone();
+            drop(watches);
+            assert!(files.is_none_or(|files| !files.exists()));
+        }
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,292 @@
+//! Just enough of WebSocket (RFC 6455) for `itsmine net --mode websocket`:
+//! the opening handshake from either side, and framing.
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+/// Appended to the client's key before hashing it into the server's reply.
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// Longest an opening handshake may take.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Largest handshake accepted, headers included.
+const MAX_HANDSHAKE: usize = 16 << 10;
+
+pub const TEXT: u8 = 0x1;
+pub const BINARY: u8 = 0x2;
+pub const CLOSE: u8 = 0x8;
+pub const PING: u8 = 0x9;
+pub const PONG: u8 = 0xa;
+
+/// One unfragmented message or control frame, unmasked.
+#[derive(Debug, PartialEq)]
+pub struct Frame {
+    pub opcode: u8,
+    pub payload: Vec<u8>,
+}
+
+/// A final frame of `payload`, masked with `mask` as clients must.
+pub fn encode(opcode: u8, payload: &[u8], mask: Option<[u8; 4]>) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    let masked = match mask {
+        Some(_) => 0x80,
+        None => 0,
+    };
+    match payload.len() {
+        len @ 0..=125 => frame.push(masked | len as u8),
+        len @ 126..=0xffff => {
+            frame.push(masked | 126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(masked | 127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    if let Some(key) = mask {
+        frame.extend_from_slice(&key);
+    }
+    let start = frame.len();
+    frame.extend_from_slice(payload);
+    if let Some(key) = mask {
+        apply_mask(&mut frame[start..], key);
+    }
+    frame
+}
+
+fn apply_mask(payload: &mut [u8], key: [u8; 4]) {
+    for (byte, key) in payload.iter_mut().zip(key.iter().cycle()) {
+        *byte ^= key;
+    }
+}
+
+/// Takes the first whole frame off the front of `buffer`, if there is one.
+pub fn decode(buffer: &mut Vec<u8>) -> Option<Frame> {
+    let (&first, &second) = (buffer.first()?, buffer.get(1)?);
+    let (len, mut start) = match second & 0x7f {
+        126 => (
+            u16::from_be_bytes(buffer.get(2..4)?.try_into().ok()?) as u64,
+            4,
+        ),
+        127 => (u64::from_be_bytes(buffer.get(2..10)?.try_into().ok()?), 10),
+        len => (len as u64, 2),
+    };
+    let mask = match second & 0x80 {
+        0 => None,
+        _ => {
+            start += 4;
+            Some(<[u8; 4]>::try_from(buffer.get(start - 4..start)?).ok()?)
+        }
+    };
+    let end = start.checked_add(usize::try_from(len).ok()?)?;
+    if buffer.len() < end {
+        return None;
+    }
+    let mut payload = buffer[start..end].to_vec();
+    buffer.drain(..end);
+    if let Some(key) = mask {
+        apply_mask(&mut payload, key);
+    }
+    Some(Frame {
+        opcode: first & 0x0f,
+        payload,
+    })
+}
+
+/// The `Sec-WebSocket-Accept` a server answers `key` with.
+pub fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{key}{GUID}").as_bytes()))
+}
+
+/// Opens the connection as a client. Returns whatever the server sent
+/// after its reply, which is already frames.
+pub fn connect(
+    stream: &mut TcpStream,
+    host: &str,
+    path: &str,
+    nonce: [u8; 16],
+) -> std::io::Result<Vec<u8>> {
+    let key = base64(&nonce);
+    write!(
+        stream,
+        "GET {path} HTTP/1.1\r\nHost: {host}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: {key}\r\nSec-WebSocket-Version: 13\r\n\r\n"
+    )?;
+    let (head, rest) = read_head(stream)?;
+    let status = head.lines().next().unwrap_or_default();
+    if status.split_whitespace().nth(1) != Some("101") {
+        return Err(refused(format!("the server answered '{status}'")));
+    }
+    match header(&head, "sec-websocket-accept") {
+        Some(accept) if accept == accept_key(&key) => Ok(rest),
+        _ => Err(refused(
+            "the server's Sec-WebSocket-Accept is wrong".to_string(),
+        )),
+    }
+}
+
+/// Accepts a client's opening handshake. Returns whatever the client sent
+/// after it, which is already frames.
+pub fn accept(stream: &mut TcpStream) -> std::io::Result<Vec<u8>> {
+    let (head, rest) = read_head(stream)?;
+    let Some(key) = header(&head, "sec-websocket-key") else {
+        stream.write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n")?;
+        return Err(refused("the client sent no Sec-WebSocket-Key".to_string()));
+    };
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(key)
+    )?;
+    Ok(rest)
+}
+
+fn refused(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Reads up to the blank line ending HTTP headers; returns them and
+/// anything read beyond.
+fn read_head(stream: &mut TcpStream) -> std::io::Result<(String, Vec<u8>)> {
+    let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+    let mut buffer = vec![];
+    let mut chunk = [0; 1024];
+    loop {
+        if let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            let rest = buffer.split_off(end + 4);
+            return Ok((String::from_utf8_lossy(&buffer).into_owned(), rest));
+        }
+        if buffer.len() > MAX_HANDSHAKE || Instant::now() >= deadline {
+            return Err(refused("no complete handshake".to_string()));
+        }
+        match stream.read(&mut chunk) {
+            Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// The value of header `name` (lowercase) in `head`.
+fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.lines().skip(1).find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        (key.trim().to_ascii_lowercase() == name).then_some(value.trim())
+    })
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char),
+                false => encoded.push('='),
+            }
+        }
+    }
+    encoded
+}
+
+/// SHA-1 (FIPS 180-4), which the handshake requires; kept here so it needs
+/// no dependency.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..20 => ((b & c) | (!b & d), 0x5a827999),
+                20..40 => (b ^ c ^ d, 0x6ed9eba1),
+                40..60 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let t = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = t;
+        }
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+    let mut digest = [0u8; 20];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(h) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_the_accept_key() {
+        // The example from RFC 6455, section 1.3.
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let mut buffer = vec![];
+        for len in [0, 125, 126, 70_000] {
+            let payload: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            buffer.extend(encode(BINARY, &payload, Some([1, 2, 3, 4])));
+            buffer.extend(encode(PONG, &payload, None));
+        }
+        // A frame cut short waits for the rest.
+        let last = buffer.pop().unwrap();
+        for len in [0, 125, 126, 70_000] {
+            let payload: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let binary = decode(&mut buffer).unwrap();
+            assert_eq!((binary.opcode, binary.payload.len()), (BINARY, len));
+            assert_eq!(binary.payload, payload);
+            if len == 70_000 {
+                assert_eq!(decode(&mut buffer), None);
+                buffer.push(last);
+            }
+            assert_eq!(decode(&mut buffer).unwrap().payload, payload);
+        }
+        assert!(buffer.is_empty());
+    }
+}
@@ -0,0 +1,128 @@
+//! `itsmine zombies`: forks children that exit at once and leaves them
+//! unreaped, holding that many process-table entries until cleanup.
+
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+use crate::{CancellationToken, StressError, Stressor, parse, report};
+
+#[derive(Clone, Debug, PartialEq, clap::Args)]
+pub struct Args {
+    /// Zombies to hold
+    #[arg(long, value_name = "N", default_value_t = 100)]
+    pub count: u32,
+    /// How long to hold them, e.g. 10s
+    #[arg(long, value_parser = parse::duration, default_value = "10s")]
+    pub duration: Duration,
+}
+
+/// Exited children not yet waited for; dropping reaps them.
+struct Zombies(Vec<libc::pid_t>);
+
+impl Zombies {
+    /// Forks up to `count` children that exit immediately, stopping early
+    /// if the kernel refuses a fork (e.g. at `pids.max`).
+    fn spawn(count: u32, cancel: &CancellationToken) -> Self {
+        let mut zombies = Zombies(Vec::with_capacity(count as usize));
+        while zombies.0.len() < count as usize && !cancel.is_cancelled() {
+            // SAFETY: the child only calls `_exit`, which is safe after fork
+            // in a threaded process.
+            match unsafe { libc::fork() } {
+                0 => unsafe { libc::_exit(0) },
+                -1 => {
+                    log::warn!(
+                        "Fork failed after {} zombies ({}); holding those.",
+                        zombies.0.len(),
+                        std::io::Error::last_os_error()
+                    );
+                    break;
+                }
+                pid => zombies.0.push(pid),
+            }
+        }
+        zombies
+    }
+
+    /// How many of the children the kernel shows as zombies right now.
+    fn confirmed(&self) -> usize {
+        self.0
+            .iter()
+            .filter(|&&pid| state(pid) == Some('Z'))
+            .count()
+    }
+}
+
+impl Drop for Zombies {
+    fn drop(&mut self) {
+        for &pid in &self.0 {
+            // SAFETY: plain syscall on our own child.
+            unsafe { libc::waitpid(pid, std::ptr::null_mut(), 0) };
+        }
+    }
+}
+
+/// The state letter from `/proc/<pid>/stat`.
+fn state(pid: libc::pid_t) -> Option<char> {
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // The command name may hold spaces and parentheses; the state follows
+    // the last `)`.
+    stat.rsplit_once(") ")?.1.chars().next()
+}
+
+impl Stressor for Args {
+    fn name(&self) -> &'static str {
+        "zombies"
+    }
+
+    fn params(&self) -> Value {
+        json!({
+            "count": self.count,
+            "duration_secs": self.duration.as_secs_f64(),
+        })
+    }
+
+    fn run(&self, cancel: &CancellationToken) -> Result<(), anyhow::Error> {
+        let started = Instant::now();
+        let zombies = Zombies::spawn(self.count, cancel);
+        let spawn_secs = started.elapsed().as_secs_f64();
+        log::info!(
+            "Holding {} zombies for {:?}.",
+            zombies.0.len(),
+            self.duration
+        );
+        cancel.sleep_until(Instant::now() + self.duration);
+        let summary = json!({
+            "zombies": zombies.0.len(),
+            "confirmed": zombies.confirmed(),
+            "spawn_secs": spawn_secs,
+        });
+        drop(zombies);
+        log::info!("Zombies: {summary}.");
+        report::measure(self.name(), summary);
+        match cancel.is_cancelled() {
+            true => Err(StressError::Interrupted.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn holds_zombies_until_dropped() {
+        let zombies = Zombies::spawn(3, &CancellationToken::new());
+        assert_eq!(zombies.0.len(), 3);
+        // Children may take a moment to finish exiting.
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while zombies.confirmed() < 3 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(zombies.confirmed(), 3);
+        let pids = zombies.0.clone();
+        drop(zombies);
+        assert!(pids.iter().all(|&pid| state(pid).is_none()));
+    }
+}